@@ -0,0 +1,114 @@
+//! Counts the heap allocations made listing a directory of 100,000 files with
+//! `fs::read_dir`, as the scanner used to, and on Linux with `getdents64` into
+//! a stack buffer, as it does now, keeping each name and path either way. Then
+//! times the listings and a count-only scan of the directory.
+
+use bfinder::{ScanConfig, parallel_scan};
+use criterion::{Criterion, criterion_group, criterion_main};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fs;
+use std::hint::black_box;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const FILES: usize = 100_000;
+
+/// The system allocator, counting every allocation
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        // SAFETY: forwarded unchanged to the system allocator
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // SAFETY: `ptr` was allocated by `alloc` above, with this layout
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// Allocations made while running `f`
+fn allocations<T>(f: impl FnOnce() -> T) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    black_box(f());
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+/// A directory holding `FILES` empty files
+fn build_dir() -> PathBuf {
+    let root = std::env::temp_dir().join(format!("bfinder-listing-{}", std::process::id()));
+    fs::create_dir_all(&root).unwrap();
+    for i in 0..FILES {
+        fs::File::create(root.join(format!("f{:06}", i))).unwrap();
+    }
+    root
+}
+
+fn list_read_dir(dir: &Path) -> usize {
+    fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| {
+            let entry = entry.unwrap();
+            (entry.file_name().into_string().unwrap(), entry.path())
+        })
+        .count()
+}
+
+#[cfg(target_os = "linux")]
+fn list_getdents(dir: &Path) -> usize {
+    use rustix::fs::{Mode, OFlags, RawDir};
+    use std::mem::MaybeUninit;
+
+    let mut buf = [MaybeUninit::<u8>::uninit(); 64 * 1024];
+    let flags = OFlags::RDONLY | OFlags::DIRECTORY | OFlags::CLOEXEC;
+    let fd = rustix::fs::open(dir, flags, Mode::empty()).unwrap();
+    let mut raw = RawDir::new(fd, &mut buf);
+    let mut count = 0;
+    while let Some(entry) = raw.next() {
+        let name = entry.unwrap().file_name().to_str().unwrap().to_owned();
+        if name != "." && name != ".." {
+            black_box(dir.join(&name));
+            count += 1;
+        }
+    }
+    count
+}
+
+fn listing(c: &mut Criterion) {
+    let root = build_dir();
+    let mut config = ScanConfig::new(&root);
+    config.count_only = true;
+
+    eprintln!(
+        "allocations listing {} files with read_dir: {}",
+        FILES,
+        allocations(|| list_read_dir(&root))
+    );
+    #[cfg(target_os = "linux")]
+    eprintln!(
+        "allocations listing {} files with getdents64: {}",
+        FILES,
+        allocations(|| list_getdents(&root))
+    );
+
+    let mut group = c.benchmark_group("listing");
+    group.sample_size(10);
+    group.bench_function("read_dir", |b| b.iter(|| black_box(list_read_dir(&root))));
+    #[cfg(target_os = "linux")]
+    group.bench_function("getdents64", |b| b.iter(|| black_box(list_getdents(&root))));
+    group.bench_function("parallel_scan_count_only", |b| {
+        b.iter(|| black_box(parallel_scan(&config)))
+    });
+    group.finish();
+    fs::remove_dir_all(&root).unwrap();
+}
+
+criterion_group!(benches, listing);
+criterion_main!(benches);
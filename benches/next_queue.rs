@@ -0,0 +1,86 @@
+//! Compares the next-level queue used by `parallel_scan` (a lock-free
+//! `SegQueue`) with the `Mutex<Vec>` it replaced, on a level with many sibling
+//! directories, and times a full scan of such a tree.
+
+use bfinder::{ScanConfig, parallel_scan};
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use crossbeam_queue::SegQueue;
+use rayon::prelude::*;
+use std::fs;
+use std::hint::black_box;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const SIBLINGS: usize = 2000;
+const CHILDREN: usize = 4;
+
+/// A root holding `SIBLINGS` directories, each with `CHILDREN` subdirectories
+/// and one small file
+fn build_tree() -> PathBuf {
+    let root = std::env::temp_dir().join(format!("bfinder-bench-{}", std::process::id()));
+    for i in 0..SIBLINGS {
+        let dir = root.join(format!("d{:05}", i));
+        for j in 0..CHILDREN {
+            fs::create_dir_all(dir.join(format!("c{}", j))).unwrap();
+        }
+        fs::write(dir.join("f"), vec![0u8; i % 4096]).unwrap();
+    }
+    root
+}
+
+fn subdirs_of(parent: &Path) -> Vec<PathBuf> {
+    (0..CHILDREN)
+        .map(|j| parent.join(format!("c{}", j)))
+        .collect()
+}
+
+fn queue_push(c: &mut Criterion) {
+    let level: Vec<PathBuf> = (0..SIBLINGS)
+        .map(|i| PathBuf::from(format!("/d{:05}", i)))
+        .collect();
+    let mut group = c.benchmark_group("next_queue");
+
+    group.bench_with_input(
+        BenchmarkId::new("mutex_vec", SIBLINGS),
+        &level,
+        |b, level| {
+            b.iter(|| {
+                let next = Mutex::new(Vec::new());
+                level.par_iter().for_each(|dir| {
+                    next.lock().unwrap().extend(subdirs_of(dir));
+                });
+                black_box(next.into_inner().unwrap())
+            })
+        },
+    );
+
+    group.bench_with_input(
+        BenchmarkId::new("seg_queue", SIBLINGS),
+        &level,
+        |b, level| {
+            b.iter(|| {
+                let next = SegQueue::new();
+                level.par_iter().for_each(|dir| {
+                    for subdir in subdirs_of(dir) {
+                        next.push(subdir);
+                    }
+                });
+                black_box(std::iter::from_fn(|| next.pop()).collect::<Vec<_>>())
+            })
+        },
+    );
+
+    group.finish();
+}
+
+fn scan_siblings(c: &mut Criterion) {
+    let root = build_tree();
+    let config = ScanConfig::new(&root);
+    c.bench_function("parallel_scan_siblings", |b| {
+        b.iter(|| black_box(parallel_scan(&config)))
+    });
+    fs::remove_dir_all(&root).unwrap();
+}
+
+criterion_group!(benches, queue_push, scan_siblings);
+criterion_main!(benches);
@@ -0,0 +1,164 @@
+//! Sizing the scan's thread pool while it runs, for `ScanConfig::auto_threads`:
+//! a sampler thread watches how many directories of the current level are
+//! waiting for a thread, adding threads while they keep piling up (the scan
+//! waits on I/O) and removing them while none wait (it is CPU bound)
+//!
+//! Rayon pools have a fixed size, so each level is scanned in chunks, each on
+//! a pool of the size the sampler last chose; pools are kept for reuse.
+
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Time between two samples of the queue
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Consecutive samples that must agree before the thread count changes
+const SAMPLES_TO_ACT: u32 = 2;
+
+/// Directories per thread in each chunk of a level; smaller chunks follow the
+/// sampler more closely but wait more often for their slowest directory
+const DIRS_PER_THREAD: usize = 32;
+
+/// What the scan and the sampler share
+#[derive(Debug)]
+pub(crate) struct Load {
+    /// Thread count for the next chunk
+    threads: AtomicUsize,
+    /// Directories of the current level not scanned yet, running or waiting
+    pending: AtomicUsize,
+    /// Directories scanned so far, for the throughput between samples
+    completed: AtomicU64,
+    stop: AtomicBool,
+}
+
+impl Load {
+    /// Note that one directory has been scanned
+    pub(crate) fn dir_done(&self) {
+        self.pending.fetch_sub(1, Ordering::Relaxed);
+        self.completed.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// The sampler thread and the pools the scan runs on
+pub(crate) struct ThreadTuner {
+    load: Arc<Load>,
+    sampler: JoinHandle<()>,
+    pools: HashMap<usize, ThreadPool>,
+}
+
+impl ThreadTuner {
+    /// Start with a thread per CPU, letting the sampler go from half as many
+    /// to four times as many
+    pub(crate) fn start() -> Self {
+        let cpus = thread::available_parallelism().map_or(1, |n| n.get());
+        let load = Arc::new(Load {
+            threads: AtomicUsize::new(cpus),
+            pending: AtomicUsize::new(0),
+            completed: AtomicU64::new(0),
+            stop: AtomicBool::new(false),
+        });
+        let sampled = Arc::clone(&load);
+        let sampler =
+            thread::spawn(move || sample(&sampled, (cpus / 2).max(1), cpus * 4, cpus.div_ceil(2)));
+        ThreadTuner {
+            load,
+            sampler,
+            pools: HashMap::new(),
+        }
+    }
+
+    /// The counters the scan updates as directories finish
+    pub(crate) fn load(&self) -> Arc<Load> {
+        Arc::clone(&self.load)
+    }
+
+    /// Run `scan` over a level's `dirs` in chunks, each on a pool of the size
+    /// the sampler last chose, concatenating the results in order
+    pub(crate) fn run_level<T: Sync, R: Send>(
+        &mut self,
+        dirs: &[T],
+        scan: impl Fn(&[T]) -> Vec<R> + Sync,
+    ) -> Vec<R> {
+        self.load.pending.store(dirs.len(), Ordering::Relaxed);
+        let mut results = Vec::with_capacity(dirs.len());
+        let mut rest = dirs;
+        while !rest.is_empty() {
+            let threads = self.load.threads.load(Ordering::Relaxed);
+            let (chunk, after) = rest.split_at(rest.len().min(threads * DIRS_PER_THREAD));
+            let pool = self.pools.entry(threads).or_insert_with(|| {
+                tracing::info!(threads, "resizing the scan's thread pool");
+                ThreadPoolBuilder::new()
+                    .num_threads(threads)
+                    .build()
+                    .expect("cannot start scan threads")
+            });
+            results.extend(pool.install(|| scan(chunk)));
+            rest = after;
+        }
+        results
+    }
+
+    /// Stop the sampler, returning the thread count it had settled on
+    pub(crate) fn finish(self) -> usize {
+        self.load.stop.store(true, Ordering::Relaxed);
+        self.sampler.thread().unpark();
+        let _ = self.sampler.join();
+        self.load.threads.load(Ordering::Relaxed)
+    }
+}
+
+/// Adjust `load.threads` between `min` and `max` by `step` until stopped.
+/// A step up that did not raise the throughput is taken back and not tried
+/// again, as more threads then only contend for the same device.
+fn sample(load: &Load, min: usize, max: usize, step: usize) {
+    let mut last = Instant::now();
+    let mut last_completed = 0;
+    let (mut busy, mut idle) = (0, 0);
+    // Thread count and throughput before the last step up
+    let mut before_growth: Option<(usize, f64)> = None;
+    let mut ceiling = max;
+    while !load.stop.load(Ordering::Relaxed) {
+        thread::park_timeout(SAMPLE_INTERVAL);
+        let elapsed = last.elapsed();
+        if elapsed < SAMPLE_INTERVAL {
+            continue;
+        }
+        let completed = load.completed.load(Ordering::Relaxed);
+        let rate = (completed - last_completed) as f64 / elapsed.as_secs_f64();
+        (last, last_completed) = (Instant::now(), completed);
+
+        let threads = load.threads.load(Ordering::Relaxed);
+        let pending = load.pending.load(Ordering::Relaxed);
+        if let Some((previous, previous_rate)) = before_growth.take()
+            && rate <= previous_rate
+        {
+            tracing::debug!(threads, rate, previous_rate, "more threads did not help");
+            ceiling = previous;
+            load.threads.store(previous, Ordering::Relaxed);
+            continue;
+        }
+        // Directories waiting beyond those being scanned
+        if pending > threads {
+            (busy, idle) = (busy + 1, 0);
+        } else if pending == 0 {
+            (busy, idle) = (0, idle + 1);
+        } else {
+            (busy, idle) = (0, 0);
+        }
+        if busy >= SAMPLES_TO_ACT && threads < ceiling {
+            before_growth = Some((threads, rate));
+            load.threads
+                .store((threads + step).min(ceiling), Ordering::Relaxed);
+            busy = 0;
+        } else if idle >= SAMPLES_TO_ACT && threads > min {
+            load.threads
+                .store(threads.saturating_sub(step).max(min), Ordering::Relaxed);
+            idle = 0;
+        }
+        tracing::debug!(threads, pending, rate, "sampled the scan's load");
+    }
+}
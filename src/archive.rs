@@ -0,0 +1,241 @@
+//! Listing the files inside tar and zip archives without extracting them
+
+use crate::{FileEntry, ScanError};
+use std::ffi::{OsStr, OsString};
+use std::fs::File;
+use std::io::{self, BufReader, Cursor, Read, Seek};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Largest nested zip read into memory; zip needs random access, which an
+/// entry of another archive cannot offer
+const MAX_NESTED_ZIP: u64 = 256 * 1024 * 1024;
+
+/// Archive formats recognized by their file name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Tar,
+    TarGz,
+    TarBz2,
+    TarXz,
+    Zip,
+}
+
+impl ArchiveKind {
+    /// Recognize an archive by its extension (`.tar`, `.tar.gz`/`.tgz`,
+    /// `.tar.bz2`/`.tbz2`, `.tar.xz`/`.txz`, `.zip`), ignoring case
+    pub fn detect(name: &str) -> Option<Self> {
+        let name = name.to_ascii_lowercase();
+        let kinds = [
+            (".tar", ArchiveKind::Tar),
+            (".tar.gz", ArchiveKind::TarGz),
+            (".tgz", ArchiveKind::TarGz),
+            (".tar.bz2", ArchiveKind::TarBz2),
+            (".tbz2", ArchiveKind::TarBz2),
+            (".tar.xz", ArchiveKind::TarXz),
+            (".txz", ArchiveKind::TarXz),
+            (".zip", ArchiveKind::Zip),
+        ];
+        kinds
+            .into_iter()
+            .find(|(suffix, _)| name.len() > suffix.len() && name.ends_with(suffix))
+            .map(|(_, kind)| kind)
+    }
+}
+
+/// `archive::member`, the path given to a file inside an archive
+fn member_path(archive: &Path, member: &str) -> PathBuf {
+    let mut path = OsString::from(archive.as_os_str());
+    path.push("::");
+    path.push(OsStr::new(member));
+    PathBuf::from(path)
+}
+
+/// Report every regular file inside the archive at `path` to `found`, with paths
+/// like `archive.tar::dir/file` and the uncompressed size. Archives inside it
+/// are listed too, `nested` levels deep. Returns the archives that could not be
+/// read, which may leave their listing incomplete.
+pub fn scan_archive(
+    path: &Path,
+    kind: ArchiveKind,
+    nested: usize,
+    found: &mut dyn FnMut(FileEntry),
+) -> Vec<ScanError> {
+    let mut errors = Vec::new();
+    let result = File::open(path).and_then(|file| match kind {
+        ArchiveKind::Zip => walk_zip(file, path, nested, found, &mut errors),
+        _ => walk_tar(
+            decoder(kind, BufReader::new(file)),
+            path,
+            nested,
+            found,
+            &mut errors,
+        ),
+    });
+    if let Err(source) = result {
+        errors.push(ScanError::ReadFailed {
+            path: path.to_path_buf(),
+            source,
+        });
+    }
+    errors
+}
+
+/// The decompressed stream of a tar archive
+fn decoder<'a>(kind: ArchiveKind, reader: impl Read + 'a) -> Box<dyn Read + 'a> {
+    match kind {
+        ArchiveKind::TarGz => Box::new(flate2::read::MultiGzDecoder::new(reader)),
+        ArchiveKind::TarBz2 => Box::new(bzip2::read::MultiBzDecoder::new(reader)),
+        ArchiveKind::TarXz => Box::new(xz2::read::XzDecoder::new_multi_decoder(reader)),
+        ArchiveKind::Tar | ArchiveKind::Zip => Box::new(reader),
+    }
+}
+
+/// List a nested archive read from `reader`, recording its failure under `path`
+fn walk_nested(
+    kind: ArchiveKind,
+    reader: &mut dyn Read,
+    path: &Path,
+    nested: usize,
+    found: &mut dyn FnMut(FileEntry),
+    errors: &mut Vec<ScanError>,
+) {
+    let result = match kind {
+        ArchiveKind::Zip => {
+            let mut buf = Vec::new();
+            reader
+                .take(MAX_NESTED_ZIP + 1)
+                .read_to_end(&mut buf)
+                .and_then(|_| {
+                    if buf.len() as u64 > MAX_NESTED_ZIP {
+                        return Err(io::Error::other("nested zip too large to read into memory"));
+                    }
+                    walk_zip(Cursor::new(buf), path, nested, found, errors)
+                })
+        }
+        _ => walk_tar(decoder(kind, reader), path, nested, found, errors),
+    };
+    if let Err(source) = result {
+        errors.push(ScanError::ReadFailed {
+            path: path.to_path_buf(),
+            source,
+        });
+    }
+}
+
+fn walk_tar(
+    reader: impl Read,
+    archive_path: &Path,
+    nested: usize,
+    found: &mut dyn FnMut(FileEntry),
+    errors: &mut Vec<ScanError>,
+) -> io::Result<()> {
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let name = entry.path()?.to_string_lossy().into_owned();
+        let name = name.strip_prefix("./").unwrap_or(&name);
+        let path = member_path(archive_path, name);
+        let size = entry.size();
+        let modified = entry
+            .header()
+            .mtime()
+            .ok()
+            .map(|secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs));
+
+        if nested > 0
+            && let Some(kind) = ArchiveKind::detect(name)
+        {
+            walk_nested(kind, &mut entry, &path, nested - 1, found, errors);
+        }
+        found(FileEntry {
+            size,
+            path,
+            modified,
+            accessed: None,
+            hash: None,
+            sparseness: 0.0,
+            checksum: None,
+            is_reflinked: false,
+            physical_size: 0,
+            allocated_size: 0,
+            xattr_size: 0,
+            nlink: 0,
+            mode: 0,
+            is_dir: false,
+            verified: None,
+        });
+    }
+    Ok(())
+}
+
+fn walk_zip(
+    reader: impl Read + Seek,
+    archive_path: &Path,
+    nested: usize,
+    found: &mut dyn FnMut(FileEntry),
+    errors: &mut Vec<ScanError>,
+) -> io::Result<()> {
+    let mut archive = zip::ZipArchive::new(reader)?;
+    for i in 0..archive.len() {
+        // Raw access reads only the headers; members are only decompressed
+        // when they are archives to list in turn
+        let (name, size, modified) = {
+            let file = archive.by_index_raw(i)?;
+            if !file.is_file() {
+                continue;
+            }
+            (
+                file.name()?.into_owned(),
+                file.size(),
+                file.last_modified().and_then(zip_time),
+            )
+        };
+        let path = member_path(archive_path, &name);
+
+        if nested > 0
+            && let Some(kind) = ArchiveKind::detect(&name)
+        {
+            match archive.by_index(i) {
+                Ok(mut file) => walk_nested(kind, &mut file, &path, nested - 1, found, errors),
+                Err(e) => errors.push(ScanError::ReadFailed {
+                    path: path.clone(),
+                    source: e.into(),
+                }),
+            }
+        }
+        found(FileEntry {
+            size,
+            path,
+            modified,
+            accessed: None,
+            hash: None,
+            sparseness: 0.0,
+            checksum: None,
+            is_reflinked: false,
+            physical_size: 0,
+            allocated_size: 0,
+            xattr_size: 0,
+            nlink: 0,
+            mode: 0,
+            is_dir: false,
+            verified: None,
+        });
+    }
+    Ok(())
+}
+
+/// A zip timestamp, which is in local time
+fn zip_time(t: zip::DateTime) -> Option<SystemTime> {
+    use chrono::{Local, NaiveDate, TimeZone};
+
+    let naive = NaiveDate::from_ymd_opt(t.year().into(), t.month().into(), t.day().into())?
+        .and_hms_opt(t.hour().into(), t.minute().into(), t.second().into())?;
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .map(SystemTime::from)
+}
@@ -0,0 +1,75 @@
+//! Checksums of the reported files
+
+use crate::{FileEntry, ScanError};
+use clap::ValueEnum;
+use rayon::prelude::*;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+/// Hash algorithms for `--checksum`; all but SHA-256 depend on a feature
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ChecksumAlgorithm {
+    Sha256,
+    #[cfg(feature = "sha1")]
+    Sha1,
+    #[cfg(feature = "md5")]
+    Md5,
+    #[cfg(feature = "blake3")]
+    Blake3,
+}
+
+/// The digest of everything `reader` yields, computed by a `Digest` hasher
+fn digest<D: sha2::Digest + io::Write>(mut reader: impl io::Read) -> io::Result<Vec<u8>> {
+    let mut hasher = D::new();
+    io::copy(&mut reader, &mut hasher)?;
+    Ok(hasher.finalize().to_vec())
+}
+
+/// Hash the contents of the file at `path`
+pub fn checksum_file(path: &Path, algorithm: ChecksumAlgorithm) -> io::Result<Vec<u8>> {
+    let reader = BufReader::new(File::open(path)?);
+    match algorithm {
+        ChecksumAlgorithm::Sha256 => digest::<sha2::Sha256>(reader),
+        #[cfg(feature = "sha1")]
+        ChecksumAlgorithm::Sha1 => digest::<sha1::Sha1>(reader),
+        #[cfg(feature = "md5")]
+        ChecksumAlgorithm::Md5 => digest::<md5::Md5>(reader),
+        #[cfg(feature = "blake3")]
+        ChecksumAlgorithm::Blake3 => {
+            let mut hasher = blake3::Hasher::new();
+            hasher.update_reader(reader)?;
+            Ok(hasher.finalize().as_bytes().to_vec())
+        }
+    }
+}
+
+/// Fill in `FileEntry::checksum` for every entry, hashing files in parallel and
+/// calling `done` after each one. Files that cannot be read keep no checksum
+/// and are returned as errors.
+pub fn add_checksums(
+    entries: &mut [FileEntry],
+    algorithm: ChecksumAlgorithm,
+    done: &(dyn Fn() + Sync),
+) -> Vec<ScanError> {
+    let mut errors: Vec<ScanError> = entries
+        .par_iter_mut()
+        .filter_map(|entry| {
+            let result = checksum_file(&entry.path, algorithm);
+            done();
+            match result {
+                Ok(digest) => {
+                    entry.checksum = Some(digest);
+                    None
+                }
+                Err(source) => Some(ScanError::ReadFailed {
+                    path: entry.path.clone(),
+                    source,
+                }),
+            }
+        })
+        .collect();
+    errors.sort_by(|a, b| a.path().cmp(b.path()));
+    errors
+}
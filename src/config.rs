@@ -0,0 +1,203 @@
+//! Defaults and named profiles from `config.toml`
+//!
+//! Every key names a command-line option (`top = 20`, `exclude = ["*.gz"]`,
+//! `follow_symlinks = true`) and is turned into the equivalent argument, so the
+//! file accepts exactly what the command line does. Those arguments are placed
+//! before the real ones, which therefore take precedence.
+//!
+//! ```toml
+//! top = 20
+//! exclude = [".git", "node_modules"]
+//!
+//! [aliases.logs]
+//! path = "/var/log"
+//! exclude = ["*.gz"]
+//! ```
+
+use clap::Command;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Settings loaded from a configuration file
+#[derive(Debug, Default)]
+pub struct BfinderConfig {
+    /// Top-level settings, as command-line arguments
+    pub defaults: Vec<OsString>,
+    /// Named profiles from the `[aliases]` table
+    pub aliases: HashMap<String, Profile>,
+}
+
+/// A named scan profile, selected with `--profile`
+#[derive(Debug, Default)]
+pub struct Profile {
+    pub args: Vec<OsString>,
+    /// Directories to scan when none are given on the command line
+    pub paths: Vec<PathBuf>,
+}
+
+/// Options that only make sense on the command line
+const CLI_ONLY: &[&str] = &["config", "no-config", "profile", "help"];
+
+/// `$XDG_CONFIG_HOME/bfinder/config.toml`, falling back to `~/.config`
+pub fn default_path() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(base.join("bfinder").join("config.toml"))
+}
+
+impl BfinderConfig {
+    /// Load `path`; a missing file is only an error when `required` is set
+    pub fn load(path: &Path, required: bool, command: &Command) -> Result<Self, String> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if !required && e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Self::default());
+            }
+            Err(e) => return Err(format!("cannot read config {}: {}", path.display(), e)),
+        };
+        Self::parse(&contents, command)
+            .map_err(|e| format!("invalid config {}: {}", path.display(), e))
+    }
+
+    fn parse(contents: &str, command: &Command) -> Result<Self, String> {
+        let mut table: toml::Table = contents
+            .parse()
+            .map_err(|e: toml::de::Error| e.to_string())?;
+
+        let mut aliases = HashMap::new();
+        if let Some(value) = table.remove("aliases") {
+            let toml::Value::Table(profiles) = value else {
+                return Err("`aliases` must be a table".to_string());
+            };
+            for (name, value) in profiles {
+                let toml::Value::Table(mut settings) = value else {
+                    return Err(format!("alias `{}` must be a table", name));
+                };
+                let paths = take_paths(&mut settings, &name)?;
+                let args = to_args(&settings, command)?;
+                aliases.insert(name, Profile { args, paths });
+            }
+        }
+
+        Ok(Self {
+            defaults: to_args(&table, command)?,
+            aliases,
+        })
+    }
+}
+
+/// Remove `path` (a string) or `paths` (an array of strings) from a profile
+fn take_paths(settings: &mut toml::Table, alias: &str) -> Result<Vec<PathBuf>, String> {
+    let mut paths = Vec::new();
+    for key in ["path", "paths"] {
+        let values = match settings.remove(key) {
+            None => continue,
+            Some(toml::Value::Array(values)) => values,
+            Some(value) => vec![value],
+        };
+        for value in values {
+            match value {
+                toml::Value::String(s) => paths.push(PathBuf::from(s)),
+                _ => return Err(format!("alias `{}`: `{}` must hold strings", alias, key)),
+            }
+        }
+    }
+    Ok(paths)
+}
+
+/// Turn `key = value` pairs into `--key=value` arguments, checking each key
+/// against the options `command` accepts
+fn to_args(table: &toml::Table, command: &Command) -> Result<Vec<OsString>, String> {
+    let mut args = Vec::new();
+    for (key, value) in table {
+        let long = key.replace('_', "-");
+        let known = command
+            .get_arguments()
+            .any(|arg| arg.get_long() == Some(long.as_str()));
+        if !known || CLI_ONLY.contains(&long.as_str()) {
+            return Err(format!("unknown setting `{}`", key));
+        }
+
+        let values = match value {
+            toml::Value::Array(values) => values.iter().collect(),
+            value => vec![value],
+        };
+        for value in values {
+            let value = match value {
+                toml::Value::Boolean(true) => {
+                    args.push(format!("--{}", long).into());
+                    continue;
+                }
+                toml::Value::Boolean(false) => continue,
+                toml::Value::String(s) => s.clone(),
+                toml::Value::Integer(n) => n.to_string(),
+                toml::Value::Float(f) => f.to_string(),
+                _ => return Err(format!("`{}` must be a string, number or boolean", key)),
+            };
+            args.push(format!("--{}={}", long, value).into());
+        }
+    }
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    fn parse(contents: &str) -> Result<BfinderConfig, String> {
+        BfinderConfig::parse(contents, &crate::Cli::command())
+    }
+
+    #[test]
+    fn settings_become_arguments() {
+        let config = parse(
+            "top = 20\nfollow_symlinks = true\nno_hidden = false\nexclude = [\".git\", \"node_modules\"]\n\
+             [aliases.logs]\npath = \"/var/log\"\nexclude = \"*.gz\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            config.defaults,
+            [
+                "--exclude=.git",
+                "--exclude=node_modules",
+                "--follow-symlinks",
+                "--top=20"
+            ]
+        );
+        let logs = &config.aliases["logs"];
+        assert_eq!(logs.args, ["--exclude=*.gz"]);
+        assert_eq!(logs.paths, [PathBuf::from("/var/log")]);
+    }
+
+    #[test]
+    fn rejects_unknown_and_command_line_only_settings() {
+        assert_eq!(
+            parse("colour = true").unwrap_err(),
+            "unknown setting `colour`"
+        );
+        assert_eq!(
+            parse("no_config = true").unwrap_err(),
+            "unknown setting `no_config`"
+        );
+        assert!(parse("[aliases]\nlogs = 1").is_err());
+        assert!(parse("top = [[1]]").is_err());
+    }
+
+    #[test]
+    fn only_a_required_file_must_exist() {
+        let missing = Path::new("/nonexistent/bfinder/config.toml");
+        let command = crate::Cli::command();
+        assert!(
+            BfinderConfig::load(missing, false, &command)
+                .unwrap()
+                .defaults
+                .is_empty()
+        );
+        assert!(BfinderConfig::load(missing, true, &command).is_err());
+    }
+}
@@ -0,0 +1,156 @@
+//! Comparison of two scans of the same tree, or of two different trees
+
+use crate::FileEntry;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// A file present in both scans whose size changed
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SizeChange {
+    pub path: PathBuf,
+    pub old_size: u64,
+    pub new_size: u64,
+}
+
+impl SizeChange {
+    /// Growth in bytes, negative when the file shrank
+    pub fn delta(&self) -> i64 {
+        self.new_size.wrapping_sub(self.old_size) as i64
+    }
+}
+
+/// The differences between an old and a new list of files
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScanDiff {
+    /// Files missing from the old list, largest first
+    pub added: Vec<FileEntry>,
+    /// Files that got larger, largest growth first
+    pub grown: Vec<SizeChange>,
+    /// Files that got smaller, largest shrinkage first
+    pub shrunk: Vec<SizeChange>,
+}
+
+/// Compare two scans by path. Both are usually top-N lists, so a file "added"
+/// to the new list may simply have grown past the old list's smallest entry.
+pub fn diff_scans(old: &[FileEntry], new: &[FileEntry]) -> ScanDiff {
+    let old_sizes: HashMap<&Path, u64> = old.iter().map(|e| (e.path.as_path(), e.size)).collect();
+    let mut diff = ScanDiff::default();
+
+    for entry in new {
+        match old_sizes.get(entry.path.as_path()) {
+            None => diff.added.push(entry.clone()),
+            Some(&old_size) if old_size != entry.size => {
+                let change = SizeChange {
+                    path: entry.path.clone(),
+                    old_size,
+                    new_size: entry.size,
+                };
+                if entry.size > old_size {
+                    diff.grown.push(change);
+                } else {
+                    diff.shrunk.push(change);
+                }
+            }
+            Some(_) => {}
+        }
+    }
+
+    diff.added
+        .sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+    let by_change = |a: &SizeChange, b: &SizeChange| {
+        b.delta()
+            .abs()
+            .cmp(&a.delta().abs())
+            .then_with(|| a.path.cmp(&b.path))
+    };
+    diff.grown.sort_by(by_change);
+    diff.shrunk.sort_by(by_change);
+    diff
+}
+
+/// A file that differs between the two trees of `compare_trees`, by its path
+/// relative to them
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TreeDifference {
+    OnlyInA {
+        path: PathBuf,
+        size: u64,
+    },
+    OnlyInB {
+        path: PathBuf,
+        size: u64,
+    },
+    /// In both trees with different sizes, `old_size` from the first
+    Changed(SizeChange),
+}
+
+impl TreeDifference {
+    pub fn path(&self) -> &Path {
+        match self {
+            TreeDifference::OnlyInA { path, .. } | TreeDifference::OnlyInB { path, .. } => path,
+            TreeDifference::Changed(change) => &change.path,
+        }
+    }
+
+    /// Bytes between the file's sizes in the two trees, a missing file
+    /// counting as empty
+    pub fn size_delta(&self) -> u64 {
+        match self {
+            TreeDifference::OnlyInA { size, .. } | TreeDifference::OnlyInB { size, .. } => *size,
+            TreeDifference::Changed(change) => change.old_size.abs_diff(change.new_size),
+        }
+    }
+}
+
+/// The files of tree A (`a`, scanned from `root_a`) and tree B that are only
+/// in one of them or differ in size, matched by their paths below the roots
+/// and ordered by size delta, largest first
+pub fn compare_trees(
+    a: &[FileEntry],
+    root_a: &Path,
+    b: &[FileEntry],
+    root_b: &Path,
+) -> Vec<TreeDifference> {
+    let relative = |entries: &[FileEntry], root: &Path| -> HashMap<PathBuf, u64> {
+        entries
+            .iter()
+            .map(|e| {
+                (
+                    e.path.strip_prefix(root).unwrap_or(&e.path).to_path_buf(),
+                    e.size,
+                )
+            })
+            .collect()
+    };
+    let sizes_a = relative(a, root_a);
+    let mut sizes_b = relative(b, root_b);
+
+    let mut differences = Vec::new();
+    for (path, size_a) in sizes_a {
+        match sizes_b.remove(&path) {
+            None => differences.push(TreeDifference::OnlyInA { path, size: size_a }),
+            Some(size_b) if size_b != size_a => {
+                differences.push(TreeDifference::Changed(SizeChange {
+                    path,
+                    old_size: size_a,
+                    new_size: size_b,
+                }))
+            }
+            Some(_) => {}
+        }
+    }
+    differences.extend(
+        sizes_b
+            .into_iter()
+            .map(|(path, size)| TreeDifference::OnlyInB { path, size }),
+    );
+    differences.sort_by(|x, y| {
+        y.size_delta()
+            .cmp(&x.size_delta())
+            .then_with(|| x.path().cmp(y.path()))
+    });
+    differences
+}
@@ -0,0 +1,135 @@
+//! Content hashing to confirm duplicates among files of equal size
+
+use crate::{FileEntry, ScanError};
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// Bytes compared before a candidate is hashed in full
+const FIRST_BLOCK: u64 = 4096;
+
+/// Files with identical contents
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DuplicateGroup {
+    pub size: u64,
+    /// SHA-256 of the contents shared by every file
+    pub hash: [u8; 32],
+    /// The identical files sorted by path, each carrying `hash`
+    pub files: Vec<FileEntry>,
+}
+
+impl DuplicateGroup {
+    /// The copy kept if the group were deduplicated: the first by path
+    pub fn canonical(&self) -> &FileEntry {
+        &self.files[0]
+    }
+
+    /// Bytes freed by replacing every other copy with a link to the canonical one
+    pub fn wasted(&self) -> u64 {
+        self.size * (self.files.len() as u64 - 1)
+    }
+}
+
+/// SHA-256 of the first `limit` bytes of a file, or of all of it
+fn hash_file(path: &Path, limit: Option<u64>) -> io::Result<[u8; 32]> {
+    let file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    match limit {
+        Some(limit) => io::copy(&mut file.take(limit), &mut hasher)?,
+        None => io::copy(&mut io::BufReader::new(file), &mut hasher)?,
+    };
+    Ok(hasher.finalize().into())
+}
+
+/// Hash `paths` in parallel and group them by (size, hash), keeping groups with
+/// at least two files. Unreadable files are reported in `errors` and left out.
+fn group_by_hash(
+    paths: Vec<(u64, PathBuf)>,
+    limit: Option<u64>,
+    errors: &mut Vec<ScanError>,
+) -> HashMap<(u64, [u8; 32]), Vec<PathBuf>> {
+    let hashed: Vec<_> = paths
+        .into_par_iter()
+        .map(|(size, path)| {
+            let hash = hash_file(&path, limit);
+            (size, path, hash)
+        })
+        .collect();
+
+    let mut groups: HashMap<_, Vec<PathBuf>> = HashMap::new();
+    for (size, path, hash) in hashed {
+        match hash {
+            Ok(hash) => groups.entry((size, hash)).or_default().push(path),
+            Err(source) => errors.push(ScanError::ReadFailed { path, source }),
+        }
+    }
+    groups.retain(|_, paths| paths.len() >= 2);
+    groups
+}
+
+/// Find files with identical contents among groups of same-size files.
+///
+/// Candidates are first compared by a hash of their first 4 KiB, and only files
+/// still matching are read in full, so most non-duplicates cost a single block.
+/// Groups are ordered by wasted space, largest first.
+pub fn find_duplicates(
+    size_groups: &HashMap<u64, Vec<PathBuf>>,
+) -> (Vec<DuplicateGroup>, Vec<ScanError>) {
+    let mut errors = Vec::new();
+    let candidates: Vec<(u64, PathBuf)> = size_groups
+        .iter()
+        .filter(|(_, paths)| paths.len() >= 2)
+        .flat_map(|(&size, paths)| paths.iter().map(move |path| (size, path.clone())))
+        .collect();
+
+    // Files no larger than the first block are already fully hashed
+    let mut confirmed = HashMap::new();
+    let mut needs_full = Vec::new();
+    for ((size, hash), paths) in group_by_hash(candidates, Some(FIRST_BLOCK), &mut errors) {
+        if size <= FIRST_BLOCK {
+            confirmed.insert((size, hash), paths);
+        } else {
+            needs_full.extend(paths.into_iter().map(|path| (size, path)));
+        }
+    }
+    confirmed.extend(group_by_hash(needs_full, None, &mut errors));
+
+    let mut groups: Vec<DuplicateGroup> = confirmed
+        .into_iter()
+        .map(|((size, hash), mut paths)| {
+            paths.sort();
+            let files = paths
+                .into_iter()
+                .map(|path| FileEntry {
+                    size,
+                    path,
+                    modified: None,
+                    accessed: None,
+                    hash: Some(hash),
+                    sparseness: 0.0,
+                    checksum: None,
+                    is_reflinked: false,
+                    physical_size: 0,
+                    allocated_size: 0,
+                    xattr_size: 0,
+                    nlink: 0,
+                    mode: 0,
+                    is_dir: false,
+                    verified: None,
+                })
+                .collect();
+            DuplicateGroup { size, hash, files }
+        })
+        .collect();
+    groups.sort_by(|a, b| {
+        b.wasted()
+            .cmp(&a.wasted())
+            .then_with(|| b.size.cmp(&a.size))
+            .then_with(|| a.canonical().path.cmp(&b.canonical().path))
+    });
+    (groups, errors)
+}
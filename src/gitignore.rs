@@ -0,0 +1,145 @@
+//! `.gitignore` rules for `ScanConfig::git_ignore`: the rules of a directory
+//! are its parent's with the directory's own `.gitignore` on top, so nested
+//! files take precedence over the ones above them, as in git
+
+use crate::{ScanConfig, ScanError, ScanStats};
+use ignore::Match;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// One ignore file, and the ones it takes precedence over
+#[derive(Debug)]
+pub(crate) struct Ignores {
+    matcher: Gitignore,
+    /// For files read above a relative root: the root as scanned and as an
+    /// absolute path, to turn scanned paths into paths under `matcher`'s root
+    rebase: Option<Arc<(PathBuf, PathBuf)>>,
+    parent: Option<Arc<Ignores>>,
+}
+
+impl Ignores {
+    /// Push the rules of the file at `path`, matched relative to `dir`, on top
+    /// of `parent`. A file that cannot be read is recorded as an error; one
+    /// with invalid lines keeps its other rules.
+    fn push(
+        parent: Option<Arc<Ignores>>,
+        dir: &Path,
+        path: &Path,
+        rebase: Option<&Arc<(PathBuf, PathBuf)>>,
+        config: &ScanConfig,
+        stats: &mut ScanStats,
+    ) -> Option<Arc<Ignores>> {
+        let mut builder = GitignoreBuilder::new(dir);
+        let error = builder.add(path);
+        let built = builder.build();
+        if let Some(e) = error.or_else(|| built.as_ref().err().cloned()) {
+            let path = path.to_path_buf();
+            stats.record_error(
+                config,
+                ScanError::ReadFailed {
+                    path,
+                    source: io::Error::other(e),
+                },
+            );
+        }
+        let Ok(matcher) = built else {
+            return parent;
+        };
+        Some(Arc::new(Ignores {
+            matcher,
+            rebase: rebase.cloned(),
+            parent,
+        }))
+    }
+
+    /// The rules that apply inside `root` before its own `.gitignore`: the
+    /// global excludes file (git's `core.excludesFile`) and, when `root` is
+    /// inside a repository, its `.git/info/exclude` and the `.gitignore`
+    /// files from the top of the repository down to `root`'s parent
+    pub(crate) fn above_root(
+        root: &Path,
+        config: &ScanConfig,
+        stats: &mut ScanStats,
+    ) -> Option<Arc<Ignores>> {
+        let absolute = std::path::absolute(root).ok()?;
+        let rebase = root
+            .is_relative()
+            .then(|| Arc::new((root.to_path_buf(), absolute.clone())));
+        let ancestors: Vec<&Path> = absolute.ancestors().skip(1).collect();
+        let above = match ancestors.iter().position(|dir| dir.join(".git").exists()) {
+            Some(top) => &ancestors[..=top],
+            None => &[],
+        };
+        let top = above.last().copied().unwrap_or(&absolute);
+
+        let (global, error) = GitignoreBuilder::new(top).build_global();
+        if let Some(e) = error {
+            tracing::warn!(error = %e, "cannot read the global gitignore");
+        }
+        let mut ignores = (!global.is_empty()).then(|| {
+            Arc::new(Ignores {
+                matcher: global,
+                rebase: rebase.clone(),
+                parent: None,
+            })
+        });
+        let exclude = top.join(".git").join("info").join("exclude");
+        if !above.is_empty() && exclude.is_file() {
+            ignores = Ignores::push(ignores, top, &exclude, rebase.as_ref(), config, stats);
+        }
+        for dir in above.iter().rev() {
+            let gitignore = dir.join(".gitignore");
+            if gitignore.is_file() {
+                ignores = Ignores::push(ignores, dir, &gitignore, rebase.as_ref(), config, stats);
+            }
+        }
+        ignores
+    }
+
+    /// The rules inside `dir`: `parent`'s, then `.git/info/exclude` when `dir`
+    /// is the top of a repository (`has_git`), then its own `.gitignore`
+    pub(crate) fn enter(
+        parent: Option<Arc<Ignores>>,
+        dir: &Path,
+        has_git: bool,
+        has_gitignore: bool,
+        config: &ScanConfig,
+        stats: &mut ScanStats,
+    ) -> Option<Arc<Ignores>> {
+        let mut ignores = parent;
+        let exclude = dir.join(".git").join("info").join("exclude");
+        if has_git && exclude.is_file() {
+            ignores = Ignores::push(ignores, dir, &exclude, None, config, stats);
+        }
+        if has_gitignore {
+            ignores = Ignores::push(ignores, dir, &dir.join(".gitignore"), None, config, stats);
+        }
+        ignores
+    }
+
+    /// Whether the entry at `path` is ignored: the innermost file with a rule
+    /// matching it decides, a negated (`!`) rule taking it back
+    pub(crate) fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let mut level = Some(self);
+        while let Some(ignores) = level {
+            let matched = match &ignores.rebase {
+                Some(rebase) => {
+                    let (root, absolute) = &**rebase;
+                    ignores.matcher.matched(
+                        absolute.join(path.strip_prefix(root).unwrap_or(path)),
+                        is_dir,
+                    )
+                }
+                None => ignores.matcher.matched(path, is_dir),
+            };
+            match matched {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => level = ignores.parent.as_deref(),
+            }
+        }
+        false
+    }
+}
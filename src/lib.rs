@@ -0,0 +1,3906 @@
+//! Fast, parallel, deterministic top-N largest file finder.
+//!
+//! The scanner walks a directory tree level by level, classifying every entry
+//! with a single `statx()` call and keeping only the N largest regular files.
+//! Results are totally ordered (size descending, then path ascending), so the
+//! same tree always produces the same output regardless of thread count.
+//!
+//! Progress is reported through `tracing`: each BFS level as an `INFO` event,
+//! every counted file and error as a `DEBUG` event inside a `scan_dir` span,
+//! and every directory entered as a `TRACE` event.
+
+use clap::ValueEnum;
+use crossbeam_queue::SegQueue;
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+use regex::{Regex, RegexSet};
+#[cfg(unix)]
+use rustix::fd::{AsFd, BorrowedFd, OwnedFd};
+#[cfg(unix)]
+use rustix::fs::{AtFlags, FileType, Mode, OFlags, statat};
+use std::borrow::Cow;
+use std::cell::{RefCell, RefMut};
+use std::collections::{HashMap, HashSet, VecDeque, hash_map};
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, mpsc};
+use std::time::{Duration, Instant, SystemTime};
+use thread_local::ThreadLocal;
+
+#[cfg(feature = "adaptive-threads")]
+mod adaptive;
+#[cfg(feature = "archives")]
+pub mod archive;
+pub mod checksum;
+pub mod diff;
+pub mod duplicates;
+mod gitignore;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub mod mounts;
+pub mod output;
+#[cfg(target_os = "linux")]
+pub mod quota;
+#[cfg(target_os = "linux")]
+pub mod reflink;
+#[cfg(feature = "serde")]
+pub mod snapshot;
+pub mod tree;
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+mod uring;
+pub mod verify;
+#[cfg(windows)]
+mod windows;
+
+/// Represents a file with its size and path for deterministic ordering
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FileEntry {
+    pub size: u64,
+    pub path: PathBuf,
+    /// Last modification time, when known
+    pub modified: Option<SystemTime>,
+    /// Last access time, when known
+    pub accessed: Option<SystemTime>,
+    /// SHA-256 of the contents, when they were hashed
+    pub hash: Option<[u8; 32]>,
+    /// Share of the size that is holes rather than allocated blocks, from 0
+    /// (fully allocated) to 1
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub sparseness: f32,
+    /// Digest from `checksum::add_checksums`, when computed
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub checksum: Option<Vec<u8>>,
+    /// Some extents are shared with other files, from `reflink::detect_reflinks`
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub is_reflinked: bool,
+    /// Bytes in extents only this file references, from
+    /// `reflink::detect_reflinks`; 0 until computed
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub physical_size: u64,
+    /// Bytes of the blocks allocated to the file (`st_blocks * 512`), which
+    /// `SortOrder::Blocks` ranks by; 0 for files not on disk
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub allocated_size: u64,
+    /// Bytes of extended attribute names and values included in `size`, with
+    /// `ScanConfig::virtual_size`
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub xattr_size: u64,
+    /// Hard links to the file (`st_nlink`); 0 for files not on disk, such as
+    /// archive members
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub nlink: u64,
+    /// `st_mode`, with the file type and permission bits; 0 for files not on
+    /// disk
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub mode: u32,
+    /// A directory ranked by its own size with `ScanConfig::include_dirs`,
+    /// not counting its contents
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub is_dir: bool,
+    /// What stat'ing the file again after the scan found, from
+    /// `verify::verify_results`
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub verified: Option<Verification>,
+}
+
+/// Whether a reported file still has the size the scan recorded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Verification {
+    Unchanged,
+    /// The file now has this size
+    Changed(u64),
+    /// The file no longer exists
+    Deleted,
+}
+
+impl Ord for FileEntry {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        // Total ordering: size descending, then path ascending for determinism
+        // When wrapped in Reverse for min-heap, this gives us largest files
+        self.size
+            .cmp(&other.size)
+            .then_with(|| self.path.cmp(&other.path))
+            .then_with(|| self.modified.cmp(&other.modified))
+            .then_with(|| self.accessed.cmp(&other.accessed))
+            .then_with(|| self.hash.cmp(&other.hash))
+            .then_with(|| self.sparseness.total_cmp(&other.sparseness))
+            .then_with(|| self.checksum.cmp(&other.checksum))
+            .then_with(|| self.is_reflinked.cmp(&other.is_reflinked))
+            .then_with(|| self.physical_size.cmp(&other.physical_size))
+            .then_with(|| self.allocated_size.cmp(&other.allocated_size))
+            .then_with(|| self.xattr_size.cmp(&other.xattr_size))
+            .then_with(|| self.nlink.cmp(&other.nlink))
+            .then_with(|| self.is_dir.cmp(&other.is_dir))
+            .then_with(|| self.verified.cmp(&other.verified))
+    }
+}
+
+impl PartialEq for FileEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == std::cmp::Ordering::Equal
+    }
+}
+
+impl Eq for FileEntry {}
+
+impl PartialOrd for FileEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl FileEntry {
+    /// Order by allocated blocks rather than size, for `TopNTracker::ordered_by`
+    /// with `ScanConfig::rank_by_blocks`; ties fall back to the usual order
+    pub fn cmp_allocated(&self, other: &Self) -> std::cmp::Ordering {
+        self.allocated_size
+            .cmp(&other.allocated_size)
+            .then_with(|| self.cmp(other))
+    }
+
+    /// Rough bytes of memory the entry takes in a tracker: the struct itself
+    /// and the allocations of its path and checksum
+    pub fn approx_size_bytes(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.path.capacity()
+            + self
+                .checksum
+                .as_ref()
+                .map_or(0, |checksum| checksum.capacity())
+    }
+}
+
+/// A directory with the total size of every qualifying file beneath it
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DirSizeEntry {
+    pub total_size: u64,
+    pub path: PathBuf,
+}
+
+/// The files beneath a directory some levels below a root, for
+/// [`aggregate_by_prefix`] and `ScanConfig::top_dirs`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DirTotal {
+    pub path: PathBuf,
+    pub total_size: u64,
+    pub file_count: u64,
+}
+
+/// Capacity of the filesystem holding a scan root, from `statvfs` (or
+/// `GetDiskFreeSpaceExW` on Windows)
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FilesystemInfo {
+    pub root: PathBuf,
+    pub total_bytes: u64,
+    /// Space available to unprivileged users
+    pub available_bytes: u64,
+    pub used_bytes: u64,
+    /// Total size of the reported files beneath `root`
+    pub results_bytes: u64,
+}
+
+impl FilesystemInfo {
+    /// Query the filesystem holding `root`; `results_bytes` starts at 0. Some
+    /// filesystems (overlays, FUSE) report more free blocks than blocks, which
+    /// count as none used.
+    #[cfg(unix)]
+    pub fn of(root: &Path) -> io::Result<Self> {
+        let vfs = rustix::fs::statvfs(root)?;
+        Ok(Self {
+            root: root.to_path_buf(),
+            total_bytes: vfs.f_blocks.saturating_mul(vfs.f_frsize),
+            available_bytes: vfs.f_bavail.saturating_mul(vfs.f_frsize),
+            used_bytes: vfs
+                .f_blocks
+                .saturating_sub(vfs.f_bfree)
+                .saturating_mul(vfs.f_frsize),
+            results_bytes: 0,
+        })
+    }
+
+    /// Query the filesystem holding `root`; `results_bytes` starts at 0
+    #[cfg(windows)]
+    pub fn of(root: &Path) -> io::Result<Self> {
+        let (total, available, free) = windows::disk_space(root)?;
+        Ok(Self {
+            root: root.to_path_buf(),
+            total_bytes: total,
+            available_bytes: available,
+            used_bytes: total.saturating_sub(free),
+            results_bytes: 0,
+        })
+    }
+
+    /// `bytes` as a percentage of the filesystem's size, if it has one
+    pub fn percent_of_total(&self, bytes: u64) -> Option<f64> {
+        (self.total_bytes > 0).then(|| bytes as f64 / self.total_bytes as f64 * 100.0)
+    }
+}
+
+/// A directory with the number of entries directly inside it
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InodeEntry {
+    pub count: u64,
+    pub path: PathBuf,
+}
+
+/// A directory with no regular file anywhere beneath it
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EmptyDirReport {
+    pub path: PathBuf,
+    /// Directories beneath it, all of them empty too
+    pub subdir_count: u64,
+}
+
+/// A mounted filesystem
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MountInfo {
+    /// The mount point
+    pub path: PathBuf,
+    pub fstype: String,
+    /// What is mounted, usually a block device such as `/dev/sda1`
+    pub device: String,
+    /// The scan does not descend into it, with `ScanConfig::same_filesystem`
+    /// or as a skipped virtual filesystem (or inside a skipped mount)
+    pub skipped: bool,
+}
+
+/// Bins of file age, by modification time, for `ScanConfig::age_buckets`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AgeBuckets {
+    /// Ages are counted back from here
+    pub now: SystemTime,
+    /// Upper bounds of the bins, strictly ascending; files older than the last
+    /// go in one more bin
+    pub bounds: Vec<Duration>,
+}
+
+impl AgeBuckets {
+    /// Bins ending at `bounds`, counted back from the current time
+    pub fn new(bounds: Vec<Duration>) -> Result<Self, String> {
+        if bounds.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err("age buckets must be given in increasing order".to_string());
+        }
+        Ok(Self {
+            now: SystemTime::now(),
+            bounds,
+        })
+    }
+
+    /// Index of the bin of a file modified at `modified`; files from the future
+    /// are treated as brand new
+    fn bin(&self, modified: SystemTime) -> usize {
+        let age = self.now.duration_since(modified).unwrap_or_default();
+        self.bounds.partition_point(|&bound| bound <= age)
+    }
+
+    /// A label for each bin such as `7d-30d`, ending with `>365d`
+    pub fn labels(&self) -> Vec<String> {
+        let days = |age: Duration| format!("{}d", age.as_secs() / (24 * 60 * 60));
+        let mut labels = Vec::with_capacity(self.bounds.len() + 1);
+        let mut lower = String::from("0");
+        for &bound in &self.bounds {
+            let upper = days(bound);
+            labels.push(format!("{}-{}", lower, upper));
+            lower = upper;
+        }
+        labels.push(format!(">{}", lower));
+        labels
+    }
+}
+
+/// The files in one age bin
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AgeBucket {
+    pub files: u64,
+    pub total_size: u64,
+}
+
+/// A user's disk quota on one filesystem, in bytes
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct QuotaInfo {
+    pub uid: u32,
+    /// Space charged to the user on the whole filesystem, not only the scanned part
+    pub used: u64,
+    /// Limit past which writes fail after the grace period; 0 if none
+    pub soft_limit: u64,
+    /// Limit past which writes fail at once; 0 if none
+    pub hard_limit: u64,
+    /// `used` as a percentage of the soft limit, or of the hard limit without
+    /// one; 0 for users without limits
+    pub pct_used: f32,
+}
+
+impl QuotaInfo {
+    /// Whether the user has any block limit on this filesystem
+    pub fn is_limited(&self) -> bool {
+        self.soft_limit > 0 || self.hard_limit > 0
+    }
+}
+
+/// What one scanned directory holds directly, for `ScanConfig::report_empty_dirs`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DirContents {
+    /// Regular files, whether or not they pass the filters
+    pub files: u64,
+    /// Subdirectories queued for scanning
+    pub subdirs: u64,
+    /// Some entry was not looked at (hidden, past the depth limit, excluded or
+    /// on another filesystem), so the directory may not be empty
+    pub incomplete: bool,
+}
+
+/// The entries that are neither regular files nor directories, by type, for
+/// `ScanConfig::count_file_types`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FileTypeCounts {
+    /// Symbolic links, including the ones followed to their targets
+    pub symlinks: u64,
+    pub sockets: u64,
+    pub fifos: u64,
+    pub block_devs: u64,
+    pub char_devs: u64,
+}
+
+impl FileTypeCounts {
+    fn record(&mut self, metadata: &EntryMetadata) {
+        match metadata {
+            EntryMetadata::Symlink => self.symlinks += 1,
+            EntryMetadata::Other(SpecialFile::Socket) => self.sockets += 1,
+            EntryMetadata::Other(SpecialFile::Fifo) => self.fifos += 1,
+            EntryMetadata::Other(SpecialFile::BlockDevice) => self.block_devs += 1,
+            EntryMetadata::Other(SpecialFile::CharDevice) => self.char_devs += 1,
+            EntryMetadata::RegularFile { .. }
+            | EntryMetadata::Directory { .. }
+            | EntryMetadata::Other(_) => {}
+        }
+    }
+
+    fn merge(&mut self, other: FileTypeCounts) {
+        self.symlinks += other.symlinks;
+        self.sockets += other.sockets;
+        self.fifos += other.fifos;
+        self.block_devs += other.block_devs;
+        self.char_devs += other.char_devs;
+    }
+}
+
+/// Shared sorted top-N tracker using VecDeque
+/// Maintains sorted order: best-ranked entries at tail, worst at head
+///
+/// Ranks any `Ord` type: the scan keeps files, `DirSizeEntry` and `InodeEntry`
+/// in one, and a type of your own works the same way, with trackers filled in
+/// parallel combined by `merge` or `merge_trackers`.
+///
+/// Invariants: the deque is always sorted ascending by rank (the entries' `Ord`,
+/// or its reverse when tracking the smallest), and never holds more than
+/// `capacity` entries.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound(deserialize = "T: serde::Deserialize<'de> + Ord"))
+)]
+pub struct TopNTracker<T = FileEntry> {
+    deque: VecDeque<T>,
+    capacity: usize,
+    smallest: bool,
+    /// How entries compare, the entries' `Ord` unless set by `ordered_by`;
+    /// not saved, so a deserialized tracker ranks by `Ord` again
+    #[cfg_attr(feature = "serde", serde(skip, default = "natural_order"))]
+    order: fn(&T, &T) -> std::cmp::Ordering,
+}
+
+#[cfg(feature = "serde")]
+fn natural_order<T: Ord>() -> fn(&T, &T) -> std::cmp::Ordering {
+    T::cmp
+}
+
+impl<T: Ord> TopNTracker<T> {
+    /// Track the `capacity` largest entries
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            deque: VecDeque::with_capacity(capacity),
+            capacity,
+            smallest: false,
+            order: T::cmp,
+        }
+    }
+
+    /// Track the `capacity` smallest entries instead of the largest
+    pub fn smallest(capacity: usize) -> Self {
+        Self {
+            smallest: true,
+            ..Self::new(capacity)
+        }
+    }
+
+    /// Rank entries by `order` instead of their `Ord`, `Greater` meaning
+    /// larger; only while the tracker is empty, as kept entries are not
+    /// re-sorted
+    pub fn ordered_by(self, order: fn(&T, &T) -> std::cmp::Ordering) -> Self {
+        debug_assert!(self.deque.is_empty());
+        Self { order, ..self }
+    }
+
+    /// Compare by rank: `Greater` means `a` is a better candidate than `b`
+    fn rank(&self, a: &T, b: &T) -> std::cmp::Ordering {
+        if self.smallest {
+            (self.order)(b, a)
+        } else {
+            (self.order)(a, b)
+        }
+    }
+
+    /// Insert entry into sorted position, maintaining top-N invariant
+    pub fn insert(&mut self, entry: T) {
+        // If deque is not full, or this entry outranks the worst, insert it
+        if self.deque.len() < self.capacity
+            || self
+                .deque
+                .front()
+                .is_some_and(|worst| self.rank(&entry, worst).is_gt())
+        {
+            // Binary search to find insertion position (deque is sorted ascending)
+            let pos = self
+                .deque
+                .binary_search_by(|probe| self.rank(probe, &entry))
+                .unwrap_or_else(|e| e);
+            self.deque.insert(pos, entry);
+
+            // If we exceeded capacity, remove the worst (head)
+            if self.deque.len() > self.capacity {
+                self.deque.pop_front();
+            }
+        }
+    }
+
+    /// Fold another tracker into this one, keeping this tracker's capacity
+    pub fn merge(&mut self, other: TopNTracker<T>) {
+        for entry in other.deque {
+            self.insert(entry);
+        }
+    }
+
+    /// Apply `f` to every entry, re-ranking them afterwards
+    pub fn map(self, f: impl FnMut(T) -> T) -> Self {
+        let mut mapped = Self {
+            deque: VecDeque::with_capacity(self.capacity),
+            ..self
+        };
+        for entry in self.deque.into_iter().map(f) {
+            mapped.insert(entry);
+        }
+        mapped
+    }
+
+    /// Iterate entries best-ranked first without consuming the tracker
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.deque.iter().rev()
+    }
+
+    /// The worst-ranked entry kept, i.e. the one the next insertion must beat
+    /// once the tracker is full
+    pub fn worst(&self) -> Option<&T> {
+        self.deque.front()
+    }
+
+    /// Whether the tracker holds `capacity` entries
+    pub fn is_full(&self) -> bool {
+        self.deque.len() >= self.capacity
+    }
+
+    /// Entries best-ranked first (largest first, or smallest first)
+    pub fn into_vec(self) -> Vec<T> {
+        // Convert to Vec and reverse to get best-first order
+        let mut vec: Vec<_> = self.deque.into_iter().collect();
+        vec.reverse();
+        vec
+    }
+}
+
+impl TopNTracker<FileEntry> {
+    /// Size of the smallest file kept once the tracker of largest files is
+    /// full: any smaller file is rejected. `None` while there is room, and
+    /// when tracking the smallest files.
+    pub fn min_size(&self) -> Option<u64> {
+        if self.smallest || !self.is_full() {
+            return None;
+        }
+        self.worst().map(|worst| worst.size)
+    }
+}
+
+/// Merge several trackers into one holding the overall top `capacity` entries,
+/// ranked like the first tracker (smallest first if it tracks the smallest)
+pub fn merge_trackers<T: Ord>(
+    trackers: impl IntoIterator<Item = TopNTracker<T>>,
+    capacity: usize,
+) -> TopNTracker<T> {
+    let mut trackers = trackers.into_iter();
+    let Some(first) = trackers.next() else {
+        return TopNTracker::new(capacity);
+    };
+    let mut merged = TopNTracker {
+        deque: VecDeque::with_capacity(capacity),
+        capacity,
+        smallest: first.smallest,
+        order: first.order,
+    };
+    merged.merge(first);
+    for tracker in trackers {
+        merged.merge(tracker);
+    }
+    merged
+}
+
+/// Ordering applied to the final results
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum SortOrder {
+    /// Largest first, then by path
+    #[default]
+    Size,
+    /// Lexicographic by full path
+    Path,
+    /// Lexicographic by file name, then by full path
+    Name,
+    /// Grouped by extension, largest first within each group
+    Ext,
+    /// Most recently modified first, then largest
+    Mtime,
+    /// Most recently accessed first, then largest
+    Atime,
+    /// Most blocks allocated on disk first, whatever the apparent size, then
+    /// largest; the top N are ranked this way too
+    Blocks,
+}
+
+/// Pick `n` of `entries` at random, each file with a chance proportional to its
+/// size (weighted reservoir sampling, the A-Res algorithm), largest first.
+/// Empty files are never picked. The same files and `seed` always give the
+/// same sample, in whatever order the scan found them.
+pub fn weighted_sample(mut entries: Vec<FileEntry>, n: usize, seed: u64) -> Vec<FileEntry> {
+    entries.retain(|e| e.size > 0);
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    let mut rng = SmallRng::seed_from_u64(seed);
+    // Each file's key is u^(1/size) for a uniform u, compared by its logarithm
+    // to keep precision; the n highest keys win
+    let mut keyed: Vec<(f64, FileEntry)> = entries
+        .into_iter()
+        .map(|e| (rng.random::<f64>().ln() / e.size as f64, e))
+        .collect();
+    if keyed.len() > n && n > 0 {
+        keyed.select_nth_unstable_by(n - 1, |a, b| b.0.total_cmp(&a.0));
+    }
+    keyed.truncate(n);
+    let mut sample: Vec<FileEntry> = keyed.into_iter().map(|(_, e)| e).collect();
+    sort_results(&mut sample, SortOrder::Size);
+    sample
+}
+
+/// Sort results in place. Every order falls back to the full path (and size),
+/// so no ties remain and the output is fully deterministic.
+///
+/// This only reorders the final list; which files make the top-N is always
+/// decided by size, so `TopNTracker`'s ordering is unaffected.
+pub fn sort_results(results: &mut [FileEntry], order: SortOrder) {
+    let by_size =
+        |a: &FileEntry, b: &FileEntry| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path));
+    match order {
+        SortOrder::Size => results.sort_by(by_size),
+        SortOrder::Path => {
+            results.sort_by(|a, b| a.path.cmp(&b.path).then_with(|| b.size.cmp(&a.size)))
+        }
+        SortOrder::Name => results.sort_by(|a, b| {
+            a.path
+                .file_name()
+                .cmp(&b.path.file_name())
+                .then_with(|| by_size(a, b))
+        }),
+        SortOrder::Ext => results.sort_by(|a, b| {
+            a.path
+                .extension()
+                .cmp(&b.path.extension())
+                .then_with(|| by_size(a, b))
+        }),
+        SortOrder::Mtime => {
+            results.sort_by(|a, b| b.modified.cmp(&a.modified).then_with(|| by_size(a, b)))
+        }
+        SortOrder::Atime => {
+            results.sort_by(|a, b| b.accessed.cmp(&a.accessed).then_with(|| by_size(a, b)))
+        }
+        SortOrder::Blocks => results.sort_by(|a, b| b.cmp_allocated(a)),
+    }
+}
+
+/// A problem encountered while scanning; the affected entry is skipped
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum ScanError {
+    #[error("cannot read directory {}: {source}", path.display())]
+    ReadDir { path: PathBuf, source: io::Error },
+    #[error("cannot stat {}: {source}", path.display())]
+    StatFailed { path: PathBuf, source: io::Error },
+    #[error("cannot read {}: {source}", path.display())]
+    ReadFailed { path: PathBuf, source: io::Error },
+}
+
+impl ScanError {
+    /// The directory or entry the error refers to
+    pub fn path(&self) -> &Path {
+        match self {
+            ScanError::ReadDir { path, .. }
+            | ScanError::StatFailed { path, .. }
+            | ScanError::ReadFailed { path, .. } => path,
+        }
+    }
+
+    /// What went wrong, without the path
+    pub fn reason(&self) -> String {
+        match self {
+            ScanError::ReadDir { source, .. } => format!("cannot read directory: {}", source),
+            ScanError::StatFailed { source, .. } => format!("cannot stat: {}", source),
+            ScanError::ReadFailed { source, .. } => format!("cannot read: {}", source),
+        }
+    }
+}
+
+/// Treatment of hidden entries: names starting with `.`, or anything beneath
+/// such a directory. The roots themselves never count as hidden.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HiddenFiles {
+    /// Scan hidden entries like any other
+    #[default]
+    Include,
+    /// Skip hidden files and do not descend into hidden directories
+    Exclude,
+    /// Only consider hidden files
+    Only,
+}
+
+/// Scanner statistics
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct ScanStats {
+    pub files_scanned: u64,
+    /// Files that passed the filters, whether or not they made the results,
+    /// and the total of their sizes
+    pub matched_files: u64,
+    pub matched_size: u64,
+    pub dirs_scanned: u64,
+    /// Additional hard links to an already-counted inode
+    pub hardlinks_skipped: u64,
+    /// Symlinks resolved to their target with `--follow-symlinks`
+    pub symlinks_followed: u64,
+    /// Directories skipped because they had already been visited via another path
+    pub symlink_cycles_detected: u64,
+    /// Files listed inside archives with `ScanConfig::archive_depth`
+    pub archive_members: u64,
+    /// `statat` calls made classifying entries: one per entry listed, two for
+    /// a followed symlink
+    pub stat_calls: u64,
+    /// Threads the scan ended on with `ScanConfig::auto_threads`; 0 without
+    pub auto_threads: u64,
+    /// Entries by type besides regular files and directories, whatever the
+    /// filters; only counted when `ScanConfig::count_file_types` is set
+    pub file_types: Option<FileTypeCounts>,
+    pub errors: u64,
+    /// The errors behind `errors`, sorted by path, up to
+    /// `ScanConfig::max_error_details` of them; only filled when
+    /// `ScanConfig::collect_errors` is set
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub error_details: Vec<ScanError>,
+    /// Directories that could not be opened or listed, so nothing beneath
+    /// them was scanned; counted in `errors` too
+    pub dirs_inaccessible: u64,
+    /// The directories behind `dirs_inaccessible` with the error opening
+    /// each, sorted by path, up to `ScanConfig::report_inaccessible` of them
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub inaccessible_dirs: Vec<(PathBuf, io::Error)>,
+    /// The scan stopped early on reaching `ScanConfig::max_errors`, so the
+    /// results and other counts cover only part of the tree
+    pub aborted: bool,
+    pub elapsed: Duration,
+    /// Extension (without the dot, `""` for none) → (total bytes, file count);
+    /// only filled when `ScanConfig::group_by_extension` is set
+    pub by_extension: HashMap<String, (u64, u64)>,
+    /// Largest files of each directory; only filled when
+    /// `ScanConfig::top_per_dir` is set
+    pub per_dir: HashMap<PathBuf, DirTop>,
+    /// Largest files of each owner by UID; only filled when
+    /// `ScanConfig::top_per_user` is set
+    pub per_user: HashMap<u32, DirTop>,
+    /// Total size of every scanned directory: only files directly inside it
+    /// while scanning, recursive once `parallel_scan` returns. Only filled when
+    /// `ScanConfig::largest_dirs` is set.
+    pub dir_sizes: HashMap<PathBuf, u64>,
+    /// The largest directories by recursive size, largest first
+    pub largest_dirs: Vec<DirSizeEntry>,
+    /// The directories with the most immediate children (of any type), most first;
+    /// only filled when `ScanConfig::inode_usage` is set
+    pub inode_usage: Vec<InodeEntry>,
+    /// Every non-empty file by size, each list sorted by path; only filled when
+    /// `ScanConfig::duplicate_sizes` is set
+    pub size_groups: HashMap<u64, Vec<PathBuf>>,
+    /// Groups of files with identical contents, most wasted space first; only
+    /// filled when `ScanConfig::hash_duplicates` is set
+    pub duplicates: Vec<duplicates::DuplicateGroup>,
+    /// The filesystem of each root in `ScanConfig::roots`, leaving out roots
+    /// whose filesystem could not be queried
+    pub filesystems: Vec<FilesystemInfo>,
+    /// What every scanned directory holds; only filled when
+    /// `ScanConfig::report_empty_dirs` is set
+    pub dir_contents: HashMap<PathBuf, DirContents>,
+    /// The outermost directories without a regular file beneath them,
+    /// shallowest first, from `dir_contents`
+    pub empty_dirs: Vec<EmptyDirReport>,
+    /// Every zero-byte file that passed the filters, sorted by path; only
+    /// filled when `ScanConfig::zero_sized` is set
+    pub zero_sized: Vec<PathBuf>,
+    /// The directories `ScanConfig::top_dirs` levels below the roots with the
+    /// size and number of the files beneath them, largest first
+    pub top_dirs: Vec<DirTotal>,
+    /// Files that passed the filters in each bin of `ScanConfig::age_buckets`,
+    /// youngest first
+    pub age_buckets: Vec<AgeBucket>,
+    /// `top_dirs` while the scan runs, as (total size, file count) by directory
+    #[cfg_attr(feature = "serde", serde(skip))]
+    prefix_totals: HashMap<PathBuf, (u64, u64)>,
+    /// The filesystems mounted beneath the roots, in mount order, when
+    /// `ScanConfig::report_mounts` is set
+    pub mounts: Vec<MountInfo>,
+    /// Files of `ScanConfig::baseline` that the scan did not find again,
+    /// largest first, with their old sizes
+    pub removed: Vec<FileEntry>,
+    /// Paths of `ScanConfig::baseline` found so far
+    #[cfg_attr(feature = "serde", serde(skip))]
+    baseline_seen: HashSet<PathBuf>,
+}
+
+/// Counters updated while the scan runs, for live progress reporting.
+///
+/// `ScanStats` is only combined at the end of each BFS level; these are shared
+/// atomics that can be read from another thread at any time.
+#[derive(Debug, Default)]
+pub struct ScanProgress {
+    pub files_scanned: AtomicU64,
+    pub dirs_scanned: AtomicU64,
+    /// Directories of the level-by-level scans listed to the end or given up
+    /// on, roots included; the others found are still queued
+    pub dirs_done: AtomicU64,
+    /// Size of the worst file in the top-N once it is full (0 until then):
+    /// other files must beat this to make the list. Each scanning thread
+    /// keeps its own top-N, so this is the tightest bound any of them has.
+    pub threshold: AtomicU64,
+}
+
+impl ScanProgress {
+    /// Read all counters as (files scanned, directories scanned, threshold)
+    pub fn snapshot(&self) -> (u64, u64, u64) {
+        (
+            self.files_scanned.load(Ordering::Relaxed),
+            self.dirs_scanned.load(Ordering::Relaxed),
+            self.threshold.load(Ordering::Relaxed),
+        )
+    }
+
+    /// Note the worst size of a thread's full top-N, keeping the tightest bound
+    fn tighten_threshold(&self, worst: u64, smallest: bool) {
+        if smallest {
+            let _ =
+                self.threshold
+                    .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |threshold| {
+                        (threshold == 0 || worst < threshold).then_some(worst)
+                    });
+        } else {
+            self.threshold.fetch_max(worst, Ordering::Relaxed);
+        }
+    }
+}
+
+/// The largest files found directly inside one directory, or owned by one
+/// user in `ScanStats::per_user`
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DirTop {
+    /// Total size of all qualifying files in the directory (or of the user),
+    /// not just the kept ones
+    pub total_size: u64,
+    pub files: TopNTracker,
+}
+
+impl ScanStats {
+    /// Add another set of counters into this one
+    pub fn merge(&mut self, other: ScanStats) {
+        self.files_scanned += other.files_scanned;
+        self.matched_files += other.matched_files;
+        self.matched_size += other.matched_size;
+        self.dirs_scanned += other.dirs_scanned;
+        self.hardlinks_skipped += other.hardlinks_skipped;
+        self.symlinks_followed += other.symlinks_followed;
+        self.symlink_cycles_detected += other.symlink_cycles_detected;
+        self.archive_members += other.archive_members;
+        self.stat_calls += other.stat_calls;
+        self.auto_threads = self.auto_threads.max(other.auto_threads);
+        if let Some(types) = other.file_types {
+            self.file_types.get_or_insert_default().merge(types);
+        }
+        self.errors += other.errors;
+        self.aborted |= other.aborted;
+        self.error_details.extend(other.error_details);
+        self.dirs_inaccessible += other.dirs_inaccessible;
+        self.inaccessible_dirs.extend(other.inaccessible_dirs);
+        for (ext, (bytes, count)) in other.by_extension {
+            let totals = self.by_extension.entry(ext).or_default();
+            totals.0 += bytes;
+            totals.1 += count;
+        }
+        for (dir, top) in other.per_dir {
+            match self.per_dir.entry(dir) {
+                hash_map::Entry::Occupied(mut existing) => {
+                    let existing = existing.get_mut();
+                    existing.total_size += top.total_size;
+                    existing.files.merge(top.files);
+                }
+                hash_map::Entry::Vacant(slot) => {
+                    slot.insert(top);
+                }
+            }
+        }
+        for (uid, top) in other.per_user {
+            match self.per_user.entry(uid) {
+                hash_map::Entry::Occupied(mut existing) => {
+                    let existing = existing.get_mut();
+                    existing.total_size += top.total_size;
+                    existing.files.merge(top.files);
+                }
+                hash_map::Entry::Vacant(slot) => {
+                    slot.insert(top);
+                }
+            }
+        }
+        for (dir, size) in other.dir_sizes {
+            *self.dir_sizes.entry(dir).or_default() += size;
+        }
+        for (size, paths) in other.size_groups {
+            self.size_groups.entry(size).or_default().extend(paths);
+        }
+        self.dir_contents.extend(other.dir_contents);
+        self.zero_sized.extend(other.zero_sized);
+        if self.age_buckets.len() < other.age_buckets.len() {
+            self.age_buckets
+                .resize(other.age_buckets.len(), AgeBucket::default());
+        }
+        for (bucket, other) in self.age_buckets.iter_mut().zip(other.age_buckets) {
+            bucket.files += other.files;
+            bucket.total_size += other.total_size;
+        }
+        for (dir, (size, count)) in other.prefix_totals {
+            let totals = self.prefix_totals.entry(dir).or_default();
+            totals.0 += size;
+            totals.1 += count;
+        }
+        self.baseline_seen.extend(other.baseline_seen);
+    }
+
+    /// Groups of at least two files with the same size, as (size, paths), ordered
+    /// by the space they would free if hard-linked together, `(count - 1) * size`
+    pub fn duplicate_size_groups(&self) -> Vec<(u64, &[PathBuf])> {
+        let mut groups: Vec<_> = self
+            .size_groups
+            .iter()
+            .filter(|(_, paths)| paths.len() >= 2)
+            .map(|(&size, paths)| (size, paths.as_slice()))
+            .collect();
+        groups.sort_by(|a, b| {
+            let savings = |(size, paths): &(u64, &[PathBuf])| size * (paths.len() as u64 - 1);
+            savings(b).cmp(&savings(a)).then_with(|| b.0.cmp(&a.0))
+        });
+        groups
+    }
+
+    /// Per-directory results sorted by directory total size descending, then path
+    pub fn dirs_by_size(&self) -> Vec<(&Path, &DirTop)> {
+        let mut dirs: Vec<_> = self.per_dir.iter().map(|(p, t)| (p.as_path(), t)).collect();
+        dirs.sort_by(|a, b| {
+            b.1.total_size
+                .cmp(&a.1.total_size)
+                .then_with(|| a.0.cmp(b.0))
+        });
+        dirs
+    }
+
+    /// Per-user results sorted by the user's total size descending, then UID
+    pub fn users_by_size(&self) -> Vec<(u32, &DirTop)> {
+        let mut users: Vec<_> = self.per_user.iter().map(|(&uid, t)| (uid, t)).collect();
+        users.sort_by(|a, b| {
+            b.1.total_size
+                .cmp(&a.1.total_size)
+                .then_with(|| a.0.cmp(&b.0))
+        });
+        users
+    }
+
+    /// Extension totals sorted by total bytes descending (then by extension),
+    /// truncated to `limit` groups
+    pub fn extensions_by_size(&self, limit: usize) -> Vec<(&str, u64, u64)> {
+        let mut groups: Vec<_> = self
+            .by_extension
+            .iter()
+            .map(|(ext, &(bytes, count))| (ext.as_str(), bytes, count))
+            .collect();
+        groups.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        groups.truncate(limit);
+        groups
+    }
+
+    /// Count an error, keeping its details if the scan collects them
+    fn record_error(&mut self, config: &ScanConfig, error: ScanError) {
+        self.errors += 1;
+        tracing::debug!(error = %error, "scan error");
+        if config.collect_errors
+            && config
+                .max_error_details
+                .is_none_or(|max| self.error_details.len() < max)
+        {
+            self.error_details.push(error);
+        }
+    }
+
+    /// Count the error that kept a directory from being scanned, keeping the
+    /// directory in `inaccessible_dirs` if it could not be opened or listed
+    fn record_dir_error(&mut self, config: &ScanConfig, error: ScanError) {
+        if let ScanError::ReadDir { path, source } = &error {
+            self.dirs_inaccessible += 1;
+            if config
+                .report_inaccessible
+                .is_some_and(|max| self.inaccessible_dirs.len() < max)
+            {
+                // `io::Error` cannot be cloned; the code or message is what is shown
+                let copy = match source.raw_os_error() {
+                    Some(code) => io::Error::from_raw_os_error(code),
+                    None => io::Error::new(source.kind(), source.to_string()),
+                };
+                self.inaccessible_dirs.push((path.clone(), copy));
+            }
+        }
+        self.record_error(config, error);
+    }
+
+    /// Account for a file owned by `uid` that passed all filters in the
+    /// optional summaries
+    fn record_file(&mut self, config: &ScanConfig, entry: &FileEntry, uid: u32) {
+        self.matched_files += 1;
+        self.matched_size += entry.size;
+        if config.group_by_extension {
+            self.record_extension(entry);
+        }
+        if let Some(n) = config.top_per_dir {
+            let parent = entry.path.parent().unwrap_or(Path::new(""));
+            let top = match self.per_dir.get_mut(parent) {
+                Some(top) => top,
+                None => self.per_dir.entry(parent.to_path_buf()).or_insert(DirTop {
+                    total_size: 0,
+                    files: config.new_tracker(n),
+                }),
+            };
+            top.total_size += entry.size;
+            top.files.insert(entry.clone());
+        }
+        if let Some(n) = config.top_per_user {
+            let top = self.per_user.entry(uid).or_insert_with(|| DirTop {
+                total_size: 0,
+                files: config.new_tracker(n),
+            });
+            top.total_size += entry.size;
+            top.files.insert(entry.clone());
+        }
+        if let (Some(buckets), Some(modified)) = (&config.age_buckets, entry.modified) {
+            if self.age_buckets.is_empty() {
+                self.age_buckets = vec![AgeBucket::default(); buckets.bounds.len() + 1];
+            }
+            let bucket = &mut self.age_buckets[buckets.bin(modified)];
+            bucket.files += 1;
+            bucket.total_size += entry.size;
+        }
+        if config.zero_sized && entry.size == 0 {
+            self.zero_sized.push(entry.path.clone());
+        }
+        if config.duplicate_sizes && entry.size > 0 {
+            self.size_groups
+                .entry(entry.size)
+                .or_default()
+                .push(entry.path.clone());
+        }
+        if let Some(depth) = config.top_dirs {
+            let root = config
+                .roots
+                .iter()
+                .filter(|root| entry.path.starts_with(root))
+                .max_by_key(|root| root.components().count());
+            if let Some(prefix) = root.and_then(|root| path_prefix(&entry.path, root, depth)) {
+                let totals = self.prefix_totals.entry(prefix).or_default();
+                totals.0 += entry.size;
+                totals.1 += 1;
+            }
+        }
+        if config.largest_dirs.is_some() {
+            let parent = entry.path.parent().unwrap_or(Path::new(""));
+            match self.dir_sizes.get_mut(parent) {
+                Some(total) => *total += entry.size,
+                None => {
+                    self.dir_sizes.insert(parent.to_path_buf(), entry.size);
+                }
+            }
+        }
+    }
+
+    /// Roll the per-directory sizes up into recursive totals and keep the
+    /// `limit` largest directories
+    fn total_dir_sizes(&mut self, limit: usize) {
+        // Deepest directories first, so each one is complete before it is
+        // added to its parent
+        let mut dirs: Vec<PathBuf> = self.dir_sizes.keys().cloned().collect();
+        dirs.sort_by_key(|dir| std::cmp::Reverse(dir.components().count()));
+        for dir in &dirs {
+            let size = self.dir_sizes[dir];
+            if let Some(parent) = dir.parent()
+                && let Some(total) = self.dir_sizes.get_mut(parent)
+            {
+                *total += size;
+            }
+        }
+
+        let mut top = TopNTracker::new(limit);
+        for (path, &total_size) in &self.dir_sizes {
+            top.insert(DirSizeEntry {
+                total_size,
+                path: path.clone(),
+            });
+        }
+        self.largest_dirs = top.into_vec();
+        self.largest_dirs.sort_by(|a, b| {
+            b.total_size
+                .cmp(&a.total_size)
+                .then_with(|| a.path.cmp(&b.path))
+        });
+    }
+
+    /// Find the empty directories in `dir_contents`. A directory is empty when
+    /// it holds no files and every subdirectory was scanned and is empty, so
+    /// unreadable subdirectories keep their parents off the list.
+    fn collect_empty_dirs(&mut self) {
+        // Deepest directories first, so each one is settled before its parent
+        let mut dirs: Vec<&PathBuf> = self.dir_contents.keys().collect();
+        dirs.sort_by_key(|dir| std::cmp::Reverse(dir.components().count()));
+        // Empty directories → directories beneath them
+        let mut empty: HashMap<&Path, u64> = HashMap::new();
+        // Directory → (empty subdirectories, directories beneath those)
+        let mut tally: HashMap<&Path, (u64, u64)> = HashMap::new();
+        for dir in dirs {
+            let contents = self.dir_contents[dir];
+            let (empty_subdirs, beneath) = tally.get(dir.as_path()).copied().unwrap_or_default();
+            if contents.files > 0 || contents.incomplete || empty_subdirs != contents.subdirs {
+                continue;
+            }
+            empty.insert(dir, beneath);
+            if let Some(parent) = dir.parent() {
+                let counts = tally.entry(parent).or_default();
+                counts.0 += 1;
+                counts.1 += 1 + beneath;
+            }
+        }
+
+        let mut outermost: Vec<EmptyDirReport> = empty
+            .iter()
+            .filter(|(dir, _)| {
+                dir.parent()
+                    .is_none_or(|parent| !empty.contains_key(parent))
+            })
+            .map(|(dir, &subdir_count)| EmptyDirReport {
+                path: dir.to_path_buf(),
+                subdir_count,
+            })
+            .collect();
+        outermost.sort_by(|a, b| {
+            let depth = |r: &EmptyDirReport| r.path.components().count();
+            depth(a).cmp(&depth(b)).then_with(|| a.path.cmp(&b.path))
+        });
+        self.empty_dirs = outermost;
+    }
+
+    fn record_extension(&mut self, entry: &FileEntry) {
+        let ext = entry
+            .path
+            .extension()
+            .map(|e| e.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let totals = self.by_extension.entry(ext).or_default();
+        totals.0 += entry.size;
+        totals.1 += 1;
+    }
+}
+
+/// Options controlling a scan
+///
+/// Construct with [`ScanConfig::builder`], or with [`ScanConfig::new`] and
+/// adjust the public fields; new options are added with defaults that preserve
+/// existing behavior.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct ScanConfig {
+    /// Directories to scan. Results are combined into a single top-N.
+    ///
+    /// Identical roots are scanned once, but a root nested inside another root
+    /// is not detected and its files will be counted twice.
+    pub roots: Vec<PathBuf>,
+    /// Number of largest files to keep
+    pub top: usize,
+    /// Smallest file size (inclusive) eligible for the top-N
+    pub min_size: u64,
+    /// Largest file size (inclusive) eligible for the top-N
+    pub max_size: u64,
+    /// Files smaller than this are not ranked, as if the top-N were already
+    /// full of larger ones. Unlike `min_size` they still count in the
+    /// statistics and the other reports. When nothing but the top-N looks at
+    /// the files, no entry is built for them at all, nor for the files below
+    /// the smallest one a full top-N holds.
+    pub prefilter_size: u64,
+    /// Directories whose full path or basename match are not descended into
+    pub exclude: GlobSet,
+    /// Skip the files and directories that the `.gitignore` files along the
+    /// way ignore, along with `.git` directories. Each directory's own file
+    /// takes precedence over those above it, which are read up to the top of
+    /// the repository holding the root, then come `.git/info/exclude` and the
+    /// global excludes file. Ignore files beneath the roots apply whether or
+    /// not they are inside a repository.
+    pub git_ignore: bool,
+    /// Count every hard link to the same inode separately instead of once
+    pub count_hardlinks: bool,
+    /// Deepest level to descend to; `Some(0)` scans only the roots themselves
+    pub max_depth: Option<usize>,
+    /// Shallowest level whose files are reported; `Some(1)` leaves out the
+    /// files directly in the roots. Shallower directories are still descended.
+    pub min_depth: Option<usize>,
+    /// Do not descend into directories on a different device than their root
+    pub same_filesystem: bool,
+    /// Do not descend into mounts of these virtual filesystems (all of them by
+    /// default), so `/` can be scanned without reading `/proc/kcore`. Roots are
+    /// scanned even when they are on one.
+    #[cfg(target_os = "linux")]
+    pub skip_virtual: Vec<mounts::VirtualFs>,
+    /// Only consider files modified after this time
+    pub newer_than: Option<SystemTime>,
+    /// Only consider files modified before this time
+    pub older_than: Option<SystemTime>,
+    /// Resolve symlinks to their targets; each directory is still visited once.
+    /// Every file's inode is remembered so targets reached by several paths are
+    /// counted once, which costs memory proportional to the number of files.
+    pub follow_symlinks: bool,
+    /// Keep the N smallest files instead of the N largest
+    pub find_smallest: bool,
+    /// Only gather statistics; no file entries are kept
+    pub count_only: bool,
+    /// Count the symlinks, sockets, FIFOs and devices the scanned directories
+    /// hold in `ScanStats::file_types`, from the stats the scan makes anyway
+    pub count_file_types: bool,
+    /// Measure files by allocated blocks (`st_blocks * 512`, as `du` does)
+    /// instead of their apparent size
+    pub disk_usage: bool,
+    /// Rank files by their allocated blocks (`FileEntry::allocated_size`)
+    /// while keeping their apparent size, for `SortOrder::Blocks`
+    pub rank_by_blocks: bool,
+    /// Add the names and values of each file's extended attributes to its
+    /// size (`FileEntry::xattr_size`), at the cost of more syscalls per file
+    pub virtual_size: bool,
+    /// Only consider files whose name matches this extension filter
+    pub extensions: Option<ExtensionFilter>,
+    /// Only consider files whose name (or path) passes these regexes
+    pub regex: Option<RegexFilter>,
+    /// Only consider files whose name matches one of these shell patterns
+    pub names: Option<NameFilter>,
+    /// Only consider files whose contents are of these types. The file is read
+    /// only once every other filter has passed it; archive members cannot be
+    /// read this way and are left out.
+    pub mime: Option<MimeFilter>,
+    /// Sizes by path from an earlier scan: files found again with the same
+    /// size are left out, so only new and resized files are considered, and
+    /// the ones not found again are listed in `ScanStats::removed`. Paths are
+    /// compared as scanned, so the roots should be given the same way.
+    pub baseline: Option<Arc<HashMap<PathBuf, u64>>>,
+    /// Accumulate per-extension totals into `ScanStats::by_extension`
+    pub group_by_extension: bool,
+    /// Also keep the N largest files of every directory in `ScanStats::per_dir`.
+    /// Memory grows with the number of directories times N.
+    pub top_per_dir: Option<usize>,
+    /// Also keep the N largest files of every owner in `ScanStats::per_user`.
+    /// Files listed inside archives are left out, having no owner of their own.
+    pub top_per_user: Option<usize>,
+    /// Report the N directories with the largest recursive size in
+    /// `ScanStats::largest_dirs` instead of individual files. Directories below
+    /// `max_depth` are not scanned, so their contents are not counted.
+    pub largest_dirs: Option<usize>,
+    /// Total the files beneath each directory this many levels below the roots
+    /// in `ScanStats::top_dirs` instead of keeping individual files, as
+    /// `du -d N` does. Files higher up count toward their own directory.
+    pub top_dirs: Option<usize>,
+    /// Keep every error in `ScanStats::error_details` rather than only counting
+    /// them. Unbounded on trees with many unreadable entries unless
+    /// `max_error_details` is set.
+    pub collect_errors: bool,
+    /// Keep at most this many errors in `ScanStats::error_details`; the rest
+    /// are only counted
+    pub max_error_details: Option<usize>,
+    /// List up to this many directories that could not be opened in
+    /// `ScanStats::inaccessible_dirs`, with their errors
+    pub report_inaccessible: Option<usize>,
+    /// Stop the scan once this many errors have occurred, setting
+    /// `ScanStats::aborted`, as many errors usually mean a failing disk or a
+    /// mount that cannot be read. Directories being scanned stop at their next
+    /// entry.
+    pub max_errors: Option<u64>,
+    /// Entries each scanning thread's top-N allocates room for up front,
+    /// when fewer than `top`, to bound the memory of many threads with a large
+    /// `top`. A thread's top-N still grows to `top` entries when that many
+    /// files reach it, as the results would otherwise be incomplete.
+    pub tracker_reserve: Option<usize>,
+    /// Shared counters to update as the scan progresses
+    pub progress: Option<Arc<ScanProgress>>,
+    /// Send every file that passes the filters here as soon as it is found,
+    /// whatever its rank, in no particular order. Multiply-linked files are
+    /// sent when the scan ends, once the kept link is known. The scan goes on
+    /// if the receiver hangs up.
+    pub stream: Option<mpsc::Sender<FileEntry>>,
+    /// Whether hidden files and directories are scanned
+    pub hidden: HiddenFiles,
+    /// Also keep the N directories with the most entries in
+    /// `ScanStats::inode_usage`. Every entry `read_dir` returns counts, whatever
+    /// the filters; only scanned directories are included.
+    pub inode_usage: Option<usize>,
+    /// Record the path of every non-empty file by size in
+    /// `ScanStats::size_groups`, to find duplicate candidates. Unlike the top-N
+    /// this keeps every file, so memory grows with the size of the tree.
+    pub duplicate_sizes: bool,
+    /// After the scan, hash the files in each same-size group to find true
+    /// duplicates (`ScanStats::duplicates`); requires `duplicate_sizes`
+    pub hash_duplicates: bool,
+    /// Only consider files whose permission bits match
+    pub permissions: Option<PermFilter>,
+    /// Only consider files with at least one of these bits of `st_mode` set,
+    /// such as `0o4000` (setuid), `0o2000` (setgid) or `0o002` (writable by
+    /// anyone); 0 for any file. Archive members have no mode and are left out.
+    pub mode_bits: u32,
+    /// Only consider files with at least this many hard links. Archive members
+    /// have none on disk and are left out by either bound.
+    pub min_hardlinks: Option<u64>,
+    /// Only consider files with at most this many hard links
+    pub max_hardlinks: Option<u64>,
+    /// Only consider files at least this sparse (see `FileEntry::sparseness`)
+    pub min_sparseness: Option<f32>,
+    /// List the files inside tar and zip archives as if they were files, and
+    /// inside archives nested this many levels deep; `None` leaves archives closed.
+    /// Only archives passing `min_depth`, `hidden`, `owners`, `groups`,
+    /// `permissions` and `min_sparseness` are opened.
+    #[cfg(feature = "archives")]
+    pub archive_depth: Option<usize>,
+    /// Experimental: resize the thread pool while the level-by-level scans
+    /// run, from a thread per CPU up to four per CPU while directories wait
+    /// for a thread, and down while none do
+    #[cfg(feature = "adaptive-threads")]
+    pub auto_threads: bool,
+    /// Find the directories without a single regular file beneath them
+    /// (`ScanStats::empty_dirs`), whatever the filters. Costs an entry per
+    /// scanned directory.
+    pub report_empty_dirs: bool,
+    /// Rank the directories scanned alongside the files, by their own size
+    /// (`st_size`, or their blocks with `disk_usage`) rather than their
+    /// contents', marked `FileEntry::is_dir`. Only the size, modification
+    /// time and hidden filters apply to them; neither the roots nor
+    /// directories reached through symlinks are included.
+    pub include_dirs: bool,
+    /// List every zero-byte file that passes the filters in
+    /// `ScanStats::zero_sized`, whatever the top-N. Unbounded, like
+    /// `duplicate_sizes`; count-only scans look at no file and list none.
+    pub zero_sized: bool,
+    /// Count the files that pass the filters, and their size, in these bins of
+    /// modification time (`ScanStats::age_buckets`)
+    pub age_buckets: Option<AgeBuckets>,
+    /// List the filesystems mounted beneath the roots in `ScanStats::mounts`,
+    /// noting which ones the scan skips (Linux and macOS)
+    pub report_mounts: bool,
+    /// Only consider files owned by one of these user IDs (any user if empty)
+    pub owners: Vec<u32>,
+    /// Only consider files whose group is one of these IDs (any group if empty)
+    pub groups: Vec<u32>,
+}
+
+impl ScanConfig {
+    /// A builder for a scan of `root`, starting from the defaults of `new`, for
+    /// example `ScanConfig::builder("/var").top(20).exclude_pattern("*.tmp").build()`
+    pub fn builder(root: impl Into<PathBuf>) -> ScanConfigBuilder {
+        ScanConfigBuilder {
+            config: Self::new(root),
+            excludes: GlobSetBuilder::new(),
+            error: None,
+        }
+    }
+
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            roots: vec![root.into()],
+            top: 10,
+            min_size: 0,
+            max_size: u64::MAX,
+            prefilter_size: 0,
+            exclude: GlobSet::empty(),
+            git_ignore: false,
+            count_hardlinks: false,
+            max_depth: None,
+            min_depth: None,
+            same_filesystem: false,
+            #[cfg(target_os = "linux")]
+            skip_virtual: mounts::VirtualFs::ALL.to_vec(),
+            newer_than: None,
+            older_than: None,
+            follow_symlinks: false,
+            find_smallest: false,
+            count_only: false,
+            count_file_types: false,
+            disk_usage: false,
+            rank_by_blocks: false,
+            virtual_size: false,
+            extensions: None,
+            regex: None,
+            names: None,
+            mime: None,
+            baseline: None,
+            group_by_extension: false,
+            top_per_dir: None,
+            top_per_user: None,
+            largest_dirs: None,
+            top_dirs: None,
+            collect_errors: false,
+            max_error_details: None,
+            report_inaccessible: None,
+            tracker_reserve: None,
+            max_errors: None,
+            progress: None,
+            stream: None,
+            hidden: HiddenFiles::Include,
+            inode_usage: None,
+            duplicate_sizes: false,
+            hash_duplicates: false,
+            report_empty_dirs: false,
+            include_dirs: false,
+            zero_sized: false,
+            age_buckets: None,
+            report_mounts: false,
+            owners: Vec::new(),
+            groups: Vec::new(),
+            permissions: None,
+            mode_bits: 0,
+            min_hardlinks: None,
+            max_hardlinks: None,
+            min_sparseness: None,
+            #[cfg(feature = "archives")]
+            archive_depth: None,
+            #[cfg(feature = "adaptive-threads")]
+            auto_threads: false,
+        }
+    }
+
+    /// An empty tracker ranking files the way this scan does
+    fn new_tracker(&self, capacity: usize) -> TopNTracker {
+        let tracker = if self.find_smallest {
+            TopNTracker::smallest(capacity)
+        } else {
+            TopNTracker::new(capacity)
+        };
+        if self.rank_by_blocks {
+            tracker.ordered_by(FileEntry::cmp_allocated)
+        } else {
+            tracker
+        }
+    }
+
+    /// A tracker for one scanning thread, as `new_tracker` but allocating
+    /// room for at most `tracker_reserve` entries until they are needed
+    fn thread_tracker(&self, capacity: usize) -> TopNTracker {
+        let reserve = self
+            .tracker_reserve
+            .map_or(capacity, |reserve| reserve.min(capacity));
+        TopNTracker {
+            deque: VecDeque::with_capacity(reserve),
+            capacity,
+            ..self.new_tracker(0)
+        }
+    }
+
+    /// Whether the file at `path` is in the baseline with this same size,
+    /// noting that it still exists
+    fn unchanged_since_baseline(&self, stats: &mut ScanStats, path: &Path, size: u64) -> bool {
+        let Some(old) = self
+            .baseline
+            .as_ref()
+            .and_then(|baseline| baseline.get(path))
+        else {
+            return false;
+        };
+        stats.baseline_seen.insert(path.to_path_buf());
+        *old == size
+    }
+
+    /// Whether a directory on device `device`, inside one on `parent`, is the
+    /// mount point of a virtual filesystem to skip
+    #[cfg(target_os = "linux")]
+    fn is_skipped_mount(&self, device: u64, parent: u64) -> bool {
+        device != parent
+            && !self.skip_virtual.is_empty()
+            && mounts::virtual_filesystem(device).is_some_and(|fs| self.skip_virtual.contains(&fs))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn is_skipped_mount(&self, _device: u64, _parent: u64) -> bool {
+        false
+    }
+
+    /// Pass a file that made it through the filters to `stream`
+    fn send_file(&self, file: &FileEntry) {
+        if let Some(stream) = &self.stream {
+            let _ = stream.send(file.clone());
+        }
+    }
+
+    /// Whether the entries of `dir` are above `min_depth`, so not reported
+    fn is_too_shallow(&self, dir: &QueuedDir) -> bool {
+        self.min_depth.is_some_and(|min| dir.depth < min)
+    }
+
+    /// Whether a report besides the top-N looks at every file that passes the
+    /// filters, so that each needs an entry even when it cannot rank
+    fn inspects_every_file(&self) -> bool {
+        self.stream.is_some()
+            || self.group_by_extension
+            || self.top_per_dir.is_some()
+            || self.top_per_user.is_some()
+            || self.largest_dirs.is_some()
+            || self.top_dirs.is_some()
+            || self.age_buckets.is_some()
+            || self.zero_sized
+            || self.duplicate_sizes
+    }
+
+    fn size_in_range(&self, size: u64) -> bool {
+        size >= self.min_size && size <= self.max_size
+    }
+
+    fn mtime_in_range(&self, modified: SystemTime) -> bool {
+        self.newer_than.is_none_or(|t| modified > t) && self.older_than.is_none_or(|t| modified < t)
+    }
+
+    fn owner_matches(&self, uid: u32, gid: u32) -> bool {
+        (self.owners.is_empty() || self.owners.contains(&uid))
+            && (self.groups.is_empty() || self.groups.contains(&gid))
+    }
+
+    /// Whether a file passes the filters on its depth, hiddenness, owner,
+    /// permissions and sparseness, which an archive's members inherit from it
+    fn attributes_match(
+        &self,
+        dir: &QueuedDir,
+        hidden: bool,
+        uid: u32,
+        gid: u32,
+        mode: u32,
+        sparseness: f32,
+    ) -> bool {
+        !self.is_too_shallow(dir)
+            && (hidden || self.hidden != HiddenFiles::Only)
+            && self.owner_matches(uid, gid)
+            && self
+                .permissions
+                .as_ref()
+                .is_none_or(|filter| filter.matches(mode))
+            && self.min_sparseness.is_none_or(|min| sparseness >= min)
+    }
+
+    fn is_excluded(&self, name: &OsStr, path: &Path) -> bool {
+        !self.exclude.is_empty() && (self.exclude.is_match(name) || self.exclude.is_match(path))
+    }
+}
+
+/// Chained construction of a [`ScanConfig`]; options without a method here are
+/// set on the built config's fields
+#[derive(Debug)]
+#[must_use]
+pub struct ScanConfigBuilder {
+    config: ScanConfig,
+    excludes: GlobSetBuilder,
+    /// The first exclude pattern that failed to parse, reported by `build`
+    error: Option<globset::Error>,
+}
+
+impl ScanConfigBuilder {
+    /// Also scan `root`; results are combined into a single top-N
+    pub fn root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.config.roots.push(root.into());
+        self
+    }
+
+    pub fn top(mut self, top: usize) -> Self {
+        self.config.top = top;
+        self
+    }
+
+    pub fn min_size(mut self, size: u64) -> Self {
+        self.config.min_size = size;
+        self
+    }
+
+    pub fn max_size(mut self, size: u64) -> Self {
+        self.config.max_size = size;
+        self
+    }
+
+    pub fn prefilter_size(mut self, size: u64) -> Self {
+        self.config.prefilter_size = size;
+        self
+    }
+
+    /// Do not descend into directories whose full path or basename match this
+    /// glob; may be given several times
+    pub fn exclude_pattern(mut self, pattern: &str) -> Self {
+        match Glob::new(pattern) {
+            Ok(glob) => {
+                self.excludes.add(glob);
+            }
+            Err(e) => self.error = self.error.or(Some(e)),
+        }
+        self
+    }
+
+    pub fn git_ignore(mut self, git_ignore: bool) -> Self {
+        self.config.git_ignore = git_ignore;
+        self
+    }
+
+    pub fn count_hardlinks(mut self, count: bool) -> Self {
+        self.config.count_hardlinks = count;
+        self
+    }
+
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.config.max_depth = Some(depth);
+        self
+    }
+
+    pub fn min_depth(mut self, depth: usize) -> Self {
+        self.config.min_depth = Some(depth);
+        self
+    }
+
+    pub fn same_filesystem(mut self, same: bool) -> Self {
+        self.config.same_filesystem = same;
+        self
+    }
+
+    pub fn follow_symlinks(mut self, follow: bool) -> Self {
+        self.config.follow_symlinks = follow;
+        self
+    }
+
+    pub fn find_smallest(mut self, smallest: bool) -> Self {
+        self.config.find_smallest = smallest;
+        self
+    }
+
+    pub fn count_only(mut self, count_only: bool) -> Self {
+        self.config.count_only = count_only;
+        self
+    }
+
+    pub fn count_file_types(mut self, count: bool) -> Self {
+        self.config.count_file_types = count;
+        self
+    }
+
+    pub fn disk_usage(mut self, disk_usage: bool) -> Self {
+        self.config.disk_usage = disk_usage;
+        self
+    }
+
+    pub fn rank_by_blocks(mut self, rank_by_blocks: bool) -> Self {
+        self.config.rank_by_blocks = rank_by_blocks;
+        self
+    }
+
+    pub fn tracker_reserve(mut self, reserve: usize) -> Self {
+        self.config.tracker_reserve = Some(reserve);
+        self
+    }
+
+    pub fn hidden(mut self, hidden: HiddenFiles) -> Self {
+        self.config.hidden = hidden;
+        self
+    }
+
+    pub fn extensions(mut self, extensions: ExtensionFilter) -> Self {
+        self.config.extensions = Some(extensions);
+        self
+    }
+
+    pub fn newer_than(mut self, time: SystemTime) -> Self {
+        self.config.newer_than = Some(time);
+        self
+    }
+
+    pub fn older_than(mut self, time: SystemTime) -> Self {
+        self.config.older_than = Some(time);
+        self
+    }
+
+    /// The config, or the first invalid exclude pattern
+    pub fn build(self) -> Result<ScanConfig, globset::Error> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        let mut config = self.config;
+        config.exclude = self.excludes.build()?;
+        Ok(config)
+    }
+}
+
+/// Multiply-linked files keyed by (device, inode), holding the entry with the
+/// lexicographically smallest path so the surviving link is deterministic
+type HardlinkMap = Mutex<HashMap<(u64, u64), (FileEntry, u32)>>;
+
+/// Matches file names against a set of extensions, compiled once up front.
+///
+/// Extensions may be given with or without the leading dot and may span several
+/// components (`tar.gz`). Matching is case-sensitive except on platforms whose
+/// filesystems are usually case-insensitive (macOS, Windows).
+#[derive(Debug, Clone, Default)]
+pub struct ExtensionFilter {
+    /// Normalized suffixes, each starting with a dot
+    suffixes: Vec<String>,
+    /// Also match names without any extension
+    match_none: bool,
+}
+
+impl ExtensionFilter {
+    pub fn new<I, S>(extensions: I, match_none: bool) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let suffixes = extensions
+            .into_iter()
+            .map(|ext| {
+                let ext = ext.as_ref().trim_start_matches('.');
+                Self::normalize_case(&format!(".{}", ext)).into_owned()
+            })
+            .collect();
+        Self {
+            suffixes,
+            match_none,
+        }
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "windows"))]
+    fn normalize_case(s: &str) -> Cow<'_, str> {
+        Cow::Owned(s.to_lowercase())
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    fn normalize_case(s: &str) -> Cow<'_, str> {
+        Cow::Borrowed(s)
+    }
+
+    /// Whether a file name passes the filter; the patterns are OR'd together
+    pub fn matches(&self, name: &str) -> bool {
+        let name = Self::normalize_case(name);
+        // A leading dot marks a hidden file, not an extension
+        let has_suffix =
+            |suffix: &String| name.len() > suffix.len() && name.ends_with(suffix.as_str());
+        if self.suffixes.iter().any(has_suffix) {
+            return true;
+        }
+        self.match_none && Path::new(name.as_ref()).extension().is_none()
+    }
+}
+
+/// Regular expressions a file must match (any of them) and must not match
+/// (none of them) to be considered, tested against the file name or full path
+#[derive(Debug, Clone)]
+pub struct RegexFilter {
+    include: RegexSet,
+    exclude: RegexSet,
+    full_path: bool,
+}
+
+impl RegexFilter {
+    /// Compile the patterns; with no `include` patterns every file not
+    /// excluded passes. Case-insensitive matching is `(?i)` in the pattern.
+    pub fn new<S: AsRef<str>>(
+        include: &[S],
+        exclude: &[S],
+        full_path: bool,
+    ) -> Result<Self, String> {
+        let compile = |patterns: &[S]| {
+            // Compile each pattern alone so the error names the one at fault
+            for pattern in patterns {
+                if let Err(e) = Regex::new(pattern.as_ref()) {
+                    return Err(format!("invalid regex '{}': {}", pattern.as_ref(), e));
+                }
+            }
+            RegexSet::new(patterns.iter().map(AsRef::as_ref)).map_err(|e| e.to_string())
+        };
+        Ok(Self {
+            include: compile(include)?,
+            exclude: compile(exclude)?,
+            full_path,
+        })
+    }
+
+    /// Whether the file called `name` at `path` passes the filter
+    pub fn matches(&self, name: &str, path: &Path) -> bool {
+        let full;
+        let subject = if self.full_path {
+            full = path.to_string_lossy();
+            &*full
+        } else {
+            name
+        };
+        (self.include.is_empty() || self.include.is_match(subject))
+            && !self.exclude.is_match(subject)
+    }
+}
+
+/// Shell patterns (`*`, `?`, `[...]`) matched against file names as
+/// `find -name` and `-iname` do; a file passes if any pattern matches
+#[derive(Debug, Clone)]
+pub struct NameFilter {
+    globs: GlobSet,
+}
+
+impl NameFilter {
+    /// Compile the case-sensitive patterns `names` and the case-insensitive
+    /// `inames`. `*` does not cross a `/`, which cannot be in a name anyway.
+    pub fn new<S: AsRef<str>>(names: &[S], inames: &[S]) -> Result<Self, String> {
+        let mut builder = GlobSetBuilder::new();
+        let patterns = names
+            .iter()
+            .map(|p| (p, false))
+            .chain(inames.iter().map(|p| (p, true)));
+        for (pattern, case_insensitive) in patterns {
+            let glob = GlobBuilder::new(pattern.as_ref())
+                .literal_separator(true)
+                .case_insensitive(case_insensitive)
+                .build()
+                .map_err(|e| format!("invalid name pattern: {}", e))?;
+            builder.add(glob);
+        }
+        let globs = builder
+            .build()
+            .map_err(|e| format!("invalid name pattern: {}", e))?;
+        Ok(Self { globs })
+    }
+
+    /// Whether a file called `name` passes the filter
+    pub fn matches(&self, name: &str) -> bool {
+        self.globs.is_match(name)
+    }
+}
+
+/// Bytes read from the start of a file to recognize its type
+const SNIFF_LEN: usize = 16;
+
+/// Groups of MIME types for [`MimeFilter`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum MimeCategory {
+    Image,
+    Video,
+    /// Compressed and archive formats, other than documents
+    Archive,
+    /// Books, office documents, PDF, PostScript and RTF
+    Document,
+}
+
+impl MimeCategory {
+    /// Types `infer` classes as archives but people file as documents
+    const DOCUMENT_MIMES: [&str; 3] = [
+        "application/pdf",
+        "application/postscript",
+        "application/rtf",
+    ];
+
+    fn contains(self, kind: &infer::Type) -> bool {
+        use infer::MatcherType;
+
+        let document = Self::DOCUMENT_MIMES.contains(&kind.mime_type());
+        match self {
+            MimeCategory::Image => kind.matcher_type() == MatcherType::Image,
+            MimeCategory::Video => kind.matcher_type() == MatcherType::Video,
+            MimeCategory::Archive => kind.matcher_type() == MatcherType::Archive && !document,
+            MimeCategory::Document => {
+                document || matches!(kind.matcher_type(), MatcherType::Book | MatcherType::Doc)
+            }
+        }
+    }
+}
+
+/// Content types a file must have, recognized from its first 16 bytes by the
+/// `infer` crate's magic numbers, whatever its name. Formats told apart only
+/// further in (`docx` inside a zip, for one) are seen as their container.
+#[derive(Debug, Clone, Default)]
+pub struct MimeFilter {
+    types: Vec<String>,
+    categories: Vec<MimeCategory>,
+}
+
+impl MimeFilter {
+    /// A filter passing files of any of `types` (such as `image/jpeg`) or of
+    /// any of `categories`; fails on a type `infer` cannot recognize
+    pub fn new<S: AsRef<str>>(types: &[S], categories: &[MimeCategory]) -> Result<Self, String> {
+        let types = types
+            .iter()
+            .map(|mime| {
+                let mime = mime.as_ref().to_ascii_lowercase();
+                if infer::is_mime_supported(&mime) {
+                    Ok(mime)
+                } else {
+                    Err(format!(
+                        "MIME type '{}' cannot be recognized from file contents",
+                        mime
+                    ))
+                }
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            types,
+            categories: categories.to_vec(),
+        })
+    }
+
+    /// Whether content starting with `head` passes the filter
+    pub fn matches(&self, head: &[u8]) -> bool {
+        infer::get(head).is_some_and(|kind| {
+            self.types.iter().any(|mime| mime == kind.mime_type())
+                || self
+                    .categories
+                    .iter()
+                    .any(|category| category.contains(&kind))
+        })
+    }
+
+    /// Whether the file at `path` passes the filter, reading its first bytes
+    /// with a single `read`
+    pub fn matches_file(&self, path: &Path) -> io::Result<bool> {
+        use std::io::Read;
+
+        let mut head = [0u8; SNIFF_LEN];
+        let read = fs::File::open(path)?.read(&mut head)?;
+        Ok(self.matches(&head[..read]))
+    }
+}
+
+/// How a [`PermFilter`]'s mode is compared with a file's permission bits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PermMatch {
+    /// The permission bits equal the mode exactly
+    Exact,
+    /// At least one bit of the mode is set (`/mode`); a mode of 0 matches any file
+    Any,
+    /// Every bit of the mode is set (`-mode`)
+    All,
+}
+
+/// A permission test like `find -perm`, built by [`parse_permissions`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PermFilter {
+    /// Permission bits to compare (`0o7777` at most)
+    pub mode: u32,
+    pub kind: PermMatch,
+}
+
+impl PermFilter {
+    /// Whether a file with this `st_mode` passes the filter
+    pub fn matches(&self, st_mode: u32) -> bool {
+        let bits = st_mode & 0o7777;
+        match self.kind {
+            PermMatch::Exact => bits == self.mode,
+            PermMatch::Any => self.mode == 0 || bits & self.mode != 0,
+            PermMatch::All => bits & self.mode == self.mode,
+        }
+    }
+}
+
+/// Add the files inside an archive to the top-N, subject to the size, time,
+/// extension and regex filters; the archive itself has passed the rest.
+/// Members are leaves: they count toward extension totals but not toward
+/// directory sizes, duplicates or per-directory lists.
+#[cfg(feature = "archives")]
+fn scan_archive_members(
+    path: &Path,
+    kind: archive::ArchiveKind,
+    nested: usize,
+    config: &ScanConfig,
+    shared: &SharedState,
+    stats: &mut ScanStats,
+) {
+    let mut members = config.new_tracker(config.top);
+    let mut count = 0;
+    let errors = archive::scan_archive(path, kind, nested, &mut |member| {
+        count += 1;
+        if config.unchanged_since_baseline(stats, &member.path, member.size) {
+            return;
+        }
+        if !config.size_in_range(member.size)
+            || !member.modified.is_none_or(|t| config.mtime_in_range(t))
+        {
+            return;
+        }
+        let name = member
+            .path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("");
+        if let Some(filter) = &config.extensions
+            && !filter.matches(name)
+        {
+            return;
+        }
+        if let Some(filter) = &config.regex
+            && !filter.matches(name, &member.path)
+        {
+            return;
+        }
+        if let Some(filter) = &config.names
+            && !filter.matches(name)
+        {
+            return;
+        }
+        if config.mime.is_some()
+            || config.min_hardlinks.is_some()
+            || config.max_hardlinks.is_some()
+            || config.mode_bits != 0
+        {
+            return;
+        }
+        if config.group_by_extension {
+            stats.record_extension(&member);
+        }
+        tracing::debug!(size = member.size, path = %member.path.display(), "found archive member");
+        config.send_file(&member);
+        if member.size >= config.prefilter_size {
+            members.insert(member);
+        }
+    });
+    stats.archive_members += count;
+    for error in errors {
+        stats.record_error(config, error);
+    }
+    shared.local_top_n(config).merge(members);
+}
+
+/// Share of `size` not backed by the `allocated` bytes (`st_blocks * 512`);
+/// files with more blocks than their size (most small files) count as 0
+fn sparseness(size: u64, allocated: u64) -> f32 {
+    if size == 0 || allocated >= size {
+        return 0.0;
+    }
+    (1.0 - allocated as f64 / size as f64) as f32
+}
+
+/// Bytes the extended attributes of the file at `path` take: the length of
+/// every name and value. Filesystems without extended attributes count as 0.
+#[cfg(unix)]
+pub fn extended_attributes_size(path: &Path) -> io::Result<u64> {
+    use rustix::io::Errno;
+    use std::os::unix::ffi::OsStrExt;
+
+    let unsupported = |e: Errno| e == Errno::NOTSUP || e == Errno::OPNOTSUPP;
+    let len = match rustix::fs::listxattr(path, &mut []) {
+        Ok(0) => return Ok(0),
+        Ok(len) => len,
+        Err(e) if unsupported(e) => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+    let mut names = vec![0; len];
+    let len = rustix::fs::listxattr(path, &mut names)?;
+    // `c_char` is signed on some targets
+    #[allow(clippy::unnecessary_cast)]
+    let names: Vec<u8> = names[..len].iter().map(|&c| c as u8).collect();
+
+    let mut total = 0;
+    for name in names.split(|&b| b == 0).filter(|name| !name.is_empty()) {
+        total += name.len() as u64;
+        match rustix::fs::getxattr(path, std::ffi::OsStr::from_bytes(name), &mut []) {
+            Ok(value_len) => total += value_len as u64,
+            // Removed since it was listed
+            Err(Errno::NODATA) => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(total)
+}
+
+/// Always 0: Windows keeps no extended attributes `listxattr` would list
+#[cfg(windows)]
+pub fn extended_attributes_size(_: &Path) -> io::Result<u64> {
+    Ok(0)
+}
+
+/// Single-observation directory entry with metadata. Names are kept as
+/// listed, whether or not they are valid UTF-8.
+struct DirEntry {
+    name: OsString,
+    path: PathBuf,
+}
+
+/// Whether a file name is hidden, starting with `.`
+fn is_hidden_name(name: &OsStr) -> bool {
+    name.as_encoded_bytes().starts_with(b".")
+}
+
+/// State shared by all directory scans of one `parallel_scan`
+struct SharedState {
+    /// The top-N of each scanning thread, merged once the scan ends, so that
+    /// keeping a file takes no lock
+    top_n: ThreadLocal<RefCell<TopNTracker>>,
+    /// Capacity of each tracker in `top_n`
+    capacity: usize,
+    hardlinks: HardlinkMap,
+    /// Directories with the most entries, for `ScanConfig::inode_usage`
+    inodes: Mutex<TopNTracker<InodeEntry>>,
+    /// Errors of the directories scanned so far, for `ScanConfig::max_errors`
+    errors: AtomicU64,
+    /// `max_errors` was reached and the scan is winding down
+    aborted: AtomicBool,
+    /// The largest `TopNTracker::min_size` of the trackers in `top_n`: as one
+    /// of them already holds `capacity` files at least this large, no smaller
+    /// file can be among the results
+    min_ranked_size: AtomicU64,
+}
+
+/// Directory waiting to be scanned, with its device (`st_dev`) and inode
+struct QueuedDir {
+    path: PathBuf,
+    device: u64,
+    inode: u64,
+    /// This directory or one of its ancestors below the root is hidden
+    hidden: bool,
+    /// Levels below the root, which is at 0
+    depth: usize,
+    /// The `.gitignore` rules of the parent directory with
+    /// `ScanConfig::git_ignore`; a root's are read when it is scanned
+    ignores: Option<Arc<gitignore::Ignores>>,
+}
+
+/// Bytes of `linux_dirent64` records fetched per `getdents64` call
+#[cfg(target_os = "linux")]
+const GETDENTS_BUFFER: usize = 64 * 1024;
+
+/// Scan a single directory atomically: read entries once, sort lexicographically,
+/// classify each with a single statx() call.
+///
+/// Entries are read with `getdents64` into a buffer on the stack, so the only
+/// allocation per entry is its name; std's `read_dir` adds a `DirEntry` each.
+#[cfg(target_os = "linux")]
+fn scan_directory(
+    dir: &QueuedDir,
+    config: &ScanConfig,
+    shared: &SharedState,
+    stats: &mut ScanStats,
+    subdirs: &mut Vec<QueuedDir>,
+    descend: bool,
+    batch: Option<&mut dyn StatBatch>,
+) -> Result<(), ScanError> {
+    use rustix::fs::RawDir;
+    use std::mem::MaybeUninit;
+
+    let mut buf = [MaybeUninit::<u8>::uninit(); GETDENTS_BUFFER];
+    // One descriptor both lists the directory and anchors every entry's stat
+    let fd;
+    let mut parent = None;
+    let listing = match open_dir(&dir.path) {
+        Ok(opened) => {
+            fd = opened;
+            parent = Some(fd.as_fd());
+            Ok(DirNames {
+                raw: RawDir::new(fd.as_fd(), &mut buf),
+                failed: false,
+            })
+        }
+        Err(e) => Err(e),
+    };
+    scan_listing(
+        dir, listing, parent, batch, config, shared, stats, subdirs, descend,
+    )
+}
+
+/// Scan a single directory atomically: read entries once, sort lexicographically,
+/// classify each with a single statx() call
+#[cfg(all(unix, not(target_os = "linux")))]
+fn scan_directory(
+    dir: &QueuedDir,
+    config: &ScanConfig,
+    shared: &SharedState,
+    stats: &mut ScanStats,
+    subdirs: &mut Vec<QueuedDir>,
+    descend: bool,
+    batch: Option<&mut dyn StatBatch>,
+) -> Result<(), ScanError> {
+    let listing =
+        fs::read_dir(&dir.path).map(|read_dir| read_dir.map(|entry| entry.map(|e| e.file_name())));
+    scan_listing(
+        dir, listing, None, batch, config, shared, stats, subdirs, descend,
+    )
+}
+
+/// Scan a single directory atomically: read entries once with their metadata,
+/// sort lexicographically and classify each from what the listing gave
+#[cfg(windows)]
+fn scan_directory(
+    dir: &QueuedDir,
+    config: &ScanConfig,
+    shared: &SharedState,
+    stats: &mut ScanStats,
+    subdirs: &mut Vec<QueuedDir>,
+    descend: bool,
+    _batch: Option<&mut dyn StatBatch>,
+) -> Result<(), ScanError> {
+    // Hard links, and directories reached twice through links, only differ in
+    // their file index, which the listing does not give
+    let identify_files = !config.count_only
+        && (!config.count_hardlinks
+            || config.follow_symlinks
+            || config.min_hardlinks.is_some()
+            || config.max_hardlinks.is_some());
+    match windows::list_dir(
+        &dir.path,
+        dir.device,
+        identify_files,
+        config.follow_symlinks,
+    ) {
+        Ok((names, mut found)) => {
+            let listing = Ok(names.into_iter());
+            scan_listing(
+                dir,
+                listing,
+                None,
+                Some(&mut found),
+                config,
+                shared,
+                stats,
+                subdirs,
+                descend,
+            )
+        }
+        Err(e) => {
+            let listing: io::Result<std::iter::Empty<_>> = Err(e);
+            scan_listing(
+                dir, listing, None, None, config, shared, stats, subdirs, descend,
+            )
+        }
+    }
+}
+
+/// The names in a directory read by `getdents64`, without `.` and `..`. An
+/// error ends the listing, as it ends `fs::read_dir`'s.
+#[cfg(target_os = "linux")]
+struct DirNames<'buf, 'fd> {
+    raw: rustix::fs::RawDir<'buf, BorrowedFd<'fd>>,
+    failed: bool,
+}
+
+#[cfg(target_os = "linux")]
+impl Iterator for DirNames<'_, '_> {
+    type Item = io::Result<OsString>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        use std::os::unix::ffi::OsStrExt;
+
+        while !self.failed {
+            match self.raw.next()? {
+                Ok(entry) => {
+                    let name = entry.file_name().to_bytes();
+                    if name != b"." && name != b".." {
+                        return Some(Ok(std::ffi::OsStr::from_bytes(name).to_os_string()));
+                    }
+                }
+                Err(e) => {
+                    self.failed = true;
+                    return Some(Err(e.into()));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Open a directory for listing and for the `*at` calls on its entries
+#[cfg(unix)]
+fn open_dir(path: &Path) -> io::Result<OwnedFd> {
+    let flags = OFlags::RDONLY | OFlags::DIRECTORY | OFlags::CLOEXEC;
+    Ok(rustix::fs::open(path, flags, Mode::empty())?)
+}
+
+/// What entries are stat'ed relative to: the directory's descriptor, or its
+/// path on Windows, which has no `*at` calls
+#[cfg(unix)]
+type Parent<'a> = BorrowedFd<'a>;
+#[cfg(windows)]
+type Parent<'a> = &'a Path;
+
+/// Stats all the entries of a directory at once, for engines that batch the
+/// calls rather than making one per entry
+trait StatBatch {
+    /// What `classify_entry` without following symlinks gives for each entry
+    fn stat_all(
+        &mut self,
+        parent: Parent<'_>,
+        entries: &[DirEntry],
+    ) -> Vec<io::Result<EntryMetadata>>;
+}
+
+/// Process the names listed in a directory, however they were read. Paths are
+/// only built for entries that will be classified, which are stat'ed relative
+/// to `parent`, the directory's descriptor, opened here if not given: one by
+/// one, or all together by `batch` when given.
+#[allow(clippy::too_many_arguments)]
+fn scan_listing(
+    dir: &QueuedDir,
+    listing: io::Result<impl Iterator<Item = io::Result<OsString>>>,
+    parent: Option<Parent<'_>>,
+    batch: Option<&mut dyn StatBatch>,
+    config: &ScanConfig,
+    shared: &SharedState,
+    stats: &mut ScanStats,
+    subdirs: &mut Vec<QueuedDir>,
+    descend: bool,
+) -> Result<(), ScanError> {
+    let dir_path = dir.path.as_path();
+    let _span = tracing::debug_span!("scan_dir", path = %dir_path.display()).entered();
+    tracing::trace!(path = %dir_path.display(), "entering directory");
+    if shared.should_abort(config, stats.errors) {
+        return Ok(());
+    }
+    if config.largest_dirs.is_some() {
+        // Record the directory even if no file in it qualifies
+        stats.dir_sizes.entry(dir.path.clone()).or_default();
+    }
+
+    // Read directory entries exactly once
+    let mut entries: Vec<DirEntry> = Vec::new();
+
+    let read_dir = listing.map_err(|source| ScanError::ReadDir {
+        path: dir.path.clone(),
+        source,
+    })?;
+    let mut contents = DirContents::default();
+    let mut children = 0;
+    let (mut has_git, mut has_gitignore) = (false, false);
+    for entry in read_dir {
+        if shared.should_abort(config, stats.errors) {
+            break;
+        }
+        children += 1;
+        let name = match entry {
+            Ok(name) => name,
+            Err(source) => {
+                let path = dir.path.clone();
+                stats.record_error(config, ScanError::ReadDir { path, source });
+                continue; // Skip entries we can't read, never retry
+            }
+        };
+
+        has_git |= name == ".git";
+        has_gitignore |= name == ".gitignore";
+        if config.git_ignore && name == ".git" {
+            contents.incomplete = true;
+            continue;
+        }
+        // Hidden entries are dropped before they cost a path or a stat
+        if config.hidden == HiddenFiles::Exclude && (dir.hidden || is_hidden_name(&name)) {
+            contents.incomplete = true;
+            continue;
+        }
+
+        entries.push(DirEntry {
+            path: dir.path.join(&name),
+            name,
+        });
+    }
+
+    if config.inode_usage.is_some() {
+        shared.inodes.lock().unwrap().insert(InodeEntry {
+            count: children,
+            path: dir.path.clone(),
+        });
+    }
+
+    // Sort entries lexicographically for deterministic traversal order
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+    #[cfg(unix)]
+    let opened;
+    #[cfg(unix)]
+    let parent = match parent {
+        Some(fd) => fd,
+        None => {
+            opened = open_dir(dir_path).map_err(|source| ScanError::ReadDir {
+                path: dir.path.clone(),
+                source,
+            })?;
+            opened.as_fd()
+        }
+    };
+    #[cfg(windows)]
+    let parent = parent.unwrap_or(dir_path);
+
+    let mut batched = batch.map(|batch| batch.stat_all(parent, &entries).into_iter());
+    let every_file = config.inspects_every_file();
+    let ignores = if !config.git_ignore {
+        None
+    } else {
+        let parent = match dir.depth {
+            0 => gitignore::Ignores::above_root(dir_path, config, stats),
+            _ => dir.ignores.clone(),
+        };
+        gitignore::Ignores::enter(parent, dir_path, has_git, has_gitignore, config, stats)
+    };
+
+    // Classify each entry exactly once with single statx() call
+    for entry in entries {
+        if shared.should_abort(config, stats.errors) {
+            break;
+        }
+        let hidden = dir.hidden || is_hidden_name(&entry.name);
+
+        // Single classification attempt - never retry
+        let mut followed = false;
+        stats.stat_calls += 1;
+        let classified = match &mut batched {
+            Some(results) => results.next().expect("one result per entry"),
+            None => classify_entry(parent, &entry.name, false),
+        };
+        if config.count_file_types
+            && let Ok(metadata) = &classified
+        {
+            stats.file_types.get_or_insert_default().record(metadata);
+        }
+        let metadata = match classified {
+            // Re-stat symlinks through to their target; a broken link is an error
+            Ok(EntryMetadata::Symlink) if config.follow_symlinks => {
+                stats.stat_calls += 1;
+                match classify_entry(parent, &entry.name, true) {
+                    Ok(m) => {
+                        stats.symlinks_followed += 1;
+                        followed = true;
+                        m
+                    }
+                    Err(source) => {
+                        let path = entry.path;
+                        stats.record_error(config, ScanError::StatFailed { path, source });
+                        continue;
+                    }
+                }
+            }
+            Ok(m) => m,
+            Err(source) => {
+                let path = entry.path;
+                stats.record_error(config, ScanError::StatFailed { path, source });
+                continue; // Failed classification, skip this entry
+            }
+        };
+        if let Some(ignores) = &ignores
+            && ignores.is_ignored(
+                &entry.path,
+                matches!(metadata, EntryMetadata::Directory { .. }),
+            )
+        {
+            contents.incomplete = true;
+            continue;
+        }
+
+        match metadata {
+            EntryMetadata::RegularFile {
+                logical_size,
+                block_size,
+                device,
+                inode,
+                nlink,
+                uid,
+                gid,
+                mode,
+                modified,
+                accessed,
+            } => {
+                stats.files_scanned += 1;
+                contents.files += 1;
+                if let Some(progress) = &config.progress {
+                    progress.files_scanned.fetch_add(1, Ordering::Relaxed);
+                }
+                if config.count_only {
+                    continue;
+                }
+                let sparseness = sparseness(logical_size, block_size);
+                let attributes_match =
+                    config.attributes_match(dir, hidden, uid, gid, mode, sparseness);
+                // Members have sizes, times and names of their own, but take
+                // the archive's place, owner, permissions and storage
+                #[cfg(feature = "archives")]
+                if let Some(nested) = config.archive_depth
+                    && attributes_match
+                    && let Some(kind) = entry.name.to_str().and_then(archive::ArchiveKind::detect)
+                {
+                    scan_archive_members(&entry.path, kind, nested, config, shared, stats);
+                }
+                let mut size = if config.disk_usage {
+                    block_size
+                } else {
+                    logical_size
+                };
+                let mut xattr_size = 0;
+                if config.virtual_size {
+                    match extended_attributes_size(&entry.path) {
+                        Ok(bytes) => xattr_size = bytes,
+                        Err(source) => {
+                            let path = entry.path.clone();
+                            stats.record_error(config, ScanError::ReadFailed { path, source });
+                        }
+                    }
+                    size += xattr_size;
+                }
+                // Before the filters, so a file that no longer passes them is
+                // not taken for removed
+                if config.unchanged_since_baseline(stats, &entry.path, size) {
+                    continue;
+                }
+                // Filter before building the entry so rejected files cost nothing
+                if !attributes_match
+                    || !config.size_in_range(size)
+                    || !config.mtime_in_range(modified)
+                {
+                    continue;
+                }
+                // The name filters match text, taking invalid UTF-8 as U+FFFD
+                let name = entry.name.to_string_lossy();
+                if let Some(filter) = &config.extensions
+                    && !filter.matches(&name)
+                {
+                    continue;
+                }
+                if let Some(filter) = &config.regex
+                    && !filter.matches(&name, &entry.path)
+                {
+                    continue;
+                }
+                if let Some(filter) = &config.names
+                    && !filter.matches(&name)
+                {
+                    continue;
+                }
+                if config.mode_bits != 0 && mode & config.mode_bits == 0 {
+                    continue;
+                }
+                if config.min_hardlinks.is_some_and(|min| nlink < min)
+                    || config.max_hardlinks.is_some_and(|max| nlink > max)
+                {
+                    continue;
+                }
+                // Last, as the only filter that reads the file
+                if let Some(filter) = &config.mime {
+                    match filter.matches_file(&entry.path) {
+                        Ok(true) => {}
+                        Ok(false) => continue,
+                        Err(source) => {
+                            let path = entry.path.clone();
+                            stats.record_error(config, ScanError::ReadFailed { path, source });
+                            continue;
+                        }
+                    }
+                }
+                // Deferred files go through the inode table, which counts the
+                // skipped links, so they need an entry whatever their size
+                let deferred = (nlink > 1 || config.follow_symlinks) && !config.count_hardlinks;
+                if !deferred && !every_file && size < shared.rank_threshold(config) {
+                    stats.matched_files += 1;
+                    stats.matched_size += size;
+                    continue;
+                }
+                let file = FileEntry {
+                    size,
+                    path: entry.path,
+                    modified: Some(modified),
+                    accessed: Some(accessed),
+                    hash: None,
+                    sparseness,
+                    checksum: None,
+                    is_reflinked: false,
+                    physical_size: 0,
+                    allocated_size: block_size,
+                    xattr_size,
+                    nlink,
+                    mode,
+                    is_dir: false,
+                    verified: None,
+                };
+
+                // Defer multiply-linked files until the scan ends, keeping one per inode.
+                // When following symlinks any file may also be reached through a link,
+                // so every file goes through the inode table.
+                if deferred {
+                    let mut seen = shared.hardlinks.lock().unwrap();
+                    match seen.entry((device, inode)) {
+                        hash_map::Entry::Occupied(mut kept) => {
+                            stats.hardlinks_skipped += 1;
+                            if file.path < kept.get().0.path {
+                                kept.insert((file, uid));
+                            }
+                        }
+                        hash_map::Entry::Vacant(slot) => {
+                            slot.insert((file, uid));
+                        }
+                    }
+                    continue;
+                }
+
+                stats.record_file(config, &file, uid);
+                tracing::debug!(size = file.size, path = %file.path.display(), "found file");
+                config.send_file(&file);
+                if file.size < config.prefilter_size {
+                    continue;
+                }
+                let mut top_n = shared.local_top_n(config);
+                top_n.insert(file);
+                // Ranked by blocks, a smaller file may still outrank the worst kept
+                if !config.rank_by_blocks
+                    && let Some(size) = top_n.min_size()
+                {
+                    shared.min_ranked_size.fetch_max(size, Ordering::Relaxed);
+                }
+                if let Some(progress) = &config.progress
+                    && top_n.is_full()
+                    && let Some(worst) = top_n.worst()
+                {
+                    progress.tighten_threshold(worst.size, config.find_smallest);
+                }
+            }
+            EntryMetadata::Directory {
+                device,
+                inode,
+                logical_size,
+                block_size,
+                modified,
+                accessed,
+            } => {
+                // At the depth limit subdirectories are neither counted nor queued,
+                // nor are mount points with --same-filesystem, virtual filesystems
+                // or excluded directories
+                if !descend
+                    || (config.same_filesystem && device != dir.device)
+                    || config.is_skipped_mount(device, dir.device)
+                    || config.is_excluded(&entry.name, &entry.path)
+                {
+                    contents.incomplete = true;
+                    continue;
+                }
+                contents.subdirs += 1;
+                stats.dirs_scanned += 1;
+                if let Some(progress) = &config.progress {
+                    progress.dirs_scanned.fetch_add(1, Ordering::Relaxed);
+                }
+                let size = if config.disk_usage {
+                    block_size
+                } else {
+                    logical_size
+                };
+                if config.include_dirs
+                    && !config.count_only
+                    && !followed
+                    && !config.is_too_shallow(dir)
+                    && config.size_in_range(size)
+                    && config.mtime_in_range(modified)
+                    && (hidden || config.hidden != HiddenFiles::Only)
+                {
+                    let dir_entry = FileEntry {
+                        size,
+                        path: entry.path.clone(),
+                        modified: Some(modified),
+                        accessed: Some(accessed),
+                        hash: None,
+                        sparseness: 0.0,
+                        checksum: None,
+                        is_reflinked: false,
+                        physical_size: 0,
+                        allocated_size: block_size,
+                        xattr_size: 0,
+                        nlink: 0,
+                        mode: 0,
+                        is_dir: true,
+                        verified: None,
+                    };
+                    config.send_file(&dir_entry);
+                    shared.local_top_n(config).insert(dir_entry);
+                }
+                subdirs.push(QueuedDir {
+                    path: entry.path,
+                    device,
+                    inode,
+                    hidden,
+                    depth: dir.depth + 1,
+                    ignores: ignores.clone(),
+                });
+            }
+            EntryMetadata::Symlink | EntryMetadata::Other(_) => {
+                // Unfollowed symlinks, devices, etc. - ignore
+            }
+        }
+    }
+
+    if config.report_empty_dirs {
+        stats.dir_contents.insert(dir.path.clone(), contents);
+    }
+    Ok(())
+}
+
+/// Entry classification result
+enum EntryMetadata {
+    RegularFile {
+        /// Apparent size (`st_size`)
+        logical_size: u64,
+        /// Allocated size (`st_blocks * 512`)
+        block_size: u64,
+        device: u64,
+        inode: u64,
+        nlink: u64,
+        uid: u32,
+        gid: u32,
+        /// `st_mode`, including the file type bits
+        mode: u32,
+        modified: SystemTime,
+        accessed: SystemTime,
+    },
+    Directory {
+        device: u64,
+        inode: u64,
+        /// `st_size`, the space taken by the entries themselves
+        logical_size: u64,
+        block_size: u64,
+        modified: SystemTime,
+        accessed: SystemTime,
+    },
+    Symlink,
+    /// Devices, sockets and FIFOs, which Windows listings never hold
+    #[cfg_attr(windows, allow(dead_code))]
+    Other(SpecialFile),
+}
+
+/// The type of an `EntryMetadata::Other`
+#[cfg_attr(windows, allow(dead_code))]
+#[derive(Debug, Clone, Copy)]
+enum SpecialFile {
+    Socket,
+    Fifo,
+    BlockDevice,
+    CharDevice,
+    /// A type the platform reports that is none of the others
+    Unknown,
+}
+
+/// Classify an entry with a single statx() call, using d_type as hint but not guarantee.
+/// With `follow` set the symlink itself is resolved and its target classified.
+/// `parent` is the directory's descriptor, which `scan_listing` opens once and
+/// shares between all of its entries, so no entry costs an `open` of its own.
+#[cfg(unix)]
+fn classify_entry(parent: BorrowedFd<'_>, name: &OsStr, follow: bool) -> io::Result<EntryMetadata> {
+    let _span = tracing::trace_span!("classify_entry", name = ?name, follow).entered();
+
+    // Single statx() call - never retry, don't follow symlinks unless asked to
+    let flags = if follow {
+        AtFlags::empty()
+    } else {
+        AtFlags::SYMLINK_NOFOLLOW
+    };
+    let stat = statat(parent, name, flags)?;
+
+    let file_type = FileType::from_raw_mode(stat.st_mode as rustix::fs::RawMode);
+
+    let result = if file_type == FileType::RegularFile {
+        EntryMetadata::RegularFile {
+            logical_size: stat.st_size as u64,
+            block_size: (stat.st_blocks as u64).saturating_mul(512),
+            device: stat.st_dev as u64,
+            inode: stat.st_ino as u64,
+            nlink: stat.st_nlink as u64,
+            uid: stat.st_uid,
+            gid: stat.st_gid,
+            mode: stat.st_mode,
+            modified: system_time(stat.st_mtime as i64, stat.st_mtime_nsec as i64),
+            accessed: system_time(stat.st_atime as i64, stat.st_atime_nsec as i64),
+        }
+    } else if file_type == FileType::Directory {
+        EntryMetadata::Directory {
+            device: stat.st_dev as u64,
+            inode: stat.st_ino as u64,
+            logical_size: stat.st_size as u64,
+            block_size: (stat.st_blocks as u64).saturating_mul(512),
+            modified: system_time(stat.st_mtime as i64, stat.st_mtime_nsec as i64),
+            accessed: system_time(stat.st_atime as i64, stat.st_atime_nsec as i64),
+        }
+    } else if file_type == FileType::Symlink {
+        EntryMetadata::Symlink
+    } else {
+        EntryMetadata::Other(match file_type {
+            FileType::Socket => SpecialFile::Socket,
+            FileType::Fifo => SpecialFile::Fifo,
+            FileType::BlockDevice => SpecialFile::BlockDevice,
+            FileType::CharacterDevice => SpecialFile::CharDevice,
+            _ => SpecialFile::Unknown,
+        })
+    };
+
+    Ok(result)
+}
+
+/// Classify an entry from the information of a handle opened on it, which
+/// resolves the link when `follow` is set
+#[cfg(windows)]
+fn classify_entry(parent: &Path, name: &OsStr, follow: bool) -> io::Result<EntryMetadata> {
+    let _span = tracing::trace_span!("classify_entry", name = ?name, follow).entered();
+    windows::stat(&parent.join(name), follow)
+}
+
+/// Convert a stat timestamp (seconds and nanoseconds since the epoch) to `SystemTime`
+fn system_time(secs: i64, nsecs: i64) -> SystemTime {
+    let nsecs = Duration::from_nanos(nsecs.clamp(0, 999_999_999) as u64);
+    if secs >= 0 {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs as u64) + nsecs
+    } else {
+        SystemTime::UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs()) + nsecs
+    }
+}
+
+/// The outermost directories under `root` without a regular file beneath them,
+/// shallowest first: a count-only scan with `ScanConfig::report_empty_dirs`
+pub fn find_empty_dirs(root: &Path) -> Vec<EmptyDirReport> {
+    let mut config = ScanConfig::new(root);
+    config.count_only = true;
+    config.report_empty_dirs = true;
+    parallel_scan(&config).1.empty_dirs
+}
+
+/// Rank the files at `paths` rather than scanning a tree, each classified as if
+/// found listing its directory, so the filters apply and the statistics count
+/// them alike. Directories among them are neither descended into nor counted,
+/// and `config.roots` only serves to report the filesystems. The directories
+/// holding the files are handled in parallel, each opened once.
+pub fn rank_files(paths: &[PathBuf], config: &ScanConfig) -> (Option<Vec<FileEntry>>, ScanStats) {
+    let start = Instant::now();
+    let shared = SharedState::new(config);
+    let mut stats = ScanStats::default();
+    let mut dirs: HashMap<&Path, Vec<OsString>> = HashMap::new();
+    for path in paths {
+        match (path.parent(), path.file_name()) {
+            (Some(dir), Some(name)) => dirs.entry(dir).or_default().push(name.to_os_string()),
+            _ => {
+                let source = io::Error::new(io::ErrorKind::InvalidInput, "not a file");
+                stats.record_error(
+                    config,
+                    ScanError::StatFailed {
+                        path: path.clone(),
+                        source,
+                    },
+                );
+            }
+        }
+    }
+    let mut dirs: Vec<_> = dirs.into_iter().collect();
+    dirs.sort();
+
+    let stats_vec: Vec<_> = dirs
+        .into_par_iter()
+        .map_init(ScanStats::default, |stats, (path, mut names)| {
+            names.sort();
+            names.dedup();
+            let dir = QueuedDir {
+                path: path.to_path_buf(),
+                device: 0,
+                inode: 0,
+                hidden: false,
+                depth: 0,
+                ignores: None,
+            };
+            let listing = Ok(names.into_iter().map(Ok));
+            // A file named without a directory is in the current one
+            let here = if path.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                path
+            };
+            let mut subdirs = Vec::new();
+            #[cfg(unix)]
+            let result = match open_dir(here) {
+                Ok(fd) => {
+                    let parent = Some(fd.as_fd());
+                    scan_listing(
+                        &dir,
+                        listing,
+                        parent,
+                        None,
+                        config,
+                        &shared,
+                        stats,
+                        &mut subdirs,
+                        false,
+                    )
+                }
+                Err(source) => Err(ScanError::ReadDir {
+                    path: dir.path.clone(),
+                    source,
+                }),
+            };
+            #[cfg(windows)]
+            let result = scan_listing(
+                &dir,
+                listing,
+                Some(here),
+                None,
+                config,
+                &shared,
+                stats,
+                &mut subdirs,
+                false,
+            );
+            if let Err(e) = result {
+                stats.record_dir_error(config, e);
+            }
+            shared.add_errors(config, stats.errors);
+            std::mem::take(stats)
+        })
+        .collect();
+    for other in stats_vec {
+        stats.merge(other);
+    }
+    finish_scan(config, shared, stats, start)
+}
+
+/// Parallel directory traversal using level-by-level BFS with a top-N tracker
+/// per thread, merged at the end.
+/// Returns `None` for the entries when `config.count_only` is set.
+pub fn parallel_scan(config: &ScanConfig) -> (Option<Vec<FileEntry>>, ScanStats) {
+    scan_levels(config, |dir, shared, stats, subdirs, descend| {
+        scan_directory(dir, config, shared, stats, subdirs, descend, None)
+    })
+}
+
+/// The same level-by-level scan as [`parallel_scan`], with the `statx` calls for
+/// each directory's entries submitted together through an io_uring per thread
+/// rather than made one by one, saving context switches on low-latency storage
+/// such as NVMe. Followed symlinks are still re-stat'ed one at a time. Given the
+/// same filesystem state, results and statistics are identical to
+/// `parallel_scan`'s.
+///
+/// Fails before scanning anything when no ring can be set up, with `ENOSYS` on
+/// kernels without io_uring. A thread that cannot set up its own ring later
+/// stats its entries one by one.
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+pub fn io_uring_scan(config: &ScanConfig) -> io::Result<(Option<Vec<FileEntry>>, ScanStats)> {
+    drop(uring::StatRing::new()?);
+    let rings = ThreadLocal::new();
+    Ok(scan_levels(
+        config,
+        |dir, shared, stats, subdirs, descend| {
+            let ring = rings.get_or(|| uring::StatRing::new().ok().map(RefCell::new));
+            let mut ring = ring.as_ref().map(RefCell::borrow_mut);
+            let batch = ring.as_deref_mut().map(|ring| ring as &mut dyn StatBatch);
+            scan_directory(dir, config, shared, stats, subdirs, descend, batch)
+        },
+    ))
+}
+
+/// The level-by-level scan of `parallel_scan`, scanning each directory with
+/// `scan_dir`
+fn scan_levels(
+    config: &ScanConfig,
+    scan_dir: impl Fn(
+        &QueuedDir,
+        &SharedState,
+        &mut ScanStats,
+        &mut Vec<QueuedDir>,
+        bool,
+    ) -> Result<(), ScanError>
+    + Sync,
+) -> (Option<Vec<FileEntry>>, ScanStats) {
+    let start = Instant::now();
+    let global_stats = Mutex::new(ScanStats::default());
+    let shared = SharedState::new(config);
+    let (mut work_queue, mut visited) = queue_roots(config);
+    let mut depth = 0;
+    #[cfg(feature = "adaptive-threads")]
+    let mut tuner = config.auto_threads.then(adaptive::ThreadTuner::start);
+    #[cfg(feature = "adaptive-threads")]
+    let load = tuner.as_ref().map(adaptive::ThreadTuner::load);
+
+    while !work_queue.is_empty() {
+        let descend = config.max_depth.is_none_or(|max| depth < max);
+        tracing::info!(depth, queue = work_queue.len(), "scanning level");
+
+        // Next level queue; lock-free so sibling scans never wait on each other
+        let next_queue = SegQueue::new();
+
+        // Process current level of directories in parallel
+        let scan_level = |dirs: &[QueuedDir]| -> Vec<ScanStats> {
+            dirs.par_iter()
+                .map_init(ScanStats::default, |stats, dir| {
+                    let mut subdirs = Vec::new();
+
+                    // Scan this directory atomically, inserting into this thread's top-N
+                    if let Err(e) = scan_dir(dir, &shared, stats, &mut subdirs, descend) {
+                        stats.record_dir_error(config, e);
+                    }
+                    if let Some(progress) = &config.progress {
+                        progress.dirs_done.fetch_add(1, Ordering::Relaxed);
+                    }
+
+                    shared.add_errors(config, stats.errors);
+
+                    // Add subdirectories to next level
+                    for subdir in subdirs {
+                        next_queue.push(subdir);
+                    }
+                    #[cfg(feature = "adaptive-threads")]
+                    if let Some(load) = &load {
+                        load.dir_done();
+                    }
+
+                    std::mem::take(stats)
+                })
+                .collect()
+        };
+        #[cfg(feature = "adaptive-threads")]
+        let stats_vec = match &mut tuner {
+            Some(tuner) => tuner.run_level(&work_queue, scan_level),
+            None => scan_level(&work_queue),
+        };
+        #[cfg(not(feature = "adaptive-threads"))]
+        let stats_vec = scan_level(&work_queue);
+
+        // Aggregate stats
+        let mut global = global_stats.lock().unwrap();
+        for stats in stats_vec {
+            global.merge(stats);
+        }
+        tracing::debug!(
+            depth,
+            files = global.files_scanned,
+            dirs = global.dirs_scanned,
+            errors = global.errors,
+            "level done"
+        );
+        drop(global);
+
+        if shared.aborted.load(Ordering::Relaxed) {
+            tracing::warn!(depth, "too many errors, stopping the scan");
+            break;
+        }
+
+        // Move to next level
+        let queued = std::iter::from_fn(|| next_queue.pop()).collect();
+        work_queue = next_level(
+            config,
+            queued,
+            &mut visited,
+            &mut global_stats.lock().unwrap(),
+        );
+        depth += 1;
+    }
+
+    let stats = global_stats.into_inner().unwrap();
+    #[cfg(feature = "adaptive-threads")]
+    let stats = ScanStats {
+        auto_threads: tuner.map_or(0, |tuner| tuner.finish() as u64),
+        ..stats
+    };
+    finish_scan(config, shared, stats, start)
+}
+
+/// The same level-by-level scan as [`parallel_scan`] on a tokio runtime, for
+/// storage where threads blocked in syscalls limit throughput: directories are
+/// listed with `tokio::fs::read_dir` and their entries classified (the `statat`
+/// calls) in `spawn_blocking` tasks. Given the same filesystem state, results
+/// and statistics are identical to `parallel_scan`'s.
+#[cfg(feature = "async")]
+pub async fn async_scan(config: ScanConfig) -> (Option<Vec<FileEntry>>, ScanStats) {
+    let start = Instant::now();
+    let config = Arc::new(config);
+    let shared = Arc::new(SharedState::new(&config));
+    let (mut work_queue, mut visited) = queue_roots(&config);
+    let mut global = ScanStats::default();
+    let mut depth = 0;
+
+    while !work_queue.is_empty() {
+        let descend = config.max_depth.is_none_or(|max| depth < max);
+        tracing::info!(depth, queue = work_queue.len(), "scanning level");
+
+        let tasks: Vec<_> = work_queue
+            .into_iter()
+            .map(|dir| {
+                let config = Arc::clone(&config);
+                let shared = Arc::clone(&shared);
+                tokio::spawn(async move {
+                    let listing = list_dir_async(&dir.path).await;
+                    tokio::task::spawn_blocking(move || {
+                        let mut stats = ScanStats::default();
+                        let mut subdirs = Vec::new();
+                        let listing = listing.map(Vec::into_iter);
+                        if let Err(e) = scan_listing(
+                            &dir,
+                            listing,
+                            None,
+                            None,
+                            &config,
+                            &shared,
+                            &mut stats,
+                            &mut subdirs,
+                            descend,
+                        ) {
+                            stats.record_dir_error(&config, e);
+                        }
+                        if let Some(progress) = &config.progress {
+                            progress.dirs_done.fetch_add(1, Ordering::Relaxed);
+                        }
+                        shared.add_errors(&config, stats.errors);
+                        (stats, subdirs)
+                    })
+                    .await
+                    .expect("directory scan panicked")
+                })
+            })
+            .collect();
+
+        let mut queued = Vec::new();
+        for task in tasks {
+            let (stats, subdirs) = task.await.expect("directory scan panicked");
+            global.merge(stats);
+            queued.extend(subdirs);
+        }
+        tracing::debug!(
+            depth,
+            files = global.files_scanned,
+            dirs = global.dirs_scanned,
+            errors = global.errors,
+            "level done"
+        );
+
+        if shared.aborted.load(Ordering::Relaxed) {
+            tracing::warn!(depth, "too many errors, stopping the scan");
+            break;
+        }
+        work_queue = next_level(&config, queued, &mut visited, &mut global);
+        depth += 1;
+    }
+
+    let shared = Arc::into_inner(shared).expect("every directory scan has finished");
+    finish_scan(&config, shared, global, start)
+}
+
+/// List a directory like `fs::read_dir` does. An error ends the listing, as it
+/// ends std's iterator.
+#[cfg(feature = "async")]
+async fn list_dir_async(path: &Path) -> io::Result<Vec<io::Result<OsString>>> {
+    let mut read_dir = tokio::fs::read_dir(path).await?;
+    let mut entries = Vec::new();
+    loop {
+        match read_dir.next_entry().await {
+            Ok(Some(entry)) => entries.push(Ok(entry.file_name())),
+            Ok(None) => break,
+            Err(e) => {
+                entries.push(Err(e));
+                break;
+            }
+        }
+    }
+    Ok(entries)
+}
+
+impl SharedState {
+    fn new(config: &ScanConfig) -> Self {
+        let keeps_files =
+            !config.count_only && config.largest_dirs.is_none() && config.top_dirs.is_none();
+        let capacity = if keeps_files { config.top } else { 0 };
+        SharedState {
+            top_n: ThreadLocal::new(),
+            capacity,
+            hardlinks: HardlinkMap::default(),
+            inodes: Mutex::new(TopNTracker::new(config.inode_usage.unwrap_or(0))),
+            errors: AtomicU64::new(0),
+            aborted: AtomicBool::new(false),
+            min_ranked_size: AtomicU64::new(0),
+        }
+    }
+
+    /// The calling thread's tracker in `top_n`
+    fn local_top_n(&self, config: &ScanConfig) -> RefMut<'_, TopNTracker> {
+        self.top_n
+            .get_or(|| RefCell::new(config.thread_tracker(self.capacity)))
+            .borrow_mut()
+    }
+
+    /// Size below which a file cannot rank: `ScanConfig::prefilter_size` or
+    /// the bound the full trackers set, whichever is larger
+    fn rank_threshold(&self, config: &ScanConfig) -> u64 {
+        config
+            .prefilter_size
+            .max(self.min_ranked_size.load(Ordering::Relaxed))
+    }
+
+    /// Whether the scan is stopping, counting the `pending` errors of the
+    /// current directory; stops it once `ScanConfig::max_errors` is reached
+    fn should_abort(&self, config: &ScanConfig, pending: u64) -> bool {
+        if self.aborted.load(Ordering::Relaxed) {
+            return true;
+        }
+        let Some(max) = config.max_errors else {
+            return false;
+        };
+        let reached = self.errors.load(Ordering::Relaxed) + pending >= max;
+        if reached {
+            self.aborted.store(true, Ordering::Relaxed);
+        }
+        reached
+    }
+
+    /// Count the errors of a finished directory toward `ScanConfig::max_errors`
+    fn add_errors(&self, config: &ScanConfig, count: u64) {
+        if let Some(max) = config.max_errors
+            && count > 0
+            && self.errors.fetch_add(count, Ordering::Relaxed) + count >= max
+        {
+            self.aborted.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// The mounts strictly beneath the roots, each marked with whether the scan
+/// steps over it. Empty if the mount table cannot be read.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn mounts_under_roots(config: &ScanConfig) -> Vec<MountInfo> {
+    use std::os::unix::fs::MetadataExt;
+
+    let table = match mounts::mount_table() {
+        Ok(table) => table,
+        Err(e) => {
+            tracing::warn!(error = %e, "cannot read the mount table");
+            return Vec::new();
+        }
+    };
+    // Mount points are absolute and free of symlinks
+    let roots: Vec<(PathBuf, u64)> = config
+        .roots
+        .iter()
+        .filter_map(|root| {
+            let root = fs::canonicalize(root).ok()?;
+            let device = fs::metadata(&root).ok()?.dev();
+            Some((root, device))
+        })
+        .collect();
+
+    let mut found: Vec<MountInfo> = Vec::new();
+    for (device, mut mount) in table {
+        let Some(&(_, root_device)) = roots
+            .iter()
+            .find(|(root, _)| mount.path != *root && mount.path.starts_with(root))
+        else {
+            continue;
+        };
+        // Mounts come after the mounts holding them
+        mount.skipped = (config.same_filesystem && device != root_device)
+            || config.is_skipped_mount(device, root_device)
+            || found
+                .iter()
+                .any(|outer| outer.skipped && mount.path.starts_with(&outer.path));
+        found.push(mount);
+    }
+    found
+}
+
+/// The first level of the scan: the deduplicated roots, and the directories
+/// visited so far by (device, inode), only tracked when following symlinks
+fn queue_roots(config: &ScanConfig) -> (Vec<QueuedDir>, HashSet<(u64, u64)>) {
+    // Each root records its own device so --same-filesystem works per root
+    let mut roots = config.roots.clone();
+    roots.sort();
+    roots.dedup();
+    let mut work_queue: Vec<QueuedDir> = roots
+        .into_iter()
+        .map(|path| {
+            #[cfg(unix)]
+            let (device, inode) =
+                rustix::fs::stat(&path).map_or((0, 0), |st| (st.st_dev, st.st_ino));
+            #[cfg(windows)]
+            let (device, inode) = windows::identity(&path).unwrap_or((0, 0));
+            QueuedDir {
+                path,
+                device,
+                inode,
+                hidden: false,
+                depth: 0,
+                ignores: None,
+            }
+        })
+        .collect();
+    let mut visited = HashSet::new();
+    if config.follow_symlinks {
+        work_queue.retain(|dir| visited.insert((dir.device, dir.inode)));
+    }
+    (work_queue, visited)
+}
+
+/// Turn the subdirectories found on one level into the next level's work queue.
+/// When following symlinks a directory may be reachable by several paths; keep
+/// the lexicographically smallest unvisited one so the result does not depend
+/// on thread scheduling.
+fn next_level(
+    config: &ScanConfig,
+    mut queued: Vec<QueuedDir>,
+    visited: &mut HashSet<(u64, u64)>,
+    stats: &mut ScanStats,
+) -> Vec<QueuedDir> {
+    if config.follow_symlinks {
+        queued.sort_by(|a, b| a.path.cmp(&b.path));
+        let before = queued.len() as u64;
+        queued.retain(|dir| visited.insert((dir.device, dir.inode)));
+        let revisits = before - queued.len() as u64;
+        stats.symlink_cycles_detected += revisits;
+        stats.dirs_scanned -= revisits;
+    }
+    queued
+}
+
+/// Everything after the last level: count the deferred hard links, roll up
+/// directory sizes, hash duplicates and sort the reports
+fn finish_scan(
+    config: &ScanConfig,
+    shared: SharedState,
+    mut stats: ScanStats,
+    start: Instant,
+) -> (Option<Vec<FileEntry>>, ScanStats) {
+    stats.aborted = shared.aborted.into_inner();
+    if config.count_file_types {
+        // Counted, even if no directory could be listed
+        stats.file_types.get_or_insert_default();
+    }
+    let mut top_n = config.new_tracker(shared.capacity);
+    for tracker in shared.top_n {
+        top_n.merge(tracker.into_inner());
+    }
+    for (_, (entry, uid)) in shared.hardlinks.into_inner().unwrap() {
+        stats.record_file(config, &entry, uid);
+        tracing::debug!(size = entry.size, path = %entry.path.display(), "found file");
+        config.send_file(&entry);
+        if entry.size >= config.prefilter_size {
+            top_n.insert(entry);
+        }
+    }
+    if let Some(limit) = config.largest_dirs {
+        stats.total_dir_sizes(limit);
+    }
+    if let Some(buckets) = &config.age_buckets {
+        stats
+            .age_buckets
+            .resize(buckets.bounds.len() + 1, AgeBucket::default());
+    }
+    if config.top_dirs.is_some() {
+        stats.top_dirs = sorted_totals(std::mem::take(&mut stats.prefix_totals));
+    }
+    if config.report_empty_dirs {
+        stats.collect_empty_dirs();
+    }
+    stats.zero_sized.sort();
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    if config.report_mounts {
+        stats.mounts = mounts_under_roots(config);
+    }
+    if let Some(baseline) = &config.baseline {
+        let seen = std::mem::take(&mut stats.baseline_seen);
+        stats.removed = baseline
+            .iter()
+            .filter(|(path, _)| !seen.contains(*path))
+            .map(|(path, &size)| FileEntry {
+                size,
+                path: path.clone(),
+                modified: None,
+                accessed: None,
+                hash: None,
+                sparseness: 0.0,
+                checksum: None,
+                is_reflinked: false,
+                physical_size: 0,
+                allocated_size: 0,
+                xattr_size: 0,
+                nlink: 0,
+                mode: 0,
+                is_dir: false,
+                verified: None,
+            })
+            .collect();
+        stats
+            .removed
+            .sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+    }
+    stats.inode_usage = shared.inodes.into_inner().unwrap().into_vec();
+    stats
+        .inode_usage
+        .sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.path.cmp(&b.path)));
+    if config.hash_duplicates {
+        let (groups, errors) = duplicates::find_duplicates(&stats.size_groups);
+        stats.duplicates = groups;
+        for error in errors {
+            stats.record_error(config, error);
+        }
+    }
+    // Threads finish in any order; sort so the lists are deterministic
+    stats.error_details.sort_by(|a, b| a.path().cmp(b.path()));
+    if let Some(max) = config.max_error_details {
+        stats.error_details.truncate(max);
+    }
+    stats.inaccessible_dirs.sort_by(|a, b| a.0.cmp(&b.0));
+    stats
+        .inaccessible_dirs
+        .truncate(config.report_inaccessible.unwrap_or(0));
+    for paths in stats.size_groups.values_mut() {
+        paths.sort();
+    }
+    let results = (!config.count_only).then(|| top_n.into_vec());
+    stats.filesystems = filesystems(config, results.as_deref().unwrap_or_default());
+    stats.elapsed = start.elapsed();
+
+    (results, stats)
+}
+
+/// Filesystem capacity of every root, each with the reported files beneath it;
+/// a file under nested roots counts toward the innermost one
+fn filesystems(config: &ScanConfig, results: &[FileEntry]) -> Vec<FilesystemInfo> {
+    let mut seen = HashSet::new();
+    let mut filesystems: Vec<FilesystemInfo> = config
+        .roots
+        .iter()
+        .filter(|root| seen.insert(*root))
+        .filter_map(|root| match FilesystemInfo::of(root) {
+            Ok(info) => Some(info),
+            Err(e) => {
+                tracing::debug!(path = %root.display(), error = %e, "statvfs failed");
+                None
+            }
+        })
+        .collect();
+    count_results(&mut filesystems, results);
+    filesystems
+}
+
+/// Set each filesystem's `results_bytes` to the size of `results` beneath its
+/// root, as after a scan reporting `results`; a file under nested roots counts
+/// toward the innermost one
+pub fn count_results(filesystems: &mut [FilesystemInfo], results: &[FileEntry]) {
+    for fs in filesystems.iter_mut() {
+        fs.results_bytes = 0;
+    }
+    for entry in results {
+        let innermost = filesystems
+            .iter_mut()
+            .filter(|fs| entry.path.starts_with(&fs.root))
+            .max_by_key(|fs| fs.root.components().count());
+        if let Some(fs) = innermost {
+            fs.results_bytes += entry.size;
+        }
+    }
+}
+
+/// Group the files beneath `root` by the directory `depth` levels below it
+/// that holds them (`depth` 1 groups by top-level subdirectory); files higher
+/// up are grouped under their own directory and files outside `root` are left
+/// out. Largest total first.
+pub fn aggregate_by_prefix(entries: &[FileEntry], root: &Path, depth: usize) -> Vec<DirTotal> {
+    let mut totals: HashMap<PathBuf, (u64, u64)> = HashMap::new();
+    for entry in entries {
+        if let Some(prefix) = path_prefix(&entry.path, root, depth) {
+            let total = totals.entry(prefix).or_default();
+            total.0 += entry.size;
+            total.1 += 1;
+        }
+    }
+    sorted_totals(totals)
+}
+
+/// The directory `depth` levels below `root` holding the file at `path`, or
+/// the file's own directory if that is higher up
+fn path_prefix(path: &Path, root: &Path, depth: usize) -> Option<PathBuf> {
+    let dir = path.parent()?.strip_prefix(root).ok()?;
+    let prefix: PathBuf = dir.components().take(depth).collect();
+    // Joining an empty path would add a trailing slash
+    Some(if prefix.as_os_str().is_empty() {
+        root.to_path_buf()
+    } else {
+        root.join(prefix)
+    })
+}
+
+/// (total size, file count) by directory as `DirTotal`s, largest first
+fn sorted_totals(totals: HashMap<PathBuf, (u64, u64)>) -> Vec<DirTotal> {
+    let mut dirs: Vec<DirTotal> = totals
+        .into_iter()
+        .map(|(path, (total_size, file_count))| DirTotal {
+            path,
+            total_size,
+            file_count,
+        })
+        .collect();
+    dirs.sort_by(|a, b| {
+        b.total_size
+            .cmp(&a.total_size)
+            .then_with(|| a.path.cmp(&b.path))
+    });
+    dirs
+}
+
+/// `path` relative to the longest of `roots` that contains it, or unchanged if
+/// none does. A root itself becomes `.`.
+pub fn relative_to_roots(path: &Path, roots: &[PathBuf]) -> PathBuf {
+    let stripped = roots
+        .iter()
+        .filter_map(|root| path.strip_prefix(root).ok())
+        .min_by_key(|rest| rest.components().count());
+    match stripped {
+        Some(rest) if rest.as_os_str().is_empty() => PathBuf::from("."),
+        Some(rest) => rest.to_path_buf(),
+        None => path.to_path_buf(),
+    }
+}
+
+/// How [`format_size_styled`] writes a size
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SizeStyle {
+    /// Two decimals and a spelled-out unit: `1.50 MB`, `512 bytes`
+    #[default]
+    Human,
+    /// As `du -h` prints it, rounding up: `1.5M`, `12K`, `512`. One decimal
+    /// below 10 and none from there, so `sort -h` orders the lines.
+    Du,
+}
+
+/// Format file size in human-readable format
+pub fn format_size(size: u64) -> String {
+    format_size_styled(size, SizeStyle::Human)
+}
+
+/// Format file size in the given style
+pub fn format_size_styled(size: u64, style: SizeStyle) -> String {
+    const KB: u64 = 1024;
+    const MB: u64 = KB * 1024;
+    const GB: u64 = MB * 1024;
+
+    if style == SizeStyle::Du {
+        return format_du_size(size);
+    }
+    if size >= GB {
+        format!("{:.2} GB", size as f64 / GB as f64)
+    } else if size >= MB {
+        format!("{:.2} MB", size as f64 / MB as f64)
+    } else if size >= KB {
+        format!("{:.2} KB", size as f64 / KB as f64)
+    } else {
+        format!("{} bytes", size)
+    }
+}
+
+/// `du -h`'s rendering in integer arithmetic: the smallest unit keeping the
+/// value below 1024, rounded up to tenths below 10 and to units above
+fn format_du_size(size: u64) -> String {
+    const SUFFIXES: [char; 6] = ['K', 'M', 'G', 'T', 'P', 'E'];
+
+    if size < 1024 {
+        return size.to_string();
+    }
+    let size = u128::from(size);
+    let mut exp = 0;
+    while exp + 1 < SUFFIXES.len() && size >= 1024u128.pow(exp as u32 + 2) {
+        exp += 1;
+    }
+    let unit = 1024u128.pow(exp as u32 + 1);
+    let tenths = (size * 10).div_ceil(unit);
+    if tenths < 100 {
+        return format!("{}.{}{}", tenths / 10, tenths % 10, SUFFIXES[exp]);
+    }
+    let whole = size.div_ceil(unit);
+    if whole >= 1024 && exp + 1 < SUFFIXES.len() {
+        // Rounding up reached the next unit
+        return format!("1.0{}", SUFFIXES[exp + 1]);
+    }
+    format!("{}{}", whole, SUFFIXES[exp])
+}
+
+/// Format a timestamp as local time, e.g. `2021-03-14 09:26`
+pub fn format_time(t: SystemTime) -> String {
+    chrono::DateTime::<chrono::Local>::from(t)
+        .format("%Y-%m-%d %H:%M")
+        .to_string()
+}
+
+/// Seconds since the Unix epoch (negative for earlier times)
+pub fn unix_timestamp(t: SystemTime) -> i64 {
+    match t.duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs_f64().ceil() as i64),
+    }
+}
+
+/// Parse a relative duration such as `7 days`, `2 hours`, `90m` or `1 month`.
+/// Months are 30 days and years 365 days.
+pub fn parse_duration_arg(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (digits, unit) = s.split_at(split);
+
+    if digits.is_empty() {
+        return Err(format!("invalid duration '{}': expected a number", s));
+    }
+    let value: u64 = digits
+        .parse()
+        .map_err(|_| format!("invalid duration '{}': number too large", s))?;
+
+    let seconds: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "s" | "sec" | "secs" | "second" | "seconds" => 1,
+        "m" | "min" | "mins" | "minute" | "minutes" => 60,
+        "h" | "hour" | "hours" => 60 * 60,
+        "d" | "day" | "days" => 24 * 60 * 60,
+        "w" | "week" | "weeks" => 7 * 24 * 60 * 60,
+        "mo" | "month" | "months" => 30 * 24 * 60 * 60,
+        "y" | "year" | "years" => 365 * 24 * 60 * 60,
+        "" => {
+            return Err(format!(
+                "invalid duration '{}': missing unit (e.g. 7 days)",
+                s
+            ));
+        }
+        other => {
+            return Err(format!(
+                "invalid duration '{}': unknown unit '{}'",
+                s, other
+            ));
+        }
+    };
+
+    value
+        .checked_mul(seconds)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("invalid duration '{}': value too large", s))
+}
+
+/// Parse a file age such as `365d`: a number of days (`d`), weeks (`w`),
+/// months of 30 days (`m`) or years of 365 days (`y`)
+pub fn parse_age(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let (digits, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()));
+    if digits.is_empty() {
+        return Err(format!("invalid age '{}': expected a number", s));
+    }
+    let value: u64 = digits
+        .parse()
+        .map_err(|_| format!("invalid age '{}': number too large", s))?;
+
+    let days: u64 = match unit.to_ascii_lowercase().as_str() {
+        "d" => 1,
+        "w" => 7,
+        "m" => 30,
+        "y" => 365,
+        "" => return Err(format!("invalid age '{}': missing unit (d, w, m or y)", s)),
+        other => {
+            return Err(format!(
+                "invalid age '{}': unknown unit '{}' (d, w, m or y)",
+                s, other
+            ));
+        }
+    };
+
+    value
+        .checked_mul(days * 24 * 60 * 60)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("invalid age '{}': value too large", s))
+}
+
+/// Parse a point in time: either an ISO 8601 date/time (`2024-01-01`,
+/// `2024-01-01T12:00:00`, local time unless an offset is given) or a relative
+/// duration accepted by [`parse_duration_arg`], counted back from now.
+pub fn parse_time_arg(s: &str) -> Result<SystemTime, String> {
+    use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+
+    let s = s.trim();
+    if let Ok(t) = DateTime::parse_from_rfc3339(s) {
+        return Ok(t.into());
+    }
+
+    let naive = [
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%d %H:%M",
+    ]
+    .iter()
+    .find_map(|fmt| NaiveDateTime::parse_from_str(s, fmt).ok())
+    .or_else(|| {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d")
+            .ok()
+            .and_then(|d| d.and_hms_opt(0, 0, 0))
+    });
+    if let Some(naive) = naive {
+        return Local
+            .from_local_datetime(&naive)
+            .earliest()
+            .map(SystemTime::from)
+            .ok_or_else(|| {
+                format!(
+                    "invalid time '{}': does not exist in the local time zone",
+                    s
+                )
+            });
+    }
+
+    let ago =
+        parse_duration_arg(s).map_err(|e| format!("{} (or use a date like 2024-01-01)", e))?;
+    SystemTime::now()
+        .checked_sub(ago)
+        .ok_or_else(|| format!("invalid time '{}': too far in the past", s))
+}
+
+/// Parse a permission test as `find -perm` does: an octal (`0755`) or symbolic
+/// (`u+x,g+x`, `u=rw,go=r`) mode, optionally prefixed by `/` (any of the bits is
+/// set) or `-` (all of the bits are set); without a prefix the bits must match
+/// exactly. Symbolic modes start from no permissions.
+pub fn parse_permissions(s: &str) -> Result<PermFilter, String> {
+    let (kind, spec) = match s.as_bytes().first() {
+        Some(b'/') => (PermMatch::Any, &s[1..]),
+        Some(b'-') => (PermMatch::All, &s[1..]),
+        _ => (PermMatch::Exact, s),
+    };
+    if spec.is_empty() {
+        return Err(format!("invalid permissions '{}': missing mode", s));
+    }
+
+    let mode = if spec.bytes().all(|b| b.is_ascii_digit()) {
+        u32::from_str_radix(spec, 8)
+            .ok()
+            .filter(|&mode| mode <= 0o7777)
+            .ok_or_else(|| format!("invalid permissions '{}': not an octal mode up to 7777", s))?
+    } else {
+        parse_symbolic_mode(spec).map_err(|e| format!("invalid permissions '{}': {}", s, e))?
+    };
+    Ok(PermFilter { mode, kind })
+}
+
+/// Apply comma-separated `[ugoa]*[+-=][rwxst]*` clauses to an empty mode
+fn parse_symbolic_mode(spec: &str) -> Result<u32, String> {
+    let mut mode = 0;
+    for clause in spec.split(',') {
+        let op_at = clause
+            .find(['+', '-', '='])
+            .ok_or_else(|| format!("'{}' has no +, - or =", clause))?;
+        let (who, rest) = clause.split_at(op_at);
+        let (op, perms) = rest.split_at(1);
+
+        // Bits each class may hold: rwx, plus setuid/setgid/sticky
+        let mut class_bits = 0;
+        for c in who.chars() {
+            class_bits |= match c {
+                'u' => 0o4700,
+                'g' => 0o2070,
+                'o' => 0o1007,
+                'a' => 0o7777,
+                _ => return Err(format!("unknown class '{}' (expected u, g, o or a)", c)),
+            };
+        }
+        if who.is_empty() {
+            class_bits = 0o7777;
+        }
+
+        let mut bits = 0;
+        for c in perms.chars() {
+            bits |= match c {
+                'r' => 0o444,
+                'w' => 0o222,
+                'x' => 0o111,
+                's' => 0o6000,
+                't' => 0o1000,
+                _ => {
+                    return Err(format!(
+                        "unknown permission '{}' (expected r, w, x, s or t)",
+                        c
+                    ));
+                }
+            };
+        }
+        bits &= class_bits;
+
+        match op {
+            "+" => mode |= bits,
+            "-" => mode &= !bits,
+            _ => mode = (mode & !class_bits) | bits,
+        }
+    }
+    Ok(mode)
+}
+
+/// Parse a user name or numeric UID into a UID
+#[cfg(unix)]
+pub fn parse_user_arg(s: &str) -> Result<u32, String> {
+    if let Ok(uid) = s.parse() {
+        return Ok(uid);
+    }
+    let name = std::ffi::CString::new(s).map_err(|_| format!("invalid user name '{}'", s))?;
+    let mut buf = vec![0 as libc::c_char; 1024];
+    loop {
+        // SAFETY: passwd is plain data that getpwnam_r fills in; every pointer it
+        // stores points into `buf`, which outlives the read of `pw_uid` below
+        let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut result = std::ptr::null_mut();
+        let rc = unsafe {
+            libc::getpwnam_r(
+                name.as_ptr(),
+                &mut pwd,
+                buf.as_mut_ptr(),
+                buf.len(),
+                &mut result,
+            )
+        };
+        match rc {
+            libc::ERANGE => buf.resize(buf.len() * 2, 0),
+            0 if !result.is_null() => return Ok(pwd.pw_uid),
+            0 => return Err(format!("unknown user '{}'", s)),
+            err => {
+                let err = io::Error::from_raw_os_error(err);
+                return Err(format!("cannot look up user '{}': {}", s, err));
+            }
+        }
+    }
+}
+
+/// The name of the user with this UID, if it has one
+#[cfg(unix)]
+pub fn user_name(uid: u32) -> Option<String> {
+    let mut buf = vec![0 as libc::c_char; 1024];
+    loop {
+        // SAFETY: as in `parse_user_arg`, with `pw_name` read while `buf` is alive
+        let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut result = std::ptr::null_mut();
+        let rc =
+            unsafe { libc::getpwuid_r(uid, &mut pwd, buf.as_mut_ptr(), buf.len(), &mut result) };
+        match rc {
+            libc::ERANGE => buf.resize(buf.len() * 2, 0),
+            0 if !result.is_null() => {
+                let name = unsafe { std::ffi::CStr::from_ptr(pwd.pw_name) };
+                return Some(name.to_string_lossy().into_owned());
+            }
+            _ => return None,
+        }
+    }
+}
+
+/// Parse a group name or numeric GID into a GID
+#[cfg(unix)]
+pub fn parse_group_arg(s: &str) -> Result<u32, String> {
+    if let Ok(gid) = s.parse() {
+        return Ok(gid);
+    }
+    let name = std::ffi::CString::new(s).map_err(|_| format!("invalid group name '{}'", s))?;
+    let mut buf = vec![0 as libc::c_char; 1024];
+    loop {
+        // SAFETY: as in `parse_user_arg`, with `group` filled in by getgrnam_r
+        let mut grp: libc::group = unsafe { std::mem::zeroed() };
+        let mut result = std::ptr::null_mut();
+        let rc = unsafe {
+            libc::getgrnam_r(
+                name.as_ptr(),
+                &mut grp,
+                buf.as_mut_ptr(),
+                buf.len(),
+                &mut result,
+            )
+        };
+        match rc {
+            libc::ERANGE => buf.resize(buf.len() * 2, 0),
+            0 if !result.is_null() => return Ok(grp.gr_gid),
+            0 => return Err(format!("unknown group '{}'", s)),
+            err => {
+                let err = io::Error::from_raw_os_error(err);
+                return Err(format!("cannot look up group '{}': {}", s, err));
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub fn parse_user_arg(_: &str) -> Result<u32, String> {
+    Err("--owner is unsupported on this platform".to_string())
+}
+
+#[cfg(not(unix))]
+pub fn parse_group_arg(_: &str) -> Result<u32, String> {
+    Err("--group is unsupported on this platform".to_string())
+}
+
+#[cfg(not(unix))]
+pub fn user_name(_: u32) -> Option<String> {
+    None
+}
+
+/// Parse a human-readable size such as `4096`, `1K`, `500M` or `2G` into bytes.
+/// Suffixes are binary (1K = 1024 bytes) and case-insensitive.
+pub fn parse_size_arg(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (digits, suffix) = s.split_at(split);
+
+    if digits.is_empty() {
+        return Err(format!("invalid size '{}': expected a number", s));
+    }
+    let value: u64 = digits
+        .parse()
+        .map_err(|_| format!("invalid size '{}': number too large", s))?;
+
+    let multiplier: u64 = match suffix.to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        "T" | "TB" | "TIB" => 1 << 40,
+        _ => {
+            return Err(format!(
+                "invalid size '{}': unknown suffix '{}' (expected K, M, G or T)",
+                s, suffix
+            ));
+        }
+    };
+
+    value
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("invalid size '{}': value overflows 64 bits", s))
+}
@@ -1,315 +1,2852 @@
-use clap::Parser;
-use rayon::prelude::*;
-use rustix::fs::{statat, AtFlags, FileType, Mode};
-use std::collections::VecDeque;
+mod config;
+#[cfg(feature = "serde")]
+mod snapshot_dir;
+
+use bfinder::checksum::{ChecksumAlgorithm, add_checksums};
+#[cfg(feature = "serde")]
+use bfinder::output::render_diff_text;
+use bfinder::output::{
+    OutputFormat, OutputOptions, SizeColors, format_output, render_compare_text, render_removed,
+    render_stats_only, write_json_line, write_scan_errors,
+};
+use bfinder::{
+    AgeBuckets, DirTop, ExtensionFilter, FileEntry, HiddenFiles, MimeCategory, MimeFilter,
+    NameFilter, RegexFilter, ScanConfig, ScanProgress, ScanStats, SortOrder, count_results,
+    format_size, format_time, parallel_scan, parse_age, parse_group_arg, parse_permissions,
+    parse_size_arg, parse_time_arg, parse_user_arg, relative_to_roots, sort_results,
+    weighted_sample,
+};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser};
+use config::BfinderConfig;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use indicatif::{ProgressBar, ProgressStyle};
+use std::collections::{HashMap, HashSet, hash_map};
+use std::ffi::OsString;
 use std::fs;
-use std::io;
-use std::path::{Path, PathBuf};
-use std::sync::Mutex;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, mpsc};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+#[cfg(unix)]
+use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+/// Common invocations, shown after the options in `--help`
+const EXAMPLES: &str = "\
+Examples:
+  bfinder -n 20 /home
+      The 20 largest files under /home
+  bfinder --format json / | jq '.results[0]'
+      The largest file on the system, as JSON
+  bfinder --exclude '**/node_modules' --min-size 1M /projects
+      Files of 1 MiB or more, skipping every node_modules directory
+  bfinder --max-age 7d --format csv . > recent.csv
+      The largest files changed in the last week, as CSV
+  bfinder --largest-dirs 10 --same-filesystem /
+      The 10 largest directories of the root filesystem
+  bfinder --hash-duplicates ~/Pictures
+      Identical files and the space they waste
+  bfinder --watch 60 /var/log
+      Rescan /var/log every minute until Ctrl-C
+  bfinder --compare /opt/app-1.4 /opt/app-1.5
+      Files added, removed or resized between two installs
+  git ls-files -z | bfinder --stdin-paths --null-input
+      The largest files tracked by git";
+
+/// Shown after the examples in `--help`, and as the man page's EXIT STATUS
+const EXIT_STATUS: &str = "\
+Exit status:
+  0  Success
+  1  An error stopped bfinder, --max-errors was reached, or --report-inaccessible
+     found directories that could not be opened
+  2  Invalid usage, or fewer files than --top with --fail-if-fewer
+  3  No file reached --fail-threshold-size";
+
+/// Options after which no top-N of files is reported, leaving nothing for the
+/// `--fail-*` checks to look at
+const RANKLESS_MODES: [&str; 7] = [
+    "count_only",
+    "largest_dirs",
+    "top_dirs",
+    "duplicate_sizes",
+    "hash_duplicates",
+    "inode_usage_only",
+    "watch",
+];
+
+/// Exit status with `--fail-if-fewer` when fewer files than `--top` were found
+const EXIT_FEWER: u8 = 2;
+/// Exit status when no file reaches `--fail-threshold-size`
+const EXIT_BELOW_THRESHOLD: u8 = 3;
 
 #[derive(Parser)]
 #[command(name = "bfinder")]
 #[command(about = "Find the top N largest files with deterministic parallel scanning")]
+#[command(after_help = format!("{}\n\n{}", EXAMPLES, EXIT_STATUS))]
 struct Cli {
-    /// Number of largest files to find
+    /// Number of largest files to find. With --du-compat: show the top N and
+    /// then stop, as `du | sort -h | tail -n N` would
     #[arg(short = 'n', long, default_value = "10")]
     top: usize,
 
-    /// Directory to scan
-    #[arg(default_value = ".")]
-    path: PathBuf,
+    /// Directories to scan (results are combined across all of them); `-` reads
+    /// newline-separated directories from stdin
+    #[arg(default_value = ".", num_args = 1.., value_hint = clap::ValueHint::DirPath)]
+    paths: Vec<PathBuf>,
+
+    /// Paths read from stdin with `-` are NUL-separated (as from `find -print0`)
+    #[arg(long)]
+    null_input: bool,
+
+    /// Rank the files listed on stdin, one per line (or NUL-separated with
+    /// --null-input), instead of scanning directories, e.g. from `git ls-files`
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "paths", "absolute", "min_depth", "git_ignore", "largest_dirs", "top_dirs", "inode_usage",
+            "inode_usage_only", "report_empty_dirs"
+        ]
+    )]
+    stdin_paths: bool,
+
+    /// Print absolute paths by canonicalizing each directory before scanning
+    #[arg(long, conflicts_with = "relative")]
+    absolute: bool,
+
+    /// Print paths relative to the directory they were found under
+    #[arg(long)]
+    relative: bool,
+
+    /// Read defaults from this file instead of `$XDG_CONFIG_HOME/bfinder/config.toml`
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Ignore the configuration file
+    #[arg(long, conflicts_with = "config")]
+    no_config: bool,
+
+    /// Print the completion script for SHELL to stdout and exit, e.g.
+    /// `bfinder --generate-completion fish > ~/.config/fish/completions/bfinder.fish`
+    #[arg(long, value_name = "SHELL")]
+    generate_completion: Option<clap_complete::Shell>,
+
+    /// Print the man page to stdout and exit, e.g. `bfinder --generate-man > bfinder.1`
+    #[arg(long)]
+    generate_man: bool,
+
+    /// Apply the settings of profile NAME from the config file's `[aliases]` table
+    #[arg(short, long, value_name = "NAME", conflicts_with = "no_config")]
+    profile: Option<String>,
 
     /// Number of threads to use (default: number of CPUs)
     #[arg(short = 'j', long)]
     threads: Option<usize>,
-}
 
-/// Represents a file with its size and path for deterministic ordering
-#[derive(Debug, Clone, Eq, PartialEq)]
-struct FileEntry {
-    size: u64,
-    path: PathBuf,
+    /// Experimental: start with a thread per CPU and add threads, up to four
+    /// per CPU, while directories keep waiting for one (the scan waits on
+    /// I/O), removing them while none wait; the final count is in the stats.
+    /// Its threads cannot be pinned with --affinity
+    #[cfg(feature = "adaptive-threads")]
+    #[arg(long, conflicts_with_all = ["threads", "compare", "stdin_paths"])]
+    auto_threads: bool,
+
+    /// Pin the scan threads to these CPU cores (e.g. 0,1,2,3), one thread
+    /// per core unless --threads is given, such as the cores closest to the
+    /// disk on NUMA systems
+    #[cfg(target_os = "linux")]
+    #[arg(
+        long,
+        value_name = "CORE_LIST",
+        value_delimiter = ',',
+        value_parser = parse_core,
+        conflicts_with = "compare"
+    )]
+    #[cfg_attr(feature = "adaptive-threads", arg(conflicts_with = "auto_threads"))]
+    affinity: Vec<usize>,
+
+    /// Scanning backend; `async` (built with the `async` feature) runs on a
+    /// tokio runtime, with --threads sizing both of its pools; `io-uring` (built
+    /// with the `io-uring` feature, Linux only) batches each directory's stat
+    /// calls, falling back to `sync` on kernels without io_uring
+    #[arg(long, value_enum, default_value_t = Engine::Sync)]
+    engine: Engine,
+
+    /// Only consider files at least this large (e.g. 1K, 500M, 2G)
+    #[arg(long, value_parser = parse_size_arg)]
+    min_size: Option<u64>,
+
+    /// Only consider files at most this large (e.g. 1K, 500M, 2G)
+    #[arg(long, value_parser = parse_size_arg)]
+    max_size: Option<u64>,
+
+    /// Don't rank files smaller than this, when the top N are known to be
+    /// larger (e.g. 100M): they are dropped before an entry is built for
+    /// them, but unlike --min-size still count in the statistics
+    #[arg(
+        long,
+        value_name = "SIZE",
+        value_parser = parse_size_arg,
+        conflicts_with_all = ["find_smallest", "random_sample", "count_only"]
+    )]
+    prefilter_size: Option<u64>,
+
+    /// Only consider files modified within this period (e.g. "7 days") or since a date
+    #[arg(long, value_name = "TIME", value_parser = parse_time_arg)]
+    newer_than: Option<SystemTime>,
+
+    /// Only consider files last modified longer ago than this (e.g. "1 month") or before a date
+    #[arg(long, value_name = "TIME", value_parser = parse_time_arg)]
+    older_than: Option<SystemTime>,
+
+    /// Only consider files not modified for at least this long: Nd, Nw, Nm (30
+    /// days) or Ny (365 days), e.g. 365d; the same bound as --older-than
+    #[arg(long, value_name = "AGE", value_parser = parse_age, conflicts_with = "older_than")]
+    min_age: Option<Duration>,
+
+    /// Only consider files modified at most this long ago, e.g. 2w; the same
+    /// bound as --newer-than
+    #[arg(long, value_name = "AGE", value_parser = parse_age, conflicts_with = "newer_than")]
+    max_age: Option<Duration>,
+
+    /// Only consider files with this extension, e.g. `log` or `.tar.gz` (repeatable)
+    #[arg(long = "extension", short = 'e', value_name = "EXT")]
+    extensions: Vec<String>,
+
+    /// Only consider files without an extension (combines with --extension)
+    #[arg(long)]
+    no_extension: bool,
+
+    /// Only consider files whose name matches this regular expression, e.g.
+    /// `\.log\.\d+$`; repeat to match any of several. `(?i)` ignores case.
+    #[arg(long, value_name = "PATTERN")]
+    regex: Vec<String>,
+
+    /// Leave out files whose name matches this regular expression (repeatable)
+    #[arg(long, value_name = "PATTERN")]
+    not_regex: Vec<String>,
+
+    /// Match --regex and --not-regex against the full path instead of the name
+    #[arg(long)]
+    regex_path: bool,
+
+    /// Only consider files whose name matches this shell pattern, as with
+    /// `find -name`: `*` and `?` wildcards and `[...]` classes. Repeat to match
+    /// any of several.
+    #[arg(long, value_name = "GLOB")]
+    name: Vec<String>,
+
+    /// Like --name, ignoring case
+    #[arg(long, value_name = "GLOB")]
+    iname: Vec<String>,
+
+    /// Only consider files whose contents (first 16 bytes) are of this MIME
+    /// type, whatever their name (repeatable). Every file passing the other
+    /// filters is read, so narrow the scan with --min-size where possible.
+    #[arg(long, value_name = "TYPE")]
+    mime_type: Vec<String>,
+
+    /// Only consider files whose contents are of a type in this group
+    /// (repeatable; combines with --mime-type)
+    #[arg(long, value_enum, value_name = "CATEGORY")]
+    mime_category: Vec<MimeCategory>,
+
+    /// Only consider files whose permissions match, as with `find -perm`: octal
+    /// (`644`) or symbolic (`u+x`), prefixed by `/` for any bit or `-` for all bits
+    #[arg(long, value_name = "MODE", allow_hyphen_values = true, value_parser = parse_permissions)]
+    permissions: Option<bfinder::PermFilter>,
+
+    /// Only consider setuid files, and show each file's permissions; with
+    /// --sgid-files or --world-writable, files with any of those bits
+    #[cfg(unix)]
+    #[arg(long)]
+    suid_files: bool,
+
+    /// Only consider setgid files, and show each file's permissions
+    #[cfg(unix)]
+    #[arg(long)]
+    sgid_files: bool,
+
+    /// Only consider files anyone may write to, and show each file's permissions
+    #[cfg(unix)]
+    #[arg(long)]
+    world_writable: bool,
+
+    /// Only consider files with at least N hard links, such as the unchanged
+    /// files of rsnapshot-style backups, and show the link count
+    #[arg(long, value_name = "N")]
+    min_hardlinks: Option<u64>,
+
+    /// Only consider files with at most N hard links (1 for files no backup
+    /// shares), and show the link count
+    #[arg(long, value_name = "N")]
+    max_hardlinks: Option<u64>,
+
+    /// Read each reported file's extent map to find reflinked (copy-on-write
+    /// shared) files on Btrfs and XFS, and show the disk cost without the
+    /// extents they share
+    #[cfg(target_os = "linux")]
+    #[arg(long)]
+    reflink_detect: bool,
+
+    /// Stat each reported file again after the scan and mark those that changed
+    /// size ([CHANGED]) or were removed ([DELETED]) while it ran
+    #[arg(long)]
+    verify: bool,
+
+    /// Like --verify, then rank the files by the sizes they have now, leaving
+    /// out removed ones
+    #[arg(long)]
+    verify_strict: bool,
+
+    /// Hash each reported file after the scan and show the checksum after its path
+    #[arg(long, value_enum, value_name = "ALGO")]
+    checksum: Option<ChecksumAlgorithm>,
+
+    /// Only consider sparse files, whose holes make up at least --sparse-threshold
+    /// of their size, and show that share; apparent size is compared with
+    /// allocated blocks
+    #[arg(long)]
+    sparse: bool,
+
+    /// Share of a file's size that must be holes for --sparse, from 0 to 1
+    #[arg(long, value_name = "RATIO", default_value_t = 0.5, requires = "sparse", value_parser = parse_ratio)]
+    sparse_threshold: f32,
+
+    /// Also list the files inside .tar, .tar.gz, .tar.bz2, .tar.xz and .zip
+    /// archives, with paths like `archive.tar::dir/file`. The archive itself
+    /// must pass --owner, --group, --permissions, --only-hidden, --min-depth
+    /// and --sparse; its members are then filtered by size, time and name.
+    #[cfg(feature = "archives")]
+    #[arg(long)]
+    scan_archives: bool,
+
+    /// Like --scan-archives, and also list archives inside archives, at most
+    /// DEPTH levels deep
+    #[cfg(feature = "archives")]
+    #[arg(long, value_name = "DEPTH", num_args = 0..=1, default_missing_value = "3")]
+    scan_archives_recursive: Option<usize>,
+
+    /// Only consider files owned by this user name or UID (repeatable, any may match)
+    #[arg(long, value_name = "USER", value_parser = parse_user_arg)]
+    owner: Vec<u32>,
+
+    /// Only consider files belonging to this group name or GID (repeatable, any may match)
+    #[arg(long, value_name = "GROUP", value_parser = parse_group_arg)]
+    group: Vec<u32>,
+
+    /// Skip directories whose path or name matches this glob (repeatable)
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+
+    /// Read exclude globs from a file, one per line (`#` starts a comment)
+    #[arg(long, value_name = "FILE")]
+    exclude_from: Vec<PathBuf>,
+
+    /// Skip what .gitignore files ignore, with nested files taking precedence,
+    /// as well as .git/info/exclude, the global excludes file and .git itself
+    #[arg(long)]
+    git_ignore: bool,
+
+    /// Respect no .gitignore files, overriding --git-ignore (from a config file)
+    #[arg(long)]
+    no_ignore: bool,
+
+    /// Skip hidden files and directories (names starting with `.`) and everything in them
+    #[arg(long, conflicts_with = "only_hidden")]
+    no_hidden: bool,
+
+    /// Only consider hidden files, including any file inside a hidden directory
+    #[arg(long)]
+    only_hidden: bool,
+
+    /// Maximum directory depth to descend (0 scans only the given directories)
+    #[arg(long, value_name = "N")]
+    max_depth: Option<usize>,
+
+    /// Only report files at least N directories below the given ones (1 skips
+    /// the files directly in them); shallower directories are still scanned
+    #[arg(long, value_name = "N")]
+    min_depth: Option<usize>,
+
+    /// Stay on the filesystem of each root: skip directories whose device
+    /// number (`st_dev` from stat) differs from the root's, i.e. mount points
+    #[arg(short = 'x', long)]
+    same_filesystem: bool,
+
+    /// List the filesystems mounted beneath the scanned paths with the
+    /// statistics, and which of them are skipped (by --same-filesystem or as
+    /// virtual filesystems)
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    #[arg(long)]
+    follow_mounts: bool,
+
+    /// Descend into /proc, /sys and /dev mounts too, which are skipped by default
+    /// (by filesystem type, wherever they are mounted); the --skip-* flags still
+    /// apply
+    #[cfg(target_os = "linux")]
+    #[arg(long)]
+    no_auto_skip: bool,
+
+    /// Skip proc filesystems even with --no-auto-skip
+    #[cfg(target_os = "linux")]
+    #[arg(long)]
+    skip_proc: bool,
+
+    /// Skip sysfs filesystems even with --no-auto-skip
+    #[cfg(target_os = "linux")]
+    #[arg(long)]
+    skip_sys: bool,
+
+    /// Skip devtmpfs filesystems even with --no-auto-skip
+    #[cfg(target_os = "linux")]
+    #[arg(long)]
+    skip_dev: bool,
+
+    /// Follow symlinks to files and directories, visiting each directory once
+    #[arg(short = 'L', long)]
+    follow_symlinks: bool,
+
+    /// Measure allocated disk usage (st_blocks * 512, like du) instead of apparent size
+    #[arg(short = 'd', long)]
+    disk_usage: bool,
+
+    /// Add the size of each file's extended attributes (names and values) to
+    /// its size, shown as `(+SIZE xattr)`. Costs extra syscalls per file.
+    #[arg(long)]
+    virtual_size: bool,
+
+    /// Count every hard link to a file instead of each inode once
+    #[arg(long)]
+    count_hardlinks: bool,
+
+    /// Find the N smallest files instead of the largest (combine with --min-size 1
+    /// to skip empty files)
+    #[arg(long)]
+    find_smallest: bool,
+
+    /// Report N files picked at random instead of the largest, each with a
+    /// chance proportional to its size, for a rough audit of a huge tree.
+    /// Every file passing the filters is kept until the scan ends.
+    #[arg(
+        long,
+        value_name = "N",
+        conflicts_with_all = [
+            "find_smallest", "exclude_top", "tree", "top_per_dir", "group_by_extension", "count_only",
+            "largest_dirs", "top_dirs", "duplicate_sizes", "hash_duplicates", "inode_usage_only", "compare"
+        ]
+    )]
+    random_sample: Option<usize>,
+
+    /// Seed for --random-sample, so that scanning the same files picks the
+    /// same sample again (random if not given)
+    #[arg(long, value_name = "SEED", requires = "random_sample")]
+    sort_seed: Option<u64>,
+
+    /// Only count files and directories; don't collect or list any files
+    #[arg(long)]
+    count_only: bool,
+
+    /// Count the symlinks, sockets, FIFOs and block and character devices
+    /// found, shown with the statistics (also with --verbose)
+    #[arg(long)]
+    count_file_types: bool,
+
+    /// Print only the total size of the results and their share of all the
+    /// files that passed the filters, without listing the files; with
+    /// --group-by-extension, only the extension totals
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "count_only", "tree", "top_per_dir", "top_per_user", "largest_dirs", "top_dirs", "duplicate_sizes",
+            "hash_duplicates", "inode_usage", "report_empty_dirs", "zero_sized", "zero_sized_count", "age_buckets"
+        ]
+    )]
+    summarize: bool,
+
+    /// Report total size and count per file extension instead of individual files;
+    /// --top then limits the number of extensions shown
+    #[arg(long)]
+    group_by_extension: bool,
+
+    /// Report the N extensions with the largest total size, with their file
+    /// counts; the same as --group-by-extension --top N
+    #[arg(
+        long,
+        value_name = "N",
+        conflicts_with_all = [
+            "group_by_extension", "top", "random_sample", "count_only", "top_per_dir", "top_per_user",
+            "largest_dirs", "top_dirs", "duplicate_sizes", "hash_duplicates", "inode_usage", "exclude_top", "tree"
+        ]
+    )]
+    top_extensions: Option<usize>,
+
+    /// Show the N largest files of each directory, grouped by directory
+    #[arg(long, value_name = "N")]
+    top_per_dir: Option<usize>,
+
+    /// Show the N largest files of each user, users with the most space first
+    #[arg(
+        long,
+        value_name = "N",
+        conflicts_with_all = ["count_only", "group_by_extension", "top_per_dir", "largest_dirs"]
+    )]
+    top_per_user: Option<usize>,
+
+    /// Show each --top-per-user user's quota on the quota-enabled filesystems
+    /// (from /etc/mtab) holding the scanned paths. Needs root or CAP_SYS_ADMIN
+    /// to read other users' quotas.
+    #[cfg(target_os = "linux")]
+    #[arg(long, requires = "top_per_user")]
+    quota_report: bool,
+
+    /// Show users by name with --top-per-user; `false` shows numeric UIDs
+    #[arg(long, value_name = "BOOL", default_value_t = true, action = clap::ArgAction::Set)]
+    resolve_names: bool,
+
+    /// Leave out the N largest files (the N smallest with --find-smallest) and
+    /// report the ones ranked after them. Scans twice: once to find the
+    /// outliers, then again for the files beneath them.
+    #[arg(
+        long,
+        value_name = "N",
+        conflicts_with_all = [
+            "count_only", "group_by_extension", "top_per_dir", "duplicate_sizes", "hash_duplicates"
+        ]
+    )]
+    exclude_top: Option<usize>,
+
+    /// Show the N directories with the largest total size, counting everything
+    /// beneath them, instead of individual files
+    #[arg(
+        long,
+        value_name = "N",
+        conflicts_with_all = [
+            "top", "find_smallest", "count_only", "group_by_extension", "top_per_dir", "exclude_top"
+        ]
+    )]
+    largest_dirs: Option<usize>,
+
+    /// Show the total size and file count beneath each directory DEPTH levels
+    /// below the roots (1 if not given), largest first, like `du -d DEPTH`
+    #[arg(
+        long,
+        value_name = "DEPTH",
+        num_args = 0..=1,
+        default_missing_value = "1",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..),
+        conflicts_with_all = [
+            "top", "find_smallest", "count_only", "group_by_extension", "top_per_dir", "exclude_top",
+            "largest_dirs"
+        ]
+    )]
+    top_dirs: Option<usize>,
+
+    /// Print every error (unreadable directory, failed stat, ...) to stderr
+    /// after the scan instead of only counting them
+    #[arg(long)]
+    verbose_errors: bool,
+
+    /// List the errors with their paths after the report, or under
+    /// `scan_errors` in JSON, to check that no part of the tree was missed
+    #[arg(long)]
+    report_errors: bool,
+
+    /// Write the --report-errors list to FILE instead of the report
+    #[arg(long, value_name = "FILE")]
+    errors_output: Option<PathBuf>,
+
+    /// Most errors --report-errors lists; any more are only counted
+    #[arg(long, value_name = "N", default_value_t = 1000)]
+    max_reported_errors: usize,
+
+    /// List the directories that could not be opened, with the reason, after
+    /// the report, or under `inaccessible_dirs` in JSON, and exit with status
+    /// 1 if there are any: files beneath them were not ranked
+    #[arg(long)]
+    report_inaccessible: bool,
+
+    /// Most directories --report-inaccessible lists; any more are only counted
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 100,
+        requires = "report_inaccessible"
+    )]
+    max_inaccessible_report: usize,
+
+    /// Exit with status 2 if fewer files than --top are found, for monitoring
+    /// checks
+    #[arg(long, conflicts_with_all = RANKLESS_MODES)]
+    fail_if_fewer: bool,
+
+    /// Exit with status 3 if no file found is at least SIZE
+    #[arg(long, value_name = "SIZE", value_parser = parse_size_arg, conflicts_with_all = RANKLESS_MODES)]
+    fail_threshold_size: Option<u64>,
+
+    /// Stop scanning after N errors, reporting what was found so far and
+    /// exiting with status 1; 0 never stops
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    max_errors: u64,
+
+    /// Log each file found to stderr; repeat (-vv) to also log each directory entered
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Memory the scan threads' top-N lists should fit in together (e.g.
+    /// 512M): each thread reserves room for its share only. A warning says
+    /// when --top files per thread need more, as each thread still keeps that
+    /// many for the results to be exact.
+    #[arg(long, value_name = "BYTES", value_parser = parse_size_arg)]
+    limit_memory: Option<u64>,
+
+    /// Show live progress on stderr while scanning (ignored when stdout is not a terminal)
+    #[arg(long)]
+    progress: bool,
+
+    /// Estimate the time the scan has left from the rate it lists directories
+    /// at and the directories still queued, on stderr every 2 seconds or in
+    /// the --progress line; only a rough guide, as the queue grows while
+    /// directories are found
+    #[arg(long, conflicts_with = "stdin_paths")]
+    estimate_time: bool,
+
+    /// Repeat the scan RUNS times and report timing statistics on stderr; the
+    /// output is that of the last run. Runs after the first hit a warm page cache.
+    #[arg(long, value_name = "RUNS", value_parser = clap::value_parser!(u32).range(1..))]
+    benchmark: Option<u32>,
+
+    /// Report on stderr about how many system calls the scan made, counting
+    /// the stat of each entry and estimating those listing each directory
+    #[arg(long)]
+    syscall_stats: bool,
+
+    /// Rescan every SECS seconds, redrawing the report on a cleared terminal;
+    /// files new to the results are shown in green and those that dropped out
+    /// in red. Stop with Ctrl-C.
+    #[arg(
+        long,
+        value_name = "SECS",
+        value_parser = clap::value_parser!(u64).range(1..),
+        conflicts_with_all = ["benchmark", "output", "null"]
+    )]
+    watch: Option<u64>,
+
+    /// Also list the N directories with the most entries (files, subdirectories,
+    /// anything else), e.g. to track down inode exhaustion
+    #[arg(
+        long,
+        value_name = "N",
+        conflicts_with_all = ["count_only", "group_by_extension", "top_per_dir", "largest_dirs"]
+    )]
+    inode_usage: Option<usize>,
+
+    /// Rank directories alongside the files by their own size, not their
+    /// contents', to find directories swollen by many past entries. They are
+    /// shown with a trailing `/`.
+    #[arg(long, conflicts_with_all = ["count_only", "tree"])]
+    include_dirs: bool,
+
+    /// Also list the directories without a single regular file beneath them,
+    /// shallowest first, as candidates for cleanup
+    #[arg(long)]
+    report_empty_dirs: bool,
+
+    /// Also list every zero-byte file (stale locks, failed downloads), sorted
+    /// by path, whatever --top
+    #[arg(long, conflicts_with = "count_only")]
+    zero_sized: bool,
+
+    /// Like --zero-sized, but give only the number of zero-byte files
+    #[arg(long, conflicts_with_all = ["zero_sized", "count_only"])]
+    zero_sized_count: bool,
+
+    /// Also count the files and their size by age, in bins ending at these
+    /// ages (comma-separated, 1d,7d,30d,90d,365d if not given), to see which
+    /// data is cold
+    #[arg(
+        long,
+        value_name = "AGES",
+        value_parser = parse_age,
+        value_delimiter = ',',
+        num_args = 0..=1,
+        default_missing_value = "1d,7d,30d,90d,365d",
+        conflicts_with = "count_only"
+    )]
+    age_buckets: Option<Vec<Duration>>,
+
+    /// With --inode-usage, list only the directories and no files
+    #[arg(long, requires = "inode_usage")]
+    inode_usage_only: bool,
+
+    /// List groups of non-empty files that share a size, as duplicate candidates,
+    /// most reclaimable space first. Keeps every file in memory.
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "top", "count_only", "group_by_extension", "top_per_dir", "largest_dirs",
+            "inode_usage_only",
+        ]
+    )]
+    duplicate_sizes: bool,
+
+    /// Like --duplicate-sizes, but confirm duplicates by SHA-256 of their contents
+    /// (comparing the first 4 KiB before reading whole files)
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "top", "count_only", "group_by_extension", "top_per_dir", "largest_dirs",
+            "inode_usage_only",
+        ]
+    )]
+    hash_duplicates: bool,
+
+    /// Show the files beneath their directories as a tree; --max-depth then
+    /// limits how deep the tree is printed instead of how deep the scan goes
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "count_only", "group_by_extension", "top_per_dir", "largest_dirs", "inode_usage",
+            "duplicate_sizes", "hash_duplicates", "null",
+        ]
+    )]
+    tree: bool,
+
+    /// Order of the reported files; `blocks` also picks the top N by
+    /// allocated blocks rather than size, showing both
+    #[arg(long, visible_alias = "sort-by", value_enum, default_value_t = SortOrder::Size)]
+    sort: SortOrder,
+
+    /// Hide modification times in text output
+    #[arg(long)]
+    no_time: bool,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+
+    /// Highlight large files in text output; `auto` colors only a terminal and
+    /// honors NO_COLOR
+    #[arg(long, value_enum, value_name = "WHEN", default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+
+    /// Show files at least this large in yellow
+    #[arg(long, value_name = "SIZE", value_parser = parse_size_arg, default_value = "100M")]
+    color_threshold_warn: u64,
+
+    /// Show files at least this large in red
+    #[arg(long, value_name = "SIZE", value_parser = parse_size_arg, default_value = "1G")]
+    color_threshold_error: u64,
+
+    /// Write the results to this file (created or truncated) instead of stdout;
+    /// text statistics then go to stderr
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+
+    /// Print only the paths, each terminated by a NUL byte, for `xargs -0`
+    #[arg(short = '0', long)]
+    null: bool,
+
+    /// Print `SIZE<TAB>PATH` lines with sizes as `du -h` writes them (`12K`,
+    /// `1.5M`), for scripts written around `du`, e.g. `bfinder --du-compat / |
+    /// sort -h`. Sizes are apparent unless --disk-usage is given, the reverse
+    /// of du's default. Works with --largest-dirs.
+    #[arg(long, conflicts_with_all = ["null", "format", "count_only", "watch"])]
+    du_compat: bool,
+
+    /// Scan two trees side by side, each on half of the threads, and list the
+    /// files only in PATH_A (`<`), only in PATH_B (`>`) or sized differently
+    /// in the two (`~`), by their paths below them, largest difference first
+    #[arg(
+        long,
+        num_args = 2,
+        value_names = ["PATH_A", "PATH_B"],
+        value_hint = clap::ValueHint::DirPath,
+        conflicts_with_all = ["paths", "format", "null", "du_compat", "count_only", "watch", "benchmark", "exclude_top"]
+    )]
+    compare: Option<Vec<PathBuf>>,
+
+    /// Save the results as a JSON snapshot for a later --diff
+    #[cfg(feature = "serde")]
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["watch", "compare"])]
+    save_snapshot: Option<PathBuf>,
+
+    /// Compare the results with a snapshot saved by --save-snapshot and show
+    /// new, grown and shrunk files instead of the usual report
+    #[cfg(feature = "serde")]
+    #[arg(long, value_name = "SNAPSHOT_FILE", conflicts_with_all = ["watch", "compare"])]
+    diff: Option<PathBuf>,
+
+    /// Scan as usual but report only the files that are new or changed size
+    /// since this snapshot (from --save-snapshot or --format binary), and list
+    /// its files that are gone. Give the paths the way the snapshot has them.
+    #[cfg(feature = "serde")]
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["diff", "load_binary", "compare"])]
+    since_snapshot: Option<PathBuf>,
+
+    /// Save a snapshot of every scan in DIR, by the scanned roots, and show
+    /// what changed since the last scan of the same roots instead of the
+    /// usual report, as --diff does (the first scan shows the usual report)
+    #[cfg(feature = "serde")]
+    #[arg(
+        long,
+        value_name = "DIR",
+        value_hint = clap::ValueHint::DirPath,
+        conflicts_with_all = ["watch", "compare", "diff", "since_snapshot", "load_binary", "stdin_paths", "count_only"]
+    )]
+    snapshot_dir: Option<PathBuf>,
+
+    /// Delete the snapshots in --snapshot-dir saved more than AGE ago (e.g.
+    /// 7d, 4w, 3m) after each scan; the one just saved is always kept
+    #[cfg(feature = "serde")]
+    #[arg(long, value_name = "AGE", value_parser = parse_age, default_value = "7d", requires = "snapshot_dir")]
+    snapshot_max_age: Duration,
+
+    /// List the snapshots in --snapshot-dir, by their roots, with when they
+    /// were saved and how many files they hold, instead of scanning
+    #[cfg(feature = "serde")]
+    #[arg(long, requires = "snapshot_dir")]
+    list_snapshots: bool,
+
+    /// Show (or --diff) a snapshot saved with --format binary instead of scanning
+    #[cfg(feature = "serde")]
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = [
+            "watch", "benchmark", "exclude_top", "checksum", "reflink_detect", "verify", "verify_strict", "compare"
+        ]
+    )]
+    load_binary: Option<PathBuf>,
+
+    /// Field delimiter for CSV output (use `\t` or `tab` for tab-separated)
+    #[arg(long, value_parser = parse_delimiter_arg, default_value = ",")]
+    csv_delimiter: char,
+
+    /// Append scan statistics to CSV output as a trailing `#` comment line
+    #[arg(long)]
+    csv_stats: bool,
 }
 
-impl Ord for FileEntry {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        // Total ordering: size descending, then path ascending for determinism
-        // When wrapped in Reverse for min-heap, this gives us largest files
-        self.size
-            .cmp(&other.size)
-            .then_with(|| self.path.cmp(&other.path))
+impl Cli {
+    /// Exit with a usage error if options contradict the output format or each
+    /// other in ways that depend on their values, which clap cannot check
+    fn check_conflicts(&self) {
+        if self.format == OutputFormat::Csv {
+            #[cfg(feature = "serde")]
+            let snapshots = [("--since-snapshot", self.since_snapshot.is_some())];
+            #[cfg(not(feature = "serde"))]
+            let snapshots = [];
+            #[cfg(target_os = "linux")]
+            let quotas = [("--quota-report", self.quota_report)];
+            #[cfg(not(target_os = "linux"))]
+            let quotas = [];
+            let tables = [
+                ("--report-empty-dirs", self.report_empty_dirs),
+                ("--age-buckets", self.age_buckets.is_some()),
+                ("--zero-sized", self.zero_sized),
+                ("--zero-sized-count", self.zero_sized_count),
+            ];
+            if let Some((flag, _)) = tables
+                .into_iter()
+                .chain(snapshots)
+                .chain(quotas)
+                .find(|&(_, set)| set)
+            {
+                conflict(&format!(
+                    "CSV output holds a single table; {} needs another format",
+                    flag
+                ));
+            }
+            if self.inode_usage.is_some() && !self.inode_usage_only {
+                conflict(
+                    "CSV output holds a single table; use --inode-usage-only with --format csv",
+                );
+            }
+        }
+
+        // Errors, inaccessible directories and totals follow the files in the
+        // text, table and JSON reports only
+        let has_room = !self.null
+            && !self.du_compat
+            && matches!(
+                self.format,
+                OutputFormat::Text | OutputFormat::Table | OutputFormat::Json
+            );
+        if !has_room {
+            if self.report_errors && self.errors_output.is_none() {
+                conflict(
+                    "this output has no room for errors; use --report-errors with --errors-output FILE",
+                );
+            }
+            if self.report_inaccessible {
+                conflict(
+                    "this output has no room for --report-inaccessible; use text, table or JSON output",
+                );
+            }
+            if self.summarize {
+                conflict("--summarize prints totals, which need --format text, table or json");
+            }
+        }
+        if !self.format.is_text() {
+            let text_only = [
+                ("--tree", self.tree),
+                ("--watch", self.watch.is_some()),
+                ("--null", self.null),
+            ];
+            if let Some((flag, _)) = text_only.into_iter().find(|&(_, set)| set) {
+                conflict(&format!(
+                    "{} cannot be combined with --format json or csv",
+                    flag
+                ));
+            }
+        }
+
+        #[cfg(all(feature = "async", target_os = "linux"))]
+        if !self.affinity.is_empty() && self.engine == Engine::Async {
+            conflict("--affinity pins the rayon threads, which --engine async does not scan on");
+        }
+        #[cfg(all(feature = "adaptive-threads", feature = "async"))]
+        if self.auto_threads && self.engine == Engine::Async {
+            conflict(
+                "--auto-threads resizes the rayon pool, which --engine async does not scan on",
+            );
+        }
+        if self.estimate_time && self.format == OutputFormat::JsonLines && !self.count_only {
+            conflict(
+                "--estimate-time cannot be combined with --format jsonlines, which streams the files instead",
+            );
+        }
+        if self.find_smallest && matches!(self.sort, SortOrder::Mtime | SortOrder::Atime) {
+            conflict("--find-smallest cannot be combined with --sort-by mtime or atime");
+        }
+        if self.color_threshold_warn > self.color_threshold_error {
+            conflict("--color-threshold-warn must not be larger than --color-threshold-error");
+        }
+        #[cfg(feature = "serde")]
+        if self.format == OutputFormat::Binary
+            && self.output.is_none()
+            && io::stdout().is_terminal()
+        {
+            conflict("refusing to write binary output to a terminal; use -o FILE or a redirection");
+        }
+    }
+
+    /// Exit with a usage error if an option needs the ranked results that
+    /// `--format jsonlines` does without, and warn about those it ignores
+    fn check_json_lines(&self, matches: &ArgMatches) {
+        #[cfg(target_os = "linux")]
+        let reflink_detect = self.reflink_detect;
+        #[cfg(not(target_os = "linux"))]
+        let reflink_detect = false;
+        #[cfg(feature = "serde")]
+        let snapshots = [
+            ("--since-snapshot", self.since_snapshot.is_some()),
+            ("--diff", self.diff.is_some()),
+            ("--save-snapshot", self.save_snapshot.is_some()),
+            ("--snapshot-dir", self.snapshot_dir.is_some()),
+        ];
+        #[cfg(not(feature = "serde"))]
+        let snapshots = [];
+        let conflicts = [
+            ("--find-smallest", self.find_smallest),
+            ("--group-by-extension", self.group_by_extension),
+            ("--top-extensions", self.top_extensions.is_some()),
+            ("--top-per-dir", self.top_per_dir.is_some()),
+            ("--top-per-user", self.top_per_user.is_some()),
+            ("--largest-dirs", self.largest_dirs.is_some()),
+            ("--top-dirs", self.top_dirs.is_some()),
+            ("--duplicate-sizes", self.duplicate_sizes),
+            ("--hash-duplicates", self.hash_duplicates),
+            ("--inode-usage", self.inode_usage.is_some()),
+            ("--report-empty-dirs", self.report_empty_dirs),
+            ("--zero-sized", self.zero_sized),
+            ("--zero-sized-count", self.zero_sized_count),
+            ("--age-buckets", self.age_buckets.is_some()),
+            ("--fail-if-fewer", self.fail_if_fewer),
+            ("--fail-threshold-size", self.fail_threshold_size.is_some()),
+            ("--exclude-top", self.exclude_top.is_some()),
+            ("--benchmark", self.benchmark.is_some()),
+            ("--checksum", self.checksum.is_some()),
+            ("--reflink-detect", reflink_detect),
+        ];
+        if let Some((flag, _)) = conflicts.into_iter().chain(snapshots).find(|&(_, set)| set) {
+            conflict(&format!(
+                "--format jsonlines writes files as they are found and cannot be combined with {}",
+                flag
+            ));
+        }
+        for (arg, flag) in [("top", "--top"), ("sort", "--sort-by")] {
+            if matches.value_source(arg) == Some(ValueSource::CommandLine) {
+                eprintln!(
+                    "bfinder: warning: --format jsonlines lists every matching file unranked; {} is ignored",
+                    flag
+                );
+            }
+        }
     }
-}
 
-impl PartialOrd for FileEntry {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cmp(other))
+    /// The scan the options describe, exiting with a usage error when they
+    /// are invalid or contradict each other
+    fn scan_config(&self) -> ScanConfig {
+        let mut config = ScanConfig::new(".");
+        config.roots = Vec::with_capacity(self.paths.len());
+        // The files to rank are read from stdin when scanning
+        for path in self.paths.iter().filter(|_| !self.stdin_paths) {
+            if path.as_os_str() != "-" {
+                config.roots.push(path.clone());
+                continue;
+            }
+            match read_stdin_paths(self.null_input) {
+                Ok(paths) => config.roots.extend(paths),
+                Err(msg) => Cli::command()
+                    .error(clap::error::ErrorKind::InvalidValue, msg)
+                    .exit(),
+            }
+        }
+        if self.absolute {
+            // Only the roots are canonicalized; symlinks below them stay as found
+            for root in &mut config.roots {
+                match fs::canonicalize(&*root) {
+                    Ok(path) => *root = path,
+                    Err(e) => Cli::command()
+                        .error(
+                            clap::error::ErrorKind::InvalidValue,
+                            format!("cannot resolve {}: {}", root.display(), e),
+                        )
+                        .exit(),
+                }
+            }
+        }
+        config.top = self.top;
+        config.min_size = self.min_size.unwrap_or(0);
+        config.max_size = self.max_size.unwrap_or(u64::MAX);
+        config.prefilter_size = self.prefilter_size.unwrap_or(0);
+        if config.min_size > config.max_size {
+            conflict(&format!(
+                "--min-size ({}) is greater than --max-size ({})",
+                format_size(config.min_size),
+                format_size(config.max_size)
+            ));
+        }
+        config.count_hardlinks = self.count_hardlinks;
+        config.max_depth = if self.tree { None } else { self.max_depth };
+        config.min_depth = self.min_depth;
+        config.same_filesystem = self.same_filesystem;
+        #[cfg(any(target_os = "linux", target_os = "macos"))]
+        {
+            config.report_mounts = self.follow_mounts;
+        }
+        #[cfg(target_os = "linux")]
+        if self.no_auto_skip {
+            use bfinder::mounts::VirtualFs;
+            config.skip_virtual = [
+                (self.skip_proc, VirtualFs::Proc),
+                (self.skip_sys, VirtualFs::Sys),
+                (self.skip_dev, VirtualFs::Dev),
+            ]
+            .into_iter()
+            .filter(|&(skip, _)| skip)
+            .map(|(_, fs)| fs)
+            .collect();
+        }
+        config.follow_symlinks = self.follow_symlinks;
+        config.find_smallest = self.find_smallest;
+        config.count_only = self.count_only;
+        config.count_file_types = self.count_file_types || self.verbose > 0;
+        config.disk_usage = self.disk_usage;
+        config.rank_by_blocks = self.sort == SortOrder::Blocks;
+        config.virtual_size = self.virtual_size;
+        config.group_by_extension = self.group_by_extension || self.top_extensions.is_some();
+        config.top_per_dir = self.top_per_dir;
+        config.top_per_user = self.top_per_user;
+        config.largest_dirs = self.largest_dirs;
+        config.top_dirs = self.top_dirs;
+        let report_errors = self.report_errors || self.errors_output.is_some();
+        config.collect_errors = self.verbose_errors || report_errors;
+        config.max_error_details =
+            (report_errors && !self.verbose_errors).then_some(self.max_reported_errors);
+        config.report_inaccessible = self
+            .report_inaccessible
+            .then_some(self.max_inaccessible_report);
+        config.max_errors = (self.max_errors > 0).then_some(self.max_errors);
+        config.inode_usage = self.inode_usage;
+        config.permissions = self.permissions;
+        #[cfg(unix)]
+        for (set, bits) in [
+            (self.suid_files, 0o4000),
+            (self.sgid_files, 0o2000),
+            (self.world_writable, 0o002),
+        ] {
+            if set {
+                config.mode_bits |= bits;
+            }
+        }
+        config.min_hardlinks = self.min_hardlinks;
+        config.max_hardlinks = self.max_hardlinks;
+        config.min_sparseness = self.sparse.then_some(self.sparse_threshold);
+        if cfg!(not(target_os = "linux")) && self.sparse {
+            eprintln!(
+                "bfinder: warning: --sparse relies on st_blocks, which may not reflect holes on this platform"
+            );
+        }
+        #[cfg(feature = "archives")]
+        {
+            config.archive_depth = self
+                .scan_archives_recursive
+                .or(self.scan_archives.then_some(0));
+        }
+        #[cfg(feature = "adaptive-threads")]
+        {
+            config.auto_threads = self.auto_threads;
+        }
+        config.owners = self.owner.clone();
+        config.groups = self.group.clone();
+        config.duplicate_sizes = self.duplicate_sizes || self.hash_duplicates;
+        config.hash_duplicates = self.hash_duplicates;
+        if config.duplicate_sizes {
+            config.top = 0;
+        }
+        config.report_empty_dirs = self.report_empty_dirs;
+        config.include_dirs = self.include_dirs;
+        config.zero_sized = self.zero_sized || self.zero_sized_count;
+        if let Some(bounds) = &self.age_buckets {
+            match AgeBuckets::new(bounds.clone()) {
+                Ok(buckets) => config.age_buckets = Some(buckets),
+                Err(msg) => Cli::command()
+                    .error(clap::error::ErrorKind::InvalidValue, msg)
+                    .exit(),
+            }
+        }
+        #[cfg(feature = "serde")]
+        if let Some(path) = &self.since_snapshot {
+            let old = load_snapshot(path).unwrap_or_else(|msg| {
+                Cli::command()
+                    .error(clap::error::ErrorKind::InvalidValue, msg)
+                    .exit()
+            });
+            config.baseline = Some(Arc::new(
+                old.into_iter().map(|e| (e.path, e.size)).collect(),
+            ));
+        }
+        if self.inode_usage_only {
+            config.top = 0;
+        }
+        config.git_ignore = self.git_ignore && !self.no_ignore;
+        config.hidden = if self.no_hidden {
+            HiddenFiles::Exclude
+        } else if self.only_hidden {
+            HiddenFiles::Only
+        } else {
+            HiddenFiles::Include
+        };
+        if !self.extensions.is_empty() || self.no_extension {
+            config.extensions = Some(ExtensionFilter::new(&self.extensions, self.no_extension));
+        }
+        if !self.regex.is_empty() || !self.not_regex.is_empty() {
+            match RegexFilter::new(&self.regex, &self.not_regex, self.regex_path) {
+                Ok(filter) => config.regex = Some(filter),
+                Err(msg) => Cli::command()
+                    .error(clap::error::ErrorKind::InvalidValue, msg)
+                    .exit(),
+            }
+        }
+        if !self.name.is_empty() || !self.iname.is_empty() {
+            match NameFilter::new(&self.name, &self.iname) {
+                Ok(filter) => config.names = Some(filter),
+                Err(msg) => Cli::command()
+                    .error(clap::error::ErrorKind::InvalidValue, msg)
+                    .exit(),
+            }
+        }
+        if !self.mime_type.is_empty() || !self.mime_category.is_empty() {
+            match MimeFilter::new(&self.mime_type, &self.mime_category) {
+                Ok(filter) => config.mime = Some(filter),
+                Err(msg) => Cli::command()
+                    .error(clap::error::ErrorKind::InvalidValue, msg)
+                    .exit(),
+            }
+        }
+        if let (Some(min), Some(max)) = (self.min_hardlinks, self.max_hardlinks)
+            && min > max
+        {
+            conflict("--min-hardlinks must not be larger than --max-hardlinks");
+        }
+        let now = SystemTime::now();
+        let age_to_time = |age: Duration| now.checked_sub(age).unwrap_or(SystemTime::UNIX_EPOCH);
+        config.newer_than = self.newer_than.or(self.max_age.map(age_to_time));
+        config.older_than = self.older_than.or(self.min_age.map(age_to_time));
+        if let (Some(newer), Some(older)) = (config.newer_than, config.older_than)
+            && newer >= older
+        {
+            let newer_flag = if self.max_age.is_some() {
+                "--max-age"
+            } else {
+                "--newer-than"
+            };
+            let older_flag = if self.min_age.is_some() {
+                "--min-age"
+            } else {
+                "--older-than"
+            };
+            conflict(&format!(
+                "{} ({}) is not earlier than {} ({}), so no file can match",
+                newer_flag,
+                format_time(newer),
+                older_flag,
+                format_time(older)
+            ));
+        }
+        config.exclude = match build_exclude_set(&self.exclude, &self.exclude_from) {
+            Ok(set) => set,
+            Err(msg) => Cli::command()
+                .error(clap::error::ErrorKind::InvalidValue, msg)
+                .exit(),
+        };
+        config
     }
 }
 
-/// Shared sorted top-N tracker using VecDeque
-/// Maintains sorted order: largest files at tail, smallest at head
-struct TopNTracker {
-    deque: VecDeque<FileEntry>,
-    capacity: usize,
+/// Backend running the scan
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Engine {
+    /// `parallel_scan` on the rayon thread pool
+    Sync,
+    /// `async_scan` on a tokio runtime
+    #[cfg(feature = "async")]
+    Async,
+    /// `io_uring_scan`, with a ring per thread of the rayon pool
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    IoUring,
+}
+
+/// When to color text output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum ColorChoice {
+    /// Only when writing to a terminal, NO_COLOR is unset and TERM is not `dumb`
+    Auto,
+    Always,
+    Never,
 }
 
-impl TopNTracker {
-    fn new(capacity: usize) -> Self {
-        Self {
-            deque: VecDeque::with_capacity(capacity),
-            capacity,
+impl ColorChoice {
+    fn enabled(self, to_terminal: bool) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                to_terminal
+                    && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+                    && std::env::var_os("TERM").is_none_or(|term| term != "dumb")
+            }
         }
     }
+}
+
+/// Parse a ratio between 0 and 1
+fn parse_ratio(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(ratio) if (0.0..=1.0).contains(&ratio) => Ok(ratio),
+        _ => Err(format!("expected a number from 0 to 1, got '{}'", s)),
+    }
+}
+
+/// Parse a CPU core of --affinity, which must be below the number of CPUs the
+/// system has
+#[cfg(target_os = "linux")]
+fn parse_core(s: &str) -> Result<usize, String> {
+    // SAFETY: sysconf only reads system configuration
+    let cpus = unsafe { libc::sysconf(libc::_SC_NPROCESSORS_CONF) }.max(1) as usize;
+    match s.trim().parse::<usize>() {
+        Ok(n) if n < cpus => Ok(n),
+        Ok(n) => Err(format!(
+            "CPU core {} does not exist; this system has cores 0 to {}",
+            n,
+            cpus - 1
+        )),
+        Err(_) => Err(format!("expected a CPU core number, got '{}'", s)),
+    }
+}
 
-    /// Insert entry into sorted position, maintaining top-N invariant
-    fn insert(&mut self, entry: FileEntry) {
-        // If deque is not full, or this entry is larger than the smallest, insert it
-        if self.deque.len() < self.capacity || entry > *self.deque.front().unwrap() {
-            // Binary search to find insertion position (deque is sorted ascending)
-            let pos = self.deque.binary_search(&entry).unwrap_or_else(|e| e);
-            self.deque.insert(pos, entry);
+/// Pin the calling thread to `core` for --affinity, warning once (through
+/// `warned`) if the kernel refuses, as the scan still works unpinned
+#[cfg(target_os = "linux")]
+fn pin_to_core(core: usize, warned: &AtomicBool) {
+    // SAFETY: an all-zero `cpu_set_t` is the empty set, and `core` is below the
+    // number of CPUs, well within `CPU_SETSIZE`
+    let result = unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(core, &mut set);
+        libc::sched_setaffinity(0, size_of::<libc::cpu_set_t>(), &set)
+    };
+    if result != 0 && !warned.swap(true, Ordering::Relaxed) {
+        eprintln!(
+            "bfinder: warning: cannot pin scan threads to CPU {}: {}; running unpinned",
+            core,
+            io::Error::last_os_error()
+        );
+    }
+}
 
-            // If we exceeded capacity, remove smallest (head)
-            if self.deque.len() > self.capacity {
-                self.deque.pop_front();
+/// Parse a single-character CSV delimiter, accepting `\t` and `tab` for tabs
+fn parse_delimiter_arg(s: &str) -> Result<char, String> {
+    let delimiter = match s {
+        "\\t" | "tab" => '\t',
+        _ => {
+            let mut chars = s.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => c,
+                _ => return Err(format!("delimiter must be a single character, got '{}'", s)),
             }
         }
+    };
+
+    if matches!(delimiter, '"' | '\n' | '\r') {
+        return Err(format!("{:?} cannot be used as a delimiter", delimiter));
     }
+    Ok(delimiter)
+}
 
-    fn into_vec(self) -> Vec<FileEntry> {
-        // Convert to Vec and reverse to get descending order
-        let mut vec: Vec<_> = self.deque.into_iter().collect();
-        vec.reverse();
-        vec
+/// Read directory paths from stdin, one per line or NUL-separated, checking
+/// that each one exists and is a directory
+fn read_stdin_paths(null_separated: bool) -> Result<Vec<PathBuf>, String> {
+    let paths = read_stdin_lines(null_separated, "'-' reads directories")?;
+    if let Some(path) = paths
+        .iter()
+        .find(|path| !fs::metadata(path).is_ok_and(|m| m.is_dir()))
+    {
+        return Err(format!(
+            "{} (from stdin) is not a directory",
+            path.display()
+        ));
+    }
+    if paths.is_empty() {
+        return Err("no directories given on stdin".to_string());
     }
+    Ok(paths)
 }
 
-/// Scanner statistics
-#[derive(Default)]
-struct ScanStats {
-    files_scanned: u64,
-    dirs_scanned: u64,
-    errors: u64,
+/// Read the files to rank with --stdin-paths, one per line or NUL-separated
+fn read_stdin_files(null_separated: bool) -> Result<Vec<PathBuf>, String> {
+    let paths = read_stdin_lines(null_separated, "--stdin-paths reads files")?;
+    if paths.is_empty() {
+        return Err("no files given on stdin".to_string());
+    }
+    Ok(paths)
+}
+
+/// Read paths from stdin, one per line or NUL-separated, refusing a terminal;
+/// `reader` says who reads what, for the error
+fn read_stdin_lines(null_separated: bool, reader: &str) -> Result<Vec<PathBuf>, String> {
+    let mut stdin = io::stdin().lock();
+    if stdin.is_terminal() {
+        return Err(format!("{} from stdin, but stdin is a terminal", reader));
+    }
+    let mut input = Vec::new();
+    stdin
+        .read_to_end(&mut input)
+        .map_err(|e| format!("cannot read paths from stdin: {}", e))?;
+
+    let separator = if null_separated { b'\0' } else { b'\n' };
+    let mut paths = Vec::new();
+    for line in input
+        .split(|&b| b == separator)
+        .filter(|line| !line.is_empty())
+    {
+        #[cfg(unix)]
+        let path = PathBuf::from(OsStr::from_bytes(line));
+        // Names are UTF-16 on Windows, given here as UTF-8 lines that may end in CR
+        #[cfg(windows)]
+        let path = PathBuf::from(
+            String::from_utf8_lossy(line.strip_suffix(b"\r").unwrap_or(line)).into_owned(),
+        );
+        paths.push(path);
+    }
+    Ok(paths)
 }
 
-/// Single-observation directory entry with metadata
-struct DirEntry {
-    name: String,
-    path: PathBuf,
+/// Compile the `--exclude` and `--exclude-from` patterns into a single set
+fn build_exclude_set(patterns: &[String], files: &[PathBuf]) -> Result<GlobSet, String> {
+    let mut owned = patterns.to_vec();
+    for file in files {
+        let contents = fs::read_to_string(file)
+            .map_err(|e| format!("cannot read exclude file {}: {}", file.display(), e))?;
+        owned.extend(
+            contents
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(String::from),
+        );
+    }
+
+    let mut builder = GlobSetBuilder::new();
+    for pattern in &owned {
+        let glob = Glob::new(pattern).map_err(|e| format!("invalid exclude pattern: {}", e))?;
+        builder.add(glob);
+    }
+    builder
+        .build()
+        .map_err(|e| format!("invalid exclude pattern: {}", e))
 }
 
-/// Scan a single directory atomically: read entries once, sort lexicographically,
-/// classify each with a single statx() call
-fn scan_directory(
-    dir_path: &Path,
-    top_n: &Mutex<TopNTracker>,
-    stats: &mut ScanStats,
-    subdirs: &mut Vec<PathBuf>,
-) -> io::Result<()> {
-    // Read directory entries exactly once
-    let mut entries: Vec<DirEntry> = Vec::new();
+/// Run `scan` while writing every file it finds to `output` as a JSON line,
+/// relative to `roots` if given
+fn scan_streaming(
+    config: &ScanConfig,
+    roots: Option<&[PathBuf]>,
+    output: &mut dyn Write,
+    scan: impl FnOnce(&ScanConfig) -> (Option<Vec<FileEntry>>, ScanStats) + Send,
+) -> (Option<Vec<FileEntry>>, ScanStats) {
+    let (sender, receiver) = mpsc::channel();
+    let mut streaming = config.clone();
+    streaming.stream = Some(sender);
 
-    for entry in fs::read_dir(dir_path)? {
-        let entry = match entry {
-            Ok(e) => e,
-            Err(_) => {
-                stats.errors += 1;
-                continue; // Skip entries we can't read, never retry
+    thread::scope(|s| {
+        // The sender goes away with `streaming` once the scan returns
+        let scanner = s.spawn(move || scan(&streaming));
+        for mut entry in receiver {
+            if let Some(roots) = roots {
+                entry.path = relative_to_roots(&entry.path, roots);
+            }
+            if let Err(e) = write_json_line(&entry, output).and_then(|_| output.flush()) {
+                eprintln!("bfinder: failed to write output: {}", e);
+                std::process::exit(1);
             }
+        }
+        scanner.join().unwrap()
+    })
+}
+
+/// Scan `a` and `b` as `config` describes, each in half of `threads` (the
+/// number of CPUs if not given), and write how they differ for --compare
+fn compare_trees(
+    config: &ScanConfig,
+    a: &std::path::Path,
+    b: &std::path::Path,
+    threads: Option<usize>,
+    output: &mut dyn Write,
+) -> ExitCode {
+    let threads = threads
+        .filter(|&n| n > 0)
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()));
+    let scan_tree = |root: &std::path::Path| {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads((threads / 2).max(1))
+            .build()
+            .unwrap();
+        let (sender, receiver) = mpsc::channel();
+        let mut tree = config.clone();
+        tree.roots = vec![root.to_path_buf()];
+        tree.top = 0;
+        tree.stream = Some(sender);
+        let stats = pool.install(|| parallel_scan(&tree)).1;
+        // Ends the channel so the files can be collected
+        drop(tree);
+        if stats.errors > 0 {
+            let plural = if stats.errors == 1 { "" } else { "s" };
+            eprintln!(
+                "bfinder: warning: {} error{} scanning {}; the comparison may be incomplete",
+                stats.errors,
+                plural,
+                root.display()
+            );
+        }
+        receiver.into_iter().collect::<Vec<FileEntry>>()
+    };
+    let (files_a, files_b) = thread::scope(|s| {
+        let scan_a = s.spawn(|| scan_tree(a));
+        let files_b = scan_tree(b);
+        (scan_a.join().unwrap(), files_b)
+    });
+
+    let differences = bfinder::diff::compare_trees(&files_a, a, &files_b, b);
+    if let Err(e) = render_compare_text(&differences, output).and_then(|_| output.flush()) {
+        eprintln!("bfinder: failed to write output: {}", e);
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+/// Wall-clock times of repeated scans
+struct Timings(Vec<Duration>);
+
+impl Timings {
+    fn min(&self) -> Duration {
+        self.0.iter().copied().min().unwrap_or_default()
+    }
+
+    fn max(&self) -> Duration {
+        self.0.iter().copied().max().unwrap_or_default()
+    }
+
+    fn mean(&self) -> f64 {
+        self.0.iter().map(Duration::as_secs_f64).sum::<f64>() / self.0.len().max(1) as f64
+    }
+
+    /// Sample standard deviation in seconds (0 for a single run)
+    fn stddev(&self) -> f64 {
+        if self.0.len() < 2 {
+            return 0.0;
+        }
+        let mean = self.mean();
+        let squares: f64 = self
+            .0
+            .iter()
+            .map(|d| (d.as_secs_f64() - mean).powi(2))
+            .sum();
+        (squares / (self.0.len() - 1) as f64).sqrt()
+    }
+
+    fn report(&self, files_per_run: u64, writer: &mut dyn Write) -> io::Result<()> {
+        let mean = self.mean();
+        let throughput = if mean > 0.0 {
+            files_per_run as f64 / mean
+        } else {
+            0.0
         };
+        writeln!(writer, "Benchmark ({} runs):", self.0.len())?;
+        writeln!(
+            writer,
+            "  Min:                 {:.3}s",
+            self.min().as_secs_f64()
+        )?;
+        writeln!(
+            writer,
+            "  Max:                 {:.3}s",
+            self.max().as_secs_f64()
+        )?;
+        writeln!(writer, "  Mean:                {:.3}s", mean)?;
+        writeln!(writer, "  Std dev:             {:.3}s", self.stddev())?;
+        writeln!(writer, "  Throughput:          {:.0} files/s", throughput)
+    }
+}
+
+/// System calls a Linux scan makes per directory listed: `open`, `getdents64`
+/// until it returns nothing (twice for most directories) and `close`
+const SYSCALLS_PER_DIR: u64 = 4;
+
+/// Write the --syscall-stats report: the stat calls counted during the scan
+/// and an estimate of those listing the `roots` and the directories below
+fn report_syscalls(stats: &ScanStats, roots: u64, writer: &mut dyn Write) -> io::Result<()> {
+    let listing = (stats.dirs_scanned + roots) * SYSCALLS_PER_DIR;
+    let total = stats.stat_calls + listing;
+    let entries = stats.files_scanned + stats.dirs_scanned;
+    writeln!(writer, "System calls (estimated):")?;
+    writeln!(writer, "  Stat calls:          {}", stats.stat_calls)?;
+    writeln!(writer, "  Directory listing:   {}", listing)?;
+    write!(writer, "  Total:               {}", total)?;
+    if entries > 0 {
+        write!(
+            writer,
+            " ({:.2} per file or directory)",
+            total as f64 / entries as f64
+        )?;
+    }
+    writeln!(writer)
+}
+
+/// Length of a file name assumed by `check_memory_limit`
+const TYPICAL_NAME_BYTES: usize = 32;
+
+/// Estimate the memory of the `threads` top-N lists of `top` files each, logged
+/// with -v before the scan, taking every path to be as long as the longest
+/// root with a typical name. With --limit-memory, return how many such files
+/// each thread's share of `limit` holds, as the room its list reserves up
+/// front, warning when that is fewer than `top`, which each thread still keeps.
+fn check_memory_limit(
+    limit: Option<u64>,
+    threads: usize,
+    top: usize,
+    roots: &[PathBuf],
+) -> Option<usize> {
+    let longest = roots
+        .iter()
+        .max_by_key(|root| root.as_os_str().len())
+        .cloned()
+        .unwrap_or_default();
+    let typical = FileEntry {
+        path: longest.join("x".repeat(TYPICAL_NAME_BYTES)),
+        ..FileEntry::default()
+    };
+    let per_entry = typical.approx_size_bytes() as u64;
+    let total = per_entry.saturating_mul((threads * top) as u64);
+    tracing::info!(
+        threads,
+        per_thread = top,
+        per_entry,
+        total,
+        "estimated memory of the top-N lists"
+    );
+    let limit = limit?;
+    let fits = limit / (threads as u64 * per_entry);
+    if fits < top as u64 {
+        let plural = if threads == 1 { "" } else { "s" };
+        eprintln!(
+            "bfinder: warning: --limit-memory {} holds {} files per thread for {} thread{}; each reserves room \
+             for that many but keeps up to --top {} (about {} in all)",
+            format_size(limit),
+            fits,
+            threads,
+            plural,
+            top,
+            format_size(total)
+        );
+    }
+    Some(fits.min(top as u64) as usize)
+}
+
+/// Time between two --estimate-time updates
+const ESTIMATE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How long --estimate-time measures the scan rate for before estimating
+const ESTIMATE_WARMUP: Duration = Duration::from_secs(5);
+
+/// Weight of the latest rate in the --estimate-time rolling average
+const ESTIMATE_DECAY: f64 = 0.3;
+
+/// The time a scan has left for --estimate-time, from the directories still
+/// queued and the rate they are listed at: the average over the first
+/// seconds, then a rolling average that favors recent rates, as the queue
+/// grows while directories are found
+struct Estimate {
+    roots: u64,
+    started: Instant,
+    last: Instant,
+    last_done: u64,
+    /// Directories per second, once warmed up
+    rate: Option<f64>,
+}
+
+impl Estimate {
+    fn new(roots: usize) -> Self {
+        let now = Instant::now();
+        Estimate {
+            roots: roots as u64,
+            started: now,
+            last: now,
+            last_done: 0,
+            rate: None,
+        }
+    }
 
-        let name = entry.file_name();
-        let name_str = match name.to_str() {
-            Some(s) => s.to_string(),
-            None => {
-                stats.errors += 1;
-                continue; // Skip non-UTF8 names
+    /// Take the rate since the last update from `progress`, describing the
+    /// time left
+    fn update(&mut self, progress: &ScanProgress) -> String {
+        let now = Instant::now();
+        if now - self.started < ESTIMATE_WARMUP {
+            return "estimating the time left".to_string();
+        }
+        let done = progress.dirs_done.load(Ordering::Relaxed);
+        let queued =
+            (self.roots + progress.dirs_scanned.load(Ordering::Relaxed)).saturating_sub(done);
+        let rate = match self.rate {
+            None => done as f64 / (now - self.started).as_secs_f64(),
+            Some(rate) => {
+                let latest =
+                    done.saturating_sub(self.last_done) as f64 / (now - self.last).as_secs_f64();
+                ESTIMATE_DECAY * latest + (1.0 - ESTIMATE_DECAY) * rate
             }
         };
+        (self.rate, self.last, self.last_done) = (Some(rate), now, done);
+        if rate < 0.01 {
+            return format!(
+                "time left unknown ({} directories queued, none finishing)",
+                queued
+            );
+        }
+        let left = Duration::from_secs_f64(queued as f64 / rate);
+        format!(
+            "roughly {} left (estimate: {} directories queued at {:.0}/s)",
+            format_time_left(left),
+            queued,
+            rate
+        )
+    }
+}
+
+/// A duration to the second as `1h 02m`, `3m 10s` or `12s`
+fn format_time_left(left: Duration) -> String {
+    let secs = left.as_secs();
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m {:02}s", m, s),
+        (h, m, _) => format!("{}h {:02}m", h, m),
+    }
+}
 
-        entries.push(DirEntry {
-            name: name_str,
-            path: entry.path(),
+/// Run the scan while a spinner on stderr shows the live counters, with the
+/// time left when given an `estimate`; the spinner is cleared before
+/// returning so it never mixes with the results. Without `spinner` only the
+/// estimate is shown, as a line on stderr every `ESTIMATE_INTERVAL`.
+fn scan_with_progress(
+    progress: &ScanProgress,
+    spinner: bool,
+    mut estimate: Option<Estimate>,
+    scan: impl FnOnce() -> (Option<Vec<FileEntry>>, ScanStats),
+) -> (Option<Vec<FileEntry>>, ScanStats) {
+    let bar = if spinner {
+        ProgressBar::new_spinner()
+    } else {
+        ProgressBar::hidden()
+    };
+    bar.set_style(ProgressStyle::with_template("{spinner} {msg}").unwrap());
+    let done = AtomicBool::new(false);
+
+    let result = thread::scope(|s| {
+        let ticker = s.spawn(|| {
+            let mut time_left = String::new();
+            let mut next_estimate = Instant::now() + ESTIMATE_INTERVAL;
+            while !done.load(Ordering::Relaxed) {
+                if let Some(estimate) = &mut estimate
+                    && Instant::now() >= next_estimate
+                {
+                    time_left = estimate.update(progress);
+                    next_estimate += ESTIMATE_INTERVAL;
+                    if !spinner {
+                        eprintln!("bfinder: {}", time_left);
+                    }
+                }
+                let (files, dirs, threshold) = progress.snapshot();
+                let mut message = format!(
+                    "{} directories, {} files, top-N threshold {}",
+                    dirs,
+                    files,
+                    format_size(threshold)
+                );
+                if !time_left.is_empty() {
+                    message = format!("{}; {}", message, time_left);
+                }
+                bar.set_message(message);
+                bar.tick();
+                thread::park_timeout(Duration::from_millis(100));
+            }
         });
+        let result = scan();
+        done.store(true, Ordering::Relaxed);
+        ticker.thread().unpark();
+        result
+    });
+
+    bar.finish_and_clear();
+    result
+}
+
+/// Run `scan` keeping every file it finds, and report `n` of them picked by
+/// `weighted_sample` for --random-sample
+fn scan_sampled(
+    config: &ScanConfig,
+    n: usize,
+    seed: u64,
+    scan: impl Fn(&ScanConfig) -> (Option<Vec<FileEntry>>, ScanStats),
+) -> (Option<Vec<FileEntry>>, ScanStats) {
+    let (sender, receiver) = mpsc::channel();
+    let mut sampling = config.clone();
+    sampling.top = 0;
+    sampling.stream = Some(sender);
+    let mut stats = scan(&sampling).1;
+    // Ends the channel so the files can be collected
+    drop(sampling);
+    let sample = weighted_sample(receiver.into_iter().collect(), n, seed);
+    count_results(&mut stats.filesystems, &sample);
+    (Some(sample), stats)
+}
+
+/// Run `scan` for the `n` top-ranked files of `config`, then again with the
+/// size limit moved to the last of them, and drop them from the second
+/// results. Files the same size as that last outlier stay eligible. Ranked by
+/// blocks, sizes bound nothing, so the second scan keeps `n` more files.
+fn scan_excluding_top(
+    config: &ScanConfig,
+    n: usize,
+    scan: impl Fn(&ScanConfig) -> (Option<Vec<FileEntry>>, ScanStats),
+) -> (Option<Vec<FileEntry>>, ScanStats) {
+    let mut first = config.clone();
+    first.top = n;
+    first.inode_usage = None;
+    first.collect_errors = false;
+    let outliers = scan(&first).0.unwrap_or_default();
+
+    let mut second = config.clone();
+    if config.rank_by_blocks {
+        second.top += outliers.len();
+        return drop_outliers(scan(&second), &outliers, config.top);
+    }
+    let boundary = if config.find_smallest {
+        outliers.iter().map(|e| e.size).max()
+    } else {
+        outliers.iter().map(|e| e.size).min()
+    };
+    if let Some(boundary) = boundary {
+        if config.find_smallest {
+            second.min_size = boundary;
+        } else {
+            second.max_size = boundary;
+        }
+        // Room for the outliers that are let through again at the boundary
+        second.top += outliers.iter().filter(|e| e.size == boundary).count();
+    }
+    drop_outliers(scan(&second), &outliers, config.top)
+}
+
+/// The scan's results without the `outliers`, cut to `top`
+fn drop_outliers(
+    (mut results, stats): (Option<Vec<FileEntry>>, ScanStats),
+    outliers: &[FileEntry],
+    top: usize,
+) -> (Option<Vec<FileEntry>>, ScanStats) {
+    if let Some(results) = results.as_mut() {
+        let excluded: HashSet<&PathBuf> = outliers.iter().map(|e| &e.path).collect();
+        results.retain(|e| !excluded.contains(&e.path));
+        results.truncate(top);
+    }
+    (results, stats)
+}
+
+/// Set by the SIGINT handler installed for `--watch`
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_sigint(_: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::Relaxed);
+    // A second Ctrl-C during a long scan terminates as usual
+    // SAFETY: signal() is async-signal-safe
+    unsafe {
+        libc::signal(libc::SIGINT, libc::SIG_DFL);
     }
+}
 
-    // Sort entries lexicographically for deterministic traversal order
-    entries.sort_by(|a, b| a.name.cmp(&b.name));
+/// Count down the `--watch` interval on the last line, returning false as soon
+/// as Ctrl-C is pressed
+fn countdown(secs: u64, writer: &mut impl Write) -> bool {
+    use crossterm::cursor::MoveToColumn;
+    use crossterm::terminal::{Clear, ClearType};
 
-    // Classify each entry exactly once with single statx() call
-    for entry in entries {
-        // Single classification attempt - never retry
-        let metadata = match classify_entry(dir_path, &entry.name) {
-            Ok(m) => m,
-            Err(_) => {
-                stats.errors += 1;
-                continue; // Failed classification, skip this entry
+    for remaining in (1..=secs).rev() {
+        let _ = crossterm::queue!(writer, MoveToColumn(0), Clear(ClearType::CurrentLine));
+        let _ = write!(writer, "Next scan in {}s (Ctrl-C to stop)", remaining);
+        let _ = writer.flush();
+        for _ in 0..10 {
+            if INTERRUPTED.load(Ordering::Relaxed) {
+                return false;
             }
-        };
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+    let _ = crossterm::queue!(writer, MoveToColumn(0), Clear(ClearType::CurrentLine));
+    !INTERRUPTED.load(Ordering::Relaxed)
+}
+
+/// Paths in `current` that were not reported by the previous scan
+fn new_paths(previous: &[FileEntry], current: &[FileEntry]) -> HashSet<PathBuf> {
+    let before: HashSet<&PathBuf> = previous.iter().map(|e| &e.path).collect();
+    current
+        .iter()
+        .filter(|e| !before.contains(&e.path))
+        .map(|e| e.path.clone())
+        .collect()
+}
 
-        match metadata {
-            EntryMetadata::RegularFile { size } => {
-                stats.files_scanned += 1;
-                // Lock only for insertion, minimizing contention
-                top_n.lock().unwrap().insert(FileEntry {
-                    size,
-                    path: entry.path,
-                });
+/// Entries of the previous scan whose paths are missing from `current`
+fn removed_entries(previous: &[FileEntry], current: &[FileEntry]) -> Vec<FileEntry> {
+    let now: HashSet<&PathBuf> = current.iter().map(|e| &e.path).collect();
+    previous
+        .iter()
+        .filter(|e| !now.contains(&e.path))
+        .cloned()
+        .collect()
+}
+
+/// Rewrite every reported path relative to the root it was found under
+fn strip_roots(results: Option<&mut [FileEntry]>, stats: &mut ScanStats, roots: &[PathBuf]) {
+    for entry in results.into_iter().flatten() {
+        entry.path = relative_to_roots(&entry.path, roots);
+    }
+    // Directories under different roots may end up with the same relative path
+    let mut per_dir: HashMap<PathBuf, DirTop> = HashMap::new();
+    for (dir, mut top) in std::mem::take(&mut stats.per_dir) {
+        top.files = top.files.map(|mut entry| {
+            entry.path = relative_to_roots(&entry.path, roots);
+            entry
+        });
+        match per_dir.entry(relative_to_roots(&dir, roots)) {
+            hash_map::Entry::Occupied(mut existing) => {
+                let existing = existing.get_mut();
+                existing.total_size += top.total_size;
+                existing.files.merge(top.files);
             }
-            EntryMetadata::Directory => {
-                stats.dirs_scanned += 1;
-                subdirs.push(entry.path);
+            hash_map::Entry::Vacant(slot) => {
+                slot.insert(top);
             }
-            EntryMetadata::Other => {
-                // Symlinks, devices, etc. - ignore
+        }
+    }
+    stats.per_dir = per_dir;
+    stats.per_user = std::mem::take(&mut stats.per_user)
+        .into_iter()
+        .map(|(uid, mut top)| {
+            top.files = top.files.map(|mut entry| {
+                entry.path = relative_to_roots(&entry.path, roots);
+                entry
+            });
+            (uid, top)
+        })
+        .collect();
+    for dir in &mut stats.largest_dirs {
+        dir.path = relative_to_roots(&dir.path, roots);
+    }
+    for dir in &mut stats.top_dirs {
+        dir.path = relative_to_roots(&dir.path, roots);
+    }
+    for dir in &mut stats.inode_usage {
+        dir.path = relative_to_roots(&dir.path, roots);
+    }
+    for dir in &mut stats.empty_dirs {
+        dir.path = relative_to_roots(&dir.path, roots);
+    }
+    for path in &mut stats.zero_sized {
+        *path = relative_to_roots(path, roots);
+    }
+    stats.zero_sized.sort();
+    for entry in &mut stats.removed {
+        entry.path = relative_to_roots(&entry.path, roots);
+    }
+    for paths in stats.size_groups.values_mut() {
+        for path in paths.iter_mut() {
+            *path = relative_to_roots(path, roots);
+        }
+        paths.sort();
+    }
+    for group in &mut stats.duplicates {
+        for entry in &mut group.files {
+            entry.path = relative_to_roots(&entry.path, roots);
+        }
+    }
+    // `filesystems` keeps its roots as given; relative to themselves they would all be `.`
+}
+
+/// Replace `path` with the --errors-output list of `stats`
+fn write_errors_file(path: &std::path::Path, stats: &ScanStats) -> io::Result<()> {
+    let mut file = io::BufWriter::new(fs::File::create(path)?);
+    write_scan_errors(stats, &mut file)?;
+    file.flush()
+}
+
+/// Read the results saved by --save-snapshot, or by --format binary
+#[cfg(feature = "serde")]
+fn load_snapshot(path: &std::path::Path) -> Result<Vec<FileEntry>, String> {
+    let bytes =
+        fs::read(path).map_err(|e| format!("cannot open snapshot {}: {}", path.display(), e))?;
+    if bfinder::snapshot::is_snapshot(&bytes) {
+        return load_binary(path).map(|(results, _)| results.unwrap_or_default());
+    }
+    serde_json::from_slice(&bytes)
+        .map_err(|e| format!("invalid snapshot {}: {}", path.display(), e))
+}
+
+/// The quotas of the users in `stats.per_user` for --quota-report, exiting
+/// with an error if they cannot be read
+#[cfg(target_os = "linux")]
+fn read_quotas(roots: &[PathBuf], stats: &ScanStats) -> Vec<(PathBuf, bfinder::QuotaInfo)> {
+    let mut uids: Vec<u32> = stats.per_user.keys().copied().collect();
+    uids.sort_unstable();
+    match bfinder::quota::quota_report(roots, &uids) {
+        Ok(quotas) => {
+            if quotas.is_empty() {
+                eprintln!(
+                    "bfinder: warning: no filesystem holding the scanned paths has user quotas enabled"
+                );
             }
+            quotas
+        }
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+            eprintln!(
+                "bfinder: {}\nbfinder: --quota-report needs root or the CAP_SYS_ADMIN capability \
+                 to read other users' quotas",
+                e
+            );
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("bfinder: --quota-report: {}", e);
+            std::process::exit(1);
         }
     }
+}
 
-    Ok(())
+/// Read a snapshot written with --format binary
+#[cfg(feature = "serde")]
+fn load_binary(path: &std::path::Path) -> Result<(Option<Vec<FileEntry>>, ScanStats), String> {
+    let file = fs::File::open(path)
+        .map_err(|e| format!("cannot open snapshot {}: {}", path.display(), e))?;
+    bfinder::snapshot::read_snapshot(&mut io::BufReader::new(file))
+        .map_err(|e| format!("cannot read snapshot {}: {}", path.display(), e))
 }
 
-/// Entry classification result
-enum EntryMetadata {
-    RegularFile { size: u64 },
-    Directory,
-    Other,
+#[cfg(feature = "serde")]
+fn save_snapshot(path: &std::path::Path, results: &[FileEntry]) -> Result<(), String> {
+    let err = |e: &dyn std::fmt::Display| format!("cannot save snapshot {}: {}", path.display(), e);
+    let file = fs::File::create(path).map_err(|e| err(&e))?;
+    let mut writer = io::BufWriter::new(file);
+    serde_json::to_writer(&mut writer, results).map_err(|e| err(&e))?;
+    writer.flush().map_err(|e| err(&e))
 }
 
-/// Classify an entry with a single statx() call, using d_type as hint but not guarantee
-fn classify_entry(parent: &Path, name: &str) -> io::Result<EntryMetadata> {
-    // Open parent directory for *at operations
-    let parent_fd = rustix::fs::openat(
-        rustix::fs::CWD,
-        parent,
-        rustix::fs::OFlags::RDONLY | rustix::fs::OFlags::DIRECTORY | rustix::fs::OFlags::CLOEXEC,
-        Mode::empty(),
-    )?;
+/// Send the library's log events to stderr. `BFINDER_LOG` (`EnvFilter` syntax,
+/// e.g. `debug` or `bfinder=trace`) takes precedence over the `-v` count.
+fn init_logging(verbose: u8) {
+    let builder = tracing_subscriber::fmt()
+        .with_thread_ids(true)
+        .with_target(false)
+        .with_writer(io::stderr);
 
-    // Single statx() call - never retry, don't follow symlinks
-    let stat = statat(
-        &parent_fd,
-        name,
-        AtFlags::SYMLINK_NOFOLLOW,
-    )?;
+    if let Some(directives) = std::env::var_os("BFINDER_LOG") {
+        match tracing_subscriber::EnvFilter::try_new(directives.to_string_lossy()) {
+            Ok(filter) => return builder.with_env_filter(filter).init(),
+            Err(e) => eprintln!("bfinder: ignoring invalid BFINDER_LOG: {}", e),
+        }
+    }
 
-    let file_type = FileType::from_raw_mode(stat.st_mode as rustix::fs::RawMode);
+    let level = match verbose {
+        0 => return,
+        1 => tracing::Level::DEBUG,
+        _ => tracing::Level::TRACE,
+    };
+    builder.with_max_level(level).init();
+}
+
+/// Write the completion script for `shell` to stdout; bash and zsh scripts
+/// start with a comment saying where to install them
+fn print_completion(shell: clap_complete::Shell) -> ExitCode {
+    use clap_complete::Shell;
 
-    let result = if file_type == FileType::RegularFile {
-        EntryMetadata::RegularFile {
-            size: stat.st_size as u64,
+    let mut script = Vec::new();
+    clap_complete::generate(shell, &mut Cli::command(), "bfinder", &mut script);
+    // clap_complete honors the directory hint on `paths` only for zsh; bash
+    // and fish would otherwise offer every file
+    match shell {
+        Shell::Bash => {
+            script = String::from_utf8_lossy(&script)
+                .replace("-o default bfinder", "-o dirnames bfinder")
+                .into_bytes();
         }
-    } else if file_type == FileType::Directory {
-        EntryMetadata::Directory
-    } else {
-        EntryMetadata::Other
+        Shell::Fish => {
+            script
+                .extend_from_slice(b"complete -c bfinder -f -a '(__fish_complete_directories)'\n");
+        }
+        _ => {}
+    }
+    let note = match shell {
+        Shell::Bash => Some(
+            "# bfinder completion for bash. Install with:\n\
+             #   bfinder --generate-completion bash > ~/.local/share/bash-completion/completions/bfinder\n\
+             # or load it for the current shell with `source <(bfinder --generate-completion bash)`\n",
+        ),
+        Shell::Zsh => Some(
+            "# bfinder completion for zsh. Install it as `_bfinder` in a directory on $fpath:\n\
+             #   bfinder --generate-completion zsh > ~/.zfunc/_bfinder\n\
+             # with `fpath=(~/.zfunc $fpath)` before `compinit` in ~/.zshrc\n",
+        ),
+        _ => None,
     };
 
-    Ok(result)
+    let mut stdout = io::stdout().lock();
+    let result = match note {
+        // `#compdef` has to stay the first line for zsh to autoload the file
+        Some(note) if shell == Shell::Zsh => {
+            let split = script.iter().position(|&b| b == b'\n').map_or(0, |i| i + 1);
+            stdout
+                .write_all(&script[..split])
+                .and_then(|_| stdout.write_all(note.as_bytes()))
+                .and_then(|_| stdout.write_all(&script[split..]))
+        }
+        Some(note) => stdout
+            .write_all(note.as_bytes())
+            .and_then(|_| stdout.write_all(&script)),
+        None => stdout.write_all(&script),
+    };
+    if let Err(e) = result.and_then(|_| stdout.flush()) {
+        eprintln!("bfinder: failed to write output: {}", e);
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
 }
 
-/// Parallel directory traversal using level-by-level BFS with shared top-N tracker
-fn parallel_scan(root: PathBuf, capacity: usize) -> (Vec<FileEntry>, ScanStats) {
-    let global_stats = Mutex::new(ScanStats::default());
-    let global_top_n = Mutex::new(TopNTracker::new(capacity));
+/// Exit with a usage error for options that cannot be used together
+fn conflict(msg: &str) -> ! {
+    Cli::command()
+        .error(clap::error::ErrorKind::ArgumentConflict, msg)
+        .exit()
+}
 
-    // Work queue of directories to process
-    let mut work_queue = vec![root];
+/// Write the man page to stdout: clap's sections for the options, then the
+/// exit statuses and the examples of `--help`
+fn print_man_page() -> ExitCode {
+    use clap_mangen::roff::{Roff, bold, roman};
 
-    while !work_queue.is_empty() {
-        // Next level queue wrapped in Mutex for parallel access
-        let next_queue = Mutex::new(Vec::new());
+    // In both texts an item starts on a line of its own, with its description
+    // beside or below it
+    let section = |title: &str, text: &str, is_item: fn(&str) -> Option<(&str, &str)>| {
+        let mut roff = Roff::new();
+        roff.control("SH", [title]);
+        for line in text.lines().skip(1) {
+            match is_item(line) {
+                Some((item, description)) => {
+                    roff.control("TP", []).text([bold(item)]);
+                    if !description.is_empty() {
+                        roff.text([roman(description.trim())]);
+                    }
+                }
+                None => {
+                    roff.text([roman(line.trim())]);
+                }
+            }
+        }
+        roff
+    };
+    let exit_status = section("EXIT STATUS", EXIT_STATUS, |line| {
+        line.trim_start()
+            .split_once("  ")
+            .filter(|(status, _)| status.parse::<u8>().is_ok())
+    });
+    let examples = section("EXAMPLES", EXAMPLES, |line| {
+        (!line.starts_with("   ")).then_some((line.trim(), ""))
+    });
 
-        // Process current level of directories in parallel
-        let stats_vec: Vec<_> = work_queue
-            .par_iter()
-            .map_init(
-                || ScanStats::default(),
-                |stats, dir| {
-                    let mut subdirs = Vec::new();
+    let man = clap_mangen::Man::new(Cli::command());
+    let mut page = Vec::new();
+    let rendered = man
+        .render_title(&mut page)
+        .and_then(|_| man.render_name_section(&mut page))
+        .and_then(|_| man.render_synopsis_section(&mut page))
+        .and_then(|_| man.render_description_section(&mut page))
+        .and_then(|_| man.render_options_section(&mut page))
+        .and_then(|_| exit_status.to_writer(&mut page))
+        .and_then(|_| examples.to_writer(&mut page));
+    let mut stdout = io::stdout().lock();
+    if let Err(e) = rendered
+        .and_then(|_| stdout.write_all(&page))
+        .and_then(|_| stdout.flush())
+    {
+        eprintln!("bfinder: failed to write output: {}", e);
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
 
-                    // Scan this directory atomically, inserting directly into shared top-N
-                    if let Err(_) = scan_directory(dir, &global_top_n, stats, &mut subdirs) {
-                        stats.errors += 1;
-                    }
+/// Parse the command line, with the configuration file's settings (and those of
+/// the selected profile) inserted before the real arguments so those win; or
+/// the exit code if there is nothing more to do (a completion script or the man
+/// page was asked for)
+fn parse_args() -> Result<(Cli, ArgMatches), ExitCode> {
+    let args: Vec<OsString> = std::env::args_os().collect();
+    let user = Cli::command().get_matches_from(&args);
+    // Before the config file is read, so a broken one cannot get in the way
+    if let Some(&shell) = user.get_one::<clap_complete::Shell>("generate_completion") {
+        return Err(print_completion(shell));
+    }
+    if user.get_flag("generate_man") {
+        return Err(print_man_page());
+    }
+    let fail = |msg: String| -> ! {
+        Cli::command()
+            .error(clap::error::ErrorKind::InvalidValue, msg)
+            .exit()
+    };
 
-                    // Add subdirectories to next level (synchronized)
-                    if !subdirs.is_empty() {
-                        next_queue.lock().unwrap().extend(subdirs);
-                    }
+    let mut full = args[..1].to_vec();
+    if !user.get_flag("no_config") {
+        let explicit = user.get_one::<PathBuf>("config");
+        let loaded = match explicit.cloned().or_else(config::default_path) {
+            Some(path) => BfinderConfig::load(&path, explicit.is_some(), &Cli::command())
+                .unwrap_or_else(|e| fail(e)),
+            None => BfinderConfig::default(),
+        };
+        full.extend(loaded.defaults);
+
+        if let Some(name) = user.get_one::<String>("profile") {
+            let Some(profile) = loaded.aliases.get(name) else {
+                fail(format!("no profile named '{}' in the config file", name));
+            };
+            full.extend(profile.args.iter().cloned());
+            if user.value_source("paths") != Some(ValueSource::CommandLine) {
+                full.extend(profile.paths.iter().map(OsString::from));
+            }
+        }
+    }
+    full.extend(args[1..].iter().cloned());
+
+    let matches = Cli::command()
+        .args_override_self(true)
+        .get_matches_from(full);
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    Ok((cli, matches))
+}
+
+fn main() -> ExitCode {
+    let (cli, matches) = match parse_args() {
+        Ok(parsed) => parsed,
+        Err(code) => return code,
+    };
+    init_logging(cli.verbose);
+
+    cli.check_conflicts();
+    // Fail before anything else happens rather than in place of the scan
+    #[cfg(feature = "serde")]
+    let mut loaded = cli.load_binary.as_deref().map(|path| {
+        load_binary(path).unwrap_or_else(|msg| {
+            Cli::command()
+                .error(clap::error::ErrorKind::InvalidValue, msg)
+                .exit()
+        })
+    });
+    if cli.null && matches.value_source("top") == Some(ValueSource::CommandLine) {
+        eprintln!(
+            "bfinder: warning: --null lists only the top {} files",
+            cli.top
+        );
+    }
+
+    if cli.format == OutputFormat::JsonLines {
+        cli.check_json_lines(&matches);
+    }
+    let mut config = cli.scan_config();
+    // The files are written as they are found, so none are ranked
+    let stream_files = cli.format == OutputFormat::JsonLines && !cli.count_only;
+    if stream_files {
+        config.top = 0;
+    }
 
-                    std::mem::take(stats)
-                },
+    // Open the output before scanning so a bad path fails fast
+    let mut output: Box<dyn Write> = match &cli.output {
+        Some(path) => match fs::File::create(path) {
+            Ok(file) => Box::new(io::BufWriter::new(file)),
+            Err(e) => Cli::command()
+                .error(
+                    clap::error::ErrorKind::Io,
+                    format!("cannot create output file {}: {}", path.display(), e),
+                )
+                .exit(),
+        },
+        None => Box::new(io::BufWriter::new(io::stdout().lock())),
+    };
+    if let Some(path) = &cli.errors_output
+        && let Err(e) = fs::File::create(path)
+    {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::Io,
+                format!("cannot create errors file {}: {}", path.display(), e),
             )
-            .collect();
+            .exit();
+    }
 
-        // Aggregate stats
-        let mut global = global_stats.lock().unwrap();
-        for stats in stats_vec {
-            global.files_scanned += stats.files_scanned;
-            global.dirs_scanned += stats.dirs_scanned;
-            global.errors += stats.errors;
+    #[cfg(feature = "serde")]
+    if cli.list_snapshots {
+        let base = cli
+            .snapshot_dir
+            .as_deref()
+            .expect("--list-snapshots requires --snapshot-dir");
+        let mut stdout = io::stdout().lock();
+        if let Err(e) = snapshot_dir::list(base, &mut stdout).and_then(|_| stdout.flush()) {
+            eprintln!(
+                "bfinder: cannot list snapshots in {}: {}",
+                base.display(),
+                e
+            );
+            return ExitCode::FAILURE;
         }
-        drop(global);
+        return ExitCode::SUCCESS;
+    }
+    #[cfg(feature = "serde")]
+    let snapshot_set = cli
+        .snapshot_dir
+        .as_deref()
+        .map(|base| snapshot_dir::SnapshotSet::new(base, &config.roots));
 
-        // Move to next level
-        work_queue = next_queue.into_inner().unwrap();
+    // Read the snapshot up front so a bad file fails before the scan
+    #[cfg(feature = "serde")]
+    let snapshot = match (&cli.diff, &snapshot_set) {
+        (Some(path), _) => Some(path.clone()),
+        (None, Some(set)) => {
+            let latest = set.latest();
+            if latest.is_none() {
+                eprintln!("bfinder: no earlier snapshot of these paths, showing the full report");
+            }
+            latest
+        }
+        (None, None) => None,
     }
+    .map(|path| {
+        load_snapshot(&path).unwrap_or_else(|msg| {
+            Cli::command()
+                .error(clap::error::ErrorKind::InvalidValue, msg)
+                .exit()
+        })
+    });
 
-    let results = global_top_n.into_inner().unwrap().into_vec();
-    let stats = global_stats.into_inner().unwrap();
+    let mut pool = rayon::ThreadPoolBuilder::new();
+    if let Some(threads) = cli.threads {
+        pool = pool.num_threads(threads);
+    }
+    #[cfg(target_os = "linux")]
+    let pinned = !cli.affinity.is_empty();
+    #[cfg(not(target_os = "linux"))]
+    let pinned = false;
+    #[cfg(target_os = "linux")]
+    if pinned {
+        let mut cores = cli.affinity.clone();
+        cores.sort_unstable();
+        cores.dedup();
+        if cli.threads.is_none() {
+            pool = pool.num_threads(cores.len());
+        }
+        let warned = AtomicBool::new(false);
+        pool = pool.start_handler(move |index| pin_to_core(cores[index % cores.len()], &warned));
+    }
+    if cli.threads.is_some() || pinned {
+        pool.build_global().unwrap();
+    }
+    if !config.count_only && config.top > 0 {
+        config.tracker_reserve = check_memory_limit(
+            cli.limit_memory,
+            rayon::current_num_threads(),
+            config.top,
+            &config.roots,
+        );
+    }
+    if let Some(trees) = &cli.compare {
+        return compare_trees(&config, &trees[0], &trees[1], cli.threads, &mut output);
+    }
 
-    (results, stats)
-}
+    #[cfg(feature = "async")]
+    let runtime = (cli.engine == Engine::Async).then(|| {
+        let mut builder = tokio::runtime::Builder::new_multi_thread();
+        if let Some(threads) = cli.threads.filter(|&n| n > 0) {
+            builder
+                .worker_threads(threads)
+                .max_blocking_threads(threads);
+        }
+        builder.build().unwrap_or_else(|e| {
+            eprintln!("bfinder: cannot start the async runtime: {}", e);
+            std::process::exit(1);
+        })
+    });
+    // Cleared once io_uring turns out to be unavailable, so --watch warns once
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    let io_uring = AtomicBool::new(cli.engine == Engine::IoUring);
+    let stdin_files = cli.stdin_paths.then(|| {
+        read_stdin_files(cli.null_input).unwrap_or_else(|msg| {
+            Cli::command()
+                .error(clap::error::ErrorKind::InvalidValue, msg)
+                .exit()
+        })
+    });
+    let run_scan = |config: &ScanConfig| {
+        if let Some(files) = &stdin_files {
+            return bfinder::rank_files(files, config);
+        }
+        #[cfg(feature = "async")]
+        if let Some(runtime) = &runtime {
+            return runtime.block_on(bfinder::async_scan(config.clone()));
+        }
+        #[cfg(all(feature = "io-uring", target_os = "linux"))]
+        if io_uring.load(Ordering::Relaxed) {
+            match bfinder::io_uring_scan(config) {
+                Ok(scan) => return scan,
+                Err(e) if e.raw_os_error() == Some(libc::ENOSYS) => {
+                    eprintln!(
+                        "bfinder: warning: io_uring is not supported by this kernel; using the sync engine"
+                    );
+                    io_uring.store(false, Ordering::Relaxed);
+                }
+                Err(e) => {
+                    eprintln!("bfinder: cannot set up io_uring: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        parallel_scan(config)
+    };
 
-/// Format file size in human-readable format
-fn format_size(size: u64) -> String {
-    const KB: u64 = 1024;
-    const MB: u64 = KB * 1024;
-    const GB: u64 = MB * 1024;
+    if cli.watch.is_some() {
+        // SAFETY: the handler only stores to an atomic and calls signal(), both
+        // async-signal-safe
+        unsafe {
+            libc::signal(
+                libc::SIGINT,
+                on_sigint as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            );
+        }
+    }
+    // Drawn once so every --watch rescan samples alike
+    let seed = cli.sort_seed.unwrap_or_else(rand::random);
+    let mut previous: Option<Vec<FileEntry>> = None;
+    let mut scans = 0;
+    let (results, stats) = loop {
+        let runs = cli.benchmark.unwrap_or(1) as usize;
+        let mut timings = Timings(Vec::with_capacity(runs));
+        let (mut results, mut stats) = loop {
+            let started = Instant::now();
+            #[cfg(feature = "serde")]
+            if let Some(loaded) = loaded.take() {
+                break loaded;
+            }
+            let scan_once = |config: &ScanConfig| match (cli.exclude_top, cli.random_sample) {
+                (Some(n), _) => scan_excluding_top(config, n, run_scan),
+                (None, Some(n)) => scan_sampled(config, n, seed, run_scan),
+                (None, None) => run_scan(config),
+            };
+            let scan = if stream_files {
+                let roots = cli.relative.then_some(config.roots.as_slice());
+                scan_streaming(&config, roots, &mut output, scan_once)
+            } else if (cli.progress && io::stdout().is_terminal()) || cli.estimate_time {
+                let progress = Arc::new(ScanProgress::default());
+                config.progress = Some(Arc::clone(&progress));
+                let spinner = cli.progress && io::stdout().is_terminal();
+                let estimate = cli.estimate_time.then(|| Estimate::new(config.roots.len()));
+                scan_with_progress(&progress, spinner, estimate, || scan_once(&config))
+            } else {
+                scan_once(&config)
+            };
+            timings.0.push(started.elapsed());
+            if timings.0.len() == runs {
+                break scan;
+            }
+        };
+        if cli.benchmark.is_some() {
+            let _ = timings.report(stats.files_scanned, &mut io::stderr());
+        }
+        if cli.syscall_stats {
+            let _ = report_syscalls(&stats, config.roots.len() as u64, &mut io::stderr());
+        }
+        if cli.verbose_errors {
+            for error in &stats.error_details {
+                eprintln!("bfinder: {}", error);
+            }
+        }
+        // Hash before --relative rewrites the paths the files are opened by
+        if let (Some(algorithm), Some(results)) = (cli.checksum, results.as_mut()) {
+            let bar = if cli.progress && io::stdout().is_terminal() {
+                ProgressBar::new(results.len() as u64)
+            } else {
+                ProgressBar::hidden()
+            };
+            bar.set_style(ProgressStyle::with_template("hashing {pos}/{len} {wide_bar}").unwrap());
+            for error in add_checksums(results, algorithm, &|| bar.inc(1)) {
+                eprintln!("bfinder: {}", error);
+            }
+            bar.finish_and_clear();
+        }
+        #[cfg(target_os = "linux")]
+        if let (true, Some(results)) = (cli.reflink_detect, results.as_mut()) {
+            let (unsupported, errors) = bfinder::reflink::detect_reflinks(results);
+            for error in errors {
+                eprintln!("bfinder: {}", error);
+            }
+            if unsupported > 0 {
+                eprintln!(
+                    "bfinder: warning: {} of the reported files are on filesystems without extent maps; \
+                     reflinks were not detected there",
+                    unsupported
+                );
+            }
+        }
+        if let (true, Some(results)) = (cli.verify || cli.verify_strict, results.as_mut()) {
+            for error in bfinder::verify::verify_results(results, &config) {
+                eprintln!("bfinder: {}", error);
+            }
+            if cli.verify_strict {
+                bfinder::verify::rerank_verified(results, cli.find_smallest);
+                count_results(&mut stats.filesystems, results);
+            }
+        }
+        #[cfg(target_os = "linux")]
+        let quotas = if cli.quota_report {
+            read_quotas(&config.roots, &stats)
+        } else {
+            Vec::new()
+        };
+        #[cfg(not(target_os = "linux"))]
+        let quotas = Vec::new();
+        if cli.relative {
+            strip_roots(results.as_deref_mut(), &mut stats, &config.roots);
+        }
+        // Smallest-first results are already in rank order; sorting by rank would flip them
+        if let Some(results) = results.as_mut()
+            && !(cli.find_smallest && matches!(cli.sort, SortOrder::Size | SortOrder::Blocks))
+        {
+            sort_results(results, cli.sort);
+        }
 
-    if size >= GB {
-        format!("{:.2} GB", size as f64 / GB as f64)
-    } else if size >= MB {
-        format!("{:.2} MB", size as f64 / MB as f64)
-    } else if size >= KB {
-        format!("{:.2} KB", size as f64 / KB as f64)
-    } else {
-        format!("{} bytes", size)
+        #[cfg(feature = "serde")]
+        {
+            if let (Some(path), Some(results)) = (&cli.save_snapshot, &results)
+                && let Err(msg) = save_snapshot(path, results)
+            {
+                eprintln!("bfinder: {}", msg);
+                return ExitCode::FAILURE;
+            }
+            if let (Some(set), Some(results)) = (&snapshot_set, &results) {
+                let base = cli
+                    .snapshot_dir
+                    .as_deref()
+                    .expect("a snapshot set has a directory");
+                let saved = match set.save(results) {
+                    Ok(path) => path,
+                    Err(msg) => {
+                        eprintln!("bfinder: {}", msg);
+                        return ExitCode::FAILURE;
+                    }
+                };
+                match snapshot_dir::purge(base, cli.snapshot_max_age, &saved) {
+                    Ok(0) => {}
+                    Ok(purged) => {
+                        tracing::info!(purged, "deleted snapshots past --snapshot-max-age")
+                    }
+                    Err(e) => eprintln!(
+                        "bfinder: warning: cannot purge snapshots in {}: {}",
+                        base.display(),
+                        e
+                    ),
+                }
+            }
+            if let Some(old) = &snapshot {
+                let diff = bfinder::diff::diff_scans(old, results.as_deref().unwrap_or_default());
+                if let Err(e) = render_diff_text(&diff, &mut output).and_then(|_| output.flush()) {
+                    eprintln!("bfinder: failed to write output: {}", e);
+                    return ExitCode::FAILURE;
+                }
+                return ExitCode::SUCCESS;
+            }
+        }
+
+        let to_terminal = cli.output.is_none() && io::stdout().is_terminal();
+        #[cfg(feature = "serde")]
+        let since_snapshot = cli.since_snapshot.is_some();
+        #[cfg(not(feature = "serde"))]
+        let since_snapshot = false;
+        #[cfg(target_os = "linux")]
+        let reflink_detect = cli.reflink_detect;
+        #[cfg(not(target_os = "linux"))]
+        let reflink_detect = false;
+        let opts = OutputOptions {
+            format: cli.format,
+            csv_delimiter: cli.csv_delimiter,
+            csv_stats: cli.csv_stats,
+            sort: cli.sort,
+            show_time: !cli.no_time,
+            smallest: cli.find_smallest,
+            sampled: cli.random_sample.is_some(),
+            disk_usage: cli.disk_usage,
+            group_by_extension: match cli.top_extensions {
+                Some(n) => Some(n),
+                None => cli.group_by_extension.then_some(cli.top),
+            },
+            top_per_dir: cli.top_per_dir.is_some(),
+            top_per_user: cli.top_per_user.is_some(),
+            resolve_names: cli.resolve_names,
+            quotas,
+            largest_dirs: cli.largest_dirs.is_some(),
+            top_dirs: cli.top_dirs.is_some(),
+            text_stats: cli.output.is_none(),
+            null_terminated: cli.null,
+            du_compat: cli.du_compat,
+            inode_usage: cli.inode_usage.is_some(),
+            inode_usage_only: cli.inode_usage_only,
+            empty_dirs: cli.report_empty_dirs,
+            zero_sized: cli.zero_sized || cli.zero_sized_count,
+            zero_sized_count: cli.zero_sized_count,
+            scan_errors: cli.report_errors && cli.errors_output.is_none(),
+            inaccessible_dirs: cli.report_inaccessible,
+            age_labels: config
+                .age_buckets
+                .as_ref()
+                .map(AgeBuckets::labels)
+                .unwrap_or_default(),
+            removed: since_snapshot,
+            duplicate_sizes: cli.duplicate_sizes,
+            hash_duplicates: cli.hash_duplicates,
+            tree: cli.tree,
+            tree_depth: cli.max_depth,
+            terminal_width: if to_terminal {
+                terminal_size::terminal_size().map(|(width, _)| width.0 as usize)
+            } else {
+                None
+            },
+            show_sparseness: cli.sparse,
+            show_links: cli.min_hardlinks.is_some() || cli.max_hardlinks.is_some(),
+            show_mode: config.mode_bits != 0 || cli.permissions.is_some() || cli.verbose > 0,
+            show_allocated: cli.sort == SortOrder::Blocks,
+            colors: cli.color.enabled(to_terminal).then_some(SizeColors {
+                warn: cli.color_threshold_warn,
+                error: cli.color_threshold_error,
+            }),
+            reflinks: reflink_detect,
+            summarize: cli.summarize,
+            new_paths: previous
+                .as_ref()
+                .map(|previous| new_paths(previous, results.as_deref().unwrap_or_default())),
+        };
+        if cli.watch.is_some() {
+            use crossterm::cursor::MoveTo;
+            use crossterm::terminal::{Clear, ClearType};
+            let _ = crossterm::queue!(output, Clear(ClearType::All), MoveTo(0, 0));
+        }
+        if let Err(e) = format_output(results.as_deref(), &stats, &opts, &mut output)
+            .and_then(|_| output.flush())
+        {
+            eprintln!("bfinder: failed to write output: {}", e);
+            return ExitCode::FAILURE;
+        }
+        if let Some(path) = &cli.errors_output
+            && let Err(e) = write_errors_file(path, &stats)
+        {
+            eprintln!(
+                "bfinder: cannot write errors file {}: {}",
+                path.display(),
+                e
+            );
+            return ExitCode::FAILURE;
+        }
+
+        let Some(interval) = cli.watch else {
+            break (results, stats);
+        };
+        scans += 1;
+        let current = results.unwrap_or_default();
+        if let Some(previous) = &previous {
+            let removed = removed_entries(previous, &current);
+            let _ = render_removed(&removed, opts.colors.is_some(), &mut output);
+        }
+        let _ = writeln!(output);
+        if !countdown(interval, &mut output) {
+            let _ = writeln!(output);
+            let _ = writeln!(output);
+            let plural = if scans == 1 { "" } else { "s" };
+            let _ = writeln!(
+                output,
+                "Stopped after {} scan{}; the last one found:",
+                scans, plural
+            );
+            let _ = render_stats_only(&stats, OutputFormat::Text, &mut output);
+            let _ = output.flush();
+            return ExitCode::SUCCESS;
+        }
+        previous = Some(current);
+    };
+    if cli.output.is_some() && cli.format.is_text() && !cli.null && results.is_some() {
+        let _ = render_stats_only(&stats, OutputFormat::Text, &mut io::stderr());
     }
+    if stats.aborted {
+        eprintln!(
+            "bfinder: scan aborted after {} errors (--max-errors)",
+            stats.errors
+        );
+        return ExitCode::FAILURE;
+    }
+    if cli.report_inaccessible && stats.dirs_inaccessible > 0 {
+        return ExitCode::FAILURE;
+    }
+    let results = results.unwrap_or_default();
+    if cli.fail_if_fewer && results.len() < cli.top {
+        return ExitCode::from(EXIT_FEWER);
+    }
+    if let Some(threshold) = cli.fail_threshold_size
+        && results
+            .iter()
+            .map(|e| e.size)
+            .max()
+            .is_none_or(|largest| largest < threshold)
+    {
+        return ExitCode::from(EXIT_BELOW_THRESHOLD);
+    }
+    ExitCode::SUCCESS
 }
 
-fn main() {
-    let cli = Cli::parse();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    if let Some(threads) = cli.threads {
-        rayon::ThreadPoolBuilder::new()
-            .num_threads(threads)
-            .build_global()
-            .unwrap();
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn cores_must_exist() {
+        assert_eq!(parse_core("0"), Ok(0));
+        assert_eq!(parse_core(" 0"), Ok(0));
+        assert!(
+            parse_core("100000")
+                .unwrap_err()
+                .starts_with("CPU core 100000 does not exist")
+        );
+        assert!(parse_core("first").is_err());
+    }
+
+    #[test]
+    fn time_left_to_the_second() {
+        assert_eq!(format_time_left(Duration::from_millis(12_900)), "12s");
+        assert_eq!(format_time_left(Duration::from_secs(190)), "3m 10s");
+        assert_eq!(
+            format_time_left(Duration::from_secs(3600 + 2 * 60 + 59)),
+            "1h 02m"
+        );
+    }
+
+    #[test]
+    fn exclude_files_skip_comments_and_blank_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("excludes");
+        fs::write(&file, "# build output\ntarget\n\n  *.tmp  \n").unwrap();
+        let set = build_exclude_set(&["node_modules".to_string()], &[file]).unwrap();
+        assert_eq!(set.len(), 3);
+        assert!(set.is_match("target") && set.is_match("x.tmp") && set.is_match("node_modules"));
+        assert!(!set.is_match("# build output"));
+        assert!(build_exclude_set(&["a[".to_string()], &[]).is_err());
+    }
+
+    #[test]
+    fn delimiters() {
+        assert_eq!(parse_delimiter_arg(";"), Ok(';'));
+        assert_eq!(parse_delimiter_arg("\\t"), Ok('\t'));
+        assert_eq!(parse_delimiter_arg("tab"), Ok('\t'));
+        assert!(parse_delimiter_arg(",,").is_err());
+        assert!(parse_delimiter_arg("\"").is_err());
     }
 
-    let start = std::time::Instant::now();
-    let (results, stats) = parallel_scan(cli.path, cli.top);
-    let elapsed = start.elapsed();
+    #[test]
+    fn benchmark_timings() {
+        let timings = Timings([2, 4, 4, 4, 5, 5, 7, 9].map(Duration::from_secs).to_vec());
+        assert_eq!(timings.min(), Duration::from_secs(2));
+        assert_eq!(timings.max(), Duration::from_secs(9));
+        assert_eq!(timings.mean(), 5.0);
+        assert!((timings.stddev() - (32.0f64 / 7.0).sqrt()).abs() < 1e-9);
+        assert_eq!(Timings(vec![Duration::from_secs(3)]).stddev(), 0.0);
 
-    // Output results
-    println!("Top {} largest files:", cli.top);
-    println!();
-    for (i, entry) in results.iter().enumerate() {
-        println!(
-            "{:4}. {:>12}  {}",
-            i + 1,
-            format_size(entry.size),
-            entry.path.display()
+        let mut out = Vec::new();
+        timings.report(400, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("Benchmark (8 runs):\n"), "{}", out);
+        assert!(
+            out.ends_with("  Throughput:          80 files/s\n"),
+            "{}",
+            out
         );
     }
 
-    println!();
-    println!("Statistics:");
-    println!("  Files scanned:       {}", stats.files_scanned);
-    println!("  Directories scanned: {}", stats.dirs_scanned);
-    println!("  Errors:              {}", stats.errors);
-    println!("  Time elapsed:        {:.3}s", elapsed.as_secs_f64());
+    #[test]
+    fn watch_compares_paths_with_the_previous_scan() {
+        let entry = |path: &str, size| FileEntry {
+            size,
+            path: PathBuf::from(path),
+            modified: None,
+            accessed: None,
+            hash: None,
+            sparseness: 0.0,
+            checksum: None,
+            is_reflinked: false,
+            physical_size: 0,
+            allocated_size: 0,
+            xattr_size: 0,
+            nlink: 0,
+            mode: 0,
+            is_dir: false,
+            verified: None,
+        };
+        let previous = [entry("/a", 3), entry("/b", 2), entry("/c", 1)];
+        let current = [entry("/d", 9), entry("/a", 4), entry("/c", 1)];
+        assert_eq!(
+            new_paths(&previous, &current),
+            HashSet::from([PathBuf::from("/d")])
+        );
+        assert_eq!(removed_entries(&previous, &current), [entry("/b", 2)]);
+        assert!(new_paths(&current, &current).is_empty());
+    }
+
+    /// A directory holding a file of each size, named by its index
+    fn sized_files(sizes: &[usize]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for (i, &size) in sizes.iter().enumerate() {
+            fs::write(dir.path().join(format!("f{}", i)), vec![b'x'; size]).unwrap();
+        }
+        dir
+    }
+
+    fn exclude_top(
+        dir: &std::path::Path,
+        top: usize,
+        n: usize,
+        smallest: bool,
+    ) -> Vec<(u64, String)> {
+        let mut config = ScanConfig::new(dir);
+        config.top = top;
+        config.find_smallest = smallest;
+        let results = scan_excluding_top(&config, n, parallel_scan).0.unwrap();
+        results
+            .into_iter()
+            .map(|e| {
+                (
+                    e.size,
+                    e.path.file_name().unwrap().to_string_lossy().into_owned(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn exclude_top_skips_the_largest() {
+        let dir = sized_files(&[500, 400, 300, 200, 100]);
+        let expected = [(300, "f2".to_string()), (200, "f3".to_string())];
+        assert_eq!(exclude_top(dir.path(), 2, 2, false), expected);
+        // More outliers than files leaves nothing
+        assert!(exclude_top(dir.path(), 2, 10, false).is_empty());
+    }
+
+    #[test]
+    fn exclude_top_with_ties_at_the_boundary() {
+        // Four files share the second largest size; exactly two files are left
+        // out, and the other ties rank right after them
+        let dir = sized_files(&[900, 300, 300, 300, 300, 100]);
+        let results = exclude_top(dir.path(), 10, 2, false);
+        assert_eq!(results.len(), 4, "{:?}", results);
+        let sizes: Vec<_> = results.iter().map(|(size, _)| *size).collect();
+        assert_eq!(sizes, [300, 300, 300, 100]);
+        // The excluded tie is the best ranked, as in the first scan
+        let kept: Vec<_> = results.iter().map(|(_, name)| name.as_str()).collect();
+        assert_eq!(kept, ["f3", "f2", "f1", "f5"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn exclude_top_ranked_by_blocks() {
+        // The sparse file is the largest but holds no blocks, so it ranks last
+        let dir = tempfile::tempdir().unwrap();
+        for (name, size) in [
+            ("dense", 195 * 1024),
+            ("mid", 100 * 1024),
+            ("small", 10 * 1024),
+        ] {
+            fs::write(dir.path().join(name), vec![b'x'; size]).unwrap();
+        }
+        fs::File::create(dir.path().join("sparse"))
+            .unwrap()
+            .set_len(1 << 20)
+            .unwrap();
+        let mut config = ScanConfig::new(dir.path());
+        config.top = 10;
+        config.rank_by_blocks = true;
+        let results = scan_excluding_top(&config, 1, parallel_scan).0.unwrap();
+        let names: Vec<_> = results
+            .iter()
+            .map(|e| e.path.file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(names, ["mid", "small", "sparse"]);
+        assert_eq!(results[2].size, 1 << 20);
+    }
+
+    #[test]
+    fn exclude_top_skips_the_smallest() {
+        let dir = sized_files(&[100, 200, 200, 300, 400]);
+        // One of the two files of 200 bytes is the second smallest
+        let results = exclude_top(dir.path(), 2, 2, true);
+        assert_eq!(results, [(200, "f2".to_string()), (300, "f3".to_string())]);
+    }
 }
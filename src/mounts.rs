@@ -0,0 +1,206 @@
+//! The mounted filesystems, from `/proc/self/mountinfo` on Linux and
+//! `getmntinfo` on macOS, and recognizing the virtual filesystems of Linux
+//! (`/proc`, `/sys`, `/dev`), whose files describe the kernel rather than use
+//! disk space
+
+use crate::MountInfo;
+use std::io;
+use std::path::PathBuf;
+#[cfg(target_os = "linux")]
+use std::{collections::HashMap, fs, sync::OnceLock};
+
+/// A kernel filesystem the scanner can skip
+#[cfg(target_os = "linux")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VirtualFs {
+    /// `proc`, home of `/proc/kcore`, as large as the address space
+    Proc,
+    /// `sysfs`, whose attributes often fail to read
+    Sys,
+    /// `devtmpfs`, the device nodes of `/dev`
+    Dev,
+}
+
+#[cfg(target_os = "linux")]
+impl VirtualFs {
+    /// Every virtual filesystem, which are all skipped by default
+    pub const ALL: [VirtualFs; 3] = [VirtualFs::Proc, VirtualFs::Sys, VirtualFs::Dev];
+
+    /// The type `/proc/self/mountinfo` gives this filesystem
+    pub fn fs_type(self) -> &'static str {
+        match self {
+            VirtualFs::Proc => "proc",
+            VirtualFs::Sys => "sysfs",
+            VirtualFs::Dev => "devtmpfs",
+        }
+    }
+}
+
+/// Undo the octal escapes (`\040` for a space) of mountinfo and mtab fields
+#[cfg(target_os = "linux")]
+pub(crate) fn unescape(field: &str) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+
+    let bytes = field.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let octal = bytes.get(i + 1..i + 4).and_then(|digits| {
+            let digits = std::str::from_utf8(digits).ok()?;
+            u8::from_str_radix(digits, 8).ok()
+        });
+        match (bytes[i], octal) {
+            (b'\\', Some(byte)) => {
+                out.push(byte);
+                i += 4;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    PathBuf::from(std::ffi::OsStr::from_bytes(&out))
+}
+
+/// The device number and description of a mountinfo line such as
+/// `22 28 0:21 / /proc rw,nosuid shared:13 - proc proc rw`
+#[cfg(target_os = "linux")]
+fn parse_mount(line: &str) -> Option<(u64, MountInfo)> {
+    let (mount, fs) = line.split_once(" - ")?;
+    let mut fields = mount.split_whitespace();
+    let (major, minor) = fields.nth(2)?.split_once(':')?;
+    let path = unescape(fields.nth(1)?);
+    let mut fs = fs.split_whitespace();
+    let fstype = fs.next()?.to_string();
+    let device = unescape(fs.next()?).to_string_lossy().into_owned();
+    let dev = libc::makedev(major.parse().ok()?, minor.parse().ok()?);
+    Some((
+        dev,
+        MountInfo {
+            path,
+            fstype,
+            device,
+            skipped: false,
+        },
+    ))
+}
+
+/// Every mounted filesystem with its device number (`st_dev`), in mount order
+#[cfg(target_os = "linux")]
+pub fn mount_table() -> io::Result<Vec<(u64, MountInfo)>> {
+    let info = fs::read_to_string("/proc/self/mountinfo")?;
+    Ok(info.lines().filter_map(parse_mount).collect())
+}
+
+/// Every mounted filesystem with its device number (`st_dev`), in mount order
+#[cfg(target_os = "macos")]
+pub fn mount_table() -> io::Result<Vec<(u64, MountInfo)>> {
+    use std::ffi::CStr;
+    use std::os::unix::fs::MetadataExt;
+
+    let mut mounts: *mut libc::statfs = std::ptr::null_mut();
+    // SAFETY: on success `mounts` points to `count` statfs records owned by libc
+    let count = unsafe { libc::getmntinfo(&mut mounts, libc::MNT_NOWAIT) };
+    if count <= 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: see above; the records stay valid until the next call
+    let mounts = unsafe { std::slice::from_raw_parts(mounts, count as usize) };
+    let text = |field: &[libc::c_char]| {
+        // SAFETY: the name fields are NUL-terminated strings
+        unsafe { CStr::from_ptr(field.as_ptr()) }
+            .to_string_lossy()
+            .into_owned()
+    };
+    Ok(mounts
+        .iter()
+        .filter_map(|mount| {
+            let path = PathBuf::from(text(&mount.f_mntonname));
+            let dev = std::fs::metadata(&path).ok()?.dev() as u64;
+            let info = MountInfo {
+                path,
+                fstype: text(&mount.f_fstypename),
+                device: text(&mount.f_mntfromname),
+                skipped: false,
+            };
+            Some((dev, info))
+        })
+        .collect())
+}
+
+/// Virtual filesystems by device number, read from `/proc/self/mountinfo` the
+/// first time it is needed. Empty if it cannot be read.
+#[cfg(target_os = "linux")]
+fn mounted() -> &'static HashMap<u64, VirtualFs> {
+    static MOUNTED: OnceLock<HashMap<u64, VirtualFs>> = OnceLock::new();
+    MOUNTED.get_or_init(|| {
+        mount_table()
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|(dev, mount)| {
+                VirtualFs::ALL
+                    .into_iter()
+                    .find(|kind| kind.fs_type() == mount.fstype)
+                    .map(|kind| (dev, kind))
+            })
+            .collect()
+    })
+}
+
+/// Which virtual filesystem, if any, is mounted with device number `dev`
+#[cfg(target_os = "linux")]
+pub fn virtual_filesystem(dev: u64) -> Option<VirtualFs> {
+    mounted().get(&dev).copied()
+}
+
+/// Whether device number `dev` (`st_dev`) is a proc, sysfs or devtmpfs mount
+#[cfg(target_os = "linux")]
+pub fn is_virtual_filesystem(dev: u64) -> bool {
+    virtual_filesystem(dev).is_some()
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::MetadataExt;
+    use std::path::Path;
+
+    #[test]
+    fn unescapes_fields() {
+        assert_eq!(unescape("/mnt/my\\040disk"), Path::new("/mnt/my disk"));
+        assert_eq!(
+            unescape("/mnt/tab\\011and\\134slash"),
+            Path::new("/mnt/tab\tand\\slash")
+        );
+        // A backslash not followed by three octal digits is kept
+        assert_eq!(unescape("/mnt/odd\\9"), Path::new("/mnt/odd\\9"));
+    }
+
+    #[test]
+    fn parses_mountinfo_lines() {
+        let line = "36 28 8:1 / /mnt/my\\040disk rw,relatime shared:9 master:1 - ext4 /dev/sda1 rw";
+        let (dev, mount) = parse_mount(line).unwrap();
+        assert_eq!(dev, libc::makedev(8, 1));
+        assert_eq!(
+            mount,
+            MountInfo {
+                path: "/mnt/my disk".into(),
+                fstype: "ext4".to_string(),
+                device: "/dev/sda1".to_string(),
+                skipped: false,
+            }
+        );
+        assert_eq!(parse_mount("not a mount"), None);
+    }
+
+    #[test]
+    fn recognizes_proc() {
+        let Ok(proc) = fs::metadata("/proc/self") else {
+            return;
+        };
+        assert_eq!(virtual_filesystem(proc.dev()), Some(VirtualFs::Proc));
+        let here = fs::metadata(env!("CARGO_MANIFEST_DIR")).unwrap();
+        assert!(!is_virtual_filesystem(here.dev()));
+    }
+}
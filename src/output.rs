@@ -0,0 +1,2571 @@
+//! Renderers for scan results
+
+use crate::diff::{ScanDiff, SizeChange, TreeDifference};
+use crate::tree::{TreeNode, build_tree};
+use crate::{
+    FileEntry, QuotaInfo, ScanStats, SizeStyle, SortOrder, Verification, format_size,
+    format_size_styled, format_time, unix_timestamp,
+};
+use clap::ValueEnum;
+use owo_colors::{OwoColorize, Style};
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Rendering used for the final report
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable listing followed by statistics
+    Text,
+    /// A single JSON object with `results` and `stats` keys. Paths are
+    /// written with `%` and bytes that are not UTF-8 percent-encoded.
+    Json,
+    /// JSON Lines: an object per file with `"type":"file"` (`"dir"` for the
+    /// directories of `ScanConfig::include_dirs`), then one with
+    /// `"type":"stats"`. Files are written as the scan finds them.
+    #[value(name = "jsonlines")]
+    JsonLines,
+    /// A header row followed by one row per file, with paths encoded as in
+    /// `json`
+    Csv,
+    /// Like `text`, with columns sized to their contents and paths shortened
+    /// to fit the terminal
+    Table,
+    /// A compact binary snapshot of the results and statistics, for
+    /// `--load-binary` (see `snapshot`)
+    #[cfg(feature = "serde")]
+    Binary,
+}
+
+impl OutputFormat {
+    /// Whether this is one of the human-readable formats
+    pub fn is_text(self) -> bool {
+        matches!(self, OutputFormat::Text | OutputFormat::Table)
+    }
+}
+
+/// Size thresholds at which file sizes are highlighted in text output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeColors {
+    /// Sizes from here on are yellow
+    pub warn: u64,
+    /// Sizes from here on are red
+    pub error: u64,
+}
+
+impl SizeColors {
+    /// Style for a file of `size` bytes; small files keep the default color
+    fn style(&self, size: u64) -> Style {
+        if size >= self.error {
+            Style::new().red()
+        } else if size >= self.warn {
+            Style::new().yellow()
+        } else {
+            Style::new()
+        }
+    }
+}
+
+/// Output settings gathered from the command line
+pub struct OutputOptions {
+    pub format: OutputFormat,
+    pub csv_delimiter: char,
+    pub csv_stats: bool,
+    /// Order the results were sorted in; `Ext` groups text output by extension
+    pub sort: SortOrder,
+    /// Show modification times in text output
+    pub show_time: bool,
+    /// Results are the smallest files rather than the largest
+    pub smallest: bool,
+    /// Results are a random sample (`weighted_sample`) rather than the largest
+    pub sampled: bool,
+    /// Sizes are allocated disk usage rather than apparent size
+    pub disk_usage: bool,
+    /// Report per-extension totals (at most this many groups) instead of files
+    pub group_by_extension: Option<usize>,
+    /// Report the per-directory results in `ScanStats::per_dir` instead of files
+    pub top_per_dir: bool,
+    /// Report the per-user results in `ScanStats::per_user` instead of files
+    pub top_per_user: bool,
+    /// Show users by name rather than UID where they have one
+    pub resolve_names: bool,
+    /// Quotas by mount point to show under each user of `ScanStats::per_user`
+    pub quotas: Vec<(PathBuf, QuotaInfo)>,
+    /// Report the directories in `ScanStats::largest_dirs` instead of files
+    pub largest_dirs: bool,
+    /// Report the directory totals in `ScanStats::top_dirs` instead of files
+    pub top_dirs: bool,
+    /// End text reports with the statistics block
+    pub text_stats: bool,
+    /// Print bare NUL-terminated paths (for `xargs -0`) instead of `format`
+    pub null_terminated: bool,
+    /// Print `du -h` style `SIZE<TAB>PATH` lines instead of `format`
+    pub du_compat: bool,
+    /// Add the directories in `ScanStats::inode_usage` after the files
+    pub inode_usage: bool,
+    /// Report only `ScanStats::inode_usage`, without the files
+    pub inode_usage_only: bool,
+    /// Add the directories in `ScanStats::empty_dirs` after the files
+    pub empty_dirs: bool,
+    /// Add the files in `ScanStats::zero_sized` after the others
+    pub zero_sized: bool,
+    /// Give only the number of zero-sized files, not their paths
+    pub zero_sized_count: bool,
+    /// List the errors in `ScanStats::error_details` after the report in text
+    /// output, and under `scan_errors` in JSON
+    pub scan_errors: bool,
+    /// List the directories in `ScanStats::inaccessible_dirs` after the
+    /// report in text output, and under `inaccessible_dirs` in JSON
+    pub inaccessible_dirs: bool,
+    /// Labels of the bins in `ScanStats::age_buckets` (`AgeBuckets::labels`),
+    /// reported after the files; empty for no report
+    pub age_labels: Vec<String>,
+    /// The files are those changed since a baseline; add the baseline's
+    /// files in `ScanStats::removed` after them
+    pub removed: bool,
+    /// Report the groups of same-size files in `ScanStats::size_groups`
+    pub duplicate_sizes: bool,
+    /// Report the identical files in `ScanStats::duplicates` instead
+    pub hash_duplicates: bool,
+    /// Show the files in text output as a tree of their directories
+    pub tree: bool,
+    /// Deepest tree level whose contents are shown; deeper directories are
+    /// summarized by their size
+    pub tree_depth: Option<usize>,
+    /// Columns available to `table` output; paths are not shortened without it
+    pub terminal_width: Option<usize>,
+    /// Highlight sizes, ranks and paths of files in text output with ANSI colors
+    pub colors: Option<SizeColors>,
+    /// Show each file's `sparseness` in text output
+    pub show_sparseness: bool,
+    /// Show each file's hard link count in text output
+    pub show_links: bool,
+    /// Show each file's permissions in text output, as `ls -l` does
+    pub show_mode: bool,
+    /// Show each file's allocated blocks (`FileEntry::allocated_size`) next
+    /// to its size in text output
+    pub show_allocated: bool,
+    /// Files that were not reported by the previous `--watch` scan, shown in
+    /// green when `colors` is set
+    pub new_paths: Option<HashSet<PathBuf>>,
+    /// Mark reflinked files in text output and total their unshared extents
+    /// (`FileEntry::physical_size`)
+    pub reflinks: bool,
+    /// Report only the total of the results and their share of
+    /// `ScanStats::matched_size`, without the files
+    pub summarize: bool,
+}
+
+/// Render the final report in the requested format. Without results (count-only
+/// scans) only the statistics are written.
+pub fn format_output(
+    results: Option<&[FileEntry]>,
+    stats: &ScanStats,
+    opts: &OutputOptions,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    if opts.null_terminated {
+        return render_null(results.unwrap_or_default(), stats, opts, writer);
+    }
+    if opts.du_compat {
+        return render_du(results.unwrap_or_default(), stats, opts, writer);
+    }
+    render_report(results, stats, opts, writer)?;
+    if opts.scan_errors && opts.format.is_text() {
+        writeln!(writer)?;
+        write_scan_errors_section(stats, writer)?;
+    }
+    if opts.inaccessible_dirs && opts.format.is_text() {
+        writeln!(writer)?;
+        write_inaccessible_dirs_section(stats, writer)?;
+    }
+    Ok(())
+}
+
+/// Everything `format_output` writes but the error list closing text output
+fn render_report(
+    results: Option<&[FileEntry]>,
+    stats: &ScanStats,
+    opts: &OutputOptions,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    let Some(results) = results else {
+        if opts.empty_dirs && opts.format.is_text() {
+            write_empty_dirs_section(stats, writer)?;
+            writeln!(writer)?;
+        }
+        if (opts.empty_dirs || opts.scan_errors || opts.inaccessible_dirs)
+            && opts.format == OutputFormat::Json
+        {
+            write!(writer, "{{\"stats\":")?;
+            write_json_stats(stats, writer)?;
+            if opts.empty_dirs {
+                write_json_empty_dirs(stats, writer)?;
+            }
+            if opts.scan_errors {
+                write_json_scan_errors(stats, writer)?;
+            }
+            if opts.inaccessible_dirs {
+                write_json_inaccessible_dirs(stats, writer)?;
+            }
+            return writeln!(writer, "}}");
+        }
+        return render_stats_only(stats, opts.format, writer);
+    };
+    if let Some(limit) = opts.group_by_extension {
+        match opts.format {
+            OutputFormat::Text | OutputFormat::Table => {
+                return render_extension_text(stats, limit, opts, writer);
+            }
+            OutputFormat::Csv => return render_extension_csv(stats, limit, opts, writer),
+            OutputFormat::Json | OutputFormat::JsonLines => {}
+            #[cfg(feature = "serde")]
+            OutputFormat::Binary => {}
+        }
+    }
+    if opts.summarize && opts.format.is_text() {
+        return render_summary_text(results, stats, opts, writer);
+    }
+    if opts.top_per_user {
+        match opts.format {
+            OutputFormat::Text | OutputFormat::Table => {
+                return render_per_user_text(stats, opts, writer);
+            }
+            OutputFormat::Csv => return render_per_user_csv(stats, opts, writer),
+            OutputFormat::Json | OutputFormat::JsonLines => {}
+            #[cfg(feature = "serde")]
+            OutputFormat::Binary => {}
+        }
+    }
+    if opts.top_per_dir {
+        match opts.format {
+            OutputFormat::Text | OutputFormat::Table => {
+                return render_per_dir_text(stats, opts, writer);
+            }
+            OutputFormat::Csv => return render_per_dir_csv(stats, opts, writer),
+            OutputFormat::Json | OutputFormat::JsonLines => {}
+            #[cfg(feature = "serde")]
+            OutputFormat::Binary => {}
+        }
+    }
+    if opts.hash_duplicates {
+        match opts.format {
+            OutputFormat::Text | OutputFormat::Table => {
+                return render_hashed_text(stats, opts, writer);
+            }
+            OutputFormat::Csv => return render_hashed_csv(stats, opts, writer),
+            OutputFormat::Json | OutputFormat::JsonLines => {}
+            #[cfg(feature = "serde")]
+            OutputFormat::Binary => {}
+        }
+    }
+    if opts.duplicate_sizes {
+        match opts.format {
+            OutputFormat::Text | OutputFormat::Table => {
+                return render_duplicates_text(stats, opts, writer);
+            }
+            OutputFormat::Csv => return render_duplicates_csv(stats, opts, writer),
+            OutputFormat::Json | OutputFormat::JsonLines => {}
+            #[cfg(feature = "serde")]
+            OutputFormat::Binary => {}
+        }
+    }
+    if opts.inode_usage_only {
+        match opts.format {
+            OutputFormat::Text | OutputFormat::Table => {
+                return render_inodes_text(stats, opts, writer);
+            }
+            OutputFormat::Csv => return render_inodes_csv(stats, opts, writer),
+            OutputFormat::Json | OutputFormat::JsonLines => {}
+            #[cfg(feature = "serde")]
+            OutputFormat::Binary => {}
+        }
+    }
+    if opts.tree && opts.format.is_text() {
+        return render_tree_text(results, stats, opts, writer);
+    }
+    if opts.largest_dirs {
+        match opts.format {
+            OutputFormat::Text | OutputFormat::Table => {
+                return render_dirs_text(stats, opts, writer);
+            }
+            OutputFormat::Csv => return render_dirs_csv(stats, opts, writer),
+            OutputFormat::Json | OutputFormat::JsonLines => {}
+            #[cfg(feature = "serde")]
+            OutputFormat::Binary => {}
+        }
+    }
+    if opts.top_dirs {
+        match opts.format {
+            OutputFormat::Text | OutputFormat::Table => {
+                return render_top_dirs_text(stats, opts, writer);
+            }
+            OutputFormat::Csv => return render_top_dirs_csv(stats, opts, writer),
+            OutputFormat::Json | OutputFormat::JsonLines => {}
+            #[cfg(feature = "serde")]
+            OutputFormat::Binary => {}
+        }
+    }
+    match opts.format {
+        OutputFormat::Text => render_text(results, stats, opts, writer),
+        OutputFormat::Table => {
+            let sections = opts.inode_usage
+                || opts.empty_dirs
+                || opts.zero_sized
+                || !opts.age_labels.is_empty()
+                || opts.removed;
+            let table = TableFormatter {
+                stats: opts.text_stats && !sections,
+                ..TableFormatter::new(opts)
+            };
+            writer.write_all(table.render(results, stats).as_bytes())?;
+            if !sections {
+                return Ok(());
+            }
+            if opts.inode_usage {
+                writeln!(writer)?;
+                write_inode_section(stats, writer)?;
+            }
+            if opts.empty_dirs {
+                writeln!(writer)?;
+                write_empty_dirs_section(stats, writer)?;
+            }
+            if opts.zero_sized {
+                writeln!(writer)?;
+                write_zero_sized_section(stats, opts, writer)?;
+            }
+            if !opts.age_labels.is_empty() {
+                writeln!(writer)?;
+                write_age_section(stats, opts, writer)?;
+            }
+            if opts.removed {
+                writeln!(writer)?;
+                write_removed_section(stats, opts, writer)?;
+            }
+            write_text_footer(stats, opts, writer)
+        }
+        OutputFormat::Json => render_json(results, stats, opts, writer),
+        OutputFormat::JsonLines => render_json_lines(results, stats, writer),
+        #[cfg(feature = "serde")]
+        OutputFormat::Binary => crate::snapshot::write_snapshot(Some(results), stats, writer),
+        OutputFormat::Csv => render_csv(
+            results,
+            opts.csv_stats.then_some(stats),
+            opts.csv_delimiter,
+            writer,
+        ),
+    }
+}
+
+/// Render the human-readable report. When sorted by extension, results are
+/// printed under a header per extension.
+pub fn render_text(
+    results: &[FileEntry],
+    stats: &ScanStats,
+    opts: &OutputOptions,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    let group_by_ext = opts.sort == SortOrder::Ext;
+    writeln!(
+        writer,
+        "{}",
+        results_title(results.len(), opts.smallest, opts.sampled)
+    )?;
+    writeln!(writer)?;
+    write_text_header(opts, writer)?;
+    let mut current_ext = None;
+    for (i, entry) in results.iter().enumerate() {
+        let ext = entry.path.extension();
+        if group_by_ext && (i == 0 || current_ext != ext) {
+            match ext {
+                Some(ext) => writeln!(writer, ".{}", ext.to_string_lossy())?,
+                None => writeln!(writer, "(no extension)")?,
+            }
+            current_ext = ext;
+        }
+        write_text_row(i + 1, entry, opts, writer)?;
+    }
+    writeln!(writer)?;
+    write_results_total(results, stats, opts.reflinks, writer)?;
+
+    if opts.inode_usage {
+        writeln!(writer)?;
+        write_inode_section(stats, writer)?;
+    }
+    if opts.empty_dirs {
+        writeln!(writer)?;
+        write_empty_dirs_section(stats, writer)?;
+    }
+    if opts.zero_sized {
+        writeln!(writer)?;
+        write_zero_sized_section(stats, opts, writer)?;
+    }
+    if !opts.age_labels.is_empty() {
+        writeln!(writer)?;
+        write_age_section(stats, opts, writer)?;
+    }
+    if opts.removed {
+        writeln!(writer)?;
+        write_removed_section(stats, opts, writer)?;
+    }
+    write_text_footer(stats, opts, writer)
+}
+
+/// Render only the total of the results and how much of all the matching
+/// files they hold
+fn render_summary_text(
+    results: &[FileEntry],
+    stats: &ScanStats,
+    opts: &OutputOptions,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    write_results_total(results, stats, opts.reflinks, writer)?;
+    write!(
+        writer,
+        "All matching files: {} across {} file{}",
+        format_size(stats.matched_size),
+        stats.matched_files,
+        if stats.matched_files == 1 { "" } else { "s" }
+    )?;
+    if stats.matched_size > 0 {
+        let total: u64 = results.iter().map(|e| e.size).sum();
+        write!(
+            writer,
+            " (top-{} hold {:.1}%)",
+            results.len(),
+            total as f64 * 100.0 / stats.matched_size as f64
+        )?;
+    }
+    writeln!(writer)?;
+    write_text_footer(stats, opts, writer)
+}
+
+/// Render the files beneath their directories, each directory with the total
+/// size of the files shown below it
+pub fn render_tree_text(
+    results: &[FileEntry],
+    stats: &ScanStats,
+    opts: &OutputOptions,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    let kind = if opts.smallest { "smallest" } else { "largest" };
+    writeln!(writer, "Top {} {} files by directory:", results.len(), kind)?;
+    writeln!(writer)?;
+
+    let root = build_tree(results);
+    let name = if root.name.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        &root.name
+    };
+    writeln!(writer, "{:>10}  {}", format_size(root.size), name.display())?;
+    write_tree_contents(&root, "", 0, opts, writer)?;
+
+    write_text_footer(stats, opts, writer)
+}
+
+/// Write a node's files and then its subdirectories, each line prefixed by
+/// the branches leading to it
+fn write_tree_contents(
+    node: &TreeNode,
+    prefix: &str,
+    depth: usize,
+    opts: &OutputOptions,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    if opts.tree_depth.is_some_and(|max| depth >= max) {
+        return Ok(());
+    }
+    let count = node.files.len() + node.children.len();
+    for (i, entry) in node.files.iter().enumerate() {
+        let branch = if i + 1 == count {
+            "└── "
+        } else {
+            "├── "
+        };
+        let name = entry
+            .path
+            .file_name()
+            .map_or(entry.path.as_path(), Path::new);
+        write!(
+            writer,
+            "{:>10}  {}{}{}",
+            format_size(entry.size),
+            prefix,
+            branch,
+            name.display()
+        )?;
+        if opts.show_time {
+            let time = entry.modified.map_or_else(|| "-".to_string(), format_time);
+            write!(writer, "  ({})", time)?;
+        }
+        writeln!(writer)?;
+    }
+    for (i, child) in node.children.iter().enumerate() {
+        let last = node.files.len() + i + 1 == count;
+        let (branch, indent) = if last {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+        writeln!(
+            writer,
+            "{:>10}  {}{}{}/",
+            format_size(child.size),
+            prefix,
+            branch,
+            child.name.display()
+        )?;
+        let prefix = format!("{}{}", prefix, indent);
+        write_tree_contents(child, &prefix, depth + 1, opts, writer)?;
+    }
+    Ok(())
+}
+
+/// Lays out the file list of `table` output: rank, size and time columns as
+/// wide as their widest value, and paths shortened from the left with `…` so
+/// rows fit in `width` columns
+#[derive(Debug, Clone)]
+pub struct TableFormatter {
+    /// Terminal width in columns, or `None` to never shorten paths
+    pub width: Option<usize>,
+    pub show_time: bool,
+    pub show_sparseness: bool,
+    pub show_links: bool,
+    pub show_mode: bool,
+    pub show_allocated: bool,
+    pub smallest: bool,
+    pub sampled: bool,
+    pub disk_usage: bool,
+    /// End with the statistics block
+    pub stats: bool,
+    pub colors: Option<SizeColors>,
+    /// Files to highlight as new, see `OutputOptions::new_paths`
+    pub new_paths: Option<HashSet<PathBuf>>,
+    /// Add the disk cost without shared extents to the total
+    pub reflinks: bool,
+}
+
+impl TableFormatter {
+    pub fn new(opts: &OutputOptions) -> Self {
+        Self {
+            width: opts.terminal_width,
+            show_time: opts.show_time,
+            show_sparseness: opts.show_sparseness,
+            show_links: opts.show_links,
+            show_mode: opts.show_mode,
+            show_allocated: opts.show_allocated,
+            smallest: opts.smallest,
+            sampled: opts.sampled,
+            disk_usage: opts.disk_usage,
+            stats: opts.text_stats,
+            colors: opts.colors,
+            new_paths: opts.new_paths.clone(),
+            reflinks: opts.reflinks,
+        }
+    }
+
+    pub fn render(&self, results: &[FileEntry], stats: &ScanStats) -> String {
+        let sizes: Vec<String> = results.iter().map(|e| format_size(e.size)).collect();
+        let times: Vec<String> = results
+            .iter()
+            .map(|e| e.modified.map_or_else(|| "-".to_string(), format_time))
+            .collect();
+        let paths: Vec<String> = results.iter().map(display_path).collect();
+        let holes: Vec<String> = results
+            .iter()
+            .map(|e| format_sparseness(e.sparseness))
+            .collect();
+        let links: Vec<String> = results.iter().map(|e| e.nlink.to_string()).collect();
+        let modes: Vec<String> = results.iter().map(|e| format_mode(e.mode)).collect();
+        let allocated: Vec<String> = results
+            .iter()
+            .map(|e| format_size(e.allocated_size))
+            .collect();
+
+        let size_label = if self.disk_usage {
+            "Disk usage"
+        } else {
+            "Size"
+        };
+        let rank_width = results.len().to_string().len().max(1);
+        let size_width = column_width(size_label, &sizes);
+        let time_width = column_width("Modified", &times);
+        let holes_width = column_width("Holes", &holes);
+        let links_width = column_width("Links", &links);
+        let mode_width = column_width("Mode", &modes);
+        let allocated_width = column_width("Allocated", &allocated);
+        let mut fixed = rank_width + 2 + size_width + 2;
+        if self.show_allocated {
+            fixed += allocated_width + 2;
+        }
+        // Checksums follow the path at full length
+        let checksum_width = results
+            .iter()
+            .filter_map(|e| e.checksum.as_ref())
+            .map(|c| c.len() * 2 + 2)
+            .max()
+            .unwrap_or(0);
+        if self.show_sparseness {
+            fixed += holes_width + 2;
+        }
+        if self.show_links {
+            fixed += links_width + 2;
+        }
+        if self.show_mode {
+            fixed += mode_width + 2;
+        }
+        if self.show_time {
+            fixed += time_width + 2;
+        }
+        let path_width = match self.width {
+            Some(width) => width
+                .saturating_sub(fixed + checksum_width)
+                .max("Path".len()),
+            None => column_width("Path", &paths),
+        };
+
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "{}",
+            results_title(results.len(), self.smallest, self.sampled)
+        );
+        let _ = writeln!(out);
+        let _ = write!(out, "{:>rank_width$}  {:>size_width$}  ", "#", size_label);
+        if self.show_allocated {
+            let _ = write!(out, "{:>allocated_width$}  ", "Allocated");
+        }
+        if self.show_sparseness {
+            let _ = write!(out, "{:>holes_width$}  ", "Holes");
+        }
+        if self.show_links {
+            let _ = write!(out, "{:>links_width$}  ", "Links");
+        }
+        if self.show_mode {
+            let _ = write!(out, "{:mode_width$}  ", "Mode");
+        }
+        if self.show_time {
+            let _ = write!(out, "{:time_width$}  ", "Modified");
+        }
+        if checksum_width > 0 {
+            let _ = writeln!(out, "{:path_width$}  Checksum", "Path");
+        } else {
+            let _ = writeln!(out, "Path");
+        }
+        let rule_width = fixed + column_width("Path", &paths).min(path_width) + checksum_width;
+        let _ = writeln!(out, "{}", "─".repeat(rule_width));
+
+        for (i, path) in paths.iter().enumerate() {
+            let new = is_new(self.new_paths.as_ref(), &results[i].path);
+            let styles = row_styles(self.colors, results[i].size, new);
+            let rank = format!("{:>rank_width$}", i + 1);
+            let size = format!("{:>size_width$}", sizes[i]);
+            let _ = write!(
+                out,
+                "{}  {}  ",
+                rank.style(styles.rank),
+                size.style(styles.size)
+            );
+            if self.show_allocated {
+                let _ = write!(out, "{:>allocated_width$}  ", allocated[i]);
+            }
+            if self.show_sparseness {
+                let _ = write!(out, "{:>holes_width$}  ", holes[i]);
+            }
+            if self.show_links {
+                let _ = write!(out, "{:>links_width$}  ", links[i]);
+            }
+            if self.show_mode {
+                let _ = write!(out, "{:mode_width$}  ", modes[i]);
+            }
+            if self.show_time {
+                let _ = write!(out, "{:time_width$}  ", times[i]);
+            }
+            let path = shorten_left(path, path_width);
+            let _ = write!(out, "{}", path.style(styles.path));
+            if let Some(checksum) = &results[i].checksum {
+                let padding = path_width.saturating_sub(path.chars().count());
+                let _ = write!(out, "{:padding$}  {}", "", hex(checksum));
+            }
+            if let Some(note) = verification_note(&results[i]) {
+                let _ = write!(out, "  {}", note);
+            }
+            let _ = writeln!(out);
+        }
+
+        let mut buf = Vec::new();
+        let _ = writeln!(buf)
+            .and_then(|_| write_results_total(results, stats, self.reflinks, &mut buf));
+        if self.stats {
+            let _ = writeln!(buf).and_then(|_| write_text_stats(stats, &mut buf));
+        }
+        out.push_str(&String::from_utf8_lossy(&buf));
+        out
+    }
+}
+
+/// Write the total size of the reported files and its share of the scanned
+/// filesystem, or of each root's filesystem when there are several. With
+/// `reflinks` the bytes they do not share with other files follow.
+fn write_results_total(
+    results: &[FileEntry],
+    stats: &ScanStats,
+    reflinks: bool,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    let total: u64 = results.iter().map(|e| e.size).sum();
+    write!(
+        writer,
+        "Total of top-{} files: {}",
+        results.len(),
+        format_size(total)
+    )?;
+    if let [fs] = stats.filesystems.as_slice() {
+        if let Some(percent) = fs.percent_of_total(total) {
+            write!(writer, " ({:.1}% of filesystem)", percent)?;
+        }
+        writeln!(writer)?;
+    } else {
+        writeln!(writer)?;
+        for fs in &stats.filesystems {
+            write!(
+                writer,
+                "  {}: {}",
+                fs.root.display(),
+                format_size(fs.results_bytes)
+            )?;
+            if let Some(percent) = fs.percent_of_total(fs.results_bytes) {
+                write!(writer, " ({:.1}% of filesystem)", percent)?;
+            }
+            writeln!(writer)?;
+        }
+    }
+    if reflinks {
+        let reflinked = results.iter().filter(|e| e.is_reflinked).count();
+        let physical: u64 = results.iter().map(|e| e.physical_size).sum();
+        writeln!(
+            writer,
+            "Disk cost without shared extents: {} ({} reflinked file{})",
+            format_size(physical),
+            reflinked,
+            if reflinked == 1 { "" } else { "s" }
+        )?;
+    }
+    Ok(())
+}
+
+/// Width in characters of a column holding `header` and `values`
+fn column_width(header: &str, values: &[String]) -> usize {
+    values
+        .iter()
+        .map(|v| v.chars().count())
+        .chain(std::iter::once(header.len()))
+        .max()
+        .unwrap_or(0)
+}
+
+/// Fit `s` in `width` characters, replacing its start with `…` so the end of
+/// a path, usually the part that matters, stays visible
+fn shorten_left(s: &str, width: usize) -> Cow<'_, str> {
+    let len = s.chars().count();
+    if len <= width {
+        return Cow::Borrowed(s);
+    }
+    let keep = width.saturating_sub(1);
+    Cow::Owned(
+        std::iter::once('…')
+            .chain(s.chars().skip(len - keep))
+            .collect(),
+    )
+}
+
+/// Render each directory's largest files under a header for the directory,
+/// directories ordered by their total size
+pub fn render_per_dir_text(
+    stats: &ScanStats,
+    opts: &OutputOptions,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    let kind = if opts.smallest { "smallest" } else { "largest" };
+    writeln!(writer, "{} files per directory:", capitalize(kind))?;
+    writeln!(writer)?;
+    write_text_header(opts, writer)?;
+    for (dir, top) in stats.dirs_by_size() {
+        writeln!(
+            writer,
+            "{}  ({} total)",
+            dir.display(),
+            format_size(top.total_size)
+        )?;
+        for (i, entry) in top.files.iter().enumerate() {
+            write_text_row(i + 1, entry, opts, writer)?;
+        }
+    }
+
+    write_text_footer(stats, opts, writer)
+}
+
+/// The name to show for `uid`, unless names are not resolved or it has none
+fn resolved_name(uid: u32, opts: &OutputOptions) -> Option<String> {
+    opts.resolve_names.then(|| crate::user_name(uid)).flatten()
+}
+
+/// Render each user's largest files under a header for the user, users
+/// ordered by the total size of their files
+pub fn render_per_user_text(
+    stats: &ScanStats,
+    opts: &OutputOptions,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    let kind = if opts.smallest { "smallest" } else { "largest" };
+    writeln!(writer, "{} files per user:", capitalize(kind))?;
+    writeln!(writer)?;
+    write_text_header(opts, writer)?;
+    for (uid, top) in stats.users_by_size() {
+        match resolved_name(uid, opts) {
+            Some(name) => write!(writer, "{} (uid {})", name, uid)?,
+            None => write!(writer, "uid {}", uid)?,
+        }
+        writeln!(writer, "  ({} total)", format_size(top.total_size))?;
+        for (mount_point, quota) in user_quotas(uid, opts) {
+            let style = match opts.colors {
+                Some(_) if quota.hard_limit > 0 && quota.used >= quota.hard_limit => {
+                    Style::new().red()
+                }
+                Some(_) if quota.soft_limit > 0 && quota.used >= quota.soft_limit => {
+                    Style::new().yellow()
+                }
+                _ => Style::new(),
+            };
+            let line = format!(
+                "  quota on {}: {} of {} soft, {} hard ({:.1}%)",
+                mount_point.display(),
+                format_size(quota.used),
+                format_limit(quota.soft_limit),
+                format_limit(quota.hard_limit),
+                quota.pct_used
+            );
+            writeln!(writer, "{}", line.style(style))?;
+        }
+        for (i, entry) in top.files.iter().enumerate() {
+            write_text_row(i + 1, entry, opts, writer)?;
+        }
+    }
+
+    write_text_footer(stats, opts, writer)
+}
+
+/// The quotas of `uid` that set a limit, with their mount points
+fn user_quotas(uid: u32, opts: &OutputOptions) -> impl Iterator<Item = &(PathBuf, QuotaInfo)> {
+    opts.quotas
+        .iter()
+        .filter(move |(_, quota)| quota.uid == uid && quota.is_limited())
+}
+
+fn format_limit(limit: u64) -> String {
+    if limit == 0 {
+        "no".to_string()
+    } else {
+        format_size(limit)
+    }
+}
+
+/// Render the largest directories by recursive size
+pub fn render_dirs_text(
+    stats: &ScanStats,
+    opts: &OutputOptions,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    let size_label = if opts.disk_usage {
+        "Disk usage"
+    } else {
+        "Size"
+    };
+    writeln!(
+        writer,
+        "Top {} largest directories:",
+        stats.largest_dirs.len()
+    )?;
+    writeln!(writer)?;
+    writeln!(writer, "{:>5} {:>12}  Path", "#", size_label)?;
+    for (i, dir) in stats.largest_dirs.iter().enumerate() {
+        writeln!(
+            writer,
+            "{:4}. {:>12}  {}",
+            i + 1,
+            format_size(dir.total_size),
+            dir.path.display()
+        )?;
+    }
+
+    write_text_footer(stats, opts, writer)
+}
+
+/// Render the directory totals of `ScanStats::top_dirs`, largest first
+pub fn render_top_dirs_text(
+    stats: &ScanStats,
+    opts: &OutputOptions,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    let size_label = if opts.disk_usage {
+        "Disk usage"
+    } else {
+        "Size"
+    };
+    writeln!(
+        writer,
+        "{} directories by total size:",
+        stats.top_dirs.len()
+    )?;
+    writeln!(writer)?;
+    writeln!(
+        writer,
+        "{:>5} {:>12} {:>10}  Path",
+        "#", size_label, "Files"
+    )?;
+    for (i, dir) in stats.top_dirs.iter().enumerate() {
+        writeln!(
+            writer,
+            "{:4}. {:>12} {:>10}  {}",
+            i + 1,
+            format_size(dir.total_size),
+            dir.file_count,
+            dir.path.display()
+        )?;
+    }
+
+    write_text_footer(stats, opts, writer)
+}
+
+/// Render groups of files sharing a size, each with the space that
+/// hard-linking its files together would free
+pub fn render_duplicates_text(
+    stats: &ScanStats,
+    opts: &OutputOptions,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    let groups = stats.duplicate_size_groups();
+    let total: u64 = groups
+        .iter()
+        .map(|(size, paths)| size * (paths.len() as u64 - 1))
+        .sum();
+    writeln!(
+        writer,
+        "{} groups of files sharing a size, up to {} reclaimable:",
+        groups.len(),
+        format_size(total)
+    )?;
+    writeln!(writer)?;
+    writeln!(
+        writer,
+        "{:>5} {:>12}  {:>6}  {:>12}",
+        "#", "Size", "Files", "Reclaimable"
+    )?;
+    for (i, (size, paths)) in groups.into_iter().enumerate() {
+        let reclaimable = size * (paths.len() as u64 - 1);
+        writeln!(
+            writer,
+            "{:4}. {:>12}  {:>6}  {:>12}",
+            i + 1,
+            format_size(size),
+            paths.len(),
+            format_size(reclaimable)
+        )?;
+        for path in paths {
+            writeln!(writer, "        {}", path.display())?;
+        }
+    }
+
+    write_text_footer(stats, opts, writer)
+}
+
+/// Render groups of identical files, marking the copy that would be kept
+pub fn render_hashed_text(
+    stats: &ScanStats,
+    opts: &OutputOptions,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    let total: u64 = stats.duplicates.iter().map(|group| group.wasted()).sum();
+    writeln!(
+        writer,
+        "{} groups of identical files, {} wasted:",
+        stats.duplicates.len(),
+        format_size(total)
+    )?;
+    writeln!(writer)?;
+    writeln!(
+        writer,
+        "{:>5} {:>12}  {:>6}  {:>12}",
+        "#", "Size", "Copies", "Wasted"
+    )?;
+    for (i, group) in stats.duplicates.iter().enumerate() {
+        writeln!(
+            writer,
+            "{:4}. {:>12}  {:>6}  {:>12}  sha256:{}",
+            i + 1,
+            format_size(group.size),
+            group.files.len(),
+            format_size(group.wasted()),
+            hex(&group.hash)
+        )?;
+        for (j, entry) in group.files.iter().enumerate() {
+            let mark = if j == 0 { "keep" } else { "" };
+            writeln!(writer, "  {:>4}  {}", mark, entry.path.display())?;
+        }
+    }
+
+    write_text_footer(stats, opts, writer)
+}
+
+/// Render groups of identical files as delimiter-separated values, one row per
+/// file; `keep` marks the canonical copy
+pub fn render_hashed_csv(
+    stats: &ScanStats,
+    opts: &OutputOptions,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    let d = opts.csv_delimiter;
+    writeln!(writer, "group{d}size_bytes{d}count{d}sha256{d}keep{d}path")?;
+    for (i, group) in stats.duplicates.iter().enumerate() {
+        for (j, entry) in group.files.iter().enumerate() {
+            writeln!(
+                writer,
+                "{}{d}{}{d}{}{d}{}{d}{}{d}{}",
+                i + 1,
+                group.size,
+                group.files.len(),
+                hex(&group.hash),
+                j == 0,
+                csv_field(&path_to_string(&entry.path), d)
+            )?;
+        }
+    }
+
+    if opts.csv_stats {
+        write_csv_stats(stats, writer)?;
+    }
+    Ok(())
+}
+
+/// Lowercase hexadecimal digits of a digest
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .fold(String::with_capacity(bytes.len() * 2), |mut out, b| {
+            write!(out, "{:02x}", b).unwrap();
+            out
+        })
+}
+
+/// Render groups of files sharing a size as delimiter-separated values, one row
+/// per file
+pub fn render_duplicates_csv(
+    stats: &ScanStats,
+    opts: &OutputOptions,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    let d = opts.csv_delimiter;
+    writeln!(writer, "group{d}size_bytes{d}count{d}path")?;
+    for (i, (size, paths)) in stats.duplicate_size_groups().into_iter().enumerate() {
+        for path in paths {
+            writeln!(
+                writer,
+                "{}{d}{}{d}{}{d}{}",
+                i + 1,
+                size,
+                paths.len(),
+                csv_field(&path_to_string(path), d)
+            )?;
+        }
+    }
+
+    if opts.csv_stats {
+        write_csv_stats(stats, writer)?;
+    }
+    Ok(())
+}
+
+/// Render only the directories with the most entries
+pub fn render_inodes_text(
+    stats: &ScanStats,
+    opts: &OutputOptions,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    write_inode_section(stats, writer)?;
+    write_text_footer(stats, opts, writer)
+}
+
+fn write_inode_section(stats: &ScanStats, writer: &mut dyn Write) -> io::Result<()> {
+    writeln!(
+        writer,
+        "Top {} directories by entry count:",
+        stats.inode_usage.len()
+    )?;
+    writeln!(writer)?;
+    writeln!(writer, "{:>5} {:>12}  Path", "#", "Entries")?;
+    for (i, dir) in stats.inode_usage.iter().enumerate() {
+        writeln!(
+            writer,
+            "{:4}. {:>12}  {}",
+            i + 1,
+            dir.count,
+            dir.path.display()
+        )?;
+    }
+    Ok(())
+}
+
+fn write_empty_dirs_section(stats: &ScanStats, writer: &mut dyn Write) -> io::Result<()> {
+    writeln!(
+        writer,
+        "{} empty directories (no files beneath them):",
+        stats.empty_dirs.len()
+    )?;
+    if !stats.empty_dirs.is_empty() {
+        writeln!(writer)?;
+    }
+    for dir in &stats.empty_dirs {
+        match dir.subdir_count {
+            0 => writeln!(writer, "  {}", dir.path.display())?,
+            1 => writeln!(writer, "  {} (1 subdirectory)", dir.path.display())?,
+            n => writeln!(writer, "  {} ({} subdirectories)", dir.path.display(), n)?,
+        }
+    }
+    Ok(())
+}
+
+fn write_zero_sized_section(
+    stats: &ScanStats,
+    opts: &OutputOptions,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    let count = stats.zero_sized.len();
+    let plural = if count == 1 { "" } else { "s" };
+    if opts.zero_sized_count {
+        return writeln!(writer, "{} zero-sized file{}", count, plural);
+    }
+    writeln!(writer, "{} zero-sized file{}:", count, plural)?;
+    if count > 0 {
+        writeln!(writer)?;
+    }
+    for path in &stats.zero_sized {
+        writeln!(writer, "  {}", path.display())?;
+    }
+    Ok(())
+}
+
+/// Write `ScanStats::error_details` as `PATH: REASON` lines, noting the errors
+/// counted beyond them
+pub fn write_scan_errors(stats: &ScanStats, writer: &mut dyn Write) -> io::Result<()> {
+    write_error_lines(stats, "", writer)
+}
+
+fn write_error_lines(stats: &ScanStats, indent: &str, writer: &mut dyn Write) -> io::Result<()> {
+    for error in &stats.error_details {
+        writeln!(
+            writer,
+            "{}{}: {}",
+            indent,
+            error.path().display(),
+            error.reason()
+        )?;
+    }
+    let unlisted = stats
+        .errors
+        .saturating_sub(stats.error_details.len() as u64);
+    if unlisted > 0 {
+        writeln!(writer, "{}... {} not listed", indent, unlisted)?;
+    }
+    Ok(())
+}
+
+fn write_scan_errors_section(stats: &ScanStats, writer: &mut dyn Write) -> io::Result<()> {
+    if stats.errors == 0 {
+        return writeln!(writer, "No scan errors");
+    }
+    let plural = if stats.errors == 1 { "" } else { "s" };
+    writeln!(writer, "{} scan error{}:", stats.errors, plural)?;
+    writeln!(writer)?;
+    write_error_lines(stats, "  ", writer)
+}
+
+fn write_inaccessible_dirs_section(stats: &ScanStats, writer: &mut dyn Write) -> io::Result<()> {
+    if stats.dirs_inaccessible == 0 {
+        return writeln!(writer, "No inaccessible directories");
+    }
+    writeln!(writer, "Inaccessible directories:")?;
+    writeln!(writer)?;
+    for (path, error) in &stats.inaccessible_dirs {
+        writeln!(writer, "  {}: {}", path.display(), error)?;
+    }
+    let unlisted = stats
+        .dirs_inaccessible
+        .saturating_sub(stats.inaccessible_dirs.len() as u64);
+    if unlisted > 0 {
+        writeln!(writer, "  ... {} not listed", unlisted)?;
+    }
+    Ok(())
+}
+
+/// Share of `part` in `total`, in percent
+fn percent(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        part as f64 / total as f64 * 100.0
+    }
+}
+
+fn write_age_section(
+    stats: &ScanStats,
+    opts: &OutputOptions,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    let total: u64 = stats
+        .age_buckets
+        .iter()
+        .map(|bucket| bucket.total_size)
+        .sum();
+    writeln!(writer, "Files by age (modification time):")?;
+    writeln!(writer)?;
+    writeln!(
+        writer,
+        "{:<12} {:>10} {:>12} {:>10}",
+        "Age", "Files", "Size", "% of size"
+    )?;
+    for (label, bucket) in opts.age_labels.iter().zip(&stats.age_buckets) {
+        writeln!(
+            writer,
+            "{:<12} {:>10} {:>12} {:>9.1}%",
+            label,
+            bucket.files,
+            format_size(bucket.total_size),
+            percent(bucket.total_size, total)
+        )?;
+    }
+    Ok(())
+}
+
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .map(|c| c.to_uppercase().chain(chars).collect())
+        .unwrap_or_default()
+}
+
+fn write_text_header(opts: &OutputOptions, writer: &mut dyn Write) -> io::Result<()> {
+    let size_label = if opts.disk_usage {
+        "Disk usage"
+    } else {
+        "Size"
+    };
+    write!(writer, "{:>5} {:>12}  ", "#", size_label)?;
+    if opts.show_allocated {
+        write!(writer, "{:>12}  ", "Allocated")?;
+    }
+    if opts.show_sparseness {
+        write!(writer, "{:>6}  ", "Holes")?;
+    }
+    if opts.show_links {
+        write!(writer, "{:>5}  ", "Links")?;
+    }
+    if opts.show_mode {
+        write!(writer, "{:9}  ", "Mode")?;
+    }
+    if opts.show_time {
+        write!(writer, "{:16}  ", "Modified")?;
+    }
+    writeln!(writer, "Path")
+}
+
+fn write_text_row(
+    rank: usize,
+    entry: &FileEntry,
+    opts: &OutputOptions,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    let rank = format!("{:4}.", rank);
+    let size = format!("{:>12}", format_size(entry.size));
+    let path = display_path(entry);
+    let time = opts
+        .show_time
+        .then(|| entry.modified.map_or_else(|| "-".to_string(), format_time));
+    let new = is_new(opts.new_paths.as_ref(), &entry.path);
+    let styles = row_styles(opts.colors, entry.size, new);
+
+    write!(writer, "{} ", rank.style(styles.rank))?;
+    write!(writer, "{}  ", size.style(styles.size))?;
+    if opts.show_allocated {
+        write!(writer, "{:>12}  ", format_size(entry.allocated_size))?;
+    }
+    if opts.show_sparseness {
+        write!(writer, "{:>6}  ", format_sparseness(entry.sparseness))?;
+    }
+    if opts.show_links {
+        write!(writer, "{:>5}  ", entry.nlink)?;
+    }
+    if opts.show_mode {
+        write!(writer, "{:9}  ", format_mode(entry.mode))?;
+    }
+    if let Some(time) = time {
+        write!(writer, "{:16}  ", time)?;
+    }
+    write!(writer, "{}", path.style(styles.path))?;
+    if entry.xattr_size > 0 {
+        write!(writer, "  (+{} xattr)", format_size(entry.xattr_size))?;
+    }
+    if let Some(checksum) = &entry.checksum {
+        write!(writer, "  {}", hex(checksum))?;
+    }
+    if opts.reflinks && entry.is_reflinked {
+        write!(
+            writer,
+            "  [reflinked, {} unshared]",
+            format_size(entry.physical_size)
+        )?;
+    }
+    if let Some(note) = verification_note(entry) {
+        write!(writer, "  {}", note)?;
+    }
+    writeln!(writer)
+}
+
+/// The `[CHANGED, now ...]` or `[DELETED]` mark of a file that did not keep
+/// its size after the scan, from `verify::verify_results`
+fn verification_note(entry: &FileEntry) -> Option<String> {
+    match entry.verified? {
+        Verification::Unchanged => None,
+        Verification::Changed(size) => Some(format!("[CHANGED, now {}]", format_size(size))),
+        Verification::Deleted => Some("[DELETED]".to_string()),
+    }
+}
+
+/// The line above the files of a text report
+fn results_title(count: usize, smallest: bool, sampled: bool) -> String {
+    if sampled {
+        return format!("Random sample of {} files:", count);
+    }
+    let kind = if smallest { "smallest" } else { "largest" };
+    format!("Top {} {} files:", count, kind)
+}
+
+/// The path of `entry` for text output, with a trailing `/` for directories
+fn display_path(entry: &FileEntry) -> String {
+    let path = entry.path.display().to_string();
+    if entry.is_dir { path + "/" } else { path }
+}
+
+/// Sparseness as a percentage of holes, e.g. `87.5%`
+fn format_sparseness(sparseness: f32) -> String {
+    format!("{:.1}%", sparseness * 100.0)
+}
+
+/// Permission bits as `ls -l` shows them (`rwsr-xr-x`), setuid, setgid and
+/// sticky bits replacing the execute bits; `-` for files without a mode
+fn format_mode(mode: u32) -> String {
+    if mode == 0 {
+        return "-".to_string();
+    }
+    // Each class's bits, and the special bit shown in its execute position
+    let classes = [(6, 0o4000, 's'), (3, 0o2000, 's'), (0, 0o1000, 't')];
+    let mut out = String::with_capacity(9);
+    for (shift, special, letter) in classes {
+        let bits = mode >> shift;
+        out.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        out.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        out.push(match (mode & special != 0, bits & 0o1 != 0) {
+            (true, true) => letter,
+            (true, false) => letter.to_ascii_uppercase(),
+            (false, true) => 'x',
+            (false, false) => '-',
+        });
+    }
+    out
+}
+
+/// Styles of one file row's columns
+struct RowStyles {
+    rank: Style,
+    size: Style,
+    path: Style,
+}
+
+fn is_new(new_paths: Option<&HashSet<PathBuf>>, path: &Path) -> bool {
+    new_paths.is_some_and(|paths| paths.contains(path))
+}
+
+/// The rank is dimmed, and files past a threshold get a bold colored size and
+/// a path in the same color; new files have a green rank and path instead.
+/// Without colors every style is plain.
+fn row_styles(colors: Option<SizeColors>, size: u64, new: bool) -> RowStyles {
+    match colors {
+        Some(colors) => {
+            let style = colors.style(size);
+            RowStyles {
+                rank: if new {
+                    Style::new().green()
+                } else {
+                    Style::new().dimmed()
+                },
+                size: if style.is_plain() {
+                    style
+                } else {
+                    style.bold()
+                },
+                path: if new { Style::new().green() } else { style },
+            }
+        }
+        None => RowStyles {
+            rank: Style::new(),
+            size: Style::new(),
+            path: Style::new(),
+        },
+    }
+}
+
+/// List the files reported by the previous `--watch` scan that are no longer
+/// among the results, in red when `colors` is set. Writes nothing when none are.
+pub fn render_removed(
+    removed: &[FileEntry],
+    colors: bool,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    if removed.is_empty() {
+        return Ok(());
+    }
+    writeln!(writer)?;
+    writeln!(writer, "No longer in the top results:")?;
+    write_removed_rows(removed, colors, writer)
+}
+
+fn write_removed_rows(
+    removed: &[FileEntry],
+    colors: bool,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    let style = if colors {
+        Style::new().red()
+    } else {
+        Style::new()
+    };
+    for entry in removed {
+        let line = format!(
+            "    - {:>12}  {}",
+            format_size(entry.size),
+            entry.path.display()
+        );
+        writeln!(writer, "{}", line.style(style))?;
+    }
+    Ok(())
+}
+
+/// The files of the baseline that are gone, after the new and resized ones
+fn write_removed_section(
+    stats: &ScanStats,
+    opts: &OutputOptions,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    writeln!(
+        writer,
+        "Removed since the snapshot ({}):",
+        stats.removed.len()
+    )?;
+    write_removed_rows(&stats.removed, opts.colors.is_some(), writer)
+}
+
+/// Write a `SIZE<TAB>PATH` line for each reported file (or directory, with
+/// `largest_dirs`), sizes as `du -h` writes them, for tools that parse `du`
+pub fn render_du(
+    results: &[FileEntry],
+    stats: &ScanStats,
+    opts: &OutputOptions,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    let mut write_line = |size: u64, path: &Path| -> io::Result<()> {
+        write!(writer, "{}\t", format_size_styled(size, SizeStyle::Du))?;
+        writer.write_all(path.as_os_str().as_encoded_bytes())?;
+        writer.write_all(b"\n")
+    };
+    if opts.largest_dirs {
+        for dir in &stats.largest_dirs {
+            write_line(dir.total_size, &dir.path)?;
+        }
+    } else if opts.top_dirs {
+        for dir in &stats.top_dirs {
+            write_line(dir.total_size, &dir.path)?;
+        }
+    } else {
+        for entry in results {
+            write_line(entry.size, &entry.path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Write each reported path followed by a NUL byte, with nothing else, so
+/// names containing newlines survive `xargs -0`. Paths are written as raw bytes.
+pub fn render_null(
+    results: &[FileEntry],
+    stats: &ScanStats,
+    opts: &OutputOptions,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    let mut write_path = |path: &Path| -> io::Result<()> {
+        writer.write_all(path.as_os_str().as_encoded_bytes())?;
+        writer.write_all(b"\0")
+    };
+    if opts.largest_dirs {
+        for dir in &stats.largest_dirs {
+            write_path(&dir.path)?;
+        }
+    } else if opts.top_dirs {
+        for dir in &stats.top_dirs {
+            write_path(&dir.path)?;
+        }
+    } else if opts.inode_usage_only {
+        for dir in &stats.inode_usage {
+            write_path(&dir.path)?;
+        }
+    } else if opts.hash_duplicates {
+        for group in &stats.duplicates {
+            for entry in &group.files {
+                write_path(&entry.path)?;
+            }
+        }
+    } else if opts.duplicate_sizes {
+        for (_, paths) in stats.duplicate_size_groups() {
+            for path in paths {
+                write_path(path)?;
+            }
+        }
+    } else if opts.top_per_user {
+        for (_, top) in stats.users_by_size() {
+            for entry in top.files.iter() {
+                write_path(&entry.path)?;
+            }
+        }
+    } else if opts.top_per_dir {
+        for (_, top) in stats.dirs_by_size() {
+            for entry in top.files.iter() {
+                write_path(&entry.path)?;
+            }
+        }
+    } else {
+        for entry in results {
+            write_path(&entry.path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Render the changes since a snapshot: new files, then grown, then shrunk ones
+pub fn render_diff_text(diff: &ScanDiff, writer: &mut dyn Write) -> io::Result<()> {
+    writeln!(writer, "New files ({}):", diff.added.len())?;
+    for entry in &diff.added {
+        let size = format!("+{}", format_size(entry.size));
+        writeln!(writer, "  {:>13}  {}", size, entry.path.display())?;
+    }
+    for (title, changes) in [("Grown", &diff.grown), ("Shrunk", &diff.shrunk)] {
+        writeln!(writer)?;
+        writeln!(writer, "{} ({}):", title, changes.len())?;
+        for change in changes {
+            write_size_change("  ", change, writer)?;
+        }
+    }
+    Ok(())
+}
+
+/// Render the differences between two trees from `compare_trees`: `<` for
+/// files only in the first, `>` for files only in the second and `~` for files
+/// whose size differs, then how many of each there are
+pub fn render_compare_text(
+    differences: &[TreeDifference],
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    let (mut only_a, mut only_b, mut changed) = (0, 0, 0);
+    for difference in differences {
+        match difference {
+            TreeDifference::OnlyInA { path, size } => {
+                only_a += 1;
+                writeln!(writer, "< {:>13}  {}", format_size(*size), path.display())?;
+            }
+            TreeDifference::OnlyInB { path, size } => {
+                only_b += 1;
+                writeln!(writer, "> {:>13}  {}", format_size(*size), path.display())?;
+            }
+            TreeDifference::Changed(change) => {
+                changed += 1;
+                write_size_change("~ ", change, writer)?;
+            }
+        }
+    }
+    if !differences.is_empty() {
+        writeln!(writer)?;
+    }
+    writeln!(
+        writer,
+        "{} only in A, {} only in B, {} changed",
+        only_a, only_b, changed
+    )
+}
+
+fn write_size_change(marker: &str, change: &SizeChange, writer: &mut dyn Write) -> io::Result<()> {
+    let delta = change.delta();
+    let sign = if delta < 0 { '-' } else { '+' };
+    writeln!(
+        writer,
+        "{}{:>13}  {}  ({} -> {})",
+        marker,
+        format!("{}{}", sign, format_size(delta.unsigned_abs())),
+        change.path.display(),
+        format_size(change.old_size),
+        format_size(change.new_size)
+    )
+}
+
+/// Render only the statistics block, used when no file entries were collected
+pub fn render_stats_only(
+    stats: &ScanStats,
+    format: OutputFormat,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    match format {
+        OutputFormat::Text | OutputFormat::Table => write_text_stats(stats, writer),
+        OutputFormat::Json => {
+            write!(writer, "{{\"stats\":")?;
+            write_json_stats(stats, writer)?;
+            writeln!(writer, "}}")
+        }
+        OutputFormat::JsonLines => write_json_stats_line(stats, writer),
+        OutputFormat::Csv => write_csv_stats(stats, writer),
+        #[cfg(feature = "serde")]
+        OutputFormat::Binary => crate::snapshot::write_snapshot(None, stats, writer),
+    }
+}
+
+/// The statistics block closing a text report, unless it is written elsewhere
+fn write_text_footer(
+    stats: &ScanStats,
+    opts: &OutputOptions,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    if !opts.text_stats {
+        return Ok(());
+    }
+    writeln!(writer)?;
+    write_text_stats(stats, writer)
+}
+
+fn write_text_stats(stats: &ScanStats, writer: &mut dyn Write) -> io::Result<()> {
+    writeln!(writer, "Statistics:")?;
+    writeln!(writer, "  Files scanned:       {}", stats.files_scanned)?;
+    writeln!(writer, "  Directories scanned: {}", stats.dirs_scanned)?;
+    writeln!(writer, "  Hard links skipped:  {}", stats.hardlinks_skipped)?;
+    if stats.symlinks_followed > 0 || stats.symlink_cycles_detected > 0 {
+        writeln!(writer, "  Symlinks followed:   {}", stats.symlinks_followed)?;
+        writeln!(
+            writer,
+            "  Revisits skipped:    {}",
+            stats.symlink_cycles_detected
+        )?;
+    }
+    if stats.archive_members > 0 {
+        writeln!(writer, "  Archive members:     {}", stats.archive_members)?;
+    }
+    if let Some(types) = &stats.file_types {
+        writeln!(writer, "  Symlinks:            {}", types.symlinks)?;
+        writeln!(writer, "  Sockets:             {}", types.sockets)?;
+        writeln!(writer, "  FIFOs:               {}", types.fifos)?;
+        writeln!(writer, "  Block devices:       {}", types.block_devs)?;
+        writeln!(writer, "  Character devices:   {}", types.char_devs)?;
+    }
+    if stats.aborted {
+        writeln!(
+            writer,
+            "  Errors:              {} (scan aborted)",
+            stats.errors
+        )?;
+    } else {
+        writeln!(writer, "  Errors:              {}", stats.errors)?;
+    }
+    writeln!(
+        writer,
+        "  Time elapsed:        {:.3}s",
+        stats.elapsed.as_secs_f64()
+    )?;
+    if stats.auto_threads > 0 {
+        writeln!(writer, "  Threads (auto):      {}", stats.auto_threads)?;
+    }
+    for mount in &stats.mounts {
+        let action = if mount.skipped {
+            "Skipping"
+        } else {
+            "Following"
+        };
+        writeln!(
+            writer,
+            "  {} mount: {} ({})",
+            action,
+            mount.path.display(),
+            mount.fstype
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Render results and statistics as a single JSON object, plus a `by_extension`
+/// array when grouping by extension
+pub fn render_json(
+    results: &[FileEntry],
+    stats: &ScanStats,
+    opts: &OutputOptions,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    if opts.summarize {
+        write!(
+            writer,
+            "{{\"summary\":{{\"results_files\":{},\"results_bytes\":{},\"matched_files\":{},\"matched_bytes\":{}}}",
+            results.len(),
+            results.iter().map(|e| e.size).sum::<u64>(),
+            stats.matched_files,
+            stats.matched_size
+        )?;
+    } else {
+        write!(writer, "{{\"results\":")?;
+        write_json_entries(results, writer)?;
+    }
+
+    if opts.top_per_dir {
+        write!(writer, ",\"per_dir\":[")?;
+        for (i, (dir, top)) in stats.dirs_by_size().into_iter().enumerate() {
+            if i > 0 {
+                write!(writer, ",")?;
+            }
+            write!(
+                writer,
+                "{{\"path\":{},\"total_bytes\":{},\"files\":",
+                json_string(&path_to_string(dir)),
+                top.total_size
+            )?;
+            write_json_entries(top.files.iter(), writer)?;
+            write!(writer, "}}")?;
+        }
+        write!(writer, "]")?;
+    }
+
+    if opts.top_per_user {
+        write!(writer, ",\"per_user\":[")?;
+        for (i, (uid, top)) in stats.users_by_size().into_iter().enumerate() {
+            if i > 0 {
+                write!(writer, ",")?;
+            }
+            let name = resolved_name(uid, opts);
+            write!(
+                writer,
+                "{{\"uid\":{},\"user\":{},\"total_bytes\":{},\"files\":",
+                uid,
+                name.map_or_else(|| "null".to_string(), |name| json_string(&name)),
+                top.total_size
+            )?;
+            write_json_entries(top.files.iter(), writer)?;
+            if !opts.quotas.is_empty() {
+                write!(writer, ",\"quotas\":[")?;
+                for (j, (mount_point, quota)) in user_quotas(uid, opts).enumerate() {
+                    if j > 0 {
+                        write!(writer, ",")?;
+                    }
+                    write!(
+                        writer,
+                        "{{\"mount_point\":{},\"used_bytes\":{},\"soft_limit_bytes\":{},\"hard_limit_bytes\":{},\"pct_used\":{:.1}}}",
+                        json_string(&path_to_string(mount_point)),
+                        quota.used,
+                        quota.soft_limit,
+                        quota.hard_limit,
+                        quota.pct_used
+                    )?;
+                }
+                write!(writer, "]")?;
+            }
+            write!(writer, "}}")?;
+        }
+        write!(writer, "]")?;
+    }
+
+    if opts.hash_duplicates {
+        write!(writer, ",\"duplicates\":[")?;
+        for (i, group) in stats.duplicates.iter().enumerate() {
+            if i > 0 {
+                write!(writer, ",")?;
+            }
+            write!(
+                writer,
+                "{{\"size_bytes\":{},\"count\":{},\"wasted_bytes\":{},\"sha256\":\"{}\",\"keep\":{},\"paths\":[",
+                group.size,
+                group.files.len(),
+                group.wasted(),
+                hex(&group.hash),
+                json_string(&path_to_string(&group.canonical().path))
+            )?;
+            for (j, entry) in group.files.iter().enumerate() {
+                if j > 0 {
+                    write!(writer, ",")?;
+                }
+                write!(writer, "{}", json_string(&path_to_string(&entry.path)))?;
+            }
+            write!(writer, "]}}")?;
+        }
+        write!(writer, "]")?;
+    } else if opts.duplicate_sizes {
+        write!(writer, ",\"duplicate_sizes\":[")?;
+        for (i, (size, paths)) in stats.duplicate_size_groups().into_iter().enumerate() {
+            if i > 0 {
+                write!(writer, ",")?;
+            }
+            write!(
+                writer,
+                "{{\"size_bytes\":{},\"count\":{},\"reclaimable_bytes\":{},\"paths\":[",
+                size,
+                paths.len(),
+                size * (paths.len() as u64 - 1)
+            )?;
+            for (j, path) in paths.iter().enumerate() {
+                if j > 0 {
+                    write!(writer, ",")?;
+                }
+                write!(writer, "{}", json_string(&path_to_string(path)))?;
+            }
+            write!(writer, "]}}")?;
+        }
+        write!(writer, "]")?;
+    }
+
+    if opts.empty_dirs {
+        write_json_empty_dirs(stats, writer)?;
+    }
+    if opts.zero_sized {
+        write_json_zero_sized(stats, opts, writer)?;
+    }
+    if !opts.age_labels.is_empty() {
+        let total: u64 = stats
+            .age_buckets
+            .iter()
+            .map(|bucket| bucket.total_size)
+            .sum();
+        write!(writer, ",\"age_buckets\":[")?;
+        for (i, (label, bucket)) in opts.age_labels.iter().zip(&stats.age_buckets).enumerate() {
+            if i > 0 {
+                write!(writer, ",")?;
+            }
+            write!(
+                writer,
+                "{{\"age\":{},\"files\":{},\"total_bytes\":{},\"pct_of_total\":{:.1}}}",
+                json_string(label),
+                bucket.files,
+                bucket.total_size,
+                percent(bucket.total_size, total)
+            )?;
+        }
+        write!(writer, "]")?;
+    }
+
+    if opts.removed {
+        write!(writer, ",\"removed\":")?;
+        write_json_entries(&stats.removed, writer)?;
+    }
+
+    if opts.inode_usage || opts.inode_usage_only {
+        write!(writer, ",\"inode_usage\":[")?;
+        for (i, dir) in stats.inode_usage.iter().enumerate() {
+            if i > 0 {
+                write!(writer, ",")?;
+            }
+            write!(
+                writer,
+                "{{\"rank\":{},\"count\":{},\"path\":{}}}",
+                i + 1,
+                dir.count,
+                json_string(&path_to_string(&dir.path))
+            )?;
+        }
+        write!(writer, "]")?;
+    }
+
+    if opts.largest_dirs {
+        write!(writer, ",\"largest_dirs\":[")?;
+        for (i, dir) in stats.largest_dirs.iter().enumerate() {
+            if i > 0 {
+                write!(writer, ",")?;
+            }
+            write!(
+                writer,
+                "{{\"rank\":{},\"total_bytes\":{},\"size_human\":{},\"path\":{}}}",
+                i + 1,
+                dir.total_size,
+                json_string(&format_size(dir.total_size)),
+                json_string(&path_to_string(&dir.path))
+            )?;
+        }
+        write!(writer, "]")?;
+    }
+
+    if opts.top_dirs {
+        write!(writer, ",\"top_dirs\":[")?;
+        for (i, dir) in stats.top_dirs.iter().enumerate() {
+            if i > 0 {
+                write!(writer, ",")?;
+            }
+            write!(
+                writer,
+                "{{\"rank\":{},\"total_bytes\":{},\"size_human\":{},\"file_count\":{},\"path\":{}}}",
+                i + 1,
+                dir.total_size,
+                json_string(&format_size(dir.total_size)),
+                dir.file_count,
+                json_string(&path_to_string(&dir.path))
+            )?;
+        }
+        write!(writer, "]")?;
+    }
+
+    if let Some(limit) = opts.group_by_extension {
+        write!(writer, ",\"by_extension\":[")?;
+        for (i, (ext, bytes, count)) in stats.extensions_by_size(limit).into_iter().enumerate() {
+            if i > 0 {
+                write!(writer, ",")?;
+            }
+            write!(
+                writer,
+                "{{\"extension\":{},\"total_bytes\":{},\"count\":{}}}",
+                json_string(ext),
+                bytes,
+                count
+            )?;
+        }
+        write!(writer, "]")?;
+    }
+
+    let total: u64 = results.iter().map(|e| e.size).sum();
+    write!(writer, ",\"results_bytes\":{},\"filesystems\":[", total)?;
+    for (i, fs) in stats.filesystems.iter().enumerate() {
+        if i > 0 {
+            write!(writer, ",")?;
+        }
+        let percent = fs.percent_of_total(fs.results_bytes);
+        write!(
+            writer,
+            "{{\"root\":{},\"total_bytes\":{},\"available_bytes\":{},\"used_bytes\":{},\"results_bytes\":{},\"results_percent\":{}}}",
+            json_string(&path_to_string(&fs.root)),
+            fs.total_bytes,
+            fs.available_bytes,
+            fs.used_bytes,
+            fs.results_bytes,
+            percent.map_or_else(|| "null".to_string(), |p| format!("{:.2}", p))
+        )?;
+    }
+    write!(writer, "]")?;
+
+    if opts.scan_errors {
+        write_json_scan_errors(stats, writer)?;
+    }
+    if opts.inaccessible_dirs {
+        write_json_inaccessible_dirs(stats, writer)?;
+    }
+
+    write!(writer, ",\"stats\":")?;
+    write_json_stats(stats, writer)?;
+    writeln!(writer, "}}")
+}
+
+/// Display name for an extension group key
+fn extension_label(ext: &str) -> String {
+    if ext.is_empty() {
+        "(none)".to_string()
+    } else {
+        format!(".{}", ext)
+    }
+}
+
+/// Render per-extension totals followed by the statistics block
+pub fn render_extension_text(
+    stats: &ScanStats,
+    limit: usize,
+    opts: &OutputOptions,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    let groups = stats.extensions_by_size(limit);
+    writeln!(writer, "Top {} extensions by total size:", groups.len())?;
+    writeln!(writer)?;
+    writeln!(
+        writer,
+        "{:>5} {:>12}  {:>10}  Extension",
+        "#", "Size", "Files"
+    )?;
+    for (i, (ext, bytes, count)) in groups.into_iter().enumerate() {
+        writeln!(
+            writer,
+            "{:4}. {:>12}  {:>10}  {}",
+            i + 1,
+            format_size(bytes),
+            count,
+            extension_label(ext)
+        )?;
+    }
+
+    write_text_footer(stats, opts, writer)
+}
+
+/// Render per-extension totals as delimiter-separated values
+pub fn render_extension_csv(
+    stats: &ScanStats,
+    limit: usize,
+    opts: &OutputOptions,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    let d = opts.csv_delimiter;
+    writeln!(
+        writer,
+        "rank{d}total_bytes{d}size_human{d}count{d}extension"
+    )?;
+    for (i, (ext, bytes, count)) in stats.extensions_by_size(limit).into_iter().enumerate() {
+        writeln!(
+            writer,
+            "{}{d}{}{d}{}{d}{}{d}{}",
+            i + 1,
+            bytes,
+            csv_field(&format_size(bytes), d),
+            count,
+            csv_field(ext, d)
+        )?;
+    }
+
+    if opts.csv_stats {
+        write_csv_stats(stats, writer)?;
+    }
+    Ok(())
+}
+
+/// Write file entries as a JSON array of ranked objects
+fn write_json_entries<'a>(
+    entries: impl IntoIterator<Item = &'a FileEntry>,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    write!(writer, "[")?;
+    for (i, entry) in entries.into_iter().enumerate() {
+        if i > 0 {
+            write!(writer, ",")?;
+        }
+        write!(writer, "{{\"rank\":{},", i + 1)?;
+        write_json_entry_fields(entry, writer)?;
+        write!(writer, "}}")?;
+    }
+    write!(writer, "]")
+}
+
+/// Write the keys and values of a file's JSON object, without the braces
+fn write_json_entry_fields(entry: &FileEntry, writer: &mut dyn Write) -> io::Result<()> {
+    write!(
+        writer,
+        "\"size_bytes\":{},\"size_human\":{},\"modified\":{},\"sparseness\":{:.3},\"path\":{}",
+        entry.size,
+        json_string(&format_size(entry.size)),
+        entry
+            .modified
+            .map_or_else(|| "null".to_string(), |t| unix_timestamp(t).to_string()),
+        entry.sparseness,
+        json_string(&path_to_string(&entry.path))
+    )?;
+    if let Some(checksum) = &entry.checksum {
+        write!(writer, ",\"checksum\":\"{}\"", hex(checksum))?;
+    }
+    if entry.xattr_size > 0 {
+        write!(writer, ",\"xattr_size\":{}", entry.xattr_size)?;
+    }
+    if entry.allocated_size > 0 {
+        write!(writer, ",\"allocated_size\":{}", entry.allocated_size)?;
+    }
+    if entry.nlink > 1 {
+        write!(writer, ",\"nlink\":{}", entry.nlink)?;
+    }
+    if entry.mode != 0 {
+        write!(
+            writer,
+            ",\"mode\":{}",
+            json_string(&format_mode(entry.mode))
+        )?;
+    }
+    if entry.is_dir {
+        write!(writer, ",\"is_dir\":true")?;
+    }
+    match entry.verified {
+        None => {}
+        Some(Verification::Unchanged) => write!(writer, ",\"verified_size\":{}", entry.size)?,
+        Some(Verification::Changed(size)) => write!(writer, ",\"verified_size\":{}", size)?,
+        Some(Verification::Deleted) => write!(writer, ",\"verified_size\":null")?,
+    }
+    // Only set by reflink detection
+    if entry.is_reflinked || entry.physical_size > 0 {
+        write!(
+            writer,
+            ",\"is_reflinked\":{},\"physical_size\":{}",
+            entry.is_reflinked, entry.physical_size
+        )?;
+    }
+    Ok(())
+}
+
+/// Write a file as one line of `jsonlines` output: its JSON object, without
+/// a rank and with `"type":"file"`, or `"dir"` for a directory
+pub fn write_json_line(entry: &FileEntry, writer: &mut dyn Write) -> io::Result<()> {
+    let kind = if entry.is_dir { "dir" } else { "file" };
+    write!(writer, "{{\"type\":\"{}\",", kind)?;
+    write_json_entry_fields(entry, writer)?;
+    writeln!(writer, "}}")
+}
+
+/// Write the statistics as the last line of `jsonlines` output, with
+/// `"type":"stats"`
+fn write_json_stats_line(stats: &ScanStats, writer: &mut dyn Write) -> io::Result<()> {
+    write!(writer, "{{\"type\":\"stats\",")?;
+    write_json_stats_fields(stats, writer)?;
+    writeln!(writer, "}}")
+}
+
+/// Render one line per file, in rank order, then the statistics line
+pub fn render_json_lines(
+    results: &[FileEntry],
+    stats: &ScanStats,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    for entry in results {
+        write_json_line(entry, writer)?;
+    }
+    write_json_stats_line(stats, writer)
+}
+
+/// Write `ScanStats::empty_dirs` as an `empty_dirs` key following another one
+fn write_json_empty_dirs(stats: &ScanStats, writer: &mut dyn Write) -> io::Result<()> {
+    write!(writer, ",\"empty_dirs\":[")?;
+    for (i, dir) in stats.empty_dirs.iter().enumerate() {
+        if i > 0 {
+            write!(writer, ",")?;
+        }
+        write!(
+            writer,
+            "{{\"path\":{},\"subdir_count\":{}}}",
+            json_string(&path_to_string(&dir.path)),
+            dir.subdir_count
+        )?;
+    }
+    write!(writer, "]")
+}
+
+/// Write `ScanStats::zero_sized` as a `zero_sized_files` key following another
+/// one, leaving out the paths when only the count is asked for
+fn write_json_zero_sized(
+    stats: &ScanStats,
+    opts: &OutputOptions,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    write!(
+        writer,
+        ",\"zero_sized_files\":{{\"count\":{}",
+        stats.zero_sized.len()
+    )?;
+    if !opts.zero_sized_count {
+        write!(writer, ",\"paths\":[")?;
+        for (i, path) in stats.zero_sized.iter().enumerate() {
+            if i > 0 {
+                write!(writer, ",")?;
+            }
+            write!(writer, "{}", json_string(&path_to_string(path)))?;
+        }
+        write!(writer, "]")?;
+    }
+    write!(writer, "}}")
+}
+
+/// Write `ScanStats::error_details` as a `scan_errors` key following another one
+fn write_json_scan_errors(stats: &ScanStats, writer: &mut dyn Write) -> io::Result<()> {
+    write!(writer, ",\"scan_errors\":[")?;
+    for (i, error) in stats.error_details.iter().enumerate() {
+        if i > 0 {
+            write!(writer, ",")?;
+        }
+        write!(
+            writer,
+            "{{\"path\":{},\"reason\":{}}}",
+            json_string(&path_to_string(error.path())),
+            json_string(&error.reason())
+        )?;
+    }
+    write!(writer, "]")
+}
+
+/// Write `ScanStats::inaccessible_dirs` as an `inaccessible_dirs` key
+/// following another one, with the OS error code of each when there is one
+fn write_json_inaccessible_dirs(stats: &ScanStats, writer: &mut dyn Write) -> io::Result<()> {
+    write!(writer, ",\"inaccessible_dirs\":[")?;
+    for (i, (path, error)) in stats.inaccessible_dirs.iter().enumerate() {
+        if i > 0 {
+            write!(writer, ",")?;
+        }
+        write!(
+            writer,
+            "{{\"path\":{},\"reason\":{},\"os_error\":{}}}",
+            json_string(&path_to_string(path)),
+            json_string(&error.to_string()),
+            error
+                .raw_os_error()
+                .map_or_else(|| "null".to_string(), |code| code.to_string())
+        )?;
+    }
+    write!(writer, "]")
+}
+
+/// Write the statistics as a JSON object
+fn write_json_stats(stats: &ScanStats, writer: &mut dyn Write) -> io::Result<()> {
+    write!(writer, "{{")?;
+    write_json_stats_fields(stats, writer)?;
+    write!(writer, "}}")
+}
+
+/// Write the keys and values of the statistics object, without the braces
+fn write_json_stats_fields(stats: &ScanStats, writer: &mut dyn Write) -> io::Result<()> {
+    for (i, (key, value)) in stats_fields(stats).iter().enumerate() {
+        if i > 0 {
+            write!(writer, ",")?;
+        }
+        write!(writer, "\"{}\":{}", key, value)?;
+    }
+    if !stats.mounts.is_empty() {
+        write!(writer, ",\"mounts\":[")?;
+        for (i, mount) in stats.mounts.iter().enumerate() {
+            if i > 0 {
+                write!(writer, ",")?;
+            }
+            write!(
+                writer,
+                "{{\"path\":{},\"fstype\":{},\"device\":{},\"skipped\":{}}}",
+                json_string(&path_to_string(&mount.path)),
+                json_string(&mount.fstype),
+                json_string(&mount.device),
+                mount.skipped
+            )?;
+        }
+        write!(writer, "]")?;
+    }
+    Ok(())
+}
+
+/// Render results as delimiter-separated values with RFC 4180 quoting.
+/// When `stats` is given it is appended as a trailing `#` comment line.
+pub fn render_csv(
+    results: &[FileEntry],
+    stats: Option<&ScanStats>,
+    delimiter: char,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    let d = delimiter;
+    writeln!(writer, "rank{d}size_bytes{d}size_human{d}modified{d}path")?;
+
+    for (i, entry) in results.iter().enumerate() {
+        write_csv_row(i + 1, entry, d, writer)?;
+    }
+
+    if let Some(stats) = stats {
+        write_csv_stats(stats, writer)?;
+    }
+
+    Ok(())
+}
+
+fn write_csv_row(
+    rank: usize,
+    entry: &FileEntry,
+    d: char,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    writeln!(
+        writer,
+        "{}{d}{}{d}{}{d}{}{d}{}",
+        rank,
+        entry.size,
+        csv_field(&format_size(entry.size), d),
+        entry
+            .modified
+            .map_or_else(String::new, |t| unix_timestamp(t).to_string()),
+        csv_field(&path_to_string(&entry.path), d)
+    )
+}
+
+/// Write the statistics as a `#` comment line of `key=value` pairs
+fn write_csv_stats(stats: &ScanStats, writer: &mut dyn Write) -> io::Result<()> {
+    let fields: Vec<_> = stats_fields(stats)
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect();
+    writeln!(writer, "# {}", fields.join(" "))
+}
+
+/// Machine-readable statistics as (key, numeric value) pairs
+fn stats_fields(stats: &ScanStats) -> Vec<(&'static str, String)> {
+    // Zero when not counted, so the keys are always there
+    let types = stats.file_types.unwrap_or_default();
+    let mut fields = vec![
+        ("files_scanned", stats.files_scanned.to_string()),
+        ("dirs_scanned", stats.dirs_scanned.to_string()),
+        ("hardlinks_skipped", stats.hardlinks_skipped.to_string()),
+        ("symlinks_followed", stats.symlinks_followed.to_string()),
+        (
+            "symlink_cycles_detected",
+            stats.symlink_cycles_detected.to_string(),
+        ),
+        ("archive_members", stats.archive_members.to_string()),
+        ("symlinks", types.symlinks.to_string()),
+        ("sockets", types.sockets.to_string()),
+        ("fifos", types.fifos.to_string()),
+        ("block_devs", types.block_devs.to_string()),
+        ("char_devs", types.char_devs.to_string()),
+        ("errors", stats.errors.to_string()),
+        (
+            "elapsed_secs",
+            format!("{:.6}", stats.elapsed.as_secs_f64()),
+        ),
+    ];
+    if stats.auto_threads > 0 {
+        fields.push(("auto_threads", stats.auto_threads.to_string()));
+    }
+    if stats.aborted {
+        fields.push(("aborted", "true".to_string()));
+    }
+    fields
+}
+
+/// Render each directory's largest files as delimiter-separated values, with the
+/// directory in the first column and ranks restarting per directory
+pub fn render_per_dir_csv(
+    stats: &ScanStats,
+    opts: &OutputOptions,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    let d = opts.csv_delimiter;
+    writeln!(
+        writer,
+        "dir{d}rank{d}size_bytes{d}size_human{d}modified{d}path"
+    )?;
+    for (dir, top) in stats.dirs_by_size() {
+        let dir = csv_field(&path_to_string(dir), d);
+        for (i, entry) in top.files.iter().enumerate() {
+            write!(writer, "{}{d}", dir)?;
+            write_csv_row(i + 1, entry, d, writer)?;
+        }
+    }
+
+    if opts.csv_stats {
+        write_csv_stats(stats, writer)?;
+    }
+    Ok(())
+}
+
+/// Render each user's largest files as delimiter-separated values, with the
+/// user in the first column and ranks restarting per user
+pub fn render_per_user_csv(
+    stats: &ScanStats,
+    opts: &OutputOptions,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    let d = opts.csv_delimiter;
+    writeln!(
+        writer,
+        "user{d}rank{d}size_bytes{d}size_human{d}modified{d}path"
+    )?;
+    for (uid, top) in stats.users_by_size() {
+        let user = resolved_name(uid, opts).unwrap_or_else(|| uid.to_string());
+        let user = csv_field(&user, d);
+        for (i, entry) in top.files.iter().enumerate() {
+            write!(writer, "{}{d}", user)?;
+            write_csv_row(i + 1, entry, d, writer)?;
+        }
+    }
+
+    if opts.csv_stats {
+        write_csv_stats(stats, writer)?;
+    }
+    Ok(())
+}
+
+/// Render the largest directories as delimiter-separated values
+pub fn render_dirs_csv(
+    stats: &ScanStats,
+    opts: &OutputOptions,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    let d = opts.csv_delimiter;
+    writeln!(writer, "rank{d}total_bytes{d}size_human{d}path")?;
+    for (i, dir) in stats.largest_dirs.iter().enumerate() {
+        writeln!(
+            writer,
+            "{}{d}{}{d}{}{d}{}",
+            i + 1,
+            dir.total_size,
+            csv_field(&format_size(dir.total_size), d),
+            csv_field(&path_to_string(&dir.path), d)
+        )?;
+    }
+
+    if opts.csv_stats {
+        write_csv_stats(stats, writer)?;
+    }
+    Ok(())
+}
+
+/// Render the directory totals of `ScanStats::top_dirs` as delimiter-separated
+/// values
+pub fn render_top_dirs_csv(
+    stats: &ScanStats,
+    opts: &OutputOptions,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    let d = opts.csv_delimiter;
+    writeln!(
+        writer,
+        "rank{d}total_bytes{d}size_human{d}file_count{d}path"
+    )?;
+    for (i, dir) in stats.top_dirs.iter().enumerate() {
+        writeln!(
+            writer,
+            "{}{d}{}{d}{}{d}{}{d}{}",
+            i + 1,
+            dir.total_size,
+            csv_field(&format_size(dir.total_size), d),
+            dir.file_count,
+            csv_field(&path_to_string(&dir.path), d)
+        )?;
+    }
+
+    if opts.csv_stats {
+        write_csv_stats(stats, writer)?;
+    }
+    Ok(())
+}
+
+/// Render the directories with the most entries as delimiter-separated values
+pub fn render_inodes_csv(
+    stats: &ScanStats,
+    opts: &OutputOptions,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    let d = opts.csv_delimiter;
+    writeln!(writer, "rank{d}count{d}path")?;
+    for (i, dir) in stats.inode_usage.iter().enumerate() {
+        writeln!(
+            writer,
+            "{}{d}{}{d}{}",
+            i + 1,
+            dir.count,
+            csv_field(&path_to_string(&dir.path), d)
+        )?;
+    }
+
+    if opts.csv_stats {
+        write_csv_stats(stats, writer)?;
+    }
+    Ok(())
+}
+
+/// Quote a CSV field if it contains the delimiter, a quote or a line break
+fn csv_field(s: &str, delimiter: char) -> String {
+    if s.contains([delimiter, '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// Convert a path to a UTF-8 string, percent-encoding the bytes that are not
+/// valid UTF-8 and every literal `%`, so that no two paths give the same string
+/// and the bytes can be recovered. Other paths are returned unchanged.
+pub fn path_to_string(path: &Path) -> String {
+    if let Some(s) = path.to_str()
+        && !s.contains('%')
+    {
+        return s.to_string();
+    }
+
+    let mut out = String::new();
+    for chunk in path.as_os_str().as_encoded_bytes().utf8_chunks() {
+        for c in chunk.valid().chars() {
+            if c == '%' {
+                out.push_str("%25");
+            } else {
+                out.push(c);
+            }
+        }
+        for byte in chunk.invalid() {
+            write!(out, "%{:02X}", byte).unwrap();
+        }
+    }
+    out
+}
+
+/// Quote and escape a string as a JSON string literal
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::shorten_left;
+
+    #[test]
+    fn shorten_left_keeps_what_fits() {
+        assert_eq!(shorten_left("/a/b", 4), "/a/b");
+        assert_eq!(shorten_left("/a/b", 10), "/a/b");
+        assert_eq!(shorten_left("", 0), "");
+    }
+
+    #[test]
+    fn shorten_left_keeps_the_end() {
+        assert_eq!(shorten_left("/home/user/file.txt", 10), "…/file.txt");
+        assert_eq!(
+            shorten_left("/home/user/file.txt", 18),
+            "…ome/user/file.txt"
+        );
+    }
+
+    #[test]
+    fn shorten_left_at_narrow_widths() {
+        assert_eq!(shorten_left("abcdef", 3), "…ef");
+        assert_eq!(shorten_left("abcdef", 2), "…f");
+        assert_eq!(shorten_left("abcdef", 1), "…");
+        // Nothing fits, not even the ellipsis; it is kept as the one sign
+        // that the path was cut
+        assert_eq!(shorten_left("abcdef", 0), "…");
+    }
+
+    #[test]
+    fn shorten_left_counts_characters() {
+        assert_eq!(shorten_left("/données/日本語.txt", 8), "…日本語.txt");
+        assert_eq!(shorten_left("日本語", 3), "日本語");
+        assert_eq!(shorten_left("日本語", 2), "…語");
+    }
+}
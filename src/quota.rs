@@ -0,0 +1,135 @@
+//! Reading user disk quotas on Linux through `quotactl`, for filesystems
+//! mounted with quotas enabled
+
+use crate::QuotaInfo;
+use crate::mounts::unescape;
+use std::collections::HashSet;
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+/// `USRQUOTA` from `sys/quota.h`; libc only exports the group and project kinds
+const USRQUOTA: libc::c_int = 0;
+/// Unit of the block limits in `struct dqblk`
+const QUOTA_BLOCK_SIZE: u64 = 1024;
+/// Mount options that turn on user quotas (ext4, XFS and the journaled variants)
+const QUOTA_OPTIONS: [&str; 4] = ["usrquota", "quota", "uquota", "uqnoenforce"];
+
+/// A filesystem mounted with user quotas
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuotaMount {
+    /// The block device `quotactl` is given
+    pub device: PathBuf,
+    pub mount_point: PathBuf,
+}
+
+/// The filesystems `/etc/mtab` lists with user quotas enabled
+pub fn quota_mounts() -> io::Result<Vec<QuotaMount>> {
+    let mtab = fs::read_to_string("/etc/mtab")?;
+    Ok(mtab
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let (device, mount_point) = (fields.next()?, fields.next()?);
+            let options = fields.nth(1)?;
+            options
+                .split(',')
+                .any(|option| QUOTA_OPTIONS.contains(&option) || option.starts_with("usrjquota="))
+                .then(|| QuotaMount {
+                    device: unescape(device),
+                    mount_point: unescape(mount_point),
+                })
+        })
+        .collect())
+}
+
+/// The block quota of user `uid` on `mount`. Reading another user's quota
+/// needs root or `CAP_SYS_ADMIN` and fails with `PermissionDenied` otherwise.
+pub fn user_quota(mount: &QuotaMount, uid: u32) -> io::Result<QuotaInfo> {
+    let device = CString::new(mount.device.as_os_str().as_bytes()).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "device path contains a NUL byte",
+        )
+    })?;
+    // SAFETY: all-zero is a valid `dqblk`, a plain struct of integers
+    let mut quota: libc::dqblk = unsafe { std::mem::zeroed() };
+    // SAFETY: `device` is NUL-terminated and `quota` is the `dqblk` Q_GETQUOTA fills
+    let rc = unsafe {
+        libc::quotactl(
+            libc::QCMD(libc::Q_GETQUOTA, USRQUOTA),
+            device.as_ptr(),
+            uid as libc::c_int,
+            &mut quota as *mut libc::dqblk as *mut libc::c_char,
+        )
+    };
+    if rc < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let used = quota.dqb_curspace;
+    let soft_limit = quota.dqb_bsoftlimit * QUOTA_BLOCK_SIZE;
+    let hard_limit = quota.dqb_bhardlimit * QUOTA_BLOCK_SIZE;
+    let limit = if soft_limit > 0 {
+        soft_limit
+    } else {
+        hard_limit
+    };
+    let pct_used = if limit > 0 {
+        used as f32 / limit as f32 * 100.0
+    } else {
+        0.0
+    };
+    Ok(QuotaInfo {
+        uid,
+        used,
+        soft_limit,
+        hard_limit,
+        pct_used,
+    })
+}
+
+/// The quotas of `uids` on every quota-enabled filesystem holding or inside one
+/// of `roots`, by mount point. Filesystems whose quotas are mounted but not
+/// turned on are left out; any other failure, such as lacking the privilege to
+/// read other users' quotas, is returned.
+pub fn quota_report(roots: &[PathBuf], uids: &[u32]) -> io::Result<Vec<(PathBuf, QuotaInfo)>> {
+    let devices: HashSet<u64> = roots
+        .iter()
+        .filter_map(|root| fs::metadata(root).ok())
+        .map(|meta| meta.dev())
+        .collect();
+    let holds_root = |mount_point: &Path| {
+        roots.iter().any(|root| mount_point.starts_with(root))
+            || fs::metadata(mount_point).is_ok_and(|meta| devices.contains(&meta.dev()))
+    };
+
+    let mut report = Vec::new();
+    let mut seen = HashSet::new();
+    for mount in quota_mounts()? {
+        if !holds_root(&mount.mount_point) || !seen.insert(mount.device.clone()) {
+            continue;
+        }
+        for &uid in uids {
+            match user_quota(&mount, uid) {
+                Ok(quota) => report.push((mount.mount_point.clone(), quota)),
+                // Quotas are off on this filesystem (or unsupported by the kernel)
+                Err(e) if matches!(e.raw_os_error(), Some(libc::ESRCH | libc::ENOSYS)) => break,
+                Err(e) => {
+                    return Err(io::Error::new(
+                        e.kind(),
+                        format!(
+                            "cannot read quotas on {}: {}",
+                            mount.mount_point.display(),
+                            e
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+    Ok(report)
+}
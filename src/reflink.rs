@@ -0,0 +1,147 @@
+//! Detecting reflinked files, whose copy-on-write extents are shared with
+//! other files on Btrfs and XFS, through the `FS_IOC_FIEMAP` extent map
+
+use crate::{FileEntry, ScanError};
+use rayon::prelude::*;
+use std::fs::File;
+use std::io;
+use std::os::fd::AsRawFd;
+use std::path::Path;
+
+/// `_IOWR('f', 11, struct fiemap)`
+const FS_IOC_FIEMAP: u32 = 0xC020_660B;
+/// Flush delayed allocations so every extent has a physical location
+const FIEMAP_FLAG_SYNC: u32 = 0x1;
+const FIEMAP_EXTENT_LAST: u32 = 0x1;
+const FIEMAP_EXTENT_SHARED: u32 = 0x2000;
+/// Extents fetched per ioctl; files with more are mapped in several calls
+const EXTENTS_PER_CALL: usize = 256;
+
+/// `struct fiemap_extent` from `linux/fiemap.h`
+#[repr(C)]
+#[allow(dead_code)]
+#[derive(Clone, Copy, Default)]
+struct FiemapExtent {
+    logical: u64,
+    physical: u64,
+    length: u64,
+    reserved64: [u64; 2],
+    flags: u32,
+    reserved: [u32; 3],
+}
+
+/// `struct fiemap` with room for `EXTENTS_PER_CALL` extents
+#[repr(C)]
+struct Fiemap {
+    start: u64,
+    length: u64,
+    flags: u32,
+    mapped_extents: u32,
+    extent_count: u32,
+    reserved: u32,
+    extents: [FiemapExtent; EXTENTS_PER_CALL],
+}
+
+/// How a file's extents divide between blocks it owns and blocks it shares
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExtentUsage {
+    /// Bytes in extents no other file references
+    pub exclusive: u64,
+    /// Bytes in extents shared with other files or snapshots
+    pub shared: u64,
+}
+
+/// Whether `FS_IOC_FIEMAP` failed because the filesystem does not implement it
+fn is_unsupported(e: &io::Error) -> bool {
+    matches!(
+        e.raw_os_error(),
+        Some(libc::EOPNOTSUPP | libc::ENOTTY | libc::EINVAL)
+    )
+}
+
+/// Read the extent map of the file at `path`
+pub fn extent_usage(path: &Path) -> io::Result<ExtentUsage> {
+    let file = File::open(path)?;
+    let mut map = Box::new(Fiemap {
+        start: 0,
+        length: 0,
+        flags: 0,
+        mapped_extents: 0,
+        extent_count: 0,
+        reserved: 0,
+        extents: [FiemapExtent::default(); EXTENTS_PER_CALL],
+    });
+    let mut usage = ExtentUsage::default();
+    let mut start = 0;
+    loop {
+        map.start = start;
+        map.length = u64::MAX - start;
+        map.flags = FIEMAP_FLAG_SYNC;
+        map.mapped_extents = 0;
+        map.extent_count = EXTENTS_PER_CALL as u32;
+        // SAFETY: `map` is a `struct fiemap` followed by `extent_count` extents,
+        // all of which the kernel may write and none beyond
+        let rc = unsafe {
+            libc::ioctl(
+                file.as_raw_fd(),
+                FS_IOC_FIEMAP as _,
+                &mut *map as *mut Fiemap,
+            )
+        };
+        if rc < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mapped = (map.mapped_extents as usize).min(EXTENTS_PER_CALL);
+        let mut last = mapped == 0;
+        for extent in &map.extents[..mapped] {
+            if extent.flags & FIEMAP_EXTENT_SHARED != 0 {
+                usage.shared += extent.length;
+            } else {
+                usage.exclusive += extent.length;
+            }
+            start = extent.logical.saturating_add(extent.length);
+            last |= extent.flags & FIEMAP_EXTENT_LAST != 0;
+        }
+        if last || start == u64::MAX {
+            return Ok(usage);
+        }
+    }
+}
+
+/// Fill in `FileEntry::is_reflinked` and `FileEntry::physical_size` for every
+/// entry, in parallel. Files on filesystems without extent maps are treated as
+/// owning all of their size; their count is returned with the files that could
+/// not be read.
+pub fn detect_reflinks(entries: &mut [FileEntry]) -> (usize, Vec<ScanError>) {
+    let outcomes: Vec<Option<Result<(), ScanError>>> = entries
+        .par_iter_mut()
+        .map(|entry| match extent_usage(&entry.path) {
+            Ok(usage) => {
+                entry.is_reflinked = usage.shared > 0;
+                entry.physical_size = usage.exclusive;
+                Some(Ok(()))
+            }
+            Err(e) if is_unsupported(&e) => {
+                entry.physical_size = entry.size;
+                None
+            }
+            Err(source) => {
+                entry.physical_size = entry.size;
+                Some(Err(ScanError::ReadFailed {
+                    path: entry.path.clone(),
+                    source,
+                }))
+            }
+        })
+        .collect();
+
+    let unsupported = outcomes.iter().filter(|o| o.is_none()).count();
+    let mut errors: Vec<ScanError> = outcomes
+        .into_iter()
+        .flatten()
+        .filter_map(Result::err)
+        .collect();
+    errors.sort_by(|a, b| a.path().cmp(b.path()));
+    (unsupported, errors)
+}
@@ -0,0 +1,68 @@
+//! Compact binary snapshots of a scan, for storing many entries and reading
+//! them back without rescanning
+//!
+//! A snapshot is `MAGIC`, a `VERSION` byte, the payload length as a
+//! little-endian `u64`, the results and statistics encoded with `postcard`,
+//! and the SHA-256 of that payload.
+
+use crate::{FileEntry, ScanStats};
+use sha2::{Digest, Sha256};
+use std::io::{self, Read, Write};
+
+/// First bytes of every snapshot
+pub const MAGIC: &[u8; 8] = b"BFNDSNAP";
+
+/// Layout of the payload; bumped whenever `FileEntry` or `ScanStats` change
+/// their fields, since the encoding carries no field names
+pub const VERSION: u8 = 18;
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+/// Write the results (`None` for count-only scans) and statistics as a snapshot
+pub fn write_snapshot(
+    results: Option<&[FileEntry]>,
+    stats: &ScanStats,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    let payload = postcard::to_stdvec(&(results, stats)).map_err(io::Error::other)?;
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[VERSION])?;
+    writer.write_all(&(payload.len() as u64).to_le_bytes())?;
+    writer.write_all(&payload)?;
+    writer.write_all(&Sha256::digest(&payload))
+}
+
+/// Read a snapshot written by `write_snapshot`, rejecting other versions and
+/// corrupted payloads
+pub fn read_snapshot(reader: &mut dyn Read) -> io::Result<(Option<Vec<FileEntry>>, ScanStats)> {
+    let mut header = [0u8; 17];
+    reader.read_exact(&mut header)?;
+    if &header[..8] != MAGIC {
+        return Err(invalid("not a bfinder binary snapshot"));
+    }
+    if header[8] != VERSION {
+        return Err(invalid(format!(
+            "snapshot format version {} is not supported (expected {})",
+            header[8], VERSION
+        )));
+    }
+    let len = u64::from_le_bytes(header[9..].try_into().unwrap());
+
+    let mut payload = Vec::new();
+    reader.take(len).read_to_end(&mut payload)?;
+    let mut checksum = [0u8; 32];
+    if payload.len() as u64 != len || reader.read_exact(&mut checksum).is_err() {
+        return Err(invalid("snapshot is truncated"));
+    }
+    if Sha256::digest(&payload).as_slice() != checksum {
+        return Err(invalid("snapshot checksum mismatch"));
+    }
+    postcard::from_bytes(&payload).map_err(|e| invalid(format!("invalid snapshot: {}", e)))
+}
+
+/// Whether `bytes` start like a binary snapshot
+pub fn is_snapshot(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
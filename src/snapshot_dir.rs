@@ -0,0 +1,285 @@
+//! Snapshots kept by `--snapshot-dir`, so each scan is compared with the last
+//! one of the same roots without naming snapshot files
+//!
+//! Every set of roots has a subdirectory named by the SHA-256 of its canonical
+//! paths, holding `roots.txt`, which lists them, and a snapshot per scan named
+//! by the Unix time it was saved, to the nanosecond
+//! (`1791984812.048213377.json`), in the format of `--save-snapshot`, so
+//! `--diff` reads them too.
+
+use bfinder::{FileEntry, format_time};
+use sha2::{Digest, Sha256};
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const ROOTS_FILE: &str = "roots.txt";
+
+/// The snapshots of one set of roots
+pub struct SnapshotSet {
+    dir: PathBuf,
+    /// The canonical roots, one per line
+    roots: String,
+}
+
+impl SnapshotSet {
+    /// The snapshots of `roots` in `base`. Roots that cannot be canonicalized
+    /// (they fail the scan anyway) are keyed by their absolute path.
+    pub fn new(base: &Path, roots: &[PathBuf]) -> Self {
+        let mut canonical: Vec<String> = roots
+            .iter()
+            .map(|root| {
+                let path = fs::canonicalize(root).or_else(|_| std::path::absolute(root));
+                path.unwrap_or_else(|_| root.clone())
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect();
+        canonical.sort_unstable();
+        canonical.dedup();
+        let roots = canonical.join("\n");
+        let key = Sha256::digest(roots.as_bytes())
+            .iter()
+            .fold(String::new(), |mut hex, b| {
+                let _ = write!(hex, "{:02x}", b);
+                hex
+            });
+        SnapshotSet {
+            dir: base.join(key),
+            roots,
+        }
+    }
+
+    /// The most recent snapshot, if any
+    pub fn latest(&self) -> Option<PathBuf> {
+        snapshots(&self.dir).into_iter().max().map(|(_, path)| path)
+    }
+
+    /// Save `results` as a new snapshot, returning its path. A snapshot
+    /// already saved at the same time (on a coarse clock) moves this one a
+    /// nanosecond later, so the last one saved stays the latest.
+    pub fn save(&self, results: &[FileEntry]) -> Result<PathBuf, String> {
+        let err = |e: io::Error| format!("cannot save snapshot in {}: {}", self.dir.display(), e);
+        fs::create_dir_all(&self.dir).map_err(err)?;
+        fs::write(self.dir.join(ROOTS_FILE), format!("{}\n", self.roots)).map_err(err)?;
+        let mut saved = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        if let Some((latest, _)) = snapshots(&self.dir).into_iter().max() {
+            saved = saved.max(latest + Duration::from_nanos(1));
+        }
+        let path = self.dir.join(file_name(saved));
+        super::save_snapshot(&path, results)?;
+        Ok(path)
+    }
+}
+
+/// The name of a snapshot saved `saved` after the Unix epoch
+fn file_name(saved: Duration) -> String {
+    format!("{}.{:09}.json", saved.as_secs(), saved.subsec_nanos())
+}
+
+/// The time after the Unix epoch in a snapshot's `file_name`, without `.json`
+fn parse_time(stem: &str) -> Option<Duration> {
+    let (secs, nanos) = stem.split_once('.')?;
+    if nanos.len() != 9 {
+        return None;
+    }
+    Some(Duration::new(secs.parse().ok()?, nanos.parse().ok()?))
+}
+
+/// The snapshots in a set's directory, with the Unix time each was saved at
+fn snapshots(dir: &Path) -> Vec<(Duration, PathBuf)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != "json" {
+                return None;
+            }
+            let saved = parse_time(path.file_stem()?.to_str()?)?;
+            Some((saved, path))
+        })
+        .collect()
+}
+
+/// The directories of the snapshot sets in `base`
+fn sets(base: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut dirs: Vec<PathBuf> = fs::read_dir(base)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.join(ROOTS_FILE).is_file())
+        .collect();
+    dirs.sort_unstable();
+    Ok(dirs)
+}
+
+/// Delete the snapshots in `base` saved more than `max_age` ago, except
+/// `keep`, and the sets left without any. Returns how many were deleted.
+pub fn purge(base: &Path, max_age: Duration, keep: &Path) -> io::Result<usize> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let cutoff = now.saturating_sub(max_age);
+    let mut purged = 0;
+    for dir in sets(base)? {
+        let mut left = 0;
+        for (saved, path) in snapshots(&dir) {
+            if saved >= cutoff || path == keep {
+                left += 1;
+                continue;
+            }
+            match fs::remove_file(&path) {
+                Ok(()) => purged += 1,
+                Err(e) => {
+                    eprintln!(
+                        "bfinder: warning: cannot delete snapshot {}: {}",
+                        path.display(),
+                        e
+                    );
+                    left += 1;
+                }
+            }
+        }
+        if left == 0 {
+            fs::remove_file(dir.join(ROOTS_FILE))?;
+            fs::remove_dir(&dir)?;
+        }
+    }
+    Ok(purged)
+}
+
+/// List the snapshots in `base` by their roots, oldest first, with the files
+/// each holds
+pub fn list(base: &Path, writer: &mut dyn Write) -> io::Result<()> {
+    let dirs = match sets(base) {
+        Ok(dirs) => dirs,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e),
+    };
+    if dirs.is_empty() {
+        return writeln!(writer, "No snapshots in {}", base.display());
+    }
+    for (i, dir) in dirs.iter().enumerate() {
+        if i > 0 {
+            writeln!(writer)?;
+        }
+        for root in fs::read_to_string(dir.join(ROOTS_FILE))?.lines() {
+            writeln!(writer, "{}", root)?;
+        }
+        let mut saved = snapshots(dir);
+        saved.sort_unstable();
+        for (saved, path) in saved {
+            let time = format_time(UNIX_EPOCH + saved);
+            match super::load_snapshot(&path) {
+                Ok(entries) => {
+                    let files = if entries.len() == 1 { "file" } else { "files" };
+                    writeln!(
+                        writer,
+                        "  {}  {:>8} {:<5}  {}",
+                        time,
+                        entries.len(),
+                        files,
+                        path.display()
+                    )?
+                }
+                Err(msg) => writeln!(writer, "  {}  {}", time, msg)?,
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str, size: u64) -> FileEntry {
+        FileEntry {
+            size,
+            path: PathBuf::from(path),
+            ..FileEntry::default()
+        }
+    }
+
+    /// Save an empty snapshot of `set` as if `age` ago
+    fn save_aged(set: &SnapshotSet, age: Duration) -> PathBuf {
+        let saved = set.save(&[]).unwrap();
+        let then = SystemTime::now().duration_since(UNIX_EPOCH).unwrap() - age;
+        let path = set.dir.join(file_name(then));
+        fs::rename(&saved, &path).unwrap();
+        path
+    }
+
+    #[test]
+    fn names_round_trip() {
+        let saved = Duration::new(1791984812, 48213377);
+        assert_eq!(file_name(saved), "1791984812.048213377.json");
+        assert_eq!(parse_time("1791984812.048213377"), Some(saved));
+        assert_eq!(parse_time("1791984812"), None);
+        assert_eq!(parse_time("1791984812.48213377"), None);
+    }
+
+    #[test]
+    fn scans_in_the_same_second_do_not_collide() {
+        let base = tempfile::tempdir().unwrap();
+        let set = SnapshotSet::new(base.path(), &[base.path().to_path_buf()]);
+        let first = set.save(&[entry("/a", 1)]).unwrap();
+        let second = set.save(&[entry("/a", 1), entry("/b", 2)]).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(set.latest(), Some(second.clone()));
+        assert_eq!(crate::load_snapshot(&second).unwrap().len(), 2);
+        assert_eq!(crate::load_snapshot(&first).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn purge_keeps_recent_snapshots_and_the_one_saved() {
+        let base = tempfile::tempdir().unwrap();
+        let day = Duration::from_secs(86400);
+        let kept = SnapshotSet::new(base.path(), &[PathBuf::from("/kept")]);
+        let old = save_aged(&kept, day * 10);
+        let recent = save_aged(&kept, day);
+        // A set whose only snapshot is old goes entirely, unless it is `keep`
+        let gone = SnapshotSet::new(base.path(), &[PathBuf::from("/gone")]);
+        save_aged(&gone, day * 30);
+        let just_saved = SnapshotSet::new(base.path(), &[PathBuf::from("/just-saved")]);
+        let keep = save_aged(&just_saved, day * 30);
+
+        assert_eq!(purge(base.path(), day * 7, &keep).unwrap(), 2);
+        assert!(!old.exists());
+        assert!(recent.exists() && keep.exists());
+        assert!(!gone.dir.exists());
+        assert_eq!(sets(base.path()).unwrap().len(), 2);
+        assert_eq!(purge(base.path(), day * 7, &keep).unwrap(), 0);
+    }
+
+    #[test]
+    fn lists_each_set_with_its_snapshots() {
+        let base = tempfile::tempdir().unwrap();
+        let mut out = Vec::new();
+        list(&base.path().join("missing"), &mut out).unwrap();
+        assert!(
+            String::from_utf8(out)
+                .unwrap()
+                .starts_with("No snapshots in ")
+        );
+
+        let set = SnapshotSet::new(base.path(), &[PathBuf::from("/data")]);
+        set.save(&[entry("/data/a", 1)]).unwrap();
+        set.save(&[entry("/data/a", 1), entry("/data/b", 2)])
+            .unwrap();
+        fs::write(set.dir.join("9.000000000.json"), "not a snapshot").unwrap();
+        let mut out = Vec::new();
+        list(base.path(), &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(lines.len(), 4, "{}", out);
+        assert_eq!(lines[0], set.roots);
+        assert!(lines[1].contains("invalid snapshot"), "{}", out);
+        assert!(lines[2].contains("       1 file   "), "{}", out);
+        assert!(lines[3].contains("       2 files  "), "{}", out);
+    }
+}
@@ -0,0 +1,72 @@
+//! Hierarchical view of scan results
+
+use crate::FileEntry;
+use std::ffi::OsStr;
+use std::path::{Component, Path, PathBuf};
+
+/// A directory holding some of the reported files, directly or below it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TreeNode {
+    /// Path relative to the parent node; the root holds the ancestor shared by
+    /// every file (empty for relative paths without one)
+    pub name: PathBuf,
+    /// Total size of the reported files in this directory and beneath it
+    pub size: u64,
+    /// Reported files directly in this directory, in the order they were given
+    pub files: Vec<FileEntry>,
+    /// Subdirectories, largest first
+    pub children: Vec<TreeNode>,
+}
+
+impl TreeNode {
+    fn child_mut(&mut self, name: &OsStr) -> &mut TreeNode {
+        let index = match self.children.iter().position(|c| c.name == name) {
+            Some(index) => index,
+            None => {
+                self.children.push(TreeNode {
+                    name: PathBuf::from(name),
+                    ..TreeNode::default()
+                });
+                self.children.len() - 1
+            }
+        };
+        &mut self.children[index]
+    }
+
+    fn sort(&mut self) {
+        self.children
+            .sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+        for child in &mut self.children {
+            child.sort();
+        }
+    }
+}
+
+/// Arrange files into the directories above them. Only ancestors of the given
+/// files appear, so sizes are totals of those files rather than of everything
+/// scanned; leading directories shared by every file are merged into the root.
+pub fn build_tree(entries: &[FileEntry]) -> TreeNode {
+    let mut root = TreeNode::default();
+    for entry in entries {
+        root.size += entry.size;
+        let mut node = &mut root;
+        for component in entry.path.parent().unwrap_or(Path::new("")).components() {
+            if component == Component::CurDir {
+                continue;
+            }
+            node = node.child_mut(component.as_os_str());
+            node.size += entry.size;
+        }
+        node.files.push(entry.clone());
+    }
+
+    while root.files.is_empty() && root.children.len() == 1 {
+        let child = root.children.pop().unwrap();
+        root = TreeNode {
+            name: root.name.join(child.name),
+            ..child
+        };
+    }
+    root.sort();
+    root
+}
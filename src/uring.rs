@@ -0,0 +1,157 @@
+//! Stat'ing the entries of a directory in batches through io_uring, for
+//! `io_uring_scan`: one submission carries up to `RING_ENTRIES` `statx` calls,
+//! so a directory costs a few `io_uring_enter` calls rather than one syscall
+//! per entry
+
+use crate::{DirEntry, EntryMetadata, SpecialFile, StatBatch, classify_entry, system_time};
+use io_uring::{IoUring, opcode, types};
+use std::ffi::CString;
+use std::io;
+use std::os::fd::{AsRawFd, BorrowedFd};
+use std::os::unix::ffi::OsStrExt;
+
+/// Submission queue entries per ring; larger directories take several batches
+const RING_ENTRIES: u32 = 256;
+
+/// One thread's ring, with the buffers its `statx` calls fill
+pub(crate) struct StatRing {
+    ring: IoUring,
+    buffers: Vec<libc::statx>,
+    /// Waiting for completions failed, so requests may still be in flight;
+    /// their names and buffers are then leaked, and entries stat'ed one by one
+    broken: bool,
+}
+
+impl StatRing {
+    /// Set up a ring; fails with `ENOSYS` on kernels without io_uring
+    pub(crate) fn new() -> io::Result<Self> {
+        Ok(StatRing {
+            ring: IoUring::new(RING_ENTRIES)?,
+            // SAFETY: all-zero is a valid `statx`, a plain struct of integers
+            buffers: vec![unsafe { std::mem::zeroed() }; RING_ENTRIES as usize],
+            broken: false,
+        })
+    }
+
+    /// Stat at most `RING_ENTRIES` entries in one submission, appending a
+    /// result per entry to `results`
+    fn stat_batch(
+        &mut self,
+        parent: BorrowedFd<'_>,
+        entries: &[DirEntry],
+        results: &mut Vec<io::Result<EntryMetadata>>,
+    ) {
+        let names: Vec<CString> = entries
+            .iter()
+            .map(|entry| CString::new(entry.name.as_bytes()).expect("file names have no NUL byte"))
+            .collect();
+        {
+            let mut queue = self.ring.submission();
+            for (i, (name, buffer)) in names.iter().zip(&mut self.buffers).enumerate() {
+                let statx = opcode::Statx::new(
+                    types::Fd(parent.as_raw_fd()),
+                    name.as_ptr(),
+                    buffer as *mut libc::statx as *mut types::statx,
+                )
+                .flags(libc::AT_SYMLINK_NOFOLLOW)
+                .mask(libc::STATX_BASIC_STATS)
+                .build()
+                .user_data(i as u64);
+                // SAFETY: the name and buffer outlive the request, as every
+                // completion is waited for below, and if waiting fails both are
+                // leaked rather than dropped or reused
+                unsafe { queue.push(&statx) }.expect("a batch fits in the submission queue");
+            }
+        }
+
+        // The `statx` result of each entry, `None` until it completes
+        let mut codes = vec![None; entries.len()];
+        let mut done = 0;
+        while done < entries.len() {
+            match self.ring.submit_and_wait(entries.len() - done) {
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    tracing::warn!(error = %e, "io_uring wait failed, stat'ing entries one by one");
+                    self.broken = true;
+                    break;
+                }
+            }
+            for completion in self.ring.completion() {
+                codes[completion.user_data() as usize] = Some(completion.result());
+                done += 1;
+            }
+        }
+
+        for ((entry, code), buffer) in entries.iter().zip(codes).zip(&self.buffers) {
+            results.push(match code {
+                Some(code) if code < 0 => Err(io::Error::from_raw_os_error(-code)),
+                Some(_) => Ok(metadata(buffer)),
+                None => classify_entry(parent, &entry.name, false),
+            });
+        }
+        if self.broken {
+            // The kernel may still read the names and fill the buffers of the
+            // requests that never completed, so neither may be freed
+            std::mem::forget(names);
+            std::mem::forget(std::mem::take(&mut self.buffers));
+        }
+    }
+}
+
+impl StatBatch for StatRing {
+    fn stat_all(
+        &mut self,
+        parent: BorrowedFd<'_>,
+        entries: &[DirEntry],
+    ) -> Vec<io::Result<EntryMetadata>> {
+        let mut results = Vec::with_capacity(entries.len());
+        for batch in entries.chunks(RING_ENTRIES as usize) {
+            if self.broken {
+                results.extend(
+                    batch
+                        .iter()
+                        .map(|entry| classify_entry(parent, &entry.name, false)),
+                );
+            } else {
+                self.stat_batch(parent, batch, &mut results);
+            }
+        }
+        results
+    }
+}
+
+/// Classify an entry from the `statx` buffer filled for it, as `classify_entry`
+/// classifies a `stat`
+fn metadata(stx: &libc::statx) -> EntryMetadata {
+    let mode = u32::from(stx.stx_mode);
+    let time = |t: libc::statx_timestamp| system_time(t.tv_sec, i64::from(t.tv_nsec));
+    match mode & libc::S_IFMT {
+        libc::S_IFREG => EntryMetadata::RegularFile {
+            logical_size: stx.stx_size,
+            block_size: stx.stx_blocks.saturating_mul(512),
+            device: libc::makedev(stx.stx_dev_major, stx.stx_dev_minor),
+            inode: stx.stx_ino,
+            nlink: u64::from(stx.stx_nlink),
+            uid: stx.stx_uid,
+            gid: stx.stx_gid,
+            mode,
+            modified: time(stx.stx_mtime),
+            accessed: time(stx.stx_atime),
+        },
+        libc::S_IFDIR => EntryMetadata::Directory {
+            device: libc::makedev(stx.stx_dev_major, stx.stx_dev_minor),
+            inode: stx.stx_ino,
+            logical_size: stx.stx_size,
+            block_size: stx.stx_blocks.saturating_mul(512),
+            modified: time(stx.stx_mtime),
+            accessed: time(stx.stx_atime),
+        },
+        libc::S_IFLNK => EntryMetadata::Symlink,
+        libc::S_IFSOCK => EntryMetadata::Other(SpecialFile::Socket),
+        libc::S_IFIFO => EntryMetadata::Other(SpecialFile::Fifo),
+        libc::S_IFBLK => EntryMetadata::Other(SpecialFile::BlockDevice),
+        libc::S_IFCHR => EntryMetadata::Other(SpecialFile::CharDevice),
+        _ => EntryMetadata::Other(SpecialFile::Unknown),
+    }
+}
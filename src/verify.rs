@@ -0,0 +1,69 @@
+//! Stat'ing the reported files again after a scan, to catch the files on a
+//! busy filesystem that changed size or were removed since they were measured
+
+use crate::{FileEntry, ScanConfig, ScanError, Verification, extended_attributes_size};
+use rayon::prelude::*;
+use std::fs;
+use std::io;
+
+/// The size of `entry` now, measured as `config` measures sizes
+fn current_size(entry: &FileEntry, config: &ScanConfig) -> io::Result<u64> {
+    let meta = fs::metadata(&entry.path)?;
+    #[cfg(unix)]
+    let size = if config.disk_usage {
+        std::os::unix::fs::MetadataExt::blocks(&meta).saturating_mul(512)
+    } else {
+        meta.len()
+    };
+    #[cfg(not(unix))]
+    let size = meta.len();
+    let xattr_size = if config.virtual_size {
+        extended_attributes_size(&entry.path)?
+    } else {
+        0
+    };
+    Ok(size + xattr_size)
+}
+
+/// Fill in `FileEntry::verified` for every entry, stat'ing the files again in
+/// parallel. Archive members, which cannot be stat'ed, stay unverified, as do
+/// the files that fail to stat for other reasons, which are returned.
+pub fn verify_results(entries: &mut [FileEntry], config: &ScanConfig) -> Vec<ScanError> {
+    let mut errors: Vec<ScanError> = entries
+        .par_iter_mut()
+        .filter_map(|entry| {
+            entry.verified = match current_size(entry, config) {
+                Ok(size) if size == entry.size => Some(Verification::Unchanged),
+                Ok(size) => Some(Verification::Changed(size)),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Some(Verification::Deleted),
+                // A member of an archive, which is a file rather than a directory
+                Err(e) if e.kind() == io::ErrorKind::NotADirectory => None,
+                Err(source) => {
+                    let path = entry.path.clone();
+                    return Some(ScanError::StatFailed { path, source });
+                }
+            };
+            None
+        })
+        .collect();
+    errors.sort_by(|a, b| a.path().cmp(b.path()));
+    errors
+}
+
+/// Rank verified results by the sizes they have now: changed files take their
+/// new size and removed files are dropped. Largest first, or smallest first
+/// with `smallest`; files that grew into the top N after the scan are not
+/// found.
+pub fn rerank_verified(results: &mut Vec<FileEntry>, smallest: bool) {
+    results.retain(|entry| entry.verified != Some(Verification::Deleted));
+    for entry in results.iter_mut() {
+        if let Some(Verification::Changed(size)) = entry.verified {
+            entry.size = size;
+        }
+    }
+    if smallest {
+        results.sort_by(|a, b| a.size.cmp(&b.size).then_with(|| a.path.cmp(&b.path)));
+    } else {
+        results.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+    }
+}
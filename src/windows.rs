@@ -0,0 +1,253 @@
+//! Scanning on Windows: directories are listed with `FindFirstFileExW` and
+//! `FindNextFileW`, whose `WIN32_FIND_DATAW` records already carry each entry's
+//! attributes, size and times, so most entries need no separate stat. Files are
+//! opened for their `BY_HANDLE_FILE_INFORMATION` only when hard links must be
+//! told apart.
+
+use crate::{DirEntry, EntryMetadata, StatBatch, classify_entry, system_time};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::os::windows::ffi::{OsStrExt, OsStringExt};
+use std::os::windows::fs::OpenOptionsExt;
+use std::os::windows::io::AsRawHandle;
+use std::path::Path;
+use windows_sys::Win32::Foundation::{ERROR_NO_MORE_FILES, FILETIME, HANDLE, INVALID_HANDLE_VALUE};
+use windows_sys::Win32::Storage::FileSystem::{
+    BY_HANDLE_FILE_INFORMATION, FILE_ATTRIBUTE_DIRECTORY, FILE_ATTRIBUTE_READONLY,
+    FILE_ATTRIBUTE_REPARSE_POINT, FILE_FLAG_BACKUP_SEMANTICS, FILE_FLAG_OPEN_REPARSE_POINT,
+    FIND_FIRST_EX_LARGE_FETCH, FindClose, FindExInfoBasic, FindExSearchNameMatch, FindFirstFileExW,
+    FindNextFileW, GetDiskFreeSpaceExW, GetFileInformationByHandle, WIN32_FIND_DATAW,
+};
+
+/// Reparse tag of a directory junction, from `winnt.h`
+const IO_REPARSE_TAG_MOUNT_POINT: u32 = 0xA000_0003;
+/// Reparse tag of a symbolic link, from `winnt.h`
+const IO_REPARSE_TAG_SYMLINK: u32 = 0xA000_000C;
+/// Seconds from 1601, where `FILETIME` starts, to the Unix epoch
+const FILETIME_EPOCH_OFFSET: i64 = 11_644_473_600;
+
+/// `path` as a NUL-terminated UTF-16 string
+fn wide(path: &Path) -> Vec<u16> {
+    path.as_os_str()
+        .encode_wide()
+        .chain(std::iter::once(0))
+        .collect()
+}
+
+fn system_time_of(time: FILETIME) -> std::time::SystemTime {
+    let ticks = (i64::from(time.dwHighDateTime) << 32) | i64::from(time.dwLowDateTime);
+    system_time(
+        ticks / 10_000_000 - FILETIME_EPOCH_OFFSET,
+        ticks % 10_000_000 * 100,
+    )
+}
+
+/// Classify an entry from its attributes and the fields both `WIN32_FIND_DATAW`
+/// and `BY_HANDLE_FILE_INFORMATION` have. Only symbolic links and junctions
+/// count as links; other reparse points, such as cloud placeholders, are files.
+#[allow(clippy::too_many_arguments)]
+fn metadata(
+    attributes: u32,
+    reparse_tag: Option<u32>,
+    size: u64,
+    modified: FILETIME,
+    accessed: FILETIME,
+    device: u64,
+    inode: u64,
+    nlink: u64,
+) -> EntryMetadata {
+    let is_link = attributes & FILE_ATTRIBUTE_REPARSE_POINT != 0
+        && reparse_tag
+            .is_none_or(|tag| matches!(tag, IO_REPARSE_TAG_SYMLINK | IO_REPARSE_TAG_MOUNT_POINT));
+    if is_link {
+        EntryMetadata::Symlink
+    } else if attributes & FILE_ATTRIBUTE_DIRECTORY != 0 {
+        EntryMetadata::Directory {
+            device,
+            inode,
+            logical_size: 0,
+            block_size: 0,
+            modified: system_time_of(modified),
+            accessed: system_time_of(accessed),
+        }
+    } else {
+        EntryMetadata::RegularFile {
+            logical_size: size,
+            // The allocation size is not listed; files count as fully allocated
+            block_size: size,
+            device,
+            inode,
+            nlink,
+            uid: 0,
+            gid: 0,
+            // Windows has no permission bits; read-only files lose their write bits
+            mode: if attributes & FILE_ATTRIBUTE_READONLY != 0 {
+                0o100444
+            } else {
+                0o100666
+            },
+            modified: system_time_of(modified),
+            accessed: system_time_of(accessed),
+        }
+    }
+}
+
+/// Classify the entry at `path` from the `BY_HANDLE_FILE_INFORMATION` of a
+/// handle opened on it, the link itself unless `follow` is set. A link opened
+/// without following has no tag here, so any reparse point counts as a link.
+pub(crate) fn stat(path: &Path, follow: bool) -> io::Result<EntryMetadata> {
+    let mut flags = FILE_FLAG_BACKUP_SEMANTICS;
+    if !follow {
+        flags |= FILE_FLAG_OPEN_REPARSE_POINT;
+    }
+    let file = fs::OpenOptions::new()
+        .access_mode(0)
+        .custom_flags(flags)
+        .open(path)?;
+    // SAFETY: all-zero is a valid `BY_HANDLE_FILE_INFORMATION`, a plain struct of integers
+    let mut info: BY_HANDLE_FILE_INFORMATION = unsafe { std::mem::zeroed() };
+    // SAFETY: the handle is open for as long as `file` lives and `info` is the struct it fills
+    if unsafe { GetFileInformationByHandle(file.as_raw_handle() as HANDLE, &mut info) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(metadata(
+        info.dwFileAttributes,
+        None,
+        (u64::from(info.nFileSizeHigh) << 32) | u64::from(info.nFileSizeLow),
+        info.ftLastWriteTime,
+        info.ftLastAccessTime,
+        u64::from(info.dwVolumeSerialNumber),
+        (u64::from(info.nFileIndexHigh) << 32) | u64::from(info.nFileIndexLow),
+        u64::from(info.nNumberOfLinks),
+    ))
+}
+
+/// The volume serial number and file index of the directory at `path`, the
+/// Windows counterparts of `st_dev` and `st_ino`
+pub(crate) fn identity(path: &Path) -> Option<(u64, u64)> {
+    match stat(path, true).ok()? {
+        EntryMetadata::Directory { device, inode, .. }
+        | EntryMetadata::RegularFile { device, inode, .. } => Some((device, inode)),
+        EntryMetadata::Symlink | EntryMetadata::Other(_) => None,
+    }
+}
+
+/// Total, available and free bytes of the volume holding `path`
+pub(crate) fn disk_space(path: &Path) -> io::Result<(u64, u64, u64)> {
+    let (mut available, mut total, mut free) = (0, 0, 0);
+    // SAFETY: the path is NUL-terminated and the three counters are what the call fills
+    let ok =
+        unsafe { GetDiskFreeSpaceExW(wide(path).as_ptr(), &mut available, &mut total, &mut free) };
+    if ok == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok((total, available, free))
+}
+
+/// The metadata a listing carried for each name, handed to `scan_listing` as
+/// if every entry had been stat'ed
+pub(crate) struct Found {
+    entries: HashMap<OsString, EntryMetadata>,
+    /// Open files and directories for the file index and link count the
+    /// listing lacks, which hard-link and cycle detection need
+    identify_files: bool,
+    identify_dirs: bool,
+}
+
+impl StatBatch for Found {
+    fn stat_all(&mut self, parent: &Path, entries: &[DirEntry]) -> Vec<io::Result<EntryMetadata>> {
+        entries
+            .iter()
+            .map(|entry| match self.entries.remove(&entry.name) {
+                // Not a link, so following it changes nothing
+                Some(EntryMetadata::RegularFile { .. }) if self.identify_files => {
+                    stat(&entry.path, true)
+                }
+                Some(EntryMetadata::Directory { .. }) if self.identify_dirs => {
+                    stat(&entry.path, true)
+                }
+                Some(metadata) => Ok(metadata),
+                None => classify_entry(parent, &entry.name, false),
+            })
+            .collect()
+    }
+}
+
+/// A `FindFirstFileExW` search, closed when dropped
+struct Search(HANDLE);
+
+impl Drop for Search {
+    fn drop(&mut self) {
+        // SAFETY: the handle came from a successful FindFirstFileExW and is closed once
+        unsafe { FindClose(self.0) };
+    }
+}
+
+/// List the directory at `path`, without `.` and `..`, with the metadata of
+/// each entry on the volume with serial number `device`. An error ends the
+/// listing, as it ends `fs::read_dir`'s.
+pub(crate) fn list_dir(
+    path: &Path,
+    device: u64,
+    identify_files: bool,
+    identify_dirs: bool,
+) -> io::Result<(Vec<io::Result<OsString>>, Found)> {
+    // SAFETY: all-zero is a valid `WIN32_FIND_DATAW`, a plain struct of integers
+    let mut data: WIN32_FIND_DATAW = unsafe { std::mem::zeroed() };
+    // SAFETY: the pattern is NUL-terminated and `data` is the record the basic info level fills
+    let handle = unsafe {
+        FindFirstFileExW(
+            wide(&path.join("*")).as_ptr(),
+            FindExInfoBasic,
+            &mut data as *mut WIN32_FIND_DATAW as *mut _,
+            FindExSearchNameMatch,
+            std::ptr::null(),
+            FIND_FIRST_EX_LARGE_FETCH,
+        )
+    };
+    if handle == INVALID_HANDLE_VALUE {
+        return Err(io::Error::last_os_error());
+    }
+    let search = Search(handle);
+
+    let mut names = Vec::new();
+    let mut found = Found {
+        entries: HashMap::new(),
+        identify_files,
+        identify_dirs,
+    };
+    loop {
+        let len = data
+            .cFileName
+            .iter()
+            .position(|&c| c == 0)
+            .unwrap_or(data.cFileName.len());
+        let name = OsString::from_wide(&data.cFileName[..len]);
+        if name != "." && name != ".." {
+            let size = (u64::from(data.nFileSizeHigh) << 32) | u64::from(data.nFileSizeLow);
+            let entry = metadata(
+                data.dwFileAttributes,
+                Some(data.dwReserved0),
+                size,
+                data.ftLastWriteTime,
+                data.ftLastAccessTime,
+                device,
+                0,
+                1,
+            );
+            found.entries.insert(name.clone(), entry);
+            names.push(Ok(name));
+        }
+        // SAFETY: the search is open and `data` is the record it fills
+        if unsafe { FindNextFileW(search.0, &mut data) } == 0 {
+            let e = io::Error::last_os_error();
+            if e.raw_os_error() != Some(ERROR_NO_MORE_FILES as i32) {
+                names.push(Err(e));
+            }
+            break;
+        }
+    }
+    Ok((names, found))
+}
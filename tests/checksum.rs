@@ -0,0 +1,58 @@
+//! `--checksum` digests of the reported files
+
+mod common;
+
+use bfinder::checksum::{ChecksumAlgorithm, add_checksums, checksum_file};
+use common::{Tree, entry};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[test]
+fn known_digests() {
+    let tree = Tree::new();
+    let path = tree.root().join("abc");
+    std::fs::write(&path, "abc").unwrap();
+    let digest = |algorithm| hex(&checksum_file(&path, algorithm).unwrap());
+    assert_eq!(
+        digest(ChecksumAlgorithm::Sha256),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    #[cfg(feature = "sha1")]
+    assert_eq!(
+        digest(ChecksumAlgorithm::Sha1),
+        "a9993e364706816aba3e25717850c26c9cd0d89d"
+    );
+    #[cfg(feature = "md5")]
+    assert_eq!(
+        digest(ChecksumAlgorithm::Md5),
+        "900150983cd24fb0d6963f7d28e17f72"
+    );
+    #[cfg(feature = "blake3")]
+    assert_eq!(
+        digest(ChecksumAlgorithm::Blake3),
+        "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+    );
+}
+
+#[test]
+fn unreadable_files_keep_no_checksum() {
+    let tree = Tree::new();
+    let path = tree.file("present", 10);
+    let missing = tree.root().join("missing");
+    let mut entries = [
+        entry(path.to_str().unwrap(), 10),
+        entry(missing.to_str().unwrap(), 5),
+    ];
+    let done = AtomicUsize::new(0);
+    let errors = add_checksums(&mut entries, ChecksumAlgorithm::Sha256, &|| {
+        done.fetch_add(1, Ordering::Relaxed);
+    });
+    assert_eq!(done.into_inner(), 2);
+    assert_eq!(entries[0].checksum.as_ref().map(Vec::len), Some(32));
+    assert_eq!(entries[1].checksum, None);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].path(), missing);
+}
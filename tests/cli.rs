@@ -0,0 +1,326 @@
+//! The `bfinder` binary's exit statuses and generated documentation
+
+mod common;
+
+use assert_cmd::Command;
+use common::sample_tree;
+
+/// `bfinder` ignoring any configuration file of the user running the tests
+fn bfinder() -> Command {
+    let mut command = Command::cargo_bin("bfinder").unwrap();
+    command.arg("--no-config");
+    command
+}
+
+#[test]
+fn success_is_zero() {
+    let tree = sample_tree();
+    bfinder()
+        .args(["-n", "3"])
+        .arg(tree.root())
+        .assert()
+        .code(0);
+}
+
+#[test]
+fn invalid_usage_is_two() {
+    bfinder().arg("--no-such-option").assert().code(2);
+}
+
+#[test]
+fn fewer_files_than_asked_for_is_two() {
+    let tree = sample_tree();
+    bfinder()
+        .args(["-n", "10", "--fail-if-fewer"])
+        .arg(tree.root())
+        .assert()
+        .code(2);
+    bfinder()
+        .args(["-n", "6", "--fail-if-fewer"])
+        .arg(tree.root())
+        .assert()
+        .code(0);
+}
+
+#[test]
+fn nothing_above_the_threshold_is_three() {
+    let tree = sample_tree();
+    bfinder()
+        .args(["--fail-threshold-size", "1M"])
+        .arg(tree.root())
+        .assert()
+        .code(3);
+    bfinder()
+        .args(["--fail-threshold-size", "4K"])
+        .arg(tree.root())
+        .assert()
+        .code(0);
+}
+
+#[test]
+fn top_extensions_ranks_whole_tree_totals() {
+    let tree = sample_tree();
+    let output = bfinder()
+        .args(["--top-extensions", "2"])
+        .arg(tree.root())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let report = String::from_utf8(output.stdout).unwrap();
+    let rows: Vec<_> = report
+        .lines()
+        .filter(|line| line.trim_start().starts_with(|c: char| c.is_ascii_digit()))
+        .collect();
+    assert_eq!(rows.len(), 2, "{}", report);
+    assert!(rows[0].ends_with("           3  .txt"), "{}", report);
+    assert!(rows[1].ends_with("           1  .bin"), "{}", report);
+    // A conflicting --top is an error rather than ignored
+    bfinder()
+        .args(["--top-extensions", "2", "-n", "5"])
+        .arg(tree.root())
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn inaccessible_directories_are_one_when_reported() {
+    let tree = sample_tree();
+    let missing = tree.root().join("gone");
+    bfinder().arg(tree.root()).arg(&missing).assert().code(0);
+    bfinder()
+        .arg("--report-inaccessible")
+        .arg(tree.root())
+        .arg(&missing)
+        .assert()
+        .code(1);
+    bfinder()
+        .arg("--report-inaccessible")
+        .arg(tree.root())
+        .assert()
+        .code(0);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn affinity_pins_to_existing_cores() {
+    let tree = sample_tree();
+    bfinder()
+        .args(["--affinity", "0", "-n", "3"])
+        .arg(tree.root())
+        .assert()
+        .code(0);
+    bfinder()
+        .args(["--affinity", "0,100000"])
+        .arg(tree.root())
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn limit_memory_warns_when_the_top_n_needs_more() {
+    let tree = sample_tree();
+    let stderr = |limit: &str| {
+        let output = bfinder()
+            .args(["-j", "2", "-n", "100", "--limit-memory", limit])
+            .arg(tree.root())
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stderr).unwrap()
+    };
+    let warning = stderr("4K");
+    assert!(
+        warning.contains("--limit-memory 4.00 KB holds"),
+        "{}",
+        warning
+    );
+    assert!(warning.contains("files per thread for 2 threads; each reserves room for that many but keeps up to --top 100"), "{}", warning);
+    assert_eq!(stderr("1G"), "");
+}
+
+#[test]
+fn man_page_lists_the_exit_statuses() {
+    let output = bfinder().arg("--generate-man").output().unwrap();
+    assert!(output.status.success());
+    let page = String::from_utf8(output.stdout).unwrap();
+    let section = &page[page
+        .find(".SH \"EXIT STATUS\"")
+        .expect("no EXIT STATUS section")..];
+    for status in ["0", "1", "2", "3"] {
+        assert!(
+            section.contains(&format!(".TP\n\\fB{}\\fR\n", status)),
+            "no status {}",
+            status
+        );
+    }
+    assert!(section.contains("\\-\\-fail\\-threshold\\-size"));
+}
+
+#[test]
+fn help_shows_the_examples() {
+    let output = bfinder().arg("--help").output().unwrap();
+    assert!(output.status.success());
+    let help = String::from_utf8(output.stdout).unwrap();
+    let examples = &help[help.find("\nExamples:\n").expect("no examples in --help")..];
+    for command in [
+        "bfinder -n 20 /home",
+        "bfinder --format json / | jq '.results[0]'",
+        "bfinder --exclude '**/node_modules' --min-size 1M /projects",
+        "bfinder --watch 60 /var/log",
+    ] {
+        assert!(
+            examples.lines().any(|line| line.trim() == command),
+            "missing example: {}",
+            command
+        );
+    }
+    // After the options, with the exit statuses last
+    assert!(help.find("Options:").unwrap() < help.find("\nExamples:\n").unwrap());
+    assert!(examples.contains("\nExit status:\n"));
+}
+
+#[test]
+fn examples_parse() {
+    // Each example's own options are ones bfinder accepts
+    let output = bfinder().arg("--help").output().unwrap();
+    let help = String::from_utf8(output.stdout).unwrap();
+    let examples =
+        &help[help.find("\nExamples:\n").unwrap()..help.find("\nExit status:\n").unwrap()];
+    let commands: Vec<_> = examples
+        .lines()
+        .filter_map(|line| {
+            line.strip_prefix("  ")?
+                .split(" | ")
+                .find_map(|part| part.strip_prefix("bfinder "))
+        })
+        .collect();
+    assert!(commands.len() >= 7, "{:?}", commands);
+    for command in commands {
+        // Parse only: the paths need not exist, and --help stops before scanning
+        let args = command
+            .split(' ')
+            .take_while(|arg| *arg != ">")
+            .map(|arg| arg.trim_matches('\''));
+        bfinder().args(args).arg("--help").assert().code(0);
+    }
+}
+
+#[test]
+fn git_ignore_reads_the_files_above_a_relative_root() {
+    let tree = sample_tree();
+    tree.dir(".git");
+    std::fs::write(tree.root().join(".gitignore"), "*.log\n").unwrap();
+    let output = bfinder()
+        .args(["--git-ignore", "-n", "10", "."])
+        .current_dir(tree.root().join("d"))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .matches(".log")
+            .count(),
+        0
+    );
+
+    let output = bfinder()
+        .args(["-n", "10", "."])
+        .current_dir(tree.root().join("d"))
+        .output()
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .matches(".log")
+            .count(),
+        2
+    );
+}
+
+#[test]
+fn no_ignore_overrides_the_config_file() {
+    let tree = sample_tree();
+    std::fs::write(tree.root().join(".gitignore"), "*.txt\n").unwrap();
+    let config = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(config.path(), "git-ignore = true\nmin-size = \"100\"\n").unwrap();
+    let txt_files = |args: &[&str]| {
+        let mut command = Command::cargo_bin("bfinder").unwrap();
+        let output = command
+            .arg("--config")
+            .arg(config.path())
+            .args(args)
+            .arg(tree.root())
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .matches(".txt")
+            .count()
+    };
+    assert_eq!(txt_files(&["-n", "10"]), 0);
+    assert_eq!(txt_files(&["-n", "10", "--no-ignore"]), 3);
+}
+
+#[test]
+fn stdin_paths_ranks_the_files_read() {
+    let tree = sample_tree();
+    let output = bfinder()
+        .args(["--stdin-paths", "--null-input", "-n", "10"])
+        .current_dir(tree.root())
+        .write_stdin("big.bin\0d/five.log\0")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let out = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        out.contains("big.bin") && out.contains("five.log"),
+        "{}",
+        out
+    );
+    assert!(!out.contains("one.txt"), "{}", out);
+
+    bfinder()
+        .args(["--stdin-paths"])
+        .write_stdin("")
+        .assert()
+        .code(2);
+    bfinder()
+        .args(["--stdin-paths", "."])
+        .write_stdin("big.bin\n")
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn conflicting_options_are_usage_errors() {
+    let tree = sample_tree();
+    for (args, message) in [
+        (
+            ["--format", "csv", "--report-empty-dirs"],
+            "CSV output holds a single table; --report-empty-dirs",
+        ),
+        (
+            ["--format", "csv", "--zero-sized-count"],
+            "CSV output holds a single table; --zero-sized-count",
+        ),
+        (
+            ["--format", "json", "--tree"],
+            "--tree cannot be combined with --format json or csv",
+        ),
+        (
+            ["--format", "csv", "--summarize"],
+            "--summarize prints totals",
+        ),
+    ] {
+        let output = bfinder().args(args).arg(tree.root()).output().unwrap();
+        assert_eq!(output.status.code(), Some(2), "{:?}", args);
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains(message), "{:?}: {}", args, stderr);
+    }
+}
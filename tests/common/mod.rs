@@ -0,0 +1,97 @@
+//! Fixture trees shared by the integration tests
+
+#![allow(dead_code)]
+
+use bfinder::FileEntry;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+/// A temporary directory removed when dropped
+pub struct Tree {
+    dir: TempDir,
+}
+
+impl Tree {
+    pub fn new() -> Self {
+        Tree {
+            dir: tempfile::tempdir().unwrap(),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// Create the file at `path`, below the root, holding `size` bytes
+    pub fn file(&self, path: &str, size: usize) -> PathBuf {
+        let path = self.root().join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, vec![b'x'; size]).unwrap();
+        path
+    }
+
+    /// Create the directory at `path`, below the root
+    pub fn dir(&self, path: &str) -> PathBuf {
+        let path = self.root().join(path);
+        fs::create_dir_all(&path).unwrap();
+        path
+    }
+}
+
+/// A tree of files of distinct sizes across a few levels:
+///
+/// ```text
+/// big.bin          5000
+/// a/one.txt        1000
+/// a/b/two.txt      4000
+/// a/b/c/three.txt  3000
+/// d/four.log       2000
+/// d/five.log        500
+/// ```
+pub fn sample_tree() -> Tree {
+    let tree = Tree::new();
+    tree.file("big.bin", 5000);
+    tree.file("a/one.txt", 1000);
+    tree.file("a/b/two.txt", 4000);
+    tree.file("a/b/c/three.txt", 3000);
+    tree.file("d/four.log", 2000);
+    tree.file("d/five.log", 500);
+    tree
+}
+
+/// The paths of `entries` relative to `root`, in order
+pub fn relative(entries: &[FileEntry], root: &Path) -> Vec<String> {
+    entries
+        .iter()
+        .map(|entry| {
+            entry
+                .path
+                .strip_prefix(root)
+                .unwrap()
+                .to_string_lossy()
+                .into_owned()
+        })
+        .collect()
+}
+
+/// An entry of `size` bytes at `path`, for tests that build results by hand
+pub fn entry(path: &str, size: u64) -> FileEntry {
+    FileEntry {
+        size,
+        path: PathBuf::from(path),
+        modified: None,
+        accessed: None,
+        hash: None,
+        sparseness: 0.0,
+        checksum: None,
+        is_reflinked: false,
+        physical_size: 0,
+        allocated_size: 0,
+        xattr_size: 0,
+        nlink: 0,
+        mode: 0,
+        is_dir: false,
+        verified: None,
+    }
+}
@@ -0,0 +1,118 @@
+//! `diff_scans` between two lists of files, and `compare_trees` between the
+//! files of two trees
+
+mod common;
+
+use bfinder::diff::{SizeChange, TreeDifference, compare_trees, diff_scans};
+use bfinder::output::render_compare_text;
+use bfinder::{FileEntry, ScanConfig, parallel_scan};
+use common::{Tree, entry};
+use std::path::{Path, PathBuf};
+
+fn change(path: &str, old_size: u64, new_size: u64) -> SizeChange {
+    SizeChange {
+        path: PathBuf::from(path),
+        old_size,
+        new_size,
+    }
+}
+
+#[test]
+fn classifies_added_grown_and_shrunk() {
+    let old = [
+        entry("/same", 10),
+        entry("/grew", 10),
+        entry("/grew-more", 5),
+        entry("/shrank", 50),
+        entry("/gone", 1),
+    ];
+    let new = [
+        entry("/same", 10),
+        entry("/grew", 20),
+        entry("/grew-more", 100),
+        entry("/shrank", 40),
+        entry("/new", 7),
+    ];
+    let diff = diff_scans(&old, &new);
+    assert_eq!(diff.added, [entry("/new", 7)]);
+    assert_eq!(
+        diff.grown,
+        [change("/grew-more", 5, 100), change("/grew", 10, 20)]
+    );
+    assert_eq!(diff.shrunk, [change("/shrank", 50, 40)]);
+    assert_eq!(diff.shrunk[0].delta(), -10);
+}
+
+#[test]
+fn no_changes() {
+    let files = [entry("/a", 1), entry("/b", 2)];
+    assert_eq!(diff_scans(&files, &files), Default::default());
+}
+
+fn scan(root: &Path) -> Vec<FileEntry> {
+    parallel_scan(&ScanConfig::builder(root).top(100).build().unwrap())
+        .0
+        .unwrap()
+}
+
+#[test]
+fn compares_two_trees() {
+    let a = Tree::new();
+    a.file("same.txt", 100);
+    a.file("lib/grew.so", 1000);
+    a.file("lib/shrank.so", 5000);
+    a.file("old/removed.bin", 3000);
+    let b = Tree::new();
+    b.file("same.txt", 100);
+    b.file("lib/grew.so", 1200);
+    b.file("lib/shrank.so", 1000);
+    b.file("new/added.bin", 3000);
+
+    let differences = compare_trees(&scan(a.root()), a.root(), &scan(b.root()), b.root());
+    assert_eq!(
+        differences,
+        [
+            TreeDifference::Changed(change("lib/shrank.so", 5000, 1000)),
+            TreeDifference::OnlyInB {
+                path: "new/added.bin".into(),
+                size: 3000
+            },
+            TreeDifference::OnlyInA {
+                path: "old/removed.bin".into(),
+                size: 3000
+            },
+            TreeDifference::Changed(change("lib/grew.so", 1000, 1200)),
+        ]
+    );
+    let deltas: Vec<_> = differences.iter().map(TreeDifference::size_delta).collect();
+    assert_eq!(deltas, [4000, 3000, 3000, 200]);
+
+    let mut out = Vec::new();
+    render_compare_text(&differences, &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    let marks: Vec<_> = out
+        .lines()
+        .filter_map(|line| line.get(..2))
+        .take(4)
+        .collect();
+    assert_eq!(marks, ["~ ", "> ", "< ", "~ "]);
+    assert!(
+        out.contains("\n<       2.93 KB  old/removed.bin\n"),
+        "{}",
+        out
+    );
+    assert!(
+        out.ends_with("\n1 only in A, 1 only in B, 2 changed\n"),
+        "{}",
+        out
+    );
+}
+
+#[test]
+fn identical_trees_do_not_differ() {
+    let a = Tree::new();
+    a.file("x/y", 10);
+    let b = Tree::new();
+    b.file("x/y", 10);
+    assert!(compare_trees(&scan(a.root()), a.root(), &scan(b.root()), b.root()).is_empty());
+}
@@ -0,0 +1,164 @@
+//! `format_size` and `parse_size_arg`
+
+use bfinder::{
+    PermFilter, PermMatch, SizeStyle, format_size, format_size_styled, parse_age,
+    parse_duration_arg, parse_permissions, parse_size_arg, parse_time_arg,
+};
+use std::time::{Duration, SystemTime};
+
+#[test]
+fn formats_sizes() {
+    assert_eq!(format_size(0), "0 bytes");
+    assert_eq!(format_size(1023), "1023 bytes");
+    assert_eq!(format_size(1024), "1.00 KB");
+    assert_eq!(format_size(1536), "1.50 KB");
+    assert_eq!(format_size(5 * 1024 * 1024), "5.00 MB");
+    assert_eq!(format_size(3 * 1024 * 1024 * 1024 / 2), "1.50 GB");
+    assert_eq!(format_size(2048 * 1024 * 1024 * 1024), "2048.00 GB");
+}
+
+#[test]
+fn formats_sizes_like_du() {
+    assert_eq!(format_size_styled(512, SizeStyle::Du), "512");
+    assert_eq!(format_size_styled(1024, SizeStyle::Du), "1.0K");
+    assert_eq!(format_size_styled(1025, SizeStyle::Du), "1.1K");
+    assert_eq!(format_size_styled(12 * 1024, SizeStyle::Du), "12K");
+    assert_eq!(format_size_styled(1024 * 1024 - 1, SizeStyle::Du), "1.0M");
+}
+
+#[test]
+fn parses_sizes() {
+    assert_eq!(parse_size_arg("4096"), Ok(4096));
+    assert_eq!(parse_size_arg("10B"), Ok(10));
+    assert_eq!(parse_size_arg("1K"), Ok(1024));
+    assert_eq!(parse_size_arg("2kb"), Ok(2048));
+    assert_eq!(parse_size_arg("500M"), Ok(500 << 20));
+    assert_eq!(parse_size_arg("2GiB"), Ok(2 << 30));
+    assert_eq!(parse_size_arg("1t"), Ok(1 << 40));
+    assert_eq!(parse_size_arg(" 3M "), Ok(3 << 20));
+}
+
+#[test]
+fn rejects_invalid_sizes() {
+    assert!(parse_size_arg("").is_err());
+    assert!(parse_size_arg("M").is_err());
+    assert!(parse_size_arg("1.5M").is_err());
+    assert!(parse_size_arg("10X").is_err());
+    assert!(parse_size_arg("-1").is_err());
+    assert!(parse_size_arg("99999999999999999999").is_err());
+    assert!(parse_size_arg("17179869184G").is_err());
+}
+
+#[test]
+fn formatted_sizes_parse_back_in_whole_units() {
+    for (text, size) in [
+        ("1.00 KB", 1 << 10),
+        ("1.00 MB", 1 << 20),
+        ("1.00 GB", 1 << 30),
+    ] {
+        assert_eq!(format_size(size), text);
+        let unit = &text[5..6];
+        assert_eq!(parse_size_arg(&format!("1{}", unit)), Ok(size));
+    }
+}
+
+#[test]
+fn parses_durations() {
+    let hours = |n: u64| Duration::from_secs(n * 3600);
+    assert_eq!(parse_duration_arg("90m"), Ok(Duration::from_secs(90 * 60)));
+    assert_eq!(parse_duration_arg("2 hours"), Ok(hours(2)));
+    assert_eq!(parse_duration_arg("7 days"), Ok(hours(7 * 24)));
+    assert_eq!(parse_duration_arg("1 month"), Ok(hours(30 * 24)));
+    assert_eq!(parse_duration_arg("1y"), Ok(hours(365 * 24)));
+    assert!(parse_duration_arg("7").is_err());
+    assert!(parse_duration_arg("days").is_err());
+    assert!(parse_duration_arg("3 fortnights").is_err());
+    assert!(parse_duration_arg("99999999999999999 years").is_err());
+}
+
+#[test]
+fn parses_times() {
+    let utc = SystemTime::UNIX_EPOCH + Duration::from_secs(1_704_110_400);
+    assert_eq!(parse_time_arg("2024-01-01T12:00:00Z"), Ok(utc));
+    assert_eq!(parse_time_arg("2024-01-01T13:00:00+01:00"), Ok(utc));
+    assert!(parse_time_arg("2024-01-01").is_ok());
+    let ago = SystemTime::now()
+        .duration_since(parse_time_arg("2 hours").unwrap())
+        .unwrap();
+    assert!(
+        ago > Duration::from_secs(7190) && ago < Duration::from_secs(7300),
+        "{:?}",
+        ago
+    );
+    assert!(parse_time_arg("yesterday").is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn parses_users_and_groups() {
+    use bfinder::{parse_group_arg, parse_user_arg};
+
+    assert_eq!(parse_user_arg("1234"), Ok(1234));
+    assert_eq!(parse_user_arg("root"), Ok(0));
+    assert_eq!(parse_group_arg("1234"), Ok(1234));
+    assert_eq!(parse_group_arg("root"), Ok(0));
+    assert_eq!(
+        parse_user_arg("no-such-user-here"),
+        Err("unknown user 'no-such-user-here'".to_string())
+    );
+    assert_eq!(
+        parse_group_arg("no-such-group-here"),
+        Err("unknown group 'no-such-group-here'".to_string())
+    );
+}
+
+#[test]
+fn parses_permissions() {
+    let perm = |mode, kind| Ok(PermFilter { mode, kind });
+    assert_eq!(parse_permissions("0755"), perm(0o755, PermMatch::Exact));
+    assert_eq!(parse_permissions("/111"), perm(0o111, PermMatch::Any));
+    assert_eq!(parse_permissions("-g+w"), perm(0o020, PermMatch::All));
+    assert_eq!(
+        parse_permissions("u=rw,go=r"),
+        perm(0o644, PermMatch::Exact)
+    );
+    assert_eq!(
+        parse_permissions("a+rwx,o-w"),
+        perm(0o775, PermMatch::Exact)
+    );
+    assert_eq!(parse_permissions("u+s"), perm(0o4000, PermMatch::Exact));
+    for bad in ["", "/", "0888", "17777", "u", "z+r", "u+q"] {
+        assert!(parse_permissions(bad).is_err(), "{}", bad);
+    }
+}
+
+#[test]
+fn permission_matching() {
+    let file = 0o100_644;
+    assert!(parse_permissions("644").unwrap().matches(file));
+    assert!(!parse_permissions("600").unwrap().matches(file));
+    assert!(parse_permissions("/o+w,u+w").unwrap().matches(file));
+    assert!(!parse_permissions("/111").unwrap().matches(file));
+    assert!(parse_permissions("/0").unwrap().matches(file));
+    assert!(parse_permissions("-u+rw").unwrap().matches(file));
+    assert!(!parse_permissions("-u+rwx").unwrap().matches(file));
+}
+
+#[test]
+fn parses_ages() {
+    let days = |n: u64| Ok(Duration::from_secs(n * 24 * 60 * 60));
+    assert_eq!(parse_age("365d"), days(365));
+    assert_eq!(parse_age("2w"), days(14));
+    assert_eq!(parse_age("3M"), days(90));
+    assert_eq!(parse_age(" 1y "), days(365));
+    for bad in [
+        "",
+        "d",
+        "12",
+        "5h",
+        "99999999999999999999d",
+        "99999999999999y",
+    ] {
+        assert!(parse_age(bad).is_err(), "{}", bad);
+    }
+}
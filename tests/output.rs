@@ -0,0 +1,723 @@
+//! The renderers of `bfinder::output`, writing into buffers
+
+mod common;
+
+use bfinder::output::{
+    OutputFormat, OutputOptions, SizeColors, TableFormatter, format_output, path_to_string,
+    render_csv, render_json_lines, render_removed, render_text, write_scan_errors,
+};
+use bfinder::{
+    DirTop, FileEntry, QuotaInfo, ScanError, ScanStats, SortOrder, TopNTracker, Verification,
+};
+use common::entry;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+fn csv(results: &[FileEntry], stats: Option<&ScanStats>, delimiter: char) -> String {
+    let mut out = Vec::new();
+    render_csv(results, stats, delimiter, &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn csv_has_a_header_and_a_row_per_file() {
+    let results = [entry("/a/big", 2048), entry("/a/small", 10)];
+    assert_eq!(
+        csv(&results, None, ','),
+        "rank,size_bytes,size_human,modified,path\n1,2048,2.00 KB,,/a/big\n2,10,10 bytes,,/a/small\n"
+    );
+}
+
+#[test]
+fn csv_quotes_paths_holding_the_delimiter() {
+    let out = csv(&[entry("/a,b", 1)], None, ',');
+    assert_eq!(out.lines().nth(1), Some("1,1,1 bytes,,\"/a,b\""));
+}
+
+#[test]
+fn csv_doubles_embedded_quotes() {
+    let out = csv(&[entry("/say \"hi\"", 1)], None, ',');
+    assert_eq!(out.lines().nth(1), Some("1,1,1 bytes,,\"/say \"\"hi\"\"\""));
+}
+
+#[test]
+fn csv_quotes_line_breaks() {
+    let out = csv(&[entry("/two\nlines", 1), entry("/cr\r", 1)], None, ',');
+    assert!(out.contains("1,1,1 bytes,,\"/two\nlines\"\n"));
+    assert!(out.contains("2,1,1 bytes,,\"/cr\r\"\n"));
+}
+
+#[test]
+fn csv_with_tabs() {
+    let out = csv(&[entry("/a,b", 1), entry("/a\tb", 2)], None, '\t');
+    assert_eq!(
+        out,
+        "rank\tsize_bytes\tsize_human\tmodified\tpath\n1\t1\t1 bytes\t\t/a,b\n2\t2\t2 bytes\t\t\"/a\tb\"\n"
+    );
+}
+
+#[test]
+fn csv_stats_are_a_trailing_comment() {
+    let mut stats = ScanStats::default();
+    stats.files_scanned = 12;
+    stats.dirs_scanned = 3;
+    stats.errors = 1;
+    let out = csv(&[entry("/a", 1)], Some(&stats), ',');
+    let last = out.lines().last().unwrap();
+    assert!(
+        last.starts_with("# files_scanned=12 dirs_scanned=3 "),
+        "{}",
+        last
+    );
+    assert!(last.split(' ').any(|field| field == "errors=1"), "{}", last);
+    assert_eq!(out.lines().filter(|line| line.starts_with('#')).count(), 1);
+    assert!(!csv(&[entry("/a", 1)], None, ',').contains('#'));
+}
+
+fn options() -> OutputOptions {
+    OutputOptions {
+        format: OutputFormat::Text,
+        csv_delimiter: ',',
+        csv_stats: false,
+        sort: SortOrder::Size,
+        show_time: false,
+        smallest: false,
+        disk_usage: false,
+        group_by_extension: None,
+        top_per_dir: false,
+        top_per_user: false,
+        resolve_names: true,
+        largest_dirs: false,
+        text_stats: true,
+        null_terminated: false,
+        inode_usage: false,
+        inode_usage_only: false,
+        duplicate_sizes: false,
+        hash_duplicates: false,
+        tree: false,
+        tree_depth: None,
+        terminal_width: None,
+        colors: None,
+        show_sparseness: false,
+        new_paths: None,
+        reflinks: false,
+        empty_dirs: false,
+        removed: false,
+        quotas: Vec::new(),
+        zero_sized: false,
+        top_dirs: false,
+        age_labels: Vec::new(),
+        scan_errors: false,
+        inaccessible_dirs: false,
+        zero_sized_count: false,
+        du_compat: false,
+        show_links: false,
+        show_mode: false,
+        show_allocated: false,
+        sampled: false,
+        summarize: false,
+    }
+}
+
+fn text(results: &[FileEntry], opts: &OutputOptions) -> String {
+    let mut out = Vec::new();
+    render_text(results, &ScanStats::default(), opts, &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn text_groups_by_extension() {
+    let results = [entry("/a.log", 3), entry("/b.log", 2), entry("/c", 1)];
+    let opts = OutputOptions {
+        sort: SortOrder::Ext,
+        ..options()
+    };
+    let out = text(&results, &opts);
+    let headers: Vec<_> = out
+        .lines()
+        .filter(|line| !line.starts_with(' ') && !line.is_empty())
+        .collect();
+    assert_eq!(
+        headers[..4],
+        [
+            "Top 3 largest files:",
+            ".log",
+            "(no extension)",
+            "Total of top-3 files: 6 bytes"
+        ]
+    );
+}
+
+#[test]
+fn text_shows_times_when_asked() {
+    let mut dated = entry("/dated", 2);
+    dated.modified = Some(SystemTime::UNIX_EPOCH + Duration::from_secs(86400 * (365 + 181)));
+    let results = [dated, entry("/undated", 1)];
+    assert!(!text(&results, &options()).contains("  -  "));
+    let opts = OutputOptions {
+        show_time: true,
+        ..options()
+    };
+    let out = text(&results, &opts);
+    assert!(out.contains("  1971-07-"), "{}", out);
+    assert!(out.contains("  -                 /undated\n"), "{}", out);
+}
+
+#[test]
+fn text_colors_sizes_past_the_thresholds() {
+    let results = [entry("/big", 2000), entry("/mid", 200), entry("/small", 20)];
+    assert!(!text(&results, &options()).contains('\x1b'));
+    let opts = OutputOptions {
+        colors: Some(SizeColors {
+            warn: 100,
+            error: 1000,
+        }),
+        ..options()
+    };
+    let out = text(&results, &opts);
+    let row = |path: &str| {
+        out.lines()
+            .find(|line| line.ends_with(path))
+            .unwrap()
+            .to_string()
+    };
+    assert!(row("/big\x1b[0m").contains("\x1b[31m/big"), "{}", out);
+    assert!(row("/mid\x1b[0m").contains("\x1b[33m/mid"), "{}", out);
+    assert!(row("/small").contains("\x1b[2m"), "{}", out);
+    assert!(!row("/small").contains("\x1b[3"), "{}", out);
+}
+
+#[test]
+fn text_shows_new_files_in_green() {
+    let results = [entry("/new", 2), entry("/old", 1)];
+    let opts = OutputOptions {
+        colors: Some(SizeColors {
+            warn: 100,
+            error: 1000,
+        }),
+        new_paths: Some(["/new".into()].into()),
+        ..options()
+    };
+    let out = text(&results, &opts);
+    assert!(out.contains("\x1b[32m/new"), "{}", out);
+    assert!(!out.contains("\x1b[32m/old"), "{}", out);
+}
+
+#[test]
+fn removed_files_follow_the_report() {
+    let mut out = Vec::new();
+    render_removed(&[], false, &mut out).unwrap();
+    assert!(out.is_empty());
+    render_removed(&[entry("/gone", 2048)], false, &mut out).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "\nNo longer in the top results:\n    -      2.00 KB  /gone\n"
+    );
+}
+
+#[test]
+fn text_statistics_can_be_left_out() {
+    let results = [entry("/a", 1)];
+    assert!(text(&results, &options()).contains("\nStatistics:\n"));
+    let opts = OutputOptions {
+        text_stats: false,
+        ..options()
+    };
+    let out = text(&results, &opts);
+    assert!(!out.contains("Statistics:"), "{}", out);
+    assert!(
+        out.ends_with("/a\n\nTotal of top-1 files: 1 bytes\n"),
+        "{}",
+        out
+    );
+}
+
+#[test]
+fn null_terminated_paths_only() {
+    let results = [entry("/a\nb", 2), entry("/c", 1)];
+    let opts = OutputOptions {
+        null_terminated: true,
+        ..options()
+    };
+    let mut out = Vec::new();
+    format_output(Some(&results), &ScanStats::default(), &opts, &mut out).unwrap();
+    assert_eq!(out, b"/a\nb\0/c\0");
+}
+
+#[test]
+fn text_tree() {
+    let results = [
+        entry("/r/a/x", 3072),
+        entry("/r/b", 1024),
+        entry("/r/a/c/y", 2048),
+    ];
+    let opts = OutputOptions {
+        tree: true,
+        text_stats: false,
+        ..options()
+    };
+    let mut out = Vec::new();
+    format_output(Some(&results), &ScanStats::default(), &opts, &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    let expected = "\
+Top 3 largest files by directory:
+
+   6.00 KB  /r
+   1.00 KB  ├── b
+   5.00 KB  └── a/
+   3.00 KB      ├── x
+   2.00 KB      └── c/
+   2.00 KB          └── y
+";
+    assert_eq!(out, expected);
+
+    let opts = OutputOptions {
+        tree_depth: Some(1),
+        ..opts
+    };
+    let mut out = Vec::new();
+    format_output(Some(&results), &ScanStats::default(), &opts, &mut out).unwrap();
+    assert!(
+        String::from_utf8(out)
+            .unwrap()
+            .ends_with("   5.00 KB  └── a/\n")
+    );
+}
+
+fn table(width: Option<usize>) -> TableFormatter {
+    TableFormatter {
+        width,
+        show_time: false,
+        show_sparseness: false,
+        smallest: false,
+        disk_usage: false,
+        stats: false,
+        colors: None,
+        new_paths: None,
+        reflinks: false,
+        show_links: false,
+        show_mode: false,
+        show_allocated: false,
+        sampled: false,
+    }
+}
+
+/// Ten files under a long directory, of 4 bytes to 1 MB
+fn table_results() -> Vec<FileEntry> {
+    (1..=10)
+        .map(|i| {
+            entry(
+                &format!("/data/projects/archive/file{:02}.bin", i),
+                1 << (i * 2),
+            )
+        })
+        .collect()
+}
+
+/// The file rows of a rendered table, after the title, header and rule
+fn rows(rendered: &str) -> Vec<&str> {
+    rendered
+        .lines()
+        .skip(4)
+        .take_while(|line| !line.is_empty())
+        .collect()
+}
+
+#[test]
+fn table_right_aligns_rank_and_size() {
+    let rendered = table(None).render(&table_results(), &ScanStats::default());
+    let lines: Vec<_> = rendered.lines().collect();
+    assert_eq!(lines[0], "Top 10 largest files:");
+    assert_eq!(lines[2], " #       Size  Path");
+    assert_eq!(lines[3], "─".repeat(48));
+    let rows = rows(&rendered);
+    assert_eq!(rows.len(), 10);
+    assert_eq!(rows[0], " 1    4 bytes  /data/projects/archive/file01.bin");
+    assert_eq!(rows[4], " 5    1.00 KB  /data/projects/archive/file05.bin");
+    assert_eq!(rows[9], "10    1.00 MB  /data/projects/archive/file10.bin");
+    assert!(rendered.contains("\nTotal of top-10 files: 1.33 MB\n"));
+}
+
+#[test]
+fn table_shortens_paths_to_the_width() {
+    let rendered = table(Some(40)).render(&table_results(), &ScanStats::default());
+    assert_eq!(rendered.lines().nth(3), Some("─".repeat(40).as_str()));
+    for (i, row) in rows(&rendered).into_iter().enumerate() {
+        assert_eq!(row.chars().count(), 40, "{}", row);
+        assert!(
+            row.ends_with(&format!("  …jects/archive/file{:02}.bin", i + 1)),
+            "{}",
+            row
+        );
+    }
+}
+
+#[test]
+fn table_keeps_paths_that_fit() {
+    let rendered = table(Some(200)).render(&table_results(), &ScanStats::default());
+    assert!(
+        rows(&rendered)
+            .iter()
+            .all(|row| row.contains("  /data/projects/archive/"))
+    );
+}
+
+#[test]
+fn table_narrower_than_its_fixed_columns() {
+    // The path column keeps the width of its header
+    let rendered = table(Some(5)).render(&table_results(), &ScanStats::default());
+    let rows = rows(&rendered);
+    assert_eq!(rows.len(), 10);
+    assert_eq!(rows[0], " 1    4 bytes  …bin");
+    assert_eq!(rendered.lines().nth(3), Some("─".repeat(19).as_str()));
+}
+
+#[test]
+fn table_of_no_files() {
+    let rendered = table(Some(80)).render(&[], &ScanStats::default());
+    assert!(rows(&rendered).is_empty());
+    assert!(rendered.contains("Total of top-0 files: 0 bytes"));
+}
+
+#[test]
+fn text_shows_quotas_under_their_user() {
+    let mut stats = ScanStats::default();
+    for (uid, size) in [(1000, 3000), (1001, 2048)] {
+        let mut files = TopNTracker::new(1);
+        files.insert(entry(&format!("/home/{}/big", uid), size));
+        stats.per_user.insert(
+            uid,
+            DirTop {
+                total_size: size,
+                files,
+            },
+        );
+    }
+    let quota = QuotaInfo {
+        uid: 1000,
+        used: 3000,
+        soft_limit: 4096,
+        hard_limit: 0,
+        pct_used: 73.2,
+    };
+    let unlimited = QuotaInfo {
+        uid: 1001,
+        used: 2048,
+        soft_limit: 0,
+        hard_limit: 0,
+        pct_used: 0.0,
+    };
+    let opts = OutputOptions {
+        top_per_user: true,
+        resolve_names: false,
+        text_stats: false,
+        quotas: vec![("/home".into(), quota), ("/home".into(), unlimited)],
+        ..options()
+    };
+    let mut out = Vec::new();
+    format_output(Some(&[]), &stats, &opts, &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("uid 1000  (2.93 KB total)\n  quota on /home: 2.93 KB of 4.00 KB soft, no hard (73.2%)\n"), "{}", out);
+    // Users without limits get no quota line
+    assert!(!out.contains("quota on /home: 2.00 KB"), "{}", out);
+}
+
+#[test]
+fn du_compat_lines() {
+    let results = [entry("/a/big", 5 * 1024 * 1024), entry("/a/small", 10)];
+    let opts = OutputOptions {
+        du_compat: true,
+        ..options()
+    };
+    let mut out = Vec::new();
+    format_output(Some(&results), &ScanStats::default(), &opts, &mut out).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "5.0M\t/a/big\n10\t/a/small\n"
+    );
+}
+
+#[test]
+fn text_shows_extended_attributes() {
+    let mut tagged = entry("/a/tagged", 3000);
+    tagged.xattr_size = 1000;
+    let out = text(&[tagged, entry("/a/plain", 10)], &options());
+    assert!(out.contains("/a/tagged  (+1000 bytes xattr)\n"), "{}", out);
+    assert!(out.contains("/a/plain\n"), "{}", out);
+}
+
+#[test]
+fn text_shows_link_counts() {
+    let mut linked = entry("/a/linked", 3000);
+    linked.nlink = 12;
+    let opts = OutputOptions {
+        show_links: true,
+        ..options()
+    };
+    let out = text(&[linked], &opts);
+    assert!(out.contains("    #         Size  Links  Path\n"), "{}", out);
+    assert!(
+        out.contains("   1.      2.93 KB     12  /a/linked\n"),
+        "{}",
+        out
+    );
+}
+
+#[test]
+fn text_shows_permissions() {
+    let modes = [0o104755, 0o102640, 0o101777, 0o100646, 0];
+    let results: Vec<_> = modes
+        .iter()
+        .enumerate()
+        .map(|(i, &mode)| FileEntry {
+            mode,
+            ..entry(&format!("/f{}", i), 5000 - i as u64)
+        })
+        .collect();
+    let opts = OutputOptions {
+        show_mode: true,
+        ..options()
+    };
+    let out = text(&results, &opts);
+    assert!(
+        out.contains("    #         Size  Mode       Path\n"),
+        "{}",
+        out
+    );
+    let shown: Vec<_> = out
+        .lines()
+        .skip(3)
+        .take(5)
+        .map(|line| line.split_whitespace().nth(3).unwrap())
+        .collect();
+    assert_eq!(
+        shown,
+        ["rwsr-xr-x", "rw-r-S---", "rwxrwxrwt", "rw-r--rw-", "-"]
+    );
+}
+
+#[test]
+fn text_shows_allocated_blocks() {
+    let mut sparse = entry("/a/sparse", 1 << 20);
+    sparse.allocated_size = 4096;
+    let opts = OutputOptions {
+        show_allocated: true,
+        ..options()
+    };
+    let out = text(&[sparse], &opts);
+    assert!(
+        out.contains("    #         Size     Allocated  Path\n"),
+        "{}",
+        out
+    );
+    assert!(
+        out.contains("   1.      1.00 MB       4.00 KB  /a/sparse\n"),
+        "{}",
+        out
+    );
+}
+
+#[test]
+fn json_lines_end_with_the_statistics() {
+    let mut stats = ScanStats::default();
+    stats.files_scanned = 2;
+    let mut out = Vec::new();
+    render_json_lines(
+        &[entry("/a/big", 2048), entry("/a/small", 10)],
+        &stats,
+        &mut out,
+    )
+    .unwrap();
+    let out = String::from_utf8(out).unwrap();
+    let lines: Vec<_> = out.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(
+        lines[0],
+        r#"{"type":"file","size_bytes":2048,"size_human":"2.00 KB","modified":null,"sparseness":0.000,"path":"/a/big"}"#
+    );
+    assert!(lines[1].contains(r#""path":"/a/small""#), "{}", lines[1]);
+    assert!(lines[2].starts_with(r#"{"type":"stats","#), "{}", lines[2]);
+    assert!(lines[2].contains(r#""files_scanned":2"#), "{}", lines[2]);
+}
+
+#[test]
+fn text_lists_zero_sized_files_after_the_report() {
+    let mut stats = ScanStats::default();
+    stats.zero_sized = vec!["/a/empty".into(), "/b/lock".into()];
+    let opts = OutputOptions {
+        zero_sized: true,
+        text_stats: false,
+        ..options()
+    };
+    let mut out = Vec::new();
+    render_text(&[entry("/a/big", 10)], &stats, &opts, &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(
+        out.ends_with("\n2 zero-sized files:\n\n  /a/empty\n  /b/lock\n"),
+        "{}",
+        out
+    );
+
+    let opts = OutputOptions {
+        zero_sized_count: true,
+        ..opts
+    };
+    let mut out = Vec::new();
+    render_text(&[entry("/a/big", 10)], &stats, &opts, &mut out).unwrap();
+    assert!(
+        String::from_utf8(out)
+            .unwrap()
+            .ends_with("\n2 zero-sized files\n")
+    );
+}
+
+#[test]
+fn scan_errors_note_the_unlisted_ones() {
+    let mut stats = ScanStats::default();
+    stats.errors = 3;
+    stats.error_details = vec![
+        ScanError::ReadDir {
+            path: "/a/locked".into(),
+            source: std::io::Error::from(std::io::ErrorKind::PermissionDenied),
+        },
+        ScanError::StatFailed {
+            path: "/a/gone".into(),
+            source: std::io::Error::from(std::io::ErrorKind::NotFound),
+        },
+    ];
+    let mut out = Vec::new();
+    write_scan_errors(&stats, &mut out).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "/a/locked: cannot read directory: permission denied\n/a/gone: cannot stat: entity not found\n... 1 not listed\n"
+    );
+}
+
+#[test]
+fn text_lists_inaccessible_directories_after_the_report() {
+    let mut stats = ScanStats::default();
+    stats.dirs_inaccessible = 2;
+    stats.inaccessible_dirs = vec![(
+        "/a/private".into(),
+        std::io::Error::from(std::io::ErrorKind::PermissionDenied),
+    )];
+    let opts = OutputOptions {
+        inaccessible_dirs: true,
+        text_stats: false,
+        ..options()
+    };
+    let mut out = Vec::new();
+    format_output(Some(&[entry("/a/big", 3000)]), &stats, &opts, &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(
+        out.ends_with(
+            "\nInaccessible directories:\n\n  /a/private: permission denied\n  ... 1 not listed\n"
+        ),
+        "{}",
+        out
+    );
+
+    let mut out = Vec::new();
+    format_output(Some(&[]), &ScanStats::default(), &opts, &mut out).unwrap();
+    assert!(
+        String::from_utf8(out)
+            .unwrap()
+            .ends_with("\nNo inaccessible directories\n")
+    );
+}
+
+#[test]
+fn text_marks_directories() {
+    let mut dir = entry("/a/huge-dir", 40960);
+    dir.is_dir = true;
+    let out = text(&[dir, entry("/a/file", 10)], &options());
+    assert!(out.contains("  /a/huge-dir/\n"), "{}", out);
+    assert!(out.contains("  /a/file\n"), "{}", out);
+}
+
+#[test]
+fn text_marks_files_that_changed_since_the_scan() {
+    let mut changed = entry("/a/changed", 3000);
+    changed.verified = Some(Verification::Changed(2048));
+    let mut deleted = entry("/a/deleted", 2000);
+    deleted.verified = Some(Verification::Deleted);
+    let mut unchanged = entry("/a/unchanged", 1000);
+    unchanged.verified = Some(Verification::Unchanged);
+    let out = text(&[changed, deleted, unchanged], &options());
+    assert!(
+        out.contains("  /a/changed  [CHANGED, now 2.00 KB]\n"),
+        "{}",
+        out
+    );
+    assert!(out.contains("  /a/deleted  [DELETED]\n"), "{}", out);
+    assert!(out.contains("  /a/unchanged\n"), "{}", out);
+}
+
+#[test]
+fn summary_shows_the_share_of_all_matching_files() {
+    let results = [entry("/a/big", 3000), entry("/a/mid", 1000)];
+    let mut stats = ScanStats::default();
+    stats.matched_files = 5;
+    stats.matched_size = 8000;
+    let opts = OutputOptions {
+        summarize: true,
+        text_stats: false,
+        ..options()
+    };
+    let mut out = Vec::new();
+    format_output(Some(&results), &stats, &opts, &mut out).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "Total of top-2 files: 3.91 KB\nAll matching files: 7.81 KB across 5 files (top-2 hold 50.0%)\n"
+    );
+
+    let opts = OutputOptions {
+        format: OutputFormat::Json,
+        ..opts
+    };
+    let mut out = Vec::new();
+    format_output(Some(&results), &stats, &opts, &mut out).unwrap();
+    let json = String::from_utf8(out).unwrap();
+    assert!(
+        json.starts_with(
+            "{\"summary\":{\"results_files\":2,\"results_bytes\":4000,\"matched_files\":5,\"matched_bytes\":8000}"
+        ),
+        "{}",
+        json
+    );
+    assert!(!json.contains("\"results\":"));
+}
+
+#[test]
+fn utf8_paths_are_unchanged() {
+    assert_eq!(
+        path_to_string(Path::new("/tmp/a b/c.txt")),
+        "/tmp/a b/c.txt"
+    );
+    assert_eq!(
+        path_to_string(Path::new("/tmp/naïve/日本")),
+        "/tmp/naïve/日本"
+    );
+}
+
+#[test]
+fn percent_signs_are_encoded() {
+    assert_eq!(path_to_string(Path::new("/tmp/100%")), "/tmp/100%25");
+    assert_eq!(path_to_string(Path::new("./a%FF")), "./a%25FF");
+}
+
+#[cfg(unix)]
+#[test]
+fn invalid_bytes_are_encoded() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = |bytes: &[u8]| Path::new(OsStr::from_bytes(bytes)).to_path_buf();
+    assert_eq!(path_to_string(&path(b"./a\xff")), "./a%FF");
+    assert_eq!(path_to_string(&path(b"./\xc3%\xfe")), "./%C3%25%FE");
+    // A name holding `%FF` and one holding the byte 0xFF stay apart
+    assert_ne!(
+        path_to_string(&path(b"./a\xff")),
+        path_to_string(Path::new("./a%FF"))
+    );
+}
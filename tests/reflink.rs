@@ -0,0 +1,30 @@
+//! `--reflink-detect` extent maps
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use bfinder::reflink::detect_reflinks;
+use common::{Tree, entry};
+
+#[test]
+fn plain_files_own_their_extents() {
+    let tree = Tree::new();
+    let path = tree.file("plain", 64 * 1024);
+    let missing = tree.root().join("missing");
+    let mut entries = [
+        entry(path.to_str().unwrap(), 64 * 1024),
+        entry(missing.to_str().unwrap(), 10),
+    ];
+    let (unsupported, errors) = detect_reflinks(&mut entries);
+    assert!(!entries[0].is_reflinked);
+    // Without extent maps the whole size counts as unshared
+    assert!(
+        entries[0].physical_size >= 64 * 1024,
+        "{}",
+        entries[0].physical_size
+    );
+    assert!(unsupported <= 1);
+    assert_eq!(entries[1].physical_size, 10);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].path(), missing);
+}
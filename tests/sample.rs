@@ -0,0 +1,106 @@
+//! `weighted_sample` with fixed seeds
+
+mod common;
+
+use bfinder::{FileEntry, weighted_sample};
+use common::entry;
+
+/// Twenty files of 100 to 2000 bytes
+fn files() -> Vec<FileEntry> {
+    (1..=20)
+        .map(|i| entry(&format!("/f{:02}", i), i * 100))
+        .collect()
+}
+
+fn paths(sample: &[FileEntry]) -> Vec<&str> {
+    sample.iter().map(|e| e.path.to_str().unwrap()).collect()
+}
+
+#[test]
+fn same_seed_same_sample() {
+    let first = weighted_sample(files(), 5, 42);
+    assert_eq!(first.len(), 5);
+    assert_eq!(paths(&first), paths(&weighted_sample(files(), 5, 42)));
+
+    // Whatever order the scan found the files in
+    let mut reversed = files();
+    reversed.reverse();
+    assert_eq!(paths(&first), paths(&weighted_sample(reversed, 5, 42)));
+}
+
+// Pinned so that a seed keeps giving the sample it gave before. `SmallRng`
+// draws different numbers on 32-bit targets.
+#[cfg(target_pointer_width = "64")]
+#[test]
+fn fixed_seed_gives_fixed_sample() {
+    assert_eq!(
+        paths(&weighted_sample(files(), 4, 7)),
+        ["/f20", "/f13", "/f09", "/f05"]
+    );
+}
+
+#[test]
+fn samples_are_largest_first() {
+    let sample = weighted_sample(files(), 8, 3);
+    assert!(sample.windows(2).all(|pair| pair[0].size >= pair[1].size));
+}
+
+#[test]
+fn different_seeds_differ() {
+    let samples: std::collections::HashSet<Vec<String>> = (0..10)
+        .map(|seed| {
+            weighted_sample(files(), 5, seed)
+                .iter()
+                .map(|e| e.path.display().to_string())
+                .collect()
+        })
+        .collect();
+    assert!(samples.len() > 1);
+}
+
+#[test]
+fn heavier_files_are_picked_more_often() {
+    // One file of 1000 bytes among nine of 10: drawn about 92% of the time
+    let mut entries = vec![entry("/heavy", 1000)];
+    entries.extend((0..9).map(|i| entry(&format!("/light{}", i), 10)));
+    let draws = 2000;
+    let heavy = (0..draws)
+        .filter(|&seed| {
+            weighted_sample(entries.clone(), 1, seed)[0].path.to_str() == Some("/heavy")
+        })
+        .count();
+    assert!(
+        heavy > draws as usize * 85 / 100,
+        "heavy file drawn {} times in {}",
+        heavy,
+        draws
+    );
+    assert!(
+        heavy < draws as usize * 98 / 100,
+        "heavy file drawn {} times in {}",
+        heavy,
+        draws
+    );
+}
+
+#[test]
+fn asking_for_all_returns_all() {
+    for n in [20, 21, 1000] {
+        let sample = weighted_sample(files(), n, 9);
+        let mut expected: Vec<_> = files().into_iter().map(|e| e.size).collect();
+        expected.reverse();
+        assert_eq!(sample.iter().map(|e| e.size).collect::<Vec<_>>(), expected);
+    }
+}
+
+#[test]
+fn empty_files_are_never_picked() {
+    let entries = vec![entry("/empty", 0), entry("/full", 10)];
+    assert_eq!(paths(&weighted_sample(entries, 5, 1)), ["/full"]);
+}
+
+#[test]
+fn nothing_to_sample() {
+    assert!(weighted_sample(files(), 0, 1).is_empty());
+    assert!(weighted_sample(Vec::new(), 5, 1).is_empty());
+}
@@ -0,0 +1,1623 @@
+//! `parallel_scan` over fixture trees
+
+mod common;
+
+use bfinder::{
+    AgeBuckets, ExtensionFilter, HiddenFiles, MimeCategory, MimeFilter, NameFilter, RegexFilter,
+    ScanConfig, ScanError, ScanProgress, ScanStats, aggregate_by_prefix, find_empty_dirs,
+    parallel_scan, relative_to_roots,
+};
+use common::{Tree, entry, relative, sample_tree};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+fn config(root: &Path, top: usize) -> ScanConfig {
+    ScanConfig::builder(root).top(top).build().unwrap()
+}
+
+#[test]
+fn ranks_largest_first() {
+    let tree = sample_tree();
+    let (results, stats) = parallel_scan(&config(tree.root(), 10));
+    let results = results.unwrap();
+    assert_eq!(
+        relative(&results, tree.root()),
+        [
+            "big.bin",
+            "a/b/two.txt",
+            "a/b/c/three.txt",
+            "d/four.log",
+            "a/one.txt",
+            "d/five.log"
+        ]
+    );
+    assert_eq!(
+        results.iter().map(|e| e.size).collect::<Vec<_>>(),
+        [5000, 4000, 3000, 2000, 1000, 500]
+    );
+    assert_eq!(stats.files_scanned, 6);
+    assert_eq!(stats.dirs_scanned, 4);
+    assert_eq!(stats.errors, 0);
+}
+
+#[test]
+fn keeps_only_top_n() {
+    let tree = sample_tree();
+    let results = parallel_scan(&config(tree.root(), 3)).0.unwrap();
+    assert_eq!(
+        relative(&results, tree.root()),
+        ["big.bin", "a/b/two.txt", "a/b/c/three.txt"]
+    );
+}
+
+#[test]
+fn equal_sizes_rank_by_path() {
+    let tree = Tree::new();
+    tree.file("z", 100);
+    tree.file("m/y", 100);
+    tree.file("a", 100);
+    // As in `FileEntry`'s `Ord`, the larger path ranks higher
+    for _ in 0..5 {
+        assert_eq!(
+            relative(
+                &parallel_scan(&config(tree.root(), 2)).0.unwrap(),
+                tree.root()
+            ),
+            ["z", "m/y"]
+        );
+    }
+}
+
+#[test]
+fn lists_directories_larger_than_one_read() {
+    // Some 150 KB of directory entries, more than one getdents64 buffer
+    let tree = Tree::new();
+    let name = |i: usize| format!("{:0>100}", i);
+    for i in 0..1500 {
+        let size = match i {
+            0 => 3,
+            750 => 4,
+            1499 => 5,
+            _ => 1,
+        };
+        tree.file(&name(i), size);
+    }
+    let (results, stats) = parallel_scan(&config(tree.root(), 3));
+    assert_eq!(stats.files_scanned, 1500);
+    let results = results.unwrap();
+    assert_eq!(
+        relative(&results, tree.root()),
+        [name(1499), name(750), name(0)]
+    );
+}
+
+#[test]
+fn streams_every_file_that_passes_the_filters() {
+    let tree = sample_tree();
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let mut config = config(tree.root(), 1);
+    config.min_size = 1000;
+    config.stream = Some(sender);
+    let results = parallel_scan(&config).0.unwrap();
+    assert_eq!(relative(&results, tree.root()), ["big.bin"]);
+    drop(config);
+    let mut streamed: Vec<_> = receiver.into_iter().collect();
+    streamed.sort_by_key(|e| std::cmp::Reverse(e.size));
+    assert_eq!(
+        relative(&streamed, tree.root()),
+        [
+            "big.bin",
+            "a/b/two.txt",
+            "a/b/c/three.txt",
+            "d/four.log",
+            "a/one.txt"
+        ]
+    );
+}
+
+#[test]
+fn lists_every_zero_sized_file() {
+    let tree = sample_tree();
+    tree.file("d/empty.lock", 0);
+    tree.file("a/b/c/empty", 0);
+    let mut config = config(tree.root(), 1);
+    config.zero_sized = true;
+    let (_, stats) = parallel_scan(&config);
+    let paths: Vec<_> = stats
+        .zero_sized
+        .iter()
+        .map(|p| p.strip_prefix(tree.root()).unwrap())
+        .collect();
+    assert_eq!(paths, [Path::new("a/b/c/empty"), Path::new("d/empty.lock")]);
+}
+
+#[test]
+fn include_dirs_ranks_directories_by_their_own_size() {
+    let tree = sample_tree();
+    let mut config = config(tree.root(), 20);
+    config.include_dirs = true;
+    let results = parallel_scan(&config).0.unwrap();
+    let mut dirs: Vec<_> = results
+        .iter()
+        .filter(|e| e.is_dir)
+        .map(|e| {
+            (
+                e.path.strip_prefix(tree.root()).unwrap().to_path_buf(),
+                e.size,
+            )
+        })
+        .collect();
+    dirs.sort();
+    let expected: Vec<_> = ["a", "a/b", "a/b/c", "d"]
+        .into_iter()
+        .map(|dir| {
+            (
+                PathBuf::from(dir),
+                std::fs::metadata(tree.root().join(dir)).unwrap().len(),
+            )
+        })
+        .collect();
+    assert_eq!(dirs, expected);
+    assert_eq!(results.iter().filter(|e| !e.is_dir).count(), 6);
+}
+
+#[test]
+fn finds_smallest() {
+    let tree = sample_tree();
+    let config = ScanConfig::builder(tree.root())
+        .top(2)
+        .find_smallest(true)
+        .build()
+        .unwrap();
+    let results = parallel_scan(&config).0.unwrap();
+    assert_eq!(relative(&results, tree.root()), ["d/five.log", "a/one.txt"]);
+}
+
+#[test]
+fn size_bounds() {
+    let tree = sample_tree();
+    let mut config = config(tree.root(), 10);
+    config.min_size = 1000;
+    config.max_size = 4000;
+    let results = parallel_scan(&config).0.unwrap();
+    assert_eq!(
+        relative(&results, tree.root()),
+        ["a/b/two.txt", "a/b/c/three.txt", "d/four.log", "a/one.txt"]
+    );
+}
+
+#[test]
+fn missing_root_is_an_error() {
+    let tree = Tree::new();
+    let (results, stats) = parallel_scan(&config(&tree.root().join("missing"), 10));
+    let results = results.unwrap();
+    assert!(results.is_empty());
+    assert_eq!(stats.errors, 1);
+}
+
+#[test]
+fn several_roots_make_one_top_n() {
+    let first = sample_tree();
+    let second = Tree::new();
+    second.file("huge", 9000);
+    second.file("tiny", 10);
+    let config = ScanConfig::builder(first.root())
+        .root(second.root())
+        .top(3)
+        .build()
+        .unwrap();
+    let paths: Vec<_> = parallel_scan(&config)
+        .0
+        .unwrap()
+        .into_iter()
+        .map(|e| e.path)
+        .collect();
+    assert_eq!(
+        paths,
+        [
+            second.root().join("huge"),
+            first.root().join("big.bin"),
+            first.root().join("a/b/two.txt")
+        ]
+    );
+}
+
+#[test]
+fn excludes_directories_by_name() {
+    let tree = sample_tree();
+    let config = ScanConfig::builder(tree.root())
+        .top(10)
+        .exclude_pattern("b")
+        .build()
+        .unwrap();
+    let results = parallel_scan(&config).0.unwrap();
+    assert_eq!(
+        relative(&results, tree.root()),
+        ["big.bin", "d/four.log", "a/one.txt", "d/five.log"]
+    );
+}
+
+#[test]
+fn excludes_directories_by_path() {
+    let tree = sample_tree();
+    let pattern = format!("{}/d", tree.root().display());
+    let config = ScanConfig::builder(tree.root())
+        .top(10)
+        .exclude_pattern(&pattern)
+        .build()
+        .unwrap();
+    let results = parallel_scan(&config).0.unwrap();
+    assert_eq!(
+        relative(&results, tree.root()),
+        ["big.bin", "a/b/two.txt", "a/b/c/three.txt", "a/one.txt"]
+    );
+}
+
+#[test]
+fn rejects_invalid_exclude_pattern() {
+    assert!(
+        ScanConfig::builder(".")
+            .exclude_pattern("a[")
+            .build()
+            .is_err()
+    );
+}
+
+#[test]
+fn hard_links_count_once() {
+    let tree = Tree::new();
+    let original = tree.file("b/original", 3000);
+    tree.dir("a");
+    std::fs::hard_link(&original, tree.root().join("a/link")).unwrap();
+    tree.file("other", 1000);
+
+    let (results, stats) = parallel_scan(&config(tree.root(), 10));
+    let results = results.unwrap();
+    // The link with the smallest path is kept
+    assert_eq!(relative(&results, tree.root()), ["a/link", "other"]);
+    assert_eq!(stats.hardlinks_skipped, 1);
+
+    let config = ScanConfig::builder(tree.root())
+        .top(10)
+        .count_hardlinks(true)
+        .build()
+        .unwrap();
+    let (results, stats) = parallel_scan(&config);
+    let results = results.unwrap();
+    assert_eq!(
+        relative(&results, tree.root()),
+        ["b/original", "a/link", "other"]
+    );
+    assert_eq!(stats.hardlinks_skipped, 0);
+}
+
+#[test]
+fn hard_link_bounds() {
+    let tree = Tree::new();
+    let original = tree.file("original", 3000);
+    std::fs::hard_link(&original, tree.root().join("link")).unwrap();
+    std::fs::hard_link(&original, tree.root().join("other link")).unwrap();
+    let twice = tree.file("twice", 2000);
+    std::fs::hard_link(&twice, tree.root().join("twice again")).unwrap();
+    tree.file("single", 1000);
+
+    let mut config = config(tree.root(), 10);
+    config.count_hardlinks = true;
+    config.min_hardlinks = Some(2);
+    let results = parallel_scan(&config).0.unwrap();
+    let links: Vec<_> = results.iter().map(|e| e.nlink).collect();
+    assert_eq!(
+        relative(&results, tree.root()),
+        ["other link", "original", "link", "twice again", "twice"]
+    );
+    assert_eq!(links, [3, 3, 3, 2, 2]);
+
+    config.max_hardlinks = Some(2);
+    let results = parallel_scan(&config).0.unwrap();
+    assert_eq!(relative(&results, tree.root()), ["twice again", "twice"]);
+}
+
+#[test]
+fn max_depth_zero_scans_only_the_root() {
+    let tree = sample_tree();
+    let config = ScanConfig::builder(tree.root())
+        .top(10)
+        .max_depth(0)
+        .build()
+        .unwrap();
+    assert_eq!(
+        relative(&parallel_scan(&config).0.unwrap(), tree.root()),
+        ["big.bin"]
+    );
+}
+
+#[test]
+fn max_depth_limits_descent() {
+    let tree = sample_tree();
+    let config = ScanConfig::builder(tree.root())
+        .top(10)
+        .max_depth(1)
+        .build()
+        .unwrap();
+    let results = parallel_scan(&config).0.unwrap();
+    assert_eq!(
+        relative(&results, tree.root()),
+        ["big.bin", "d/four.log", "a/one.txt", "d/five.log"]
+    );
+}
+
+#[test]
+fn min_depth_skips_shallower_files() {
+    let tree = sample_tree();
+    let config = ScanConfig::builder(tree.root())
+        .top(10)
+        .min_depth(1)
+        .build()
+        .unwrap();
+    let results = parallel_scan(&config).0.unwrap();
+    assert_eq!(
+        relative(&results, tree.root()),
+        [
+            "a/b/two.txt",
+            "a/b/c/three.txt",
+            "d/four.log",
+            "a/one.txt",
+            "d/five.log"
+        ]
+    );
+
+    let config = ScanConfig::builder(tree.root())
+        .top(10)
+        .min_depth(2)
+        .max_depth(2)
+        .build()
+        .unwrap();
+    assert_eq!(
+        relative(&parallel_scan(&config).0.unwrap(), tree.root()),
+        ["a/b/two.txt"]
+    );
+}
+
+#[test]
+fn counts_every_matching_file() {
+    let tree = sample_tree();
+    let config = ScanConfig::builder(tree.root())
+        .top(2)
+        .min_size(1000)
+        .build()
+        .unwrap();
+    let (results, stats) = parallel_scan(&config);
+    assert_eq!(results.unwrap().len(), 2);
+    assert_eq!((stats.matched_files, stats.matched_size), (5, 15000));
+}
+
+#[test]
+fn git_ignore_follows_the_ignore_files() {
+    let tree = Tree::new();
+    tree.file("big.bin", 5000);
+    tree.file("a.log", 1000);
+    tree.file("build/out.o", 2000);
+    tree.file("keep/important.log", 300);
+    tree.file("keep/other.log", 200);
+    tree.file("scratch.tmp", 400);
+    tree.file(".git/objects/pack", 3000);
+    std::fs::write(tree.file(".git/info/exclude", 0), "*.tmp\n").unwrap();
+    std::fs::write(tree.root().join(".gitignore"), "*.log\nbuild/\n").unwrap();
+    std::fs::write(tree.root().join("keep/.gitignore"), "!important.log\n").unwrap();
+
+    let mut config = ScanConfig::builder(tree.root())
+        .top(10)
+        .min_size(100)
+        .build()
+        .unwrap();
+    assert_eq!(parallel_scan(&config).0.unwrap().len(), 7);
+    config.git_ignore = true;
+    let results = parallel_scan(&config).0.unwrap();
+    assert_eq!(
+        relative(&results, tree.root()),
+        ["big.bin", "keep/important.log"]
+    );
+}
+
+#[test]
+fn rank_files_ranks_only_the_given_files() {
+    let tree = sample_tree();
+    let paths: Vec<_> = [
+        "a/one.txt",
+        "big.bin",
+        "d/five.log",
+        "a/b",
+        "missing",
+        "a/one.txt",
+    ]
+    .iter()
+    .map(|path| tree.root().join(path))
+    .collect();
+    let (results, stats) = bfinder::rank_files(&paths, &config(tree.root(), 10));
+    let results = results.unwrap();
+    assert_eq!(
+        relative(&results, tree.root()),
+        ["big.bin", "a/one.txt", "d/five.log"]
+    );
+    assert_eq!((stats.files_scanned, stats.dirs_scanned), (3, 0));
+    assert_eq!(stats.errors, 1);
+}
+
+#[test]
+fn same_filesystem_keeps_the_roots_device() {
+    let tree = sample_tree();
+    let config = ScanConfig::builder(tree.root())
+        .top(10)
+        .same_filesystem(true)
+        .build()
+        .unwrap();
+    let (results, stats) = parallel_scan(&config);
+    let results = results.unwrap();
+    assert_eq!(results.len(), 6);
+    assert_eq!(stats.dirs_scanned, 4);
+}
+
+#[test]
+fn modification_time_bounds() {
+    let tree = sample_tree();
+    let day = Duration::from_secs(86400);
+    let now = SystemTime::now();
+    for (path, age) in [("big.bin", 10), ("a/one.txt", 3), ("d/four.log", 1)] {
+        let file = std::fs::File::options()
+            .write(true)
+            .open(tree.root().join(path))
+            .unwrap();
+        file.set_modified(now - day * age).unwrap();
+    }
+    let mut config = config(tree.root(), 10);
+    config.newer_than = Some(now - day * 5);
+    config.older_than = Some(now - day / 2);
+    assert_eq!(
+        relative(&parallel_scan(&config).0.unwrap(), tree.root()),
+        ["d/four.log", "a/one.txt"]
+    );
+}
+
+#[test]
+fn age_buckets() {
+    let day = Duration::from_secs(86400);
+    let buckets = AgeBuckets::new(vec![day, day * 7, day * 30]).unwrap();
+    assert_eq!(buckets.labels(), ["0-1d", "1d-7d", "7d-30d", ">30d"]);
+    assert!(AgeBuckets::new(vec![day * 7, day]).is_err());
+
+    let tree = sample_tree();
+    let now = SystemTime::now();
+    for (path, age) in [
+        ("big.bin", 40),
+        ("a/one.txt", 3),
+        ("d/four.log", 2),
+        ("d/five.log", 10),
+    ] {
+        let file = std::fs::File::options()
+            .write(true)
+            .open(tree.root().join(path))
+            .unwrap();
+        file.set_modified(now - day * age).unwrap();
+    }
+    let mut config = config(tree.root(), 1);
+    config.age_buckets = Some(AgeBuckets { now, ..buckets });
+    let (_, stats) = parallel_scan(&config);
+    let bins: Vec<_> = stats
+        .age_buckets
+        .iter()
+        .map(|b| (b.files, b.total_size))
+        .collect();
+    assert_eq!(bins, [(2, 7000), (2, 3000), (1, 500), (1, 5000)]);
+}
+
+#[cfg(unix)]
+#[test]
+fn follows_symlinks_once_per_target() {
+    use std::os::unix::fs::symlink;
+
+    let tree = Tree::new();
+    let target = tree.file("real/data", 2000);
+    tree.file("real/sub/more", 1000);
+    symlink(&target, tree.root().join("file-link")).unwrap();
+    symlink(tree.root().join("real"), tree.root().join("dir-link")).unwrap();
+    // A link back up the tree must not be followed again
+    symlink(tree.root(), tree.root().join("real/sub/loop")).unwrap();
+
+    let (results, stats) = parallel_scan(&config(tree.root(), 10));
+    let results = results.unwrap();
+    assert_eq!(
+        relative(&results, tree.root()),
+        ["real/data", "real/sub/more"]
+    );
+    assert_eq!(stats.symlinks_followed, 0);
+
+    let mut config = config(tree.root(), 10);
+    config.follow_symlinks = true;
+    let (results, stats) = parallel_scan(&config);
+    let results = results.unwrap();
+    assert_eq!(results.len(), 2, "{:?}", results);
+    assert_eq!(stats.symlinks_followed, 3);
+    assert!(stats.symlink_cycles_detected >= 1);
+    assert_eq!(stats.errors, 0);
+}
+
+#[test]
+fn count_only_keeps_no_entries() {
+    let tree = sample_tree();
+    let config = ScanConfig::builder(tree.root())
+        .top(10)
+        .count_only(true)
+        .build()
+        .unwrap();
+    let (results, stats) = parallel_scan(&config);
+    assert!(results.is_none());
+    assert_eq!(stats.files_scanned, 6);
+}
+
+#[test]
+fn disk_usage_measures_allocated_blocks() {
+    let tree = Tree::new();
+    tree.file("dense", 64 * 1024);
+    let sparse = std::fs::File::create(tree.root().join("sparse")).unwrap();
+    sparse.set_len(1024 * 1024).unwrap();
+
+    let results = parallel_scan(&config(tree.root(), 10)).0.unwrap();
+    assert_eq!(relative(&results, tree.root()), ["sparse", "dense"]);
+
+    let config = ScanConfig::builder(tree.root())
+        .top(10)
+        .disk_usage(true)
+        .build()
+        .unwrap();
+    let results = parallel_scan(&config).0.unwrap();
+    assert_eq!(relative(&results, tree.root()), ["dense", "sparse"]);
+    assert!(results[0].size >= 64 * 1024);
+    assert_eq!(results[1].size, 0);
+}
+
+#[test]
+fn extension_filter() {
+    let filter = ExtensionFilter::new(["log", ".tar.gz"], false);
+    assert!(filter.matches("five.log"));
+    assert!(filter.matches("backup.tar.gz"));
+    assert!(!filter.matches("backup.gz"));
+    assert!(!filter.matches(".log"));
+    assert!(!filter.matches("README"));
+    assert!(ExtensionFilter::new(["log"], true).matches("README"));
+
+    let tree = sample_tree();
+    let mut config = config(tree.root(), 10);
+    config.extensions = Some(ExtensionFilter::new(["log"], false));
+    let results = parallel_scan(&config).0.unwrap();
+    assert_eq!(
+        relative(&results, tree.root()),
+        ["d/four.log", "d/five.log"]
+    );
+}
+
+#[test]
+fn regex_filter() {
+    let filter = RegexFilter::new(&[r"\.log\.\d+$"], &[r"^debug"], false).unwrap();
+    assert!(filter.matches("app.log.1", Path::new("/var/log/app.log.1")));
+    assert!(!filter.matches("app.log", Path::new("/var/log/app.log")));
+    assert!(!filter.matches("debug.log.2", Path::new("/var/log/debug.log.2")));
+    let error = RegexFilter::new(&["(unclosed"], &[], false).unwrap_err();
+    assert!(error.starts_with("invalid regex '(unclosed'"), "{}", error);
+
+    let tree = sample_tree();
+    let mut config = config(tree.root(), 10);
+    config.regex = Some(RegexFilter::new(&["^f"], &[], false).unwrap());
+    let results = parallel_scan(&config).0.unwrap();
+    assert_eq!(
+        relative(&results, tree.root()),
+        ["d/four.log", "d/five.log"]
+    );
+    // Against full paths, the directories a file is in match too
+    config.regex = Some(RegexFilter::new(&["/a/b/"], &[], true).unwrap());
+    let results = parallel_scan(&config).0.unwrap();
+    assert_eq!(
+        relative(&results, tree.root()),
+        ["a/b/two.txt", "a/b/c/three.txt"]
+    );
+}
+
+#[test]
+fn name_filter() {
+    let filter = NameFilter::new(&["*.log", "core"], &["README*"]).unwrap();
+    assert!(filter.matches("five.log"));
+    assert!(filter.matches("core"));
+    assert!(filter.matches("readme.md"));
+    assert!(!filter.matches("core.1"));
+    assert!(!filter.matches("FIVE.LOG"));
+    assert!(NameFilter::new(&["a[b"], &[]).is_err());
+
+    let tree = sample_tree();
+    let mut config = config(tree.root(), 10);
+    config.names = Some(NameFilter::new(&["?ne.*", "f*"], &[]).unwrap());
+    let results = parallel_scan(&config).0.unwrap();
+    assert_eq!(
+        relative(&results, tree.root()),
+        ["d/four.log", "a/one.txt", "d/five.log"]
+    );
+}
+
+#[test]
+fn mime_filter() {
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+    let images = MimeFilter::new::<&str>(&[], &[MimeCategory::Image]).unwrap();
+    assert!(images.matches(PNG));
+    assert!(!images.matches(b"plain text"));
+    assert!(MimeFilter::new(&["IMAGE/PNG"], &[]).unwrap().matches(PNG));
+    assert!(!MimeFilter::new(&["image/jpeg"], &[]).unwrap().matches(PNG));
+    assert!(MimeFilter::new(&["text/x-nonsense"], &[]).is_err());
+
+    let tree = sample_tree();
+    std::fs::write(tree.root().join("a/renamed.dat"), [PNG, &[0; 100]].concat()).unwrap();
+    let mut config = config(tree.root(), 10);
+    config.mime = Some(images);
+    let results = parallel_scan(&config).0.unwrap();
+    assert_eq!(relative(&results, tree.root()), ["a/renamed.dat"]);
+}
+
+#[test]
+fn groups_by_extension() {
+    let tree = sample_tree();
+    tree.file("a/README", 10);
+    let mut config = config(tree.root(), 1);
+    config.group_by_extension = true;
+    let (_, stats) = parallel_scan(&config);
+    // Totals cover every file, not just the top N
+    assert_eq!(
+        stats.extensions_by_size(10),
+        [
+            ("txt", 8000, 3),
+            ("bin", 5000, 1),
+            ("log", 2500, 2),
+            ("", 10, 1)
+        ]
+    );
+    assert_eq!(stats.extensions_by_size(2).len(), 2);
+}
+
+#[test]
+fn top_per_dir_keeps_each_directorys_largest() {
+    let tree = sample_tree();
+    tree.file("d/six.log", 100);
+    let mut config = config(tree.root(), 1);
+    config.top_per_dir = Some(2);
+    let (_, stats) = parallel_scan(&config);
+    let dirs: Vec<_> = stats
+        .dirs_by_size()
+        .into_iter()
+        .map(|(dir, top)| {
+            let files: Vec<_> = top.files.iter().cloned().collect();
+            (
+                dir.strip_prefix(tree.root()).unwrap().to_path_buf(),
+                top.total_size,
+                relative(&files, tree.root()),
+            )
+        })
+        .collect();
+    assert_eq!(
+        dirs,
+        [
+            ("".into(), 5000, vec!["big.bin".to_string()]),
+            ("a/b".into(), 4000, vec!["a/b/two.txt".to_string()]),
+            ("a/b/c".into(), 3000, vec!["a/b/c/three.txt".to_string()]),
+            (
+                "d".into(),
+                2600,
+                vec!["d/four.log".to_string(), "d/five.log".to_string()]
+            ),
+            ("a".into(), 1000, vec!["a/one.txt".to_string()]),
+        ]
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn top_per_user_keeps_each_owners_largest() {
+    use std::os::unix::fs::MetadataExt;
+
+    let tree = sample_tree();
+    let uid = std::fs::metadata(tree.root().join("big.bin"))
+        .unwrap()
+        .uid();
+    let mut config = config(tree.root(), 1);
+    config.top_per_user = Some(2);
+    let (_, stats) = parallel_scan(&config);
+    let users = stats.users_by_size();
+    assert_eq!(users.len(), 1);
+    let (owner, top) = users[0];
+    let files: Vec<_> = top.files.iter().cloned().collect();
+    assert_eq!(owner, uid);
+    assert_eq!(top.total_size, 15500);
+    assert_eq!(relative(&files, tree.root()), ["big.bin", "a/b/two.txt"]);
+}
+
+#[test]
+fn aggregates_by_prefix() {
+    let root = Path::new("/data");
+    let entries = [
+        entry("/data/top.bin", 50),
+        entry("/data/a/one", 100),
+        entry("/data/a/b/two", 200),
+        entry("/data/a/c/three", 300),
+        entry("/elsewhere/four", 400),
+    ];
+    let totals = |depth| -> Vec<_> {
+        aggregate_by_prefix(&entries, root, depth)
+            .into_iter()
+            .map(|dir| (dir.path, dir.total_size, dir.file_count))
+            .collect()
+    };
+    assert_eq!(
+        totals(1),
+        [("/data/a".into(), 600, 3), ("/data".into(), 50, 1)]
+    );
+    assert_eq!(
+        totals(2),
+        [
+            ("/data/a/c".into(), 300, 1),
+            ("/data/a/b".into(), 200, 1),
+            ("/data/a".into(), 100, 1),
+            ("/data".into(), 50, 1),
+        ]
+    );
+}
+
+#[test]
+fn top_dirs_total_each_subdirectory() {
+    let tree = sample_tree();
+    let mut config = config(tree.root(), 10);
+    config.top_dirs = Some(1);
+    let (results, stats) = parallel_scan(&config);
+    assert!(results.unwrap().is_empty());
+    let dirs: Vec<_> = stats
+        .top_dirs
+        .iter()
+        .map(|dir| {
+            (
+                dir.path.strip_prefix(tree.root()).unwrap().to_path_buf(),
+                dir.total_size,
+                dir.file_count,
+            )
+        })
+        .collect();
+    assert_eq!(
+        dirs,
+        [
+            ("a".into(), 8000, 3),
+            ("".into(), 5000, 1),
+            ("d".into(), 2500, 2)
+        ]
+    );
+}
+
+#[test]
+fn largest_dirs_are_recursive_totals() {
+    let tree = sample_tree();
+    std::fs::create_dir(tree.root().join("empty")).unwrap();
+    let mut config = config(tree.root(), 10);
+    config.largest_dirs = Some(4);
+    let (results, stats) = parallel_scan(&config);
+    assert!(results.unwrap().is_empty());
+    let dirs: Vec<_> = stats
+        .largest_dirs
+        .iter()
+        .map(|dir| {
+            (
+                dir.path
+                    .strip_prefix(tree.root())
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned(),
+                dir.total_size,
+            )
+        })
+        .collect();
+    assert_eq!(
+        dirs,
+        [
+            ("".into(), 15500),
+            ("a".into(), 8000),
+            ("a/b".into(), 7000),
+            ("a/b/c".to_string(), 3000)
+        ]
+    );
+}
+
+/// A wide, deep tree of 600 files whose sizes repeat, so ties are common
+fn wide_tree() -> Tree {
+    let tree = Tree::new();
+    for dir in 0..20 {
+        for depth in 0..3 {
+            let path: String = (0..=depth)
+                .map(|level| format!("d{}-{}/", dir, level))
+                .collect();
+            for file in 0..10 {
+                tree.file(
+                    &format!("{}f{}", path, file),
+                    (dir * 37 + depth * 11 + file * 53) % 400,
+                );
+            }
+        }
+    }
+    tree
+}
+
+/// The results of scanning with `config` on a pool of `threads` threads
+fn scan_on(threads: usize, config: &ScanConfig) -> (Vec<(u64, PathBuf)>, u64) {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .unwrap();
+    let (results, stats) = pool.install(|| parallel_scan(config));
+    let results = results
+        .unwrap()
+        .into_iter()
+        .map(|e| (e.size, e.path))
+        .collect();
+    (results, stats.files_scanned)
+}
+
+#[test]
+fn thread_count_does_not_change_results() {
+    let tree = wide_tree();
+    for smallest in [false, true] {
+        let config = ScanConfig::builder(tree.root())
+            .top(25)
+            .find_smallest(smallest)
+            .build()
+            .unwrap();
+        let single = scan_on(1, &config);
+        assert_eq!(single.0.len(), 25);
+        assert_eq!(single.1, 600);
+        for threads in [2, 4, 8] {
+            assert_eq!(
+                scan_on(threads, &config),
+                single,
+                "{} threads, smallest: {}",
+                threads,
+                smallest
+            );
+        }
+    }
+}
+
+/// Every file under `root` with its size, largest first and ties broken by
+/// the larger path, as `FileEntry`'s `Ord` does
+fn full_sort(root: &Path) -> Vec<(u64, PathBuf)> {
+    let mut all = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let entry = entry.unwrap();
+            let metadata = entry.metadata().unwrap();
+            if metadata.is_dir() {
+                dirs.push(entry.path());
+            } else {
+                all.push((metadata.len(), entry.path()));
+            }
+        }
+    }
+    all.sort_by(|a, b| b.cmp(a));
+    all
+}
+
+#[test]
+fn merged_trackers_match_a_full_sort() {
+    let tree = wide_tree();
+    let mut all = full_sort(tree.root());
+    all.truncate(40);
+
+    let config = ScanConfig::builder(tree.root()).top(40).build().unwrap();
+    assert_eq!(scan_on(4, &config).0, all);
+}
+
+#[test]
+fn one_stat_per_entry() {
+    // Six files and four directories, each stat'ed once relative to its parent
+    let tree = sample_tree();
+    let (_, stats) = parallel_scan(&config(tree.root(), 10));
+    assert_eq!(stats.stat_calls, 10);
+}
+
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+#[test]
+fn io_uring_scan_matches_parallel_scan() {
+    // More entries in one directory than a ring takes in one submission
+    let tree = Tree::new();
+    for i in 0..600 {
+        tree.file(&format!("many/f{:03}", i), (i * 37) % 1000);
+    }
+    tree.file("many/sub/inner", 999);
+    let mut config = config(tree.root(), 50);
+    config.group_by_extension = true;
+    let (results, stats) = parallel_scan(&config);
+    let (uring_results, uring_stats) = match bfinder::io_uring_scan(&config) {
+        Ok(scan) => scan,
+        // Kernels without io_uring, or sandboxes that forbid it
+        Err(e) => return eprintln!("io_uring unavailable: {}", e),
+    };
+    assert_eq!(uring_results, results);
+    assert_eq!(uring_stats.files_scanned, 601);
+    assert_eq!(uring_stats.files_scanned, stats.files_scanned);
+    assert_eq!(uring_stats.dirs_scanned, stats.dirs_scanned);
+    assert_eq!(uring_stats.errors, 0);
+}
+
+#[test]
+fn collects_error_details() {
+    let tree = sample_tree();
+    let missing = tree.root().join("missing");
+    let mut config = config(&missing, 10);
+    config.roots.push(tree.root().to_path_buf());
+    config.collect_errors = true;
+    let (results, stats) = parallel_scan(&config);
+    assert_eq!(results.unwrap().len(), 6);
+    assert_eq!(stats.errors, 1);
+    assert!(
+        matches!(&stats.error_details[..], [ScanError::ReadDir { path, .. }] if *path == missing)
+    );
+    assert!(
+        stats.error_details[0]
+            .to_string()
+            .starts_with("cannot read directory ")
+    );
+}
+
+#[test]
+fn caps_the_error_details() {
+    let tree = sample_tree();
+    let missing = ["gone", "lost", "moved"].map(|name| tree.root().join(name));
+    let mut config = config(&missing[0], 10);
+    config.roots.extend(missing[1..].iter().cloned());
+    config.collect_errors = true;
+    config.max_error_details = Some(2);
+    let (_, stats) = parallel_scan(&config);
+    assert_eq!(stats.errors, 3);
+    let paths: Vec<_> = stats
+        .error_details
+        .iter()
+        .map(|e| e.path().to_path_buf())
+        .collect();
+    assert_eq!(paths, missing[..2]);
+    assert!(
+        stats.error_details[0]
+            .reason()
+            .starts_with("cannot read directory: ")
+    );
+}
+
+#[test]
+fn stops_after_max_errors() {
+    let tree = sample_tree();
+    let mut builder = ScanConfig::builder(tree.root()).top(10);
+    for missing in ["gone", "lost", "moved"] {
+        builder = builder.root(tree.root().join(missing));
+    }
+    let mut config = builder.build().unwrap();
+    config.max_errors = Some(4);
+    let (_, stats) = parallel_scan(&config);
+    assert_eq!(stats.errors, 3);
+    assert!(!stats.aborted);
+
+    config.max_errors = Some(2);
+    let (_, stats) = parallel_scan(&config);
+    assert!(stats.errors >= 2);
+    assert!(stats.aborted);
+}
+
+#[test]
+fn reports_the_directories_it_cannot_open() {
+    let tree = sample_tree();
+    let mut builder = ScanConfig::builder(tree.root()).top(10);
+    for missing in ["moved", "gone", "lost"] {
+        builder = builder.root(tree.root().join(missing));
+    }
+    let mut config = builder.build().unwrap();
+    let (results, stats) = parallel_scan(&config);
+    assert_eq!(results.unwrap().len(), 6);
+    assert_eq!(stats.dirs_inaccessible, 3);
+    assert!(stats.inaccessible_dirs.is_empty());
+
+    config.report_inaccessible = Some(2);
+    let stats = parallel_scan(&config).1;
+    assert_eq!(stats.dirs_inaccessible, 3);
+    let dirs: Vec<_> = stats
+        .inaccessible_dirs
+        .iter()
+        .map(|(path, _)| path.strip_prefix(tree.root()).unwrap())
+        .collect();
+    assert_eq!(dirs, [Path::new("gone"), Path::new("lost")]);
+    assert_eq!(
+        stats.inaccessible_dirs[0].1.kind(),
+        std::io::ErrorKind::NotFound
+    );
+}
+
+#[test]
+fn reports_progress() {
+    let tree = sample_tree();
+    let progress = Arc::new(ScanProgress::default());
+    let mut config = config(tree.root(), 3);
+    config.progress = Some(Arc::clone(&progress));
+    let (_, stats) = parallel_scan(&config);
+    // The threshold is the third largest file, which the others had to beat
+    assert_eq!(progress.snapshot(), (6, stats.dirs_scanned, 3000));
+    // Every directory found was listed, and the root with them
+    assert_eq!(
+        progress
+            .dirs_done
+            .load(std::sync::atomic::Ordering::Relaxed),
+        stats.dirs_scanned + 1
+    );
+}
+
+#[test]
+fn hidden_files() {
+    let tree = Tree::new();
+    tree.file("shown", 100);
+    tree.file(".hidden", 200);
+    tree.file(".dir/inside", 300);
+    let scan = |hidden| {
+        let mut config = config(tree.root(), 10);
+        config.hidden = hidden;
+        relative(&parallel_scan(&config).0.unwrap(), tree.root())
+    };
+    assert_eq!(
+        scan(HiddenFiles::Include),
+        [".dir/inside", ".hidden", "shown"]
+    );
+    assert_eq!(scan(HiddenFiles::Exclude), ["shown"]);
+    assert_eq!(scan(HiddenFiles::Only), [".dir/inside", ".hidden"]);
+}
+
+#[test]
+fn paths_relative_to_roots() {
+    let roots = [PathBuf::from("/data"), PathBuf::from("/data/projects")];
+    assert_eq!(
+        relative_to_roots(Path::new("/data/projects/x/y"), &roots),
+        Path::new("x/y")
+    );
+    assert_eq!(
+        relative_to_roots(Path::new("/data/z"), &roots),
+        Path::new("z")
+    );
+    assert_eq!(
+        relative_to_roots(Path::new("/data"), &roots),
+        Path::new(".")
+    );
+    assert_eq!(
+        relative_to_roots(Path::new("/other/z"), &roots),
+        Path::new("/other/z")
+    );
+}
+
+#[test]
+fn inode_usage_counts_every_child() {
+    let tree = sample_tree();
+    std::fs::create_dir(tree.root().join("d/sub")).unwrap();
+    let mut config = config(tree.root(), 1);
+    config.min_size = 1_000_000;
+    config.inode_usage = Some(2);
+    let (_, stats) = parallel_scan(&config);
+    let dirs: Vec<_> = stats
+        .inode_usage
+        .iter()
+        .map(|dir| (dir.path.clone(), dir.count))
+        .collect();
+    assert_eq!(
+        dirs,
+        [(tree.root().to_path_buf(), 3), (tree.root().join("d"), 3)]
+    );
+}
+
+#[test]
+fn duplicate_sizes_ordered_by_savings() {
+    let tree = sample_tree();
+    tree.file("copy.bin", 5000);
+    tree.file("d/copy.log", 500);
+    tree.file("d/copy2.log", 500);
+    tree.file("empty1", 0);
+    tree.file("empty2", 0);
+    let mut config = config(tree.root(), 1);
+    config.duplicate_sizes = true;
+    let (_, stats) = parallel_scan(&config);
+    let groups: Vec<_> = stats
+        .duplicate_size_groups()
+        .into_iter()
+        .map(|(size, paths)| {
+            let paths: Vec<_> = paths
+                .iter()
+                .map(|p| p.strip_prefix(tree.root()).unwrap().to_path_buf())
+                .collect();
+            (size, paths)
+        })
+        .collect();
+    // Empty files are never candidates
+    assert_eq!(
+        groups,
+        [
+            (
+                5000,
+                vec![PathBuf::from("big.bin"), PathBuf::from("copy.bin")]
+            ),
+            (
+                500,
+                vec![
+                    PathBuf::from("d/copy.log"),
+                    PathBuf::from("d/copy2.log"),
+                    PathBuf::from("d/five.log")
+                ]
+            ),
+        ]
+    );
+}
+
+fn strings(items: &[&str]) -> Vec<String> {
+    items.iter().map(|item| item.to_string()).collect()
+}
+
+#[test]
+fn hash_duplicates_compares_contents() {
+    let tree = Tree::new();
+    let mut data = vec![b'x'; 10_000];
+    for name in ["a", "b", "c"] {
+        std::fs::write(tree.root().join(name), &data).unwrap();
+    }
+    // Same size and first block, different ending
+    data[9_999] = b'y';
+    std::fs::write(tree.root().join("d"), &data).unwrap();
+    tree.file("small1", 100);
+    tree.file("small2", 100);
+    std::fs::write(tree.root().join("small3"), [b'z'; 100]).unwrap();
+
+    let mut config = config(tree.root(), 10);
+    config.duplicate_sizes = true;
+    config.hash_duplicates = true;
+    let (_, stats) = parallel_scan(&config);
+    let groups: Vec<_> = stats
+        .duplicates
+        .iter()
+        .map(|group| (group.wasted(), relative(&group.files, tree.root())))
+        .collect();
+    assert_eq!(
+        groups,
+        [
+            (20_000, strings(&["a", "b", "c"])),
+            (100, strings(&["small1", "small2"]))
+        ]
+    );
+    assert_eq!(stats.duplicates[0].canonical().path, tree.root().join("a"));
+}
+
+#[cfg(unix)]
+#[test]
+fn owner_and_group_filters() {
+    use std::os::unix::fs::MetadataExt;
+
+    let tree = sample_tree();
+    let meta = std::fs::metadata(tree.root().join("big.bin")).unwrap();
+    let scan = |owners: Vec<u32>, groups: Vec<u32>| {
+        let mut config = config(tree.root(), 10);
+        config.owners = owners;
+        config.groups = groups;
+        parallel_scan(&config).0.unwrap().len()
+    };
+    assert_eq!(scan(vec![meta.uid()], vec![]), 6);
+    assert_eq!(scan(vec![meta.uid() + 1], vec![]), 0);
+    assert_eq!(scan(vec![meta.uid() + 1, meta.uid()], vec![meta.gid()]), 6);
+    assert_eq!(scan(vec![], vec![meta.gid() + 1]), 0);
+}
+
+#[test]
+fn filesystems_total_the_results_beneath_each_root() {
+    let tree = sample_tree();
+    let mut config = config(&tree.root().join("a"), 10);
+    config.roots.push(tree.root().join("d"));
+    let (_, stats) = parallel_scan(&config);
+    let totals: Vec<_> = stats
+        .filesystems
+        .iter()
+        .map(|fs| (fs.root.clone(), fs.results_bytes))
+        .collect();
+    assert_eq!(
+        totals,
+        [(tree.root().join("a"), 8000), (tree.root().join("d"), 2500)]
+    );
+    assert!(stats.filesystems.iter().all(|fs| fs.total_bytes > 0));
+}
+
+/// A tree holding `name`, a tar of one 2000-byte member, and a loose file
+#[cfg(feature = "archives")]
+fn archive_tree(name: &str) -> Tree {
+    let tree = Tree::new();
+    let archive = tree.root().join(name);
+    std::fs::create_dir_all(archive.parent().unwrap()).unwrap();
+    let mut builder = tar::Builder::new(std::fs::File::create(&archive).unwrap());
+    let mut header = tar::Header::new_gnu();
+    header.set_size(2000);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder
+        .append_data(&mut header, "inner.bin", &[0u8; 2000][..])
+        .unwrap();
+    builder.finish().unwrap();
+    tree.file("loose", 100);
+    tree
+}
+
+#[cfg(feature = "archives")]
+#[test]
+fn lists_archive_members() {
+    let tree = archive_tree("a/bundle.tar");
+    let mut config = config(tree.root(), 10);
+    config.archive_depth = Some(0);
+    let (results, stats) = parallel_scan(&config);
+    let paths: Vec<_> = results
+        .unwrap()
+        .into_iter()
+        .map(|e| e.path.display().to_string())
+        .collect();
+    let archive = tree.root().join("a/bundle.tar").display().to_string();
+    let member = format!("{}::inner.bin", archive);
+    assert_eq!(
+        paths,
+        [
+            archive,
+            member,
+            tree.root().join("loose").display().to_string()
+        ]
+    );
+    assert_eq!(stats.archive_members, 1);
+}
+
+#[test]
+fn sparse_files() {
+    let tree = Tree::new();
+    tree.file("dense", 64 * 1024);
+    let sparse = std::fs::File::create(tree.root().join("sparse")).unwrap();
+    sparse.set_len(1024 * 1024).unwrap();
+    let mut config = config(tree.root(), 10);
+    config.min_sparseness = Some(0.5);
+    let results = parallel_scan(&config).0.unwrap();
+    assert_eq!(relative(&results, tree.root()), ["sparse"]);
+    assert_eq!(results[0].sparseness, 1.0);
+}
+
+#[cfg(unix)]
+#[test]
+fn mode_bits_keep_files_with_any_of_them() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let tree = sample_tree();
+    let chmod = |path: &str, mode| {
+        std::fs::set_permissions(
+            tree.root().join(path),
+            std::fs::Permissions::from_mode(mode),
+        )
+        .unwrap();
+    };
+    chmod("a/one.txt", 0o4755);
+    chmod("d/four.log", 0o666);
+    chmod("d/five.log", 0o2750);
+    let mut config = config(tree.root(), 10);
+    config.mode_bits = 0o4000 | 0o002;
+    let results = parallel_scan(&config).0.unwrap();
+    assert_eq!(relative(&results, tree.root()), ["d/four.log", "a/one.txt"]);
+    let modes: Vec<_> = results.iter().map(|e| e.mode & 0o7777).collect();
+    assert_eq!(modes, [0o666, 0o4755]);
+}
+
+#[cfg(unix)]
+#[test]
+fn rank_by_blocks_puts_sparse_files_last() {
+    let tree = Tree::new();
+    tree.file("dense", 200 * 1024);
+    tree.file("small", 10 * 1024);
+    std::fs::File::create(tree.root().join("sparse"))
+        .unwrap()
+        .set_len(1 << 20)
+        .unwrap();
+    let config = ScanConfig::builder(tree.root())
+        .top(2)
+        .rank_by_blocks(true)
+        .build()
+        .unwrap();
+    let results = parallel_scan(&config).0.unwrap();
+    assert_eq!(relative(&results, tree.root()), ["dense", "small"]);
+    assert!(results[0].allocated_size >= 200 * 1024);
+    assert_eq!(results[1].size, 10 * 1024);
+
+    let config = ScanConfig::builder(tree.root()).top(2).build().unwrap();
+    assert_eq!(
+        relative(&parallel_scan(&config).0.unwrap(), tree.root()),
+        ["sparse", "dense"]
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn counts_the_special_files() {
+    use std::os::unix::ffi::OsStrExt;
+
+    let tree = sample_tree();
+    std::os::unix::fs::symlink(tree.root().join("big.bin"), tree.root().join("a/link")).unwrap();
+    let _listener = std::os::unix::net::UnixListener::bind(tree.root().join("d/socket")).unwrap();
+    let fifo = std::ffi::CString::new(tree.root().join("d/fifo").as_os_str().as_bytes()).unwrap();
+    // SAFETY: `fifo` is a NUL-terminated path that outlives the call
+    assert_eq!(unsafe { libc::mkfifo(fifo.as_ptr(), 0o644) }, 0);
+
+    let (results, stats) = parallel_scan(&config(tree.root(), 10));
+    assert_eq!(results.unwrap().len(), 6);
+    assert_eq!(stats.file_types, None);
+
+    let config = ScanConfig::builder(tree.root())
+        .top(10)
+        .count_file_types(true)
+        .build()
+        .unwrap();
+    let types = parallel_scan(&config).1.file_types.unwrap();
+    assert_eq!((types.symlinks, types.sockets, types.fifos), (1, 1, 1));
+    assert_eq!((types.block_devs, types.char_devs), (0, 0));
+}
+
+#[cfg(unix)]
+#[test]
+fn virtual_size_adds_extended_attributes() {
+    let tree = Tree::new();
+    let tagged = tree.file("tagged", 100);
+    tree.file("plain", 150);
+    let set = rustix::fs::setxattr(
+        &tagged,
+        "user.origin",
+        b"downloaded",
+        rustix::fs::XattrFlags::empty(),
+    );
+    if set.is_err() {
+        // The temporary directory's filesystem keeps no user attributes
+        return;
+    }
+    assert_eq!(bfinder::extended_attributes_size(&tagged).unwrap(), 21);
+
+    let mut config = config(tree.root(), 10);
+    config.virtual_size = true;
+    let results = parallel_scan(&config).0.unwrap();
+    let sizes: Vec<_> = results.iter().map(|e| (e.size, e.xattr_size)).collect();
+    assert_eq!(relative(&results, tree.root()), ["plain", "tagged"]);
+    assert_eq!(sizes, [(150, 0), (121, 21)]);
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn async_scan_matches_parallel_scan() {
+    let tree = sample_tree();
+    tree.file("e/f/g/deep.bin", 4500);
+    let mut config = config(tree.root(), 4);
+    config.group_by_extension = true;
+    let (results, stats) = parallel_scan(&config);
+    let runtime = tokio::runtime::Builder::new_multi_thread().build().unwrap();
+    let (async_results, async_stats) = runtime.block_on(bfinder::async_scan(config));
+    assert_eq!(async_results, results);
+    assert_eq!(async_stats.files_scanned, stats.files_scanned);
+    assert_eq!(async_stats.dirs_scanned, stats.dirs_scanned);
+    assert_eq!(
+        async_stats.extensions_by_size(10),
+        stats.extensions_by_size(10)
+    );
+}
+
+#[test]
+fn finds_the_outermost_empty_directories() {
+    let tree = sample_tree();
+    for dir in ["e/f/g", "e/h", "a/b/empty", "d/x/y/z"] {
+        std::fs::create_dir_all(tree.root().join(dir)).unwrap();
+    }
+    // A directory with nothing but an empty file is not empty
+    tree.file("k/l/nothing", 0);
+    let empty: Vec<_> = find_empty_dirs(tree.root())
+        .into_iter()
+        .map(|dir| {
+            (
+                dir.path
+                    .strip_prefix(tree.root())
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned(),
+                dir.subdir_count,
+            )
+        })
+        .collect();
+    assert_eq!(
+        empty,
+        [
+            ("e".to_string(), 3),
+            ("d/x".to_string(), 2),
+            ("a/b/empty".to_string(), 0)
+        ]
+    );
+}
+
+#[test]
+fn skipping_files_that_cannot_rank_keeps_the_results() {
+    // With only 3 to keep, each thread's tracker fills early and the bound
+    // it sets skips most of the 600 files before an entry is built
+    let tree = wide_tree();
+    let mut all = full_sort(tree.root());
+    all.truncate(3);
+    let config = ScanConfig::builder(tree.root()).top(3).build().unwrap();
+    for threads in [1, 2, 4, 8] {
+        assert_eq!(
+            scan_on(threads, &config),
+            (all.clone(), 600),
+            "{} threads",
+            threads
+        );
+    }
+
+    let config = ScanConfig::builder(tree.root())
+        .top(3)
+        .prefilter_size(all[2].0)
+        .build()
+        .unwrap();
+    assert_eq!(scan_on(4, &config).0, all);
+}
+
+/// A report to turn on, and its totals for comparing scans
+type Report = (fn(&mut ScanConfig), fn(&ScanStats) -> String);
+
+#[test]
+fn reports_see_files_too_small_to_rank() {
+    let tree = sample_tree();
+    tree.file("d/empty.lock", 0);
+    let with_report = |report: fn(&mut ScanConfig)| {
+        let mut config = ScanConfig::builder(tree.root())
+            .top(1)
+            .prefilter_size(4500)
+            .build()
+            .unwrap();
+        report(&mut config);
+        config
+    };
+    let reports: [Report; 4] = [
+        (
+            |config| {
+                config.age_buckets = Some(AgeBuckets::new(vec![Duration::from_secs(3600)]).unwrap())
+            },
+            |stats| format!("{:?}", stats.age_buckets),
+        ),
+        (
+            |config| config.top_dirs = Some(1),
+            |stats| format!("{:?}", stats.top_dirs),
+        ),
+        (
+            |config| config.zero_sized = true,
+            |stats| format!("{:?}", stats.zero_sized),
+        ),
+        (
+            |config| config.largest_dirs = Some(1),
+            |stats| format!("{:?}", stats.largest_dirs),
+        ),
+    ];
+    for (report, totals) in reports {
+        // The same report from a scan that keeps every file
+        let mut unfiltered = config(tree.root(), 100);
+        report(&mut unfiltered);
+        let expected = parallel_scan(&unfiltered).1;
+        let stats = parallel_scan(&with_report(report)).1;
+        assert_eq!(totals(&stats), totals(&expected));
+        assert_eq!((stats.matched_files, stats.matched_size), (7, 15500));
+    }
+}
+
+#[cfg(feature = "adaptive-threads")]
+#[test]
+fn auto_threads_do_not_change_results() {
+    let tree = wide_tree();
+    let mut config = ScanConfig::builder(tree.root()).top(25).build().unwrap();
+    let fixed = scan_on(4, &config);
+    config.auto_threads = true;
+    let (results, stats) = parallel_scan(&config);
+    let results: Vec<_> = results
+        .unwrap()
+        .into_iter()
+        .map(|e| (e.size, e.path))
+        .collect();
+    assert_eq!((results, stats.files_scanned), fixed);
+    assert!(stats.auto_threads >= 1);
+}
+
+#[cfg(unix)]
+#[test]
+fn keeps_names_that_are_not_utf8() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let tree = Tree::new();
+    let name = OsStr::from_bytes(b"b\xff.log");
+    std::fs::write(tree.root().join(name), [0u8; 700]).unwrap();
+    tree.file("a.txt", 100);
+
+    let config = ScanConfig::builder(tree.root()).top(10).build().unwrap();
+    let (results, stats) = parallel_scan(&config);
+    let paths: Vec<_> = results.unwrap().into_iter().map(|e| e.path).collect();
+    assert_eq!(paths, [tree.root().join(name), tree.root().join("a.txt")]);
+    assert_eq!(stats.errors, 0);
+
+    // Name filters see the invalid byte as U+FFFD
+    let config = ScanConfig::builder(tree.root())
+        .top(10)
+        .extensions(ExtensionFilter::new(["log"], false))
+        .build()
+        .unwrap();
+    let paths: Vec<_> = parallel_scan(&config)
+        .0
+        .unwrap()
+        .into_iter()
+        .map(|e| e.path)
+        .collect();
+    assert_eq!(paths, [tree.root().join(name)]);
+}
+
+#[test]
+fn small_tracker_reserve_keeps_results_exact() {
+    let tree = wide_tree();
+    let full = ScanConfig::builder(tree.root()).top(25).build().unwrap();
+    let reserved = ScanConfig::builder(tree.root())
+        .top(25)
+        .tracker_reserve(2)
+        .build()
+        .unwrap();
+    assert_eq!(scan_on(4, &reserved), scan_on(4, &full));
+}
+
+#[test]
+fn filesystem_of_a_root() {
+    let tree = sample_tree();
+    let fs = bfinder::FilesystemInfo::of(tree.root()).unwrap();
+    assert!(fs.used_bytes <= fs.total_bytes);
+    assert_eq!(fs.results_bytes, 0);
+}
+
+#[cfg(feature = "archives")]
+#[test]
+fn archives_must_pass_the_file_filters_to_be_opened() {
+    let tree = archive_tree("a/bundle.tar");
+    let scan = |config: &mut ScanConfig| {
+        config.archive_depth = Some(0);
+        parallel_scan(config).1.archive_members
+    };
+    let mut config = ScanConfig::builder(tree.root())
+        .top(10)
+        .min_depth(2)
+        .build()
+        .unwrap();
+    assert_eq!(scan(&mut config), 0);
+    let mut config = ScanConfig::builder(tree.root())
+        .top(10)
+        .hidden(bfinder::HiddenFiles::Only)
+        .build()
+        .unwrap();
+    assert_eq!(scan(&mut config), 0);
+    let mut config = ScanConfig::builder(tree.root()).top(10).build().unwrap();
+    config.owners = vec![u32::MAX];
+    assert_eq!(scan(&mut config), 0);
+    config.owners.clear();
+    config.min_sparseness = Some(0.5);
+    assert_eq!(scan(&mut config), 0);
+    // Size filters apply to the members, not to the larger archive
+    let mut config = ScanConfig::builder(tree.root())
+        .top(10)
+        .max_size(2500)
+        .build()
+        .unwrap();
+    config.archive_depth = Some(0);
+    let results = parallel_scan(&config).0.unwrap();
+    assert_eq!(
+        results.iter().map(|e| e.size).collect::<Vec<_>>(),
+        [2000, 100]
+    );
+}
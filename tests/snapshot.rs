@@ -0,0 +1,160 @@
+//! Binary snapshots written by `write_snapshot` and read by `read_snapshot`
+#![cfg(feature = "serde")]
+
+mod common;
+
+use bfinder::ScanStats;
+use bfinder::snapshot::{MAGIC, VERSION, is_snapshot, read_snapshot, write_snapshot};
+use common::entry;
+use std::io::ErrorKind;
+
+fn stats() -> ScanStats {
+    let mut stats = ScanStats::default();
+    stats.files_scanned = 12;
+    stats.dirs_scanned = 3;
+    stats.errors = 1;
+    stats.by_extension.insert("log".to_string(), (2500, 2));
+    stats
+}
+
+fn snapshot(results: Option<&[bfinder::FileEntry]>) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    write_snapshot(results, &stats(), &mut bytes).unwrap();
+    bytes
+}
+
+fn read_error(bytes: &[u8]) -> String {
+    let error = read_snapshot(&mut &bytes[..]).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidData, "{}", error);
+    error.to_string()
+}
+
+#[test]
+fn round_trip() {
+    let mut dated = entry("/data/big", 5000);
+    dated.modified =
+        Some(std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000));
+    dated.checksum = Some(vec![1, 2, 3]);
+    let results = [dated, entry("/data/small", 10)];
+    let bytes = snapshot(Some(&results));
+    assert!(is_snapshot(&bytes));
+    assert!(bytes.starts_with(MAGIC));
+    assert_eq!(bytes[MAGIC.len()], VERSION);
+
+    let (read, stats) = read_snapshot(&mut &bytes[..]).unwrap();
+    assert_eq!(read.unwrap(), results);
+    assert_eq!(
+        (stats.files_scanned, stats.dirs_scanned, stats.errors),
+        (12, 3, 1)
+    );
+    assert_eq!(stats.by_extension["log"], (2500, 2));
+}
+
+#[test]
+fn round_trip_without_results() {
+    let bytes = snapshot(None);
+    let (read, stats) = read_snapshot(&mut &bytes[..]).unwrap();
+    assert!(read.is_none());
+    assert_eq!(stats.files_scanned, 12);
+}
+
+#[test]
+fn rejects_other_files() {
+    let mut bytes = snapshot(None);
+    bytes[0] = b'X';
+    assert!(!is_snapshot(&bytes));
+    assert_eq!(read_error(&bytes), "not a bfinder binary snapshot");
+    assert!(!is_snapshot(b"{\"results\":[]}"));
+}
+
+#[test]
+fn rejects_other_versions() {
+    let mut bytes = snapshot(None);
+    bytes[MAGIC.len()] = VERSION + 1;
+    let expected = format!(
+        "snapshot format version {} is not supported (expected {})",
+        VERSION + 1,
+        VERSION
+    );
+    assert_eq!(read_error(&bytes), expected);
+}
+
+#[test]
+fn rejects_truncated_snapshots() {
+    let bytes = snapshot(Some(&[entry("/a", 1)]));
+    // Cut in the checksum, and in the payload
+    assert_eq!(
+        read_error(&bytes[..bytes.len() - 1]),
+        "snapshot is truncated"
+    );
+    assert_eq!(read_error(&bytes[..30]), "snapshot is truncated");
+    // Cut in the header
+    let error = read_snapshot(&mut &bytes[..10]).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn rejects_corrupted_payloads() {
+    let mut bytes = snapshot(Some(&[entry("/a", 1)]));
+    bytes[20] ^= 0xff;
+    assert_eq!(read_error(&bytes), "snapshot checksum mismatch");
+}
+
+#[test]
+fn since_snapshot_reports_the_changes() {
+    let tree = common::sample_tree();
+    let saved = tempfile::tempdir().unwrap();
+    let snapshot = saved.path().join("snapshot.json");
+    let bfinder = || {
+        let mut command = std::process::Command::new(env!("CARGO_BIN_EXE_bfinder"));
+        command.args(["--no-config", "-n", "10"]);
+        command
+    };
+    let output = bfinder()
+        .arg("--save-snapshot")
+        .arg(&snapshot)
+        .arg(tree.root())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    tree.file("new.txt", 700);
+    tree.file("a/one.txt", 1500);
+    std::fs::remove_file(tree.root().join("d/five.log")).unwrap();
+    let output = bfinder()
+        .args(["--format", "json", "--since-snapshot"])
+        .arg(&snapshot)
+        .arg(tree.root())
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let files = |key: &str| -> Vec<(String, u64)> {
+        report[key]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| {
+                let path = std::path::Path::new(e["path"].as_str().unwrap());
+                let path = path
+                    .strip_prefix(tree.root())
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned();
+                (path, e["size_bytes"].as_u64().unwrap())
+            })
+            .collect()
+    };
+    assert_eq!(
+        files("results"),
+        [
+            ("a/one.txt".to_string(), 1500),
+            ("new.txt".to_string(), 700)
+        ]
+    );
+    assert_eq!(files("removed"), [("d/five.log".to_string(), 500)]);
+}
@@ -0,0 +1,85 @@
+//! `sort_results`
+
+mod common;
+
+use bfinder::{FileEntry, SortOrder, sort_results};
+use common::entry;
+use std::time::{Duration, SystemTime};
+
+fn files() -> Vec<FileEntry> {
+    vec![
+        entry("/b/x.log", 10),
+        entry("/a/y.txt", 30),
+        entry("/c/z", 20),
+        entry("/a/x.log", 10),
+        entry("/b/w.txt", 30),
+    ]
+}
+
+fn sorted(order: SortOrder) -> Vec<String> {
+    let mut results = files();
+    sort_results(&mut results, order);
+    results
+        .into_iter()
+        .map(|e| e.path.display().to_string())
+        .collect()
+}
+
+#[test]
+fn by_size_then_path() {
+    assert_eq!(
+        sorted(SortOrder::Size),
+        ["/a/y.txt", "/b/w.txt", "/c/z", "/a/x.log", "/b/x.log"]
+    );
+}
+
+#[test]
+fn by_path() {
+    assert_eq!(
+        sorted(SortOrder::Path),
+        ["/a/x.log", "/a/y.txt", "/b/w.txt", "/b/x.log", "/c/z"]
+    );
+}
+
+#[test]
+fn by_name_then_size() {
+    assert_eq!(
+        sorted(SortOrder::Name),
+        ["/b/w.txt", "/a/x.log", "/b/x.log", "/a/y.txt", "/c/z"]
+    );
+}
+
+#[test]
+fn by_extension_then_size() {
+    // Files without an extension come first
+    assert_eq!(
+        sorted(SortOrder::Ext),
+        ["/c/z", "/a/x.log", "/b/x.log", "/a/y.txt", "/b/w.txt"]
+    );
+}
+
+#[test]
+fn by_time_newest_first_then_size() {
+    let at = |secs| Some(SystemTime::UNIX_EPOCH + Duration::from_secs(secs));
+    let mut results = files();
+    for (entry, secs) in results.iter_mut().zip([300, 100, 200, 300, 100]) {
+        entry.modified = at(secs);
+        entry.accessed = at(1000 - secs);
+    }
+    let paths = |results: &[FileEntry]| -> Vec<String> {
+        results
+            .iter()
+            .map(|e| e.path.display().to_string())
+            .collect()
+    };
+    sort_results(&mut results, SortOrder::Mtime);
+    assert_eq!(
+        paths(&results),
+        ["/a/x.log", "/b/x.log", "/c/z", "/a/y.txt", "/b/w.txt"]
+    );
+    sort_results(&mut results, SortOrder::Atime);
+    assert_eq!(
+        paths(&results),
+        ["/a/y.txt", "/b/w.txt", "/c/z", "/a/x.log", "/b/x.log"]
+    );
+}
@@ -0,0 +1,186 @@
+//! `TopNTracker` and `merge_trackers`
+
+mod common;
+
+use bfinder::{DirSizeEntry, InodeEntry, TopNTracker, merge_trackers};
+use common::entry;
+use std::path::PathBuf;
+
+fn sizes(entries: &[bfinder::FileEntry]) -> Vec<u64> {
+    entries.iter().map(|e| e.size).collect()
+}
+
+#[test]
+fn keeps_the_largest() {
+    let mut tracker = TopNTracker::new(3);
+    for (i, size) in [5, 1, 9, 3, 7, 2].into_iter().enumerate() {
+        tracker.insert(entry(&format!("f{}", i), size));
+    }
+    assert!(tracker.is_full());
+    assert_eq!(tracker.worst().unwrap().size, 5);
+    assert_eq!(sizes(&tracker.into_vec()), [9, 7, 5]);
+}
+
+#[test]
+fn keeps_the_smallest() {
+    let mut tracker = TopNTracker::smallest(2);
+    for (i, size) in [5, 1, 9, 3].into_iter().enumerate() {
+        tracker.insert(entry(&format!("f{}", i), size));
+    }
+    assert_eq!(sizes(&tracker.into_vec()), [1, 3]);
+}
+
+#[test]
+fn not_full_has_no_worst_to_beat() {
+    let mut tracker = TopNTracker::new(3);
+    tracker.insert(entry("a", 10));
+    assert!(!tracker.is_full());
+    assert_eq!(tracker.worst().unwrap().size, 10);
+}
+
+#[test]
+fn zero_capacity_keeps_nothing() {
+    let mut tracker = TopNTracker::new(0);
+    tracker.insert(entry("a", 10));
+    assert!(tracker.into_vec().is_empty());
+}
+
+#[test]
+fn ties_break_by_path() {
+    let mut tracker = TopNTracker::new(2);
+    for path in ["c", "a", "b"] {
+        tracker.insert(entry(path, 100));
+    }
+    let kept: Vec<_> = tracker
+        .into_vec()
+        .into_iter()
+        .map(|e| e.path.to_str().unwrap().to_owned())
+        .collect();
+    // Larger paths rank higher among equal sizes, like `FileEntry`'s `Ord`
+    assert_eq!(kept, ["c", "b"]);
+}
+
+#[test]
+fn merge_keeps_the_overall_top() {
+    let mut first = TopNTracker::new(3);
+    let mut second = TopNTracker::new(3);
+    for size in [10, 40, 20] {
+        first.insert(entry(&format!("first{}", size), size));
+    }
+    for size in [30, 50, 5] {
+        second.insert(entry(&format!("second{}", size), size));
+    }
+    let merged = merge_trackers([first, second], 4);
+    assert_eq!(sizes(&merged.into_vec()), [50, 40, 30, 20]);
+}
+
+#[test]
+fn merge_follows_the_first_trackers_order() {
+    let mut first = TopNTracker::smallest(2);
+    let mut second = TopNTracker::smallest(2);
+    first.insert(entry("a", 7));
+    first.insert(entry("b", 3));
+    second.insert(entry("c", 1));
+    second.insert(entry("d", 9));
+    let merged = merge_trackers([first, second], 2);
+    assert_eq!(sizes(&merged.into_vec()), [1, 3]);
+}
+
+#[test]
+fn merge_of_nothing_is_empty() {
+    let merged = merge_trackers(Vec::<TopNTracker>::new(), 5);
+    assert!(merged.into_vec().is_empty());
+}
+
+#[test]
+fn tracks_any_ord_type() {
+    let mut tracker = TopNTracker::new(3);
+    for n in [4u32, 8, 15, 16, 23, 42] {
+        tracker.insert(n);
+    }
+    assert_eq!(tracker.into_vec(), [42, 23, 16]);
+
+    let mut tracker = TopNTracker::smallest(2);
+    for word in ["pear", "apple", "fig"] {
+        tracker.insert(word);
+    }
+    assert_eq!(tracker.into_vec(), ["apple", "fig"]);
+}
+
+#[test]
+fn tracks_directories() {
+    let dir = |path: &str, total_size| DirSizeEntry {
+        total_size,
+        path: PathBuf::from(path),
+    };
+    let mut first = TopNTracker::new(2);
+    first.insert(dir("/a", 300));
+    first.insert(dir("/b", 100));
+    let mut second = TopNTracker::new(2);
+    second.insert(dir("/c", 200));
+    let merged = merge_trackers([first, second], 2).into_vec();
+    assert_eq!(merged, [dir("/a", 300), dir("/c", 200)]);
+
+    let inodes = |path: &str, count| InodeEntry {
+        count,
+        path: PathBuf::from(path),
+    };
+    let mut tracker = TopNTracker::new(1);
+    tracker.insert(inodes("/few", 3));
+    tracker.insert(inodes("/many", 3000));
+    assert_eq!(tracker.into_vec(), [inodes("/many", 3000)]);
+}
+
+#[test]
+fn tracks_types_of_the_callers_own() {
+    #[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+    struct Mailbox {
+        messages: u32,
+        owner: &'static str,
+    }
+    let per_thread = [[(10, "ann"), (70, "bob")], [(40, "cy"), (90, "dee")]].map(|boxes| {
+        let mut tracker = TopNTracker::new(2);
+        for (messages, owner) in boxes {
+            tracker.insert(Mailbox { messages, owner });
+        }
+        tracker
+    });
+    let owners: Vec<_> = merge_trackers(per_thread, 3)
+        .into_vec()
+        .into_iter()
+        .map(|m| m.owner)
+        .collect();
+    assert_eq!(owners, ["dee", "bob", "cy"]);
+}
+
+#[test]
+fn map_reranks_the_entries() {
+    let mut tracker = TopNTracker::new(3);
+    for n in [1i32, 2, 3] {
+        tracker.insert(n);
+    }
+    assert_eq!(tracker.map(|n| -n).into_vec(), [-1, -2, -3]);
+}
+
+#[test]
+fn entry_memory_counts_the_path_and_checksum() {
+    let short = entry("/a", 1);
+    let mut long = entry(&format!("/{}", "x".repeat(99)), 1);
+    assert_eq!(long.approx_size_bytes() - short.approx_size_bytes(), 98);
+    long.checksum = Some(Vec::with_capacity(64));
+    assert_eq!(
+        long.approx_size_bytes() - short.approx_size_bytes(),
+        98 + 64
+    );
+}
+
+#[test]
+fn entry_size_counts_the_path_allocation() {
+    let mut path = PathBuf::with_capacity(256);
+    path.push("/a");
+    let entry = bfinder::FileEntry {
+        path,
+        ..bfinder::FileEntry::default()
+    };
+    assert!(entry.approx_size_bytes() >= std::mem::size_of::<bfinder::FileEntry>() + 256);
+}
@@ -0,0 +1,41 @@
+//! `build_tree` over lists of files
+
+mod common;
+
+use bfinder::tree::build_tree;
+use common::entry;
+use std::path::Path;
+
+#[test]
+fn merges_shared_ancestors_into_the_root() {
+    let files = [
+        entry("/data/a/x", 5),
+        entry("/data/b/y", 7),
+        entry("/data/a/z", 1),
+        entry("/data/w", 2),
+    ];
+    let root = build_tree(&files);
+    assert_eq!(root.name, Path::new("/data"));
+    assert_eq!(root.size, 15);
+    assert_eq!(root.files, [entry("/data/w", 2)]);
+    // Largest subdirectory first, files in the order given
+    let children: Vec<_> = root
+        .children
+        .iter()
+        .map(|c| (c.name.to_str().unwrap(), c.size))
+        .collect();
+    assert_eq!(children, [("b", 7), ("a", 6)]);
+    assert_eq!(
+        root.children[1].files,
+        [entry("/data/a/x", 5), entry("/data/a/z", 1)]
+    );
+}
+
+#[test]
+fn relative_paths_without_a_shared_ancestor() {
+    let root = build_tree(&[entry("./a/x", 1), entry("b", 2)]);
+    assert_eq!(root.name, Path::new(""));
+    assert_eq!(root.files, [entry("b", 2)]);
+    assert_eq!(root.children.len(), 1);
+    assert!(build_tree(&[]).children.is_empty());
+}
@@ -0,0 +1,40 @@
+//! `verify_results` and `rerank_verified` after changing files that were scanned
+
+mod common;
+
+use bfinder::verify::{rerank_verified, verify_results};
+use bfinder::{ScanConfig, Verification, parallel_scan};
+use common::{relative, sample_tree};
+
+#[test]
+fn flags_changed_and_deleted_files() {
+    let tree = sample_tree();
+    let config = ScanConfig::builder(tree.root()).top(3).build().unwrap();
+    let mut results = parallel_scan(&config).0.unwrap();
+    assert_eq!(
+        relative(&results, tree.root()),
+        ["big.bin", "a/b/two.txt", "a/b/c/three.txt"]
+    );
+
+    tree.file("big.bin", 1000);
+    std::fs::remove_file(tree.root().join("a/b/two.txt")).unwrap();
+    let errors = verify_results(&mut results, &config);
+    assert!(errors.is_empty());
+    let verified: Vec<_> = results.iter().map(|e| e.verified).collect();
+    assert_eq!(
+        verified,
+        [
+            Some(Verification::Changed(1000)),
+            Some(Verification::Deleted),
+            Some(Verification::Unchanged)
+        ]
+    );
+
+    rerank_verified(&mut results, false);
+    let sizes: Vec<_> = results.iter().map(|e| e.size).collect();
+    assert_eq!(
+        relative(&results, tree.root()),
+        ["a/b/c/three.txt", "big.bin"]
+    );
+    assert_eq!(sizes, [3000, 1000]);
+}